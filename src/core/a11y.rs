@@ -0,0 +1,123 @@
+// Announces settled sections for screen readers. The web build keeps an aria-live element
+// next to the canvas, native logs the text. Captions show the same element on screen
+
+use crate::core::section::SectionInfo;
+
+// Seconds an announcement waits for a newer one, so scrolling past sections stays quiet
+const ANNOUNCE_DEBOUNCE: f32 = 0.6;
+#[cfg(target_arch = "wasm32")]
+const ANNOUNCER_ID: &str = "cv-announcer";
+// Readable by screen readers but not drawn
+#[cfg(target_arch = "wasm32")]
+const HIDDEN_STYLE: &str = "position:absolute;width:1px;height:1px;overflow:hidden;\
+     clip:rect(0 0 0 0);white-space:nowrap";
+#[cfg(target_arch = "wasm32")]
+const CAPTION_STYLE: &str = "position:fixed;left:50%;bottom:2rem;transform:translateX(-50%);\
+     padding:0.5rem 1rem;background:rgba(0,0,0,0.7);color:#fff;font:16px sans-serif;\
+     border-radius:6px;pointer-events:none;z-index:900";
+
+// What a section change says, the title and years when the section has them
+pub fn section_announcement(index: usize, info: Option<&SectionInfo>) -> String {
+    match info {
+        Some(info) if !info.years.is_empty() => format!("{}, {}", info.title, info.years),
+        Some(info) => info.title.clone(),
+        None => format!("Section {}", index + 1),
+    }
+}
+
+pub struct Announcer {
+    pending: Option<String>,
+    waited: f32,
+    captions: bool,
+}
+
+impl Announcer {
+    pub fn new(captions: bool) -> Announcer {
+        Announcer {
+            pending: None,
+            waited: 0.0,
+            captions,
+        }
+    }
+
+    // Replaces any announcement that hasn't gone out yet
+    pub fn announce(&mut self, text: String) {
+        self.pending = Some(text);
+        self.waited = 0.0;
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    pub fn captions(&self) -> bool {
+        self.captions
+    }
+
+    pub fn set_captions(&mut self, captions: bool) {
+        self.captions = captions;
+        #[cfg(target_arch = "wasm32")]
+        if let Some(element) = announcer_element() {
+            let _ = element.set_attribute("style", style(captions));
+        }
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        if self.pending.is_none() {
+            return;
+        }
+        self.waited += dt;
+        if self.waited < ANNOUNCE_DEBOUNCE {
+            return;
+        }
+        if let Some(text) = self.pending.take() {
+            self.flush(&text);
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn flush(&self, text: &str) {
+        if self.captions {
+            log::warn!("Caption: {}", text);
+        } else {
+            log::info!("Announce: {}", text);
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn flush(&self, text: &str) {
+        if let Some(element) = announcer_element().or_else(|| create_announcer(self.captions)) {
+            element.set_text_content(Some(text));
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn style(captions: bool) -> &'static str {
+    if captions {
+        CAPTION_STYLE
+    } else {
+        HIDDEN_STYLE
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn announcer_element() -> Option<web_sys::Element> {
+    web_sys::window()?
+        .document()?
+        .get_element_by_id(ANNOUNCER_ID)
+}
+
+// Created on the first announcement, polite so it doesn't interrupt the reader
+#[cfg(target_arch = "wasm32")]
+fn create_announcer(captions: bool) -> Option<web_sys::Element> {
+    let document = web_sys::window()?.document()?;
+    let canvas = document.get_element_by_id("canvas")?;
+    let element = document.create_element("div").ok()?;
+    element.set_id(ANNOUNCER_ID);
+    element.set_attribute("role", "status").ok()?;
+    element.set_attribute("aria-live", "polite").ok()?;
+    element.set_attribute("style", style(captions)).ok()?;
+    canvas.insert_adjacent_element("afterend", &element).ok()?;
+    Some(element)
+}
@@ -1,28 +1,175 @@
-use cgmath::{EuclideanSpace, InnerSpace, Point3, SquareMatrix, Transform, Vector3, Vector4};
+use std::sync::Mutex;
+
+use cgmath::{
+    Deg, EuclideanSpace, InnerSpace, Point3, Quaternion, Rotation, Rotation3, SquareMatrix,
+    Transform, Vector2, Vector3, Vector4,
+};
 use log::warn;
 use winit::{
+    dpi::{PhysicalPosition, PhysicalSize},
     event::{ElementState, KeyEvent, WindowEvent},
-    keyboard::{KeyCode, PhysicalKey},
+    keyboard::{KeyCode, ModifiersState, PhysicalKey},
 };
 
-use crate::entity::entity::OPENGL_TO_WGPU_MATRIX;
+use crate::{
+    core::storage,
+    entity::entity::OPENGL_TO_WGPU_MATRIX,
+    helpers::{
+        animation::EaseInEaseOut,
+        vector::{is_finite, safe_normalize},
+    },
+};
 
+#[derive(Clone)]
 pub struct Camera {
     pub eye: cgmath::Point3<f32>,
     pub target: cgmath::Point3<f32>,
     pub up: cgmath::Vector3<f32>,
+    // Degrees the camera is turned around its line of sight, for dutch angles
+    pub roll: f32,
     pub aspect: f32,
     pub fovy: f32,
     pub znear: f32,
     pub zfar: f32,
+    // Shift of the projection center in NDC, keeps the scene inside the safe area
+    pub center_offset: Vector2<f32>,
+}
+
+// Insets in physical pixels where the canvas is covered by page UI
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SafeArea {
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
+    pub left: f32,
+}
+
+impl SafeArea {
+    pub fn scaled(&self, scale_factor: f32) -> SafeArea {
+        SafeArea {
+            top: self.top * scale_factor,
+            right: self.right * scale_factor,
+            bottom: self.bottom * scale_factor,
+            left: self.left * scale_factor,
+        }
+    }
+
+    // Projection center offset that puts the middle of the visible region at the middle of the view
+    pub fn center_offset(&self, screen: &PhysicalSize<u32>) -> Vector2<f32> {
+        if screen.width == 0 || screen.height == 0 {
+            return Vector2::new(0.0, 0.0);
+        }
+        Vector2::new(
+            (self.left - self.right) / screen.width as f32,
+            (self.bottom - self.top) / screen.height as f32,
+        )
+    }
+}
+
+// Maps window cursor positions onto the surface. The two agree on native, but on hiDPI
+// web builds the canvas backing size can lag behind the window size
+#[derive(Clone, Copy, Debug)]
+pub struct ScreenMapper {
+    // Size of the surface that is rendered and ray cast into
+    pub surface: PhysicalSize<u32>,
+    // Size of the window the cursor events are reported in
+    pub window: PhysicalSize<u32>,
+    pub scale_factor: f64,
+}
+
+impl ScreenMapper {
+    pub fn new(size: PhysicalSize<u32>, scale_factor: f64) -> ScreenMapper {
+        ScreenMapper {
+            surface: size,
+            window: size,
+            scale_factor,
+        }
+    }
+
+    pub fn resize(&mut self, surface: PhysicalSize<u32>, window: PhysicalSize<u32>) {
+        self.surface = surface;
+        self.window = window;
+    }
+
+    // Cursor position in window pixels to surface pixels
+    pub fn to_surface(&self, position: PhysicalPosition<f64>) -> PhysicalPosition<f32> {
+        if self.window.width == 0 || self.window.height == 0 {
+            return PhysicalPosition::new(position.x as f32, position.y as f32);
+        }
+        PhysicalPosition::new(
+            (position.x * self.surface.width as f64 / self.window.width as f64) as f32,
+            (position.y * self.surface.height as f64 / self.window.height as f64) as f32,
+        )
+    }
+
+    // Ray through a cursor position already mapped with to_surface
+    pub fn cursor_ray(
+        &self,
+        camera: &Camera,
+        cursor: PhysicalPosition<f32>,
+    ) -> (Point3<f32>, Vector3<f32>) {
+        camera.screen_to_world_ray(
+            cursor.x,
+            cursor.y,
+            self.surface.width as f32,
+            self.surface.height as f32,
+        )
+    }
+}
+
+// Insets requested from the page, in CSS pixels, picked up on the next frame
+static SAFE_AREA_REQUEST: Mutex<Option<SafeArea>> = Mutex::new(None);
+
+pub fn request_safe_area(safe_area: SafeArea) {
+    if let Ok(mut request) = SAFE_AREA_REQUEST.lock() {
+        *request = Some(safe_area);
+    }
+}
+
+pub fn safe_area_pending() -> bool {
+    SAFE_AREA_REQUEST
+        .lock()
+        .is_ok_and(|request| request.is_some())
+}
+
+pub fn take_safe_area_request() -> Option<SafeArea> {
+    SAFE_AREA_REQUEST
+        .lock()
+        .ok()
+        .and_then(|mut request| request.take())
+}
+
+// Aspect ratio for the projection, finite for any size including zero
+pub fn aspect_for(size: PhysicalSize<u32>) -> f32 {
+    let aspect = size.width.max(1) as f32 / size.height.max(1) as f32;
+    aspect.clamp(MIN_ASPECT, MAX_ASPECT)
+}
+
+pub fn is_framing_size(size: &PhysicalSize<u32>) -> bool {
+    size.width >= MIN_FRAMING_SIZE && size.height >= MIN_FRAMING_SIZE
 }
 
 impl Camera {
+    // Up of the view, `up` turned by the roll. Movement keeps using `up` so it stays level
+    pub fn view_up(&self) -> Vector3<f32> {
+        if self.roll == 0.0 {
+            return self.up;
+        }
+        let forward = safe_normalize(self.target - self.eye, -Vector3::unit_z());
+        Quaternion::from_axis_angle(forward, Deg(self.roll)).rotate_vector(self.up)
+    }
+
+    // Eye and target are real numbers, a NaN in either blanks the whole view
+    pub fn is_finite(&self) -> bool {
+        is_finite(self.eye.to_vec()) && is_finite(self.target.to_vec())
+    }
+
     fn build_view_projection_matrix(&self) -> cgmath::Matrix4<f32> {
-        let view = cgmath::Matrix4::look_at_rh(self.eye, self.target, self.up);
+        let view = cgmath::Matrix4::look_at_rh(self.eye, self.target, self.view_up());
         let ortho = cgmath::ortho(-1.0, 1.0, -1.0, 1.0, -1.0, 1.0);
         let proj = cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, self.zfar);
-        proj * view
+        let offset = cgmath::Matrix4::from_translation(self.center_offset.extend(0.0));
+        offset * proj * view
     }
     pub fn screen_to_world_ray(
         &self,
@@ -39,7 +186,10 @@ impl Camera {
             .project_screen_to_world(mouse_x, mouse_y, 0.0, screen_width, screen_height)
             .unwrap();
 
-        (Point3::from_vec(back), (front - back).normalize())
+        (
+            Point3::from_vec(back),
+            safe_normalize(front - back, -Vector3::unit_z()),
+        )
     }
 
     pub fn project_screen_to_world(
@@ -72,6 +222,28 @@ impl Camera {
             None
         }
     }
+
+    // Inverse of project_screen_to_world, None when the point is outside the depth range
+    pub fn project_world_to_screen(
+        &self,
+        point: Vector3<f32>,
+        screen_width: f32,
+        screen_height: f32,
+    ) -> Option<(f32, f32)> {
+        let view_projection = OPENGL_TO_WGPU_MATRIX * self.build_view_projection_matrix();
+        let clip = view_projection * point.extend(1.0);
+        if clip.w <= 0.0 {
+            return None;
+        }
+        let ndc = clip.truncate() / clip.w;
+        if ndc.z < 0.0 || ndc.z > 1.0 {
+            return None;
+        }
+        Some((
+            (ndc.x + 1.0) / 2.0 * screen_width,
+            (1.0 - ndc.y) / 2.0 * screen_height,
+        ))
+    }
 }
 
 #[repr(C)]
@@ -92,6 +264,108 @@ impl CameraUniform {
     }
 }
 
+const FRAMING_SMOOTHING: f32 = 0.15;
+// Degenerate windows (1x1, a sliver while dragging) keep a usable projection
+const MIN_ASPECT: f32 = 0.1;
+const MAX_ASPECT: f32 = 10.0;
+// Below this size in either dimension the window is treated as transient and not re-framed
+pub const MIN_FRAMING_SIZE: u32 = 32;
+const BOOKMARK_SLOTS: usize = 9;
+const BOOKMARK_DURATION: f32 = 1.0;
+const BOOKMARK_KEY: &str = "camera_bookmarks";
+// `speed` was tuned as a per frame step at this rate, velocities are per second
+const SPEED_REFERENCE_FPS: f32 = 60.0;
+// Residual velocities below this stop outright instead of drifting forever
+const MIN_VELOCITY: f32 = 1e-3;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CameraPose {
+    pub eye: Point3<f32>,
+    pub target: Point3<f32>,
+    pub fovy: f32,
+    pub roll: f32,
+}
+
+impl CameraPose {
+    pub fn from_camera(camera: &Camera) -> CameraPose {
+        CameraPose {
+            eye: camera.eye,
+            target: camera.target,
+            fovy: camera.fovy,
+            roll: camera.roll,
+        }
+    }
+
+    // Roll turns the short way round, 350 to 10 degrees passes through 0
+    fn lerp(&self, other: &CameraPose, t: f32) -> CameraPose {
+        let roll_delta = (other.roll - self.roll + 180.0).rem_euclid(360.0) - 180.0;
+        CameraPose {
+            eye: self.eye + (other.eye - self.eye) * t,
+            target: self.target + (other.target - self.target) * t,
+            fovy: self.fovy + (other.fovy - self.fovy) * t,
+            roll: self.roll + roll_delta * t,
+        }
+    }
+
+    // Largest distance either the eye or the target moved
+    fn distance(&self, other: &CameraPose) -> f32 {
+        (self.eye - other.eye)
+            .magnitude()
+            .max((self.target - other.target).magnitude())
+    }
+
+    fn apply(&self, camera: &mut Camera) {
+        camera.eye = self.eye;
+        camera.target = self.target;
+        camera.fovy = self.fovy;
+        camera.roll = self.roll;
+    }
+
+    // `eye x y z target x y z fovy roll`
+    fn to_line(self) -> String {
+        format!(
+            "{} {} {} {} {} {} {} {}",
+            self.eye.x,
+            self.eye.y,
+            self.eye.z,
+            self.target.x,
+            self.target.y,
+            self.target.z,
+            self.fovy,
+            self.roll
+        )
+    }
+
+    fn from_line(line: &str) -> Option<CameraPose> {
+        let values = line
+            .split_whitespace()
+            .map(|value| value.parse().ok())
+            .collect::<Option<Vec<f32>>>()?;
+        match values[..] {
+            // Bookmarks saved before roll existed have no roll
+            [ex, ey, ez, tx, ty, tz, fovy] => Some(CameraPose {
+                eye: Point3::new(ex, ey, ez),
+                target: Point3::new(tx, ty, tz),
+                fovy,
+                roll: 0.0,
+            }),
+            [ex, ey, ez, tx, ty, tz, fovy, roll] => Some(CameraPose {
+                eye: Point3::new(ex, ey, ez),
+                target: Point3::new(tx, ty, tz),
+                fovy,
+                roll,
+            }),
+            _ => None,
+        }
+    }
+}
+
+struct PoseEase {
+    from: CameraPose,
+    to: CameraPose,
+    time: f32,
+}
+
 pub struct CameraController {
     pub speed: f32,
     pub is_up_pressed: bool,
@@ -100,6 +374,21 @@ pub struct CameraController {
     pub is_backward_pressed: bool,
     pub is_left_pressed: bool,
     pub is_right_pressed: bool,
+    pub safe_area: SafeArea,
+    pub modifiers: ModifiersState,
+    // Saved with ctrl + 1-9, recalled with 1-9
+    pub bookmarks: [Option<CameraPose>; BOOKMARK_SLOTS],
+    pose_ease: Option<PoseEase>,
+    // Exponential decay rate of the velocities per second once input is released
+    pub damping: f32,
+    // Sideways orbit step and zoom toward the target, in units per second
+    orbit_velocity: f32,
+    zoom_velocity: f32,
+    // Pose the camera drifts back to after the visitor leaves it alone
+    pub home: Option<CameraPose>,
+    last_input: instant::Instant,
+    // The running pose ease is a re-center, input cancels it
+    recentering: bool,
 }
 
 impl CameraController {
@@ -112,11 +401,159 @@ impl CameraController {
             is_backward_pressed: false,
             is_left_pressed: false,
             is_right_pressed: false,
+            safe_area: SafeArea::default(),
+            modifiers: ModifiersState::empty(),
+            bookmarks: load_bookmarks(),
+            pose_ease: None,
+            damping: 6.0,
+            orbit_velocity: 0.0,
+            zoom_velocity: 0.0,
+            home: None,
+            last_input: instant::Instant::now(),
+            recentering: false,
+        }
+    }
+
+    // Drops residual glide, for when the story or a bookmark recall takes over the camera
+    pub fn stop_inertia(&mut self) {
+        self.orbit_velocity = 0.0;
+        self.zoom_velocity = 0.0;
+    }
+
+    pub fn save_bookmark(&mut self, slot: usize, camera: &Camera) {
+        if let Some(bookmark) = self.bookmarks.get_mut(slot) {
+            *bookmark = Some(CameraPose::from_camera(camera));
+            save_bookmarks(&self.bookmarks);
+            warn!("Saved camera bookmark {}", slot + 1);
         }
     }
 
-    pub fn process_events(&mut self, event: &WindowEvent) -> bool {
+    // Eases from the current pose to the bookmark, interrupting any recall in progress
+    pub fn recall_bookmark(&mut self, slot: usize, camera: &Camera) {
+        if let Some(Some(pose)) = self.bookmarks.get(slot).copied() {
+            self.stop_inertia();
+            self.recentering = false;
+            self.pose_ease = Some(PoseEase {
+                from: CameraPose::from_camera(camera),
+                to: pose,
+                time: 0.0,
+            });
+        }
+    }
+
+    // Whether the camera has been left off its home pose by more than `threshold` for
+    // `timeout` seconds without input
+    pub fn recenter_due(&self, camera: &Camera, timeout: f32, threshold: f32) -> bool {
+        let home = match self.home {
+            Some(home) => home,
+            None => return false,
+        };
+        self.pose_ease.is_none()
+            && self.last_input.elapsed().as_secs_f32() >= timeout
+            && CameraPose::from_camera(camera).distance(&home) > threshold
+    }
+
+    // Eases back to the home pose, any input stops it where it is
+    pub fn recenter(&mut self, camera: &Camera) {
+        if let Some(home) = self.home {
+            self.stop_inertia();
+            self.recentering = true;
+            self.pose_ease = Some(PoseEase {
+                from: CameraPose::from_camera(camera),
+                to: home,
+                time: 0.0,
+            });
+        }
+    }
+
+    // Logs the pose as manifest lines, ready to paste
+    pub fn dump_pose(&self, camera: &Camera) {
+        warn!(
+            "eye = {:.3}, {:.3}, {:.3}\ntarget = {:.3}, {:.3}, {:.3}\nfovy = {:.1}\nroll = {:.1}",
+            camera.eye.x,
+            camera.eye.y,
+            camera.eye.z,
+            camera.target.x,
+            camera.target.y,
+            camera.target.z,
+            camera.fovy,
+            camera.roll
+        );
+    }
+
+    // True while held keys, inertia, a bookmark recall or re-framing still move the camera
+    pub fn is_moving(&self, camera: &Camera, screen: &PhysicalSize<u32>) -> bool {
+        let framing = self.safe_area.center_offset(screen) - camera.center_offset;
+        self.is_forward_pressed
+            || self.is_backward_pressed
+            || self.is_left_pressed
+            || self.is_right_pressed
+            || self.orbit_velocity != 0.0
+            || self.zoom_velocity != 0.0
+            || self.pose_ease.is_some()
+            || (is_framing_size(screen) && framing.magnitude2() > 1e-8)
+    }
+
+    pub fn update_pose(&mut self, camera: &mut Camera, dt: f32) {
+        if let Some(ease) = &mut self.pose_ease {
+            ease.time += dt / BOOKMARK_DURATION;
+            let t = EaseInEaseOut::ease_in_ease_out_cubic(ease.time);
+            ease.from.lerp(&ease.to, t).apply(camera);
+            if ease.time >= 1.0 {
+                self.pose_ease = None;
+                self.recentering = false;
+            }
+        }
+    }
+
+    pub fn process_events(&mut self, event: &WindowEvent, camera: &Camera) -> bool {
+        let user_input = match event {
+            WindowEvent::KeyboardInput { event, .. } => event.state.is_pressed(),
+            WindowEvent::MouseInput { state, .. } => state.is_pressed(),
+            WindowEvent::MouseWheel { .. } => true,
+            _ => false,
+        };
+        if user_input {
+            self.last_input = instant::Instant::now();
+            if std::mem::take(&mut self.recentering) {
+                self.pose_ease = None;
+            }
+        }
         match event {
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers.state();
+                false
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key: PhysicalKey::Code(keycode),
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } if bookmark_slot(*keycode).is_some() && !self.modifiers.alt_key() => {
+                let slot = bookmark_slot(*keycode).unwrap();
+                if self.modifiers.control_key() {
+                    self.save_bookmark(slot, camera);
+                } else {
+                    self.recall_bookmark(slot, camera);
+                }
+                true
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key: PhysicalKey::Code(KeyCode::Digit0),
+                        ..
+                    },
+                ..
+            } => {
+                self.dump_pose(camera);
+                true
+            }
             WindowEvent::KeyboardInput {
                 event:
                     KeyEvent {
@@ -162,18 +599,49 @@ impl CameraController {
         }
     }
 
-    pub fn update_camera(&self, camera: &mut Camera) {
+    // Eases the projection center toward the safe area so inset changes re-frame smoothly
+    pub fn update_framing(&self, camera: &mut Camera, screen: &PhysicalSize<u32>, dt: f32) {
+        if !is_framing_size(screen) {
+            return;
+        }
+        let target = self.safe_area.center_offset(screen);
+        let blend = 1.0 - (-dt / FRAMING_SMOOTHING).exp();
+        camera.center_offset += (target - camera.center_offset) * blend;
+    }
+
+    // Held keys drive the velocities directly, released ones let them decay so the camera
+    // glides to a stop
+    pub fn update_camera(&mut self, camera: &mut Camera, dt: f32) {
+        let step = self.speed * SPEED_REFERENCE_FPS;
+        let decay = (-self.damping * dt).exp();
+        let held = |positive: bool, negative: bool| match (positive, negative) {
+            (true, false) => Some(step),
+            (false, true) => Some(-step),
+            _ => None,
+        };
+        self.zoom_velocity = held(self.is_forward_pressed, self.is_backward_pressed)
+            .unwrap_or(self.zoom_velocity * decay);
+        self.orbit_velocity = held(self.is_right_pressed, self.is_left_pressed)
+            .unwrap_or(self.orbit_velocity * decay);
+        if self.zoom_velocity.abs() < MIN_VELOCITY {
+            self.zoom_velocity = 0.0;
+        }
+        if self.orbit_velocity.abs() < MIN_VELOCITY {
+            self.orbit_velocity = 0.0;
+        }
+
         let forward = camera.target - camera.eye;
-        let forward_norm = forward.normalize();
+        // Eye and target meet when zooming hard, keep going the way the camera faced
+        let forward_norm = safe_normalize(forward, -Vector3::unit_z());
         let forward_mag = forward.magnitude();
 
         // Prevents glitching when camera gets too close to the
         // center of the scene.
-        if self.is_forward_pressed && forward_mag > self.speed {
-            camera.eye += forward_norm * self.speed;
-        }
-        if self.is_backward_pressed {
-            camera.eye -= forward_norm * self.speed;
+        let zoom = self.zoom_velocity * dt;
+        if zoom < 0.0 || forward_mag > zoom {
+            camera.eye += forward_norm * zoom;
+        } else {
+            self.zoom_velocity = 0.0;
         }
 
         let right = forward_norm.cross(camera.up);
@@ -182,14 +650,238 @@ impl CameraController {
         let forward = camera.target - camera.eye;
         let forward_mag = forward.magnitude();
 
-        if self.is_right_pressed {
-            // Rescale the distance between the target and eye so
-            // that it doesn't change. The eye therefore still
-            // lies on the circle made by the target and eye.
-            camera.eye = camera.target - (forward + right * self.speed).normalize() * forward_mag;
+        // Rescale the distance between the target and eye so
+        // that it doesn't change. The eye therefore still
+        // lies on the circle made by the target and eye.
+        let orbit = self.orbit_velocity * dt;
+        if orbit != 0.0 {
+            camera.eye =
+                camera.target - safe_normalize(forward + right * orbit, forward_norm) * forward_mag;
+        }
+    }
+}
+
+fn bookmark_slot(keycode: KeyCode) -> Option<usize> {
+    let slot = match keycode {
+        KeyCode::Digit1 => 0,
+        KeyCode::Digit2 => 1,
+        KeyCode::Digit3 => 2,
+        KeyCode::Digit4 => 3,
+        KeyCode::Digit5 => 4,
+        KeyCode::Digit6 => 5,
+        KeyCode::Digit7 => 6,
+        KeyCode::Digit8 => 7,
+        KeyCode::Digit9 => 8,
+        _ => return None,
+    };
+    Some(slot)
+}
+
+// One line per slot, empty lines for unused slots
+fn load_bookmarks() -> [Option<CameraPose>; BOOKMARK_SLOTS] {
+    let mut bookmarks = [None; BOOKMARK_SLOTS];
+    if let Some(saved) = storage::load(BOOKMARK_KEY) {
+        for (bookmark, line) in bookmarks.iter_mut().zip(saved.lines()) {
+            *bookmark = CameraPose::from_line(line);
+        }
+    }
+    bookmarks
+}
+
+fn save_bookmarks(bookmarks: &[Option<CameraPose>]) {
+    let saved = bookmarks
+        .iter()
+        .map(|bookmark| bookmark.map(CameraPose::to_line).unwrap_or_default())
+        .collect::<Vec<_>>()
+        .join("\n");
+    storage::save(BOOKMARK_KEY, &saved);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn camera(aspect: f32) -> Camera {
+        Camera {
+            eye: (-18.0, 23.0, -18.0).into(),
+            target: (15.0, 0.0, 15.0).into(),
+            up: Vector3::unit_y(),
+            roll: 0.0,
+            aspect,
+            fovy: 20.0,
+            znear: 0.1,
+            zfar: 1.0,
+            center_offset: Vector2::new(0.0, 0.0),
+        }
+    }
+
+    #[test]
+    fn cursor_corners_and_center_map_onto_the_surface() {
+        let fractions = [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0), (0.5, 0.5)];
+        for scale_factor in [1.0, 1.5, 2.0].iter().copied() {
+            let window =
+                PhysicalSize::new((800.0 * scale_factor) as u32, (600.0 * scale_factor) as u32);
+            // On the web the canvas backing size can still be the CSS size
+            let lagging = PhysicalSize::new(800, 600);
+            for surface in [window, lagging].iter().copied() {
+                let mut mapper = ScreenMapper::new(window, scale_factor);
+                mapper.resize(surface, window);
+                for (fx, fy) in fractions.iter().copied() {
+                    let cursor =
+                        PhysicalPosition::new(fx * window.width as f64, fy * window.height as f64);
+                    let mapped = mapper.to_surface(cursor);
+                    assert_eq!(mapped.x, fx as f32 * surface.width as f32);
+                    assert_eq!(mapped.y, fy as f32 * surface.height as f32);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn center_ray_points_along_the_view() {
+        for scale_factor in [1.0, 1.5, 2.0].iter().copied() {
+            let window =
+                PhysicalSize::new((800.0 * scale_factor) as u32, (600.0 * scale_factor) as u32);
+            let mut mapper = ScreenMapper::new(window, scale_factor);
+            mapper.resize(PhysicalSize::new(800, 600), window);
+            let center = mapper.to_surface(PhysicalPosition::new(
+                window.width as f64 / 2.0,
+                window.height as f64 / 2.0,
+            ));
+            let camera = camera(aspect_for(mapper.surface));
+            let (_, direction) = mapper.cursor_ray(&camera, center);
+            let forward = (camera.target - camera.eye).normalize();
+            assert!(direction.cross(forward).magnitude() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn aspect_and_projection_stay_finite_for_any_size() {
+        let sizes = [
+            (0, 0),
+            (1, 1),
+            (50, 2000),
+            (2000, 50),
+            (800, 600),
+            (1920, 1080),
+            (390, 844),
+        ];
+        for (width, height) in sizes.iter().copied() {
+            let aspect = aspect_for(PhysicalSize::new(width, height));
+            assert!(aspect.is_finite() && aspect > 0.0, "{}×{}", width, height);
+            assert!((MIN_ASPECT..=MAX_ASPECT).contains(&aspect));
+            let matrix: [[f32; 4]; 4] = camera(aspect).build_view_projection_matrix().into();
+            assert!(
+                matrix.iter().flatten().all(|value| value.is_finite()),
+                "{}×{}",
+                width,
+                height
+            );
+        }
+        assert_eq!(aspect_for(PhysicalSize::new(800, 600)), 800.0 / 600.0);
+    }
+
+    #[test]
+    fn idle_camera_eases_home_unless_interrupted() {
+        let settings = crate::core::settings::Settings::default();
+        let due = |controller: &CameraController, camera: &Camera| {
+            controller.recenter_due(
+                camera,
+                settings.recenter_timeout,
+                settings.recenter_threshold,
+            )
+        };
+        let mut camera = camera(1.0);
+        let home = CameraPose::from_camera(&camera);
+        let mut controller = CameraController::new(1.0);
+        controller.home = Some(home);
+        camera.eye += Vector3::new(6.0, -4.0, 2.0);
+        assert!(!due(&controller, &camera));
+
+        // Nothing touched since before the timeout
+        let gap = std::time::Duration::from_secs_f32(settings.recenter_timeout + 1.0);
+        controller.last_input = instant::Instant::now() - gap;
+        assert!(due(&controller, &camera));
+        controller.recenter(&camera);
+        controller.update_pose(&mut camera, BOOKMARK_DURATION / 4.0);
+        let halfway = CameraPose::from_camera(&camera).distance(&home);
+        assert!(halfway > settings.recenter_threshold);
+
+        let wheel = WindowEvent::MouseWheel {
+            device_id: winit::event::DeviceId::dummy(),
+            delta: winit::event::MouseScrollDelta::LineDelta(0.0, 1.0),
+            phase: winit::event::TouchPhase::Moved,
+        };
+        controller.process_events(&wheel, &camera);
+        controller.stop_inertia();
+        controller.update_pose(&mut camera, BOOKMARK_DURATION);
+        assert_eq!(CameraPose::from_camera(&camera).distance(&home), halfway);
+        assert!(!due(&controller, &camera));
+
+        controller.last_input = instant::Instant::now() - gap;
+        controller.recenter(&camera);
+        for _ in 0..100 {
+            controller.update_pose(&mut camera, BOOKMARK_DURATION / 50.0);
         }
-        if self.is_left_pressed {
-            camera.eye = camera.target - (forward - right * self.speed).normalize() * forward_mag;
+        assert!(CameraPose::from_camera(&camera).distance(&home) < 1e-4);
+        assert!(!due(&controller, &camera));
+    }
+
+    #[test]
+    fn rolled_camera_picks_the_cube_under_the_cursor() {
+        use crate::{
+            core::test_support,
+            helpers::line_trace::{line_trace_index, PickPolicy},
+        };
+
+        let (device, queue) = test_support::device();
+        let cubes = (0..81)
+            .map(|index| {
+                test_support::cube(Vector3::new((index % 9) as f32, 0.0, (index / 9) as f32))
+            })
+            .collect::<Vec<_>>();
+        let tops = cubes
+            .iter()
+            .map(|cube| cube.position + Vector3::new(0.5, 1.0, 0.5))
+            .collect::<Vec<_>>();
+        let controller = test_support::controller(&device, &queue, cubes);
+        let screen = test_support::screen();
+        let mut camera = test_support::camera_above(Point3::new(4.5, 0.5, 4.5));
+        camera.roll = 15.0;
+        // Where the top of a cube lands on screen
+        let pixel = |camera: &Camera, point: Vector3<f32>| {
+            let clip =
+                OPENGL_TO_WGPU_MATRIX * camera.build_view_projection_matrix() * point.extend(1.0);
+            PhysicalPosition::new(
+                (clip.x / clip.w + 1.0) / 2.0 * screen.surface.width as f32,
+                (1.0 - clip.y / clip.w) / 2.0 * screen.surface.height as f32,
+            )
+        };
+        let pick = |camera: &Camera, cursor| {
+            line_trace_index(
+                &controller,
+                None,
+                PickPolicy::NearestOnly,
+                screen.cursor_ray(camera, cursor),
+            )
+        };
+        for (index, top) in tops.iter().enumerate() {
+            assert_eq!(pick(&camera, pixel(&camera, *top)), Some(index));
         }
+
+        // The roll matters, the corner lands elsewhere on a level camera
+        let corner = pixel(&camera, tops[8]);
+        camera.roll = 0.0;
+        assert_ne!(pick(&camera, corner), Some(8));
+    }
+
+    #[test]
+    fn collapsed_camera_keeps_a_finite_up() {
+        let mut camera = camera(1.0);
+        camera.target = camera.eye;
+        camera.roll = 15.0;
+        assert!(is_finite(camera.view_up()));
+        camera.target.x = f32::NAN;
+        assert!(!camera.is_finite());
     }
 }
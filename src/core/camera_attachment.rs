@@ -0,0 +1,108 @@
+// Instance groups pinned to the camera like HUD elements. Their cubes are laid out in view
+// space and placed in front of the camera again every frame, so they keep their spot on
+// screen whatever the camera does. They live outside the chunks, picking never sees them
+
+use cgmath::{Deg, EuclideanSpace, Matrix3, Quaternion, Rotation, Rotation3, Vector2, Vector3};
+
+use crate::{
+    core::camera::Camera,
+    entity::entity::{Instance, Jitter},
+    helpers::{animation::get_height_color, vector::safe_normalize},
+};
+
+pub struct CameraAttachment {
+    // Cubes in group units, laid out around the group's center
+    pub instances: Vec<Instance>,
+    // Where the group's center sits on screen, in normalized device coordinates
+    pub screen_anchor: Vector2<f32>,
+    // Fraction of the way from the near to the far clip plane
+    pub depth: f32,
+    // Fraction of the view height one group unit takes up
+    pub scale: f32,
+    // Degrees per second the group turns around its vertical axis
+    pub spin: f32,
+    angle: f32,
+}
+
+impl CameraAttachment {
+    pub fn new(
+        instances: Vec<Instance>,
+        screen_anchor: Vector2<f32>,
+        depth: f32,
+        scale: f32,
+    ) -> CameraAttachment {
+        CameraAttachment {
+            instances,
+            screen_anchor,
+            depth,
+            scale,
+            spin: 0.0,
+            angle: 0.0,
+        }
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        self.angle = (self.angle + self.spin * dt) % 360.0;
+    }
+
+    // The group's cubes in world space for `camera`. Sizes follow the view height and the
+    // anchor the aspect, so a resize keeps the group where it was on screen
+    pub fn world_instances<'a>(&'a self, camera: &Camera) -> impl Iterator<Item = Instance> + 'a {
+        let forward = safe_normalize(camera.target - camera.eye, -Vector3::unit_z());
+        let right = safe_normalize(forward.cross(camera.view_up()), Vector3::unit_x());
+        let up = right.cross(forward);
+        let distance = camera.znear + (camera.zfar - camera.znear) * self.depth.clamp(0.0, 1.0);
+        let half_height = distance * (camera.fovy.to_radians() / 2.0).tan();
+        let half_width = half_height * camera.aspect;
+        // Undo the safe area offset like the progress bar, the anchor is on the screen
+        let center = camera.eye.to_vec()
+            + forward * distance
+            + right * ((self.screen_anchor.x - camera.center_offset.x) * half_width)
+            + up * ((self.screen_anchor.y - camera.center_offset.y) * half_height);
+        let rotation = Quaternion::from(Matrix3::from_cols(right, up, -forward))
+            * Quaternion::from_angle_y(Deg(self.angle));
+        let unit = self.scale * half_height * 2.0;
+        self.instances.iter().map(move |instance| {
+            let position = center + rotation.rotate_vector(instance.position * unit);
+            let scale = instance.scale * unit;
+            Instance {
+                position,
+                rotation: rotation * instance.rotation,
+                scale,
+                bounding: position + instance.size * scale,
+                ..instance.clone()
+            }
+        })
+    }
+}
+
+// Edges of a `size`³ block of cubes centered on the origin, shaded by height
+pub fn cube_logo(size: usize) -> Vec<Instance> {
+    let last = size.saturating_sub(1);
+    let on_edge = |value: usize| value == 0 || value == last;
+    let half = size as f32 / 2.0;
+    let mut instances = Vec::new();
+    for x in 0..size {
+        for y in 0..size {
+            for z in 0..size {
+                if [x, y, z].iter().filter(|value| on_edge(**value)).count() < 2 {
+                    continue;
+                }
+                let position = Vector3::new(x as f32 - half, y as f32 - half, z as f32 - half);
+                instances.push(Instance {
+                    position,
+                    rotation: Quaternion::new(1.0, 0.0, 0.0, 0.0),
+                    should_render: true,
+                    scale: 1.0,
+                    color: get_height_color(y as f32 / last.max(1) as f32),
+                    size: Vector3::new(1.0, 1.0, 1.0),
+                    bounding: position + Vector3::new(1.0, 1.0, 1.0),
+                    emissive: 0.0,
+                    jitter: Jitter::NONE,
+                    ao: 1.0,
+                });
+            }
+        }
+    }
+    instances
+}
@@ -0,0 +1,242 @@
+// Small scripted camera moves inside a section. Each cue fires once the scroll passes its
+// progress and eases a relative move onto wherever the camera is, scrolling back past it
+// eases the move back out. Leaving the section undoes whatever the cues still add up to
+
+use cgmath::{Deg, InnerSpace, Quaternion, Rotation, Rotation3, Vector3};
+
+use crate::{
+    core::camera::Camera,
+    helpers::{animation::EaseInEaseOut, vector::safe_normalize},
+};
+
+// Closest the eye gets to the target when dollying in
+const MIN_TARGET_DISTANCE: f32 = 0.5;
+// Seconds leaving a section takes to undo its moves
+const RESET_DURATION: f32 = 1.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Easing {
+    Linear,
+    EaseInOut,
+}
+
+impl Easing {
+    pub fn from_name(name: &str) -> Option<Easing> {
+        match name {
+            "linear" => Some(Easing::Linear),
+            "ease" => Some(Easing::EaseInOut),
+            _ => None,
+        }
+    }
+
+    fn apply(self, t: f32) -> f32 {
+        match self {
+            Easing::Linear => t.clamp(0.0, 1.0),
+            Easing::EaseInOut => EaseInEaseOut::ease_in_ease_out_cubic(t),
+        }
+    }
+}
+
+// Relative camera move. The parts are independent of each other, so moves add up the same
+// in any order
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CameraMove {
+    // Units toward the target
+    pub dolly: f32,
+    // Degrees around the target, counterclockwise seen from above
+    pub orbit: f32,
+    pub roll: f32,
+    // Degrees added to the field of view
+    pub zoom: f32,
+}
+
+impl CameraMove {
+    // `dolly <units>`, `orbit <degrees>`, `roll <degrees>` or `zoom <degrees>`
+    pub fn from_line(line: &str) -> Option<CameraMove> {
+        let (kind, amount) = line.trim().split_once(' ')?;
+        let amount = amount.trim().parse::<f32>().ok()?;
+        let mut movement = CameraMove::default();
+        match kind {
+            "dolly" => movement.dolly = amount,
+            "orbit" => movement.orbit = amount,
+            "roll" => movement.roll = amount,
+            "zoom" => movement.zoom = amount,
+            _ => return None,
+        }
+        Some(movement)
+    }
+
+    fn scaled(self, factor: f32) -> CameraMove {
+        CameraMove {
+            dolly: self.dolly * factor,
+            orbit: self.orbit * factor,
+            roll: self.roll * factor,
+            zoom: self.zoom * factor,
+        }
+    }
+
+    fn add(self, other: CameraMove) -> CameraMove {
+        CameraMove {
+            dolly: self.dolly + other.dolly,
+            orbit: self.orbit + other.orbit,
+            roll: self.roll + other.roll,
+            zoom: self.zoom + other.zoom,
+        }
+    }
+
+    fn is_zero(&self) -> bool {
+        *self == CameraMove::default()
+    }
+
+    pub fn apply(&self, camera: &mut Camera) {
+        let offset = camera.eye - camera.target;
+        let distance = offset.magnitude();
+        let mut offset = if self.orbit != 0.0 {
+            Quaternion::from_axis_angle(
+                safe_normalize(camera.up, Vector3::unit_y()),
+                Deg(self.orbit),
+            )
+            .rotate_vector(offset)
+        } else {
+            offset
+        };
+        if self.dolly != 0.0 && distance > 0.0 {
+            offset *= (distance - self.dolly).max(MIN_TARGET_DISTANCE) / distance;
+        }
+        camera.eye = camera.target + offset;
+        camera.roll += self.roll;
+        camera.fovy = (camera.fovy + self.zoom).clamp(1.0, 170.0);
+    }
+}
+
+// `<progress>, <move>, <duration>, <easing>` in a section block, progress runs from the
+// section's anchor at 0 to the next one at 1
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CameraCue {
+    pub progress: f32,
+    pub movement: CameraMove,
+    pub duration: f32,
+    pub easing: Easing,
+}
+
+impl CameraCue {
+    pub fn from_line(line: &str) -> Option<CameraCue> {
+        let parts = line.split(',').map(str::trim).collect::<Vec<_>>();
+        match parts[..] {
+            [progress, movement, duration, easing] => Some(CameraCue {
+                progress: progress.parse::<f32>().ok()?.clamp(0.0, 1.0),
+                movement: CameraMove::from_line(movement)?,
+                duration: duration.parse::<f32>().ok()?.max(0.0),
+                easing: Easing::from_name(easing)?,
+            }),
+            _ => None,
+        }
+    }
+}
+
+struct MoveEase {
+    movement: CameraMove,
+    duration: f32,
+    easing: Easing,
+    time: f32,
+    // Eased fraction already applied to the camera
+    applied: f32,
+}
+
+#[derive(Default)]
+pub struct SectionCameraScript {
+    section: Option<usize>,
+    progress: f32,
+    fired: Vec<bool>,
+    // What the fired cues add up to, undone when the section is left
+    composed: CameraMove,
+    eases: Vec<MoveEase>,
+}
+
+impl SectionCameraScript {
+    pub fn is_moving(&self) -> bool {
+        !self.eases.is_empty()
+    }
+
+    // Fires the cues the progress passed since the last call, in the order the scroll
+    // passed them, and returns the part of the running eases to apply to the camera this
+    // frame. `section` is the active section and the progress within it
+    pub fn update(
+        &mut self,
+        section: Option<(usize, f32)>,
+        cues: &[CameraCue],
+        dt: f32,
+    ) -> CameraMove {
+        let index = section.map(|(index, _)| index);
+        if index != self.section || self.fired.len() != cues.len() {
+            if !self.composed.is_zero() {
+                self.start(
+                    self.composed.scaled(-1.0),
+                    RESET_DURATION,
+                    Easing::EaseInOut,
+                );
+            }
+            self.section = index;
+            // Cues up to where the section was entered fire right away, a cue at 0 plays
+            // on arrival
+            self.progress = f32::NEG_INFINITY;
+            self.fired = vec![false; cues.len()];
+            self.composed = CameraMove::default();
+        }
+        if let Some((_, progress)) = section {
+            self.fire(progress, cues);
+        }
+
+        let mut step = CameraMove::default();
+        for ease in &mut self.eases {
+            ease.time += dt;
+            let t = if ease.duration > 0.0 {
+                ease.time / ease.duration
+            } else {
+                1.0
+            };
+            let eased = ease.easing.apply(t);
+            step = step.add(ease.movement.scaled(eased - ease.applied));
+            ease.applied = eased;
+        }
+        self.eases.retain(|ease| ease.applied < 1.0);
+        step
+    }
+
+    fn fire(&mut self, progress: f32, cues: &[CameraCue]) {
+        let previous = self.progress;
+        self.progress = progress;
+        let mut order = (0..cues.len()).collect::<Vec<_>>();
+        order.sort_by(|&a, &b| cues[a].progress.total_cmp(&cues[b].progress));
+        if progress > previous {
+            for index in order {
+                let cue = &cues[index];
+                if !self.fired[index] && cue.progress > previous && cue.progress <= progress {
+                    self.fired[index] = true;
+                    self.composed = self.composed.add(cue.movement);
+                    self.start(cue.movement, cue.duration, cue.easing);
+                }
+            }
+        } else if progress < previous {
+            for index in order.into_iter().rev() {
+                let cue = &cues[index];
+                if self.fired[index] && cue.progress > progress && cue.progress <= previous {
+                    self.fired[index] = false;
+                    let inverse = cue.movement.scaled(-1.0);
+                    self.composed = self.composed.add(inverse);
+                    self.start(inverse, cue.duration, cue.easing);
+                }
+            }
+        }
+    }
+
+    fn start(&mut self, movement: CameraMove, duration: f32, easing: Easing) {
+        self.eases.push(MoveEase {
+            movement,
+            duration,
+            easing,
+            time: 0.0,
+            applied: 0.0,
+        });
+    }
+}
@@ -0,0 +1,127 @@
+// What the adapter and device can do, worked out once so every subsystem picks its
+// fallback from the same answers instead of checking the target on its own
+
+use crate::entity::entity::InstanceRaw;
+
+// Instances a single buffer is allowed to hold even when the limits would allow more
+const MAX_INSTANCES_CAP: u64 = 1 << 20;
+const PREFERRED_MSAA_SAMPLES: u32 = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Capabilities {
+    pub supports_compute: bool,
+    pub supports_indirect: bool,
+    // Depth textures can be bound and sampled, the post processing passes need this
+    pub samples_depth: bool,
+    // Instances that fit in one instance buffer
+    pub max_instances_hint: u32,
+    // Sample count the surface format supports for multisampled targets, 1 without MSAA
+    pub msaa_samples: u32,
+    // Large instance draws are split into slices, WebGL2 chokes on one huge draw
+    pub split_draws: bool,
+    // Draws may start at a non-zero instance, otherwise slices rebind the instance buffer
+    pub first_instance: bool,
+}
+
+impl Capabilities {
+    pub fn detect(
+        adapter: &wgpu::Adapter,
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+    ) -> Capabilities {
+        let downlevel = adapter.get_downlevel_capabilities();
+        let limits = device.limits();
+        let format_flags = adapter.get_texture_format_features(format).flags;
+        let instance_size = std::mem::size_of::<InstanceRaw>() as u64;
+        let gl = adapter.get_info().backend == wgpu::Backend::Gl;
+        Capabilities {
+            supports_compute: downlevel
+                .flags
+                .contains(wgpu::DownlevelFlags::COMPUTE_SHADERS)
+                && limits.max_compute_workgroups_per_dimension > 0,
+            supports_indirect: downlevel
+                .flags
+                .contains(wgpu::DownlevelFlags::INDIRECT_EXECUTION),
+            // WebGL2 is the only GL backend requested, sampling depth there is unreliable
+            samples_depth: !gl,
+            max_instances_hint: (limits.max_buffer_size / instance_size).min(MAX_INSTANCES_CAP)
+                as u32,
+            msaa_samples: if format_flags.sample_count_supported(PREFERRED_MSAA_SAMPLES) {
+                PREFERRED_MSAA_SAMPLES
+            } else {
+                1
+            },
+            split_draws: gl,
+            first_instance: !gl,
+        }
+    }
+
+    // The WebGL2 baseline, every gate closed
+    pub fn downlevel() -> Capabilities {
+        let limits = wgpu::Limits::downlevel_webgl2_defaults();
+        Capabilities {
+            supports_compute: false,
+            supports_indirect: false,
+            samples_depth: false,
+            max_instances_hint: (limits.max_buffer_size / std::mem::size_of::<InstanceRaw>() as u64)
+                .min(MAX_INSTANCES_CAP) as u32,
+            msaa_samples: 1,
+            split_draws: true,
+            first_instance: false,
+        }
+    }
+
+    // Instances per draw call, None draws each chunk at once
+    pub fn draw_slice(&self, threshold: u32) -> Option<u32> {
+        (self.split_draws && threshold > 0).then_some(threshold)
+    }
+
+    pub fn log(&self) {
+        log::warn!(
+            "Capabilities: compute {}, indirect {}, depth sampling {}, {} instances, msaa x{}, \
+             split draws {}",
+            self.supports_compute,
+            self.supports_indirect,
+            self.samples_depth,
+            self.max_instances_hint,
+            self.msaa_samples,
+            self.split_draws
+        );
+        if !self.samples_depth {
+            log::warn!("Depth sampling unavailable, ambient occlusion, depth of field and motion blur are off");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::post_process::{AmbientOcclusion, DepthOfField, MotionBlur};
+
+    #[test]
+    fn downlevel_takes_the_compat_path_everywhere() {
+        let capabilities = Capabilities::downlevel();
+        assert!(!AmbientOcclusion::is_supported(&capabilities));
+        assert!(!DepthOfField::is_supported(&capabilities));
+        assert!(!MotionBlur::is_supported(&capabilities));
+        assert_eq!(capabilities.msaa_samples, 1);
+        assert!(!capabilities.first_instance);
+        assert_eq!(capabilities.draw_slice(4096), Some(4096));
+        // A zero threshold still draws each chunk at once
+        assert_eq!(capabilities.draw_slice(0), None);
+        assert!(capabilities.max_instances_hint > 0);
+    }
+
+    #[test]
+    fn downlevel_scene_runs_through_every_section() {
+        // test_support builds the game loop on the downlevel gates
+        let mut game_loop = crate::core::test_support::game_loop(crate::core::test_support::grid());
+        assert_eq!(game_loop.capabilities, Capabilities::downlevel());
+        for section in 0..5 {
+            game_loop.go_to_section(section);
+            for _ in 0..120 {
+                game_loop.update(std::time::Duration::from_millis(16));
+            }
+        }
+    }
+}
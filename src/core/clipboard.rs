@@ -0,0 +1,58 @@
+// Copies debug text to the system clipboard and confirms it with a short toast. Without
+// the clipboard feature the text is only logged
+
+#[cfg(target_arch = "wasm32")]
+const TOAST_DURATION_MS: i32 = 1500;
+
+pub fn copy_text(text: &str) {
+    #[cfg(all(feature = "clipboard", not(target_arch = "wasm32")))]
+    {
+        let copied = arboard::Clipboard::new().and_then(|mut clipboard| clipboard.set_text(text));
+        match copied {
+            Ok(()) => log::warn!("Copied: {}", text),
+            Err(error) => log::warn!("Could not copy {}: {}", text, error),
+        }
+    }
+
+    #[cfg(all(feature = "clipboard", target_arch = "wasm32"))]
+    if let Some(window) = web_sys::window() {
+        // The promise rejects without clipboard permission, the toast still shows the text
+        let _ = window.navigator().clipboard().write_text(text);
+        show_toast(&window, &format!("Copied: {}", text));
+    }
+
+    #[cfg(not(feature = "clipboard"))]
+    log::warn!("{}", text);
+}
+
+#[cfg(all(feature = "clipboard", target_arch = "wasm32"))]
+fn show_toast(window: &web_sys::Window, text: &str) {
+    use wasm_bindgen::{closure::Closure, JsCast};
+
+    let document = match window.document() {
+        Some(document) => document,
+        None => return,
+    };
+    let (canvas, toast) = match (
+        document.get_element_by_id("canvas"),
+        document.create_element("div"),
+    ) {
+        (Some(canvas), Ok(toast)) => (canvas, toast),
+        _ => return,
+    };
+    let _ = toast.set_attribute(
+        "style",
+        "position:fixed;top:1rem;left:50%;transform:translateX(-50%);padding:0.4rem 0.8rem;\
+         background:rgba(0,0,0,0.75);color:#fff;font:13px monospace;border-radius:6px;\
+         pointer-events:none;z-index:950",
+    );
+    toast.set_text_content(Some(text));
+    if canvas.insert_adjacent_element("afterend", &toast).is_err() {
+        return;
+    }
+    let remove = Closure::once_into_js(move || toast.remove());
+    let _ = window.set_timeout_with_callback_and_timeout_and_arguments_0(
+        remove.unchecked_ref(),
+        TOAST_DURATION_MS,
+    );
+}
@@ -0,0 +1,283 @@
+// Section content, embedded in the bundle and optionally replaced by a newer manifest
+// loaded at startup from `?content=<base url>` on wasm or the CV_CONTENT directory on native
+
+use cgmath::Vector3;
+use winit::event_loop::EventLoopProxy;
+
+use crate::{
+    core::{
+        camera_script::CameraCue, detail::DetailSpec, event_loop::UserEvent, section::SectionInfo,
+    },
+    helpers::{
+        animation::TrajectoryMode,
+        effects::{EffectPreset, EffectTemplate},
+    },
+};
+
+const MANIFEST_NAME: &str = "manifest.txt";
+pub const EMBEDDED_MANIFEST: &str = include_str!("content/manifest.txt");
+// Content arriving later than this is dropped, the embedded sections stay
+#[cfg(target_arch = "wasm32")]
+const FETCH_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+#[derive(Clone, Debug, Default)]
+pub struct Manifest {
+    // Bumped whenever the content changes, pins asset urls against stale caches
+    pub version: u32,
+    pub sections: Vec<SectionInfo>,
+    // World space boxes, min then max, whose cubes stay fixed as a signature in every section
+    pub pinned: Vec<(Vector3<f32>, Vector3<f32>)>,
+    // Plays the finale after scrolling to the end
+    pub finale: bool,
+    // Named animation templates sections refer to
+    pub animations: Vec<(String, EffectTemplate)>,
+}
+
+impl Manifest {
+    pub fn embedded() -> Manifest {
+        parse_manifest(EMBEDDED_MANIFEST).unwrap_or_else(|error| {
+            log::error!("Embedded manifest is invalid: {}", error);
+            Manifest::default()
+        })
+    }
+
+    pub fn asset_url(&self, base: &str, name: &str) -> String {
+        format!("{}/{}?v={}", base.trim_end_matches('/'), name, self.version)
+    }
+
+    // The template with this name, otherwise the effect preset of that name as is
+    pub fn animation(&self, name: &str) -> Option<EffectTemplate> {
+        self.animations
+            .iter()
+            .find(|(template, _)| template == name)
+            .map(|(_, template)| *template)
+            .or_else(|| EffectPreset::from_name(name).map(EffectTemplate::from_preset))
+    }
+
+    // Sections without a title don't show a panel
+    pub fn section(&self, index: usize) -> Option<&SectionInfo> {
+        self.sections
+            .get(index)
+            .filter(|section| !section.title.is_empty())
+    }
+}
+
+// `key = value` lines grouped in [section] and [animation] blocks, # starts a comment
+pub fn parse_manifest(text: &str) -> Result<Manifest, String> {
+    let mut manifest = Manifest::default();
+    // Keys belong to the last animation block until the next section starts
+    let mut in_animation = false;
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line == "[section]" {
+            manifest.sections.push(SectionInfo::default());
+            in_animation = false;
+            continue;
+        }
+        if line == "[animation]" {
+            manifest.animations.push((
+                String::new(),
+                EffectTemplate::from_preset(EffectPreset::AssembleFromGround),
+            ));
+            in_animation = true;
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .map(|(key, value)| (key.trim(), value.trim()))
+            .ok_or_else(|| format!("line {}: expected `key = value`", number + 1))?;
+        let list = || {
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(String::from)
+                .collect()
+        };
+        let flag = || match value {
+            "true" => Ok(true),
+            "false" => Ok(false),
+            _ => Err(format!("line {}: expected true or false", number + 1)),
+        };
+        let float = || {
+            value
+                .parse::<f32>()
+                .map_err(|_| format!("line {}: expected a number", number + 1))
+        };
+        if in_animation {
+            let (name, template) = manifest
+                .animations
+                .last_mut()
+                .expect("pushed with the block");
+            match key {
+                "name" => *name = value.to_string(),
+                "preset" => {
+                    template.preset = EffectPreset::from_name(value)
+                        .ok_or_else(|| format!("line {}: unknown preset", number + 1))?
+                }
+                "distance" => template.options.distance = float()?,
+                "stagger" => template.options.stagger = float()?,
+                "trajectory" => {
+                    template.trajectory = TrajectoryMode::from_line(value)
+                        .ok_or_else(|| format!("line {}: invalid trajectory", number + 1))?
+                }
+                _ => return Err(format!("line {}: unexpected key `{}`", number + 1, key)),
+            }
+            continue;
+        }
+        match (manifest.sections.last_mut(), key) {
+            (None, "version") => {
+                manifest.version = value
+                    .parse()
+                    .map_err(|_| format!("line {}: invalid version", number + 1))?
+            }
+            (None, "finale") => manifest.finale = flag()?,
+            (None, "pinned") => {
+                let corners = value
+                    .split(',')
+                    .map(|value| value.trim().parse::<f32>())
+                    .collect::<Result<Vec<_>, _>>();
+                match corners.as_deref() {
+                    Ok([x0, y0, z0, x1, y1, z1]) => manifest
+                        .pinned
+                        .push((Vector3::new(*x0, *y0, *z0), Vector3::new(*x1, *y1, *z1))),
+                    _ => return Err(format!("line {}: expected six numbers", number + 1)),
+                }
+            }
+            (Some(section), "title") => section.title = value.to_string(),
+            (Some(section), "years") => section.years = value.to_string(),
+            (Some(section), "tags") => section.tags = list(),
+            (Some(section), "links") => section.links = list(),
+            (Some(section), "label") => section.labels.push(value.to_string()),
+            (Some(section), "entrance") => section.entrance = Some(value.to_string()),
+            (Some(section), "camera") => section.camera.push(
+                CameraCue::from_line(value)
+                    .ok_or_else(|| format!("line {}: invalid camera cue", number + 1))?,
+            ),
+            (Some(section), "detail") => {
+                section.detail = Some(
+                    DetailSpec::from_line(value)
+                        .ok_or_else(|| format!("line {}: invalid detail", number + 1))?,
+                )
+            }
+            (Some(section), "allow_delete") => section.policy.allow_delete = flag()?,
+            (Some(section), "allow_explode") => section.policy.allow_explode = flag()?,
+            (Some(section), "allow_place") => section.policy.allow_place = flag()?,
+            (Some(section), "allow_paint") => section.policy.allow_paint = flag()?,
+            _ => return Err(format!("line {}: unexpected key `{}`", number + 1, key)),
+        }
+    }
+    if manifest.animations.iter().any(|(name, _)| name.is_empty()) {
+        return Err("animation without a name".to_string());
+    }
+    for section in &manifest.sections {
+        if let Some(name) = &section.entrance {
+            if manifest.animation(name).is_none() {
+                return Err(format!("unknown entrance `{}`", name));
+            }
+        }
+    }
+    Ok(manifest)
+}
+
+// Sends UserEvent::Content once a manifest was loaded, failures keep the embedded content
+pub fn spawn_content_loader(proxy: EventLoopProxy<UserEvent>) {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let dir = match std::env::var("CV_CONTENT") {
+            Ok(dir) => dir,
+            Err(_) => return,
+        };
+        std::thread::spawn(move || {
+            let path = std::path::Path::new(&dir).join(MANIFEST_NAME);
+            let manifest = std::fs::read_to_string(&path)
+                .map_err(|error| format!("{}: {}", path.display(), error))
+                .and_then(|text| parse_manifest(&text));
+            send_manifest(&proxy, manifest);
+        });
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    {
+        let base = match crate::core::settings::url_param("content") {
+            Some(base) => base,
+            None => return,
+        };
+        wasm_bindgen_futures::spawn_local(async move {
+            // The manifest carries the version, so it is always fetched fresh
+            let url = format!(
+                "{}/{}?t={}",
+                base.trim_end_matches('/'),
+                MANIFEST_NAME,
+                wasm_bindgen_futures::js_sys::Date::now() as u64
+            );
+            let manifest = match fetch_before_timeout(url).await {
+                Ok(Some(text)) => parse_manifest(&text),
+                Ok(None) => {
+                    log::warn!(
+                        "Content fetch timed out after {:?}, using offline content",
+                        FETCH_TIMEOUT
+                    );
+                    return;
+                }
+                Err(error) => Err(error),
+            };
+            send_manifest(&proxy, manifest);
+        });
+    }
+}
+
+// Races the fetch against a FETCH_TIMEOUT timer, None when the timer wins. The request
+// itself isn't cancelled, its late answer is just never looked at
+#[cfg(target_arch = "wasm32")]
+async fn fetch_before_timeout(url: String) -> Result<Option<String>, String> {
+    use wasm_bindgen::JsValue;
+    use wasm_bindgen_futures::{js_sys, JsFuture};
+
+    let fetch = wasm_bindgen_futures::future_to_promise(async move {
+        fetch_text(&url)
+            .await
+            .map(JsValue::from)
+            .map_err(JsValue::from)
+    });
+    // Resolves with undefined, which is how the race tells the timer apart from the text
+    let timer = js_sys::Promise::new(&mut |resolve, _| {
+        if let Some(window) = web_sys::window() {
+            let _ = window.set_timeout_with_callback_and_timeout_and_arguments_0(
+                &resolve,
+                FETCH_TIMEOUT.as_millis() as i32,
+            );
+        }
+    });
+    match JsFuture::from(js_sys::Promise::race(&js_sys::Array::of2(&fetch, &timer))).await {
+        Ok(text) => Ok(text.as_string()),
+        Err(error) => Err(error
+            .as_string()
+            .unwrap_or_else(|| "content fetch failed".to_string())),
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+async fn fetch_text(url: &str) -> Result<String, String> {
+    let response = reqwest::get(url)
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|error| format!("{}: {}", url, error))?;
+    response
+        .text()
+        .await
+        .map_err(|error| format!("{}: {}", url, error))
+}
+
+fn send_manifest(proxy: &EventLoopProxy<UserEvent>, manifest: Result<Manifest, String>) {
+    match manifest {
+        Ok(manifest) => {
+            log::warn!("Loaded content version {}", manifest.version);
+            let _ = proxy.send_event(UserEvent::Content(manifest));
+        }
+        Err(error) => log::warn!("Using offline content, {}", error),
+    }
+}
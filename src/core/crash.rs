@@ -0,0 +1,229 @@
+// Keeps the most recent log lines around so a panic can report what led up to it.
+// The web build shows the panic next to the canvas, native writes a crash log
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use log::{Log, Metadata, Record};
+
+pub const LOG_RING_CAPACITY: usize = 200;
+
+// Set once the adapter is picked, included in crash reports
+static ADAPTER_INFO: Mutex<Option<String>> = Mutex::new(None);
+static LOG_RING: Mutex<LogRing> = Mutex::new(LogRing::new(LOG_RING_CAPACITY));
+
+// Fixed size buffer of formatted log lines, the oldest line is dropped when full
+pub struct LogRing {
+    lines: VecDeque<String>,
+    capacity: usize,
+}
+
+impl LogRing {
+    pub const fn new(capacity: usize) -> LogRing {
+        LogRing {
+            lines: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, line: String) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.lines.len() == self.capacity {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line);
+    }
+
+    pub fn lines(&self) -> impl Iterator<Item = &String> {
+        self.lines.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+}
+
+// Copies every record into the ring buffer before handing it to the platform logger
+struct RingLogger {
+    #[cfg(not(target_arch = "wasm32"))]
+    inner: env_logger::Logger,
+}
+
+impl Log for RingLogger {
+    #[cfg(not(target_arch = "wasm32"))]
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::Level::Info
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        if let Ok(mut ring) = LOG_RING.lock() {
+            ring.push(format!(
+                "[{} {}] {}",
+                record.level(),
+                record.target(),
+                record.args()
+            ));
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        self.inner.log(record);
+        #[cfg(target_arch = "wasm32")]
+        console_log::log(record);
+    }
+
+    fn flush(&self) {
+        #[cfg(not(target_arch = "wasm32"))]
+        self.inner.flush();
+    }
+}
+
+// Replaces env_logger::init and console_log::init_with_level
+pub fn init_logging() {
+    #[cfg(not(target_arch = "wasm32"))]
+    let logger = RingLogger {
+        inner: env_logger::Builder::from_default_env().build(),
+    };
+    #[cfg(not(target_arch = "wasm32"))]
+    let max_level = logger.inner.filter();
+    #[cfg(target_arch = "wasm32")]
+    let logger = RingLogger {};
+    #[cfg(target_arch = "wasm32")]
+    let max_level = log::LevelFilter::Info;
+
+    if log::set_boxed_logger(Box::new(logger)).is_ok() {
+        log::set_max_level(max_level);
+    }
+}
+
+pub fn set_adapter_info(info: &wgpu::AdapterInfo) {
+    if let Ok(mut adapter) = ADAPTER_INFO.lock() {
+        *adapter = Some(format!(
+            "{} ({:?}, {:?}, driver {} {})",
+            info.name, info.device_type, info.backend, info.driver, info.driver_info
+        ));
+    }
+}
+
+// Panic message, adapter and the buffered log lines as plain text
+pub fn crash_report(message: &str) -> String {
+    let mut report = format!("cv_game crashed: {}\n", message);
+    let adapter = ADAPTER_INFO.lock().ok().and_then(|adapter| adapter.clone());
+    report.push_str(&format!(
+        "Adapter: {}\n",
+        adapter.as_deref().unwrap_or("not selected yet")
+    ));
+    // The panic may have happened while the ring was locked
+    if let Ok(ring) = LOG_RING.try_lock() {
+        report.push_str(&format!("Last {} log lines:\n", ring.len()));
+        for line in ring.lines() {
+            report.push_str(line);
+            report.push('\n');
+        }
+    }
+    report
+}
+
+pub fn install_panic_hook() {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let default_hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            default_hook(info);
+            let report = crash_report(&info.to_string());
+            match crate::core::storage::save_file(CRASH_LOG, &report) {
+                Some(path) => eprintln!("Crash log written to {}", path.display()),
+                None => eprintln!("{}", report),
+            }
+        }));
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    std::panic::set_hook(Box::new(|info| {
+        console_error_panic_hook::hook(info);
+        show_error_overlay(&info.to_string());
+    }));
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+const CRASH_LOG: &str = "crash.log";
+
+// Puts the message next to the canvas with a reload button, instead of a frozen frame
+#[cfg(target_arch = "wasm32")]
+fn show_error_overlay(message: &str) {
+    let document = match web_sys::window().and_then(|window| window.document()) {
+        Some(document) => document,
+        None => return,
+    };
+    let canvas = match document.get_element_by_id("canvas") {
+        Some(canvas) => canvas,
+        None => return,
+    };
+    let html = format!(
+        "<div id=\"cv-crash\" role=\"alert\" style=\"position:fixed;inset:auto 1rem 1rem 1rem;\
+         padding:1rem;background:#1e1e1e;color:#fff;font:14px sans-serif;border-radius:8px;\
+         z-index:1000\"><p>Something went wrong and the scene stopped.</p>\
+         <pre style=\"white-space:pre-wrap\">{}</pre>\
+         <button onclick=\"location.reload()\">Reload</button></div>",
+        escape_html(message)
+    );
+    let _ = canvas.insert_adjacent_html("afterend", &html);
+}
+
+#[cfg(target_arch = "wasm32")]
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contents(ring: &LogRing) -> Vec<&str> {
+        ring.lines().map(String::as_str).collect()
+    }
+
+    #[test]
+    fn ring_keeps_lines_in_order_until_full() {
+        let mut ring = LogRing::new(3);
+        assert!(ring.is_empty());
+        ring.push("a".to_string());
+        ring.push("b".to_string());
+        assert_eq!(contents(&ring), vec!["a", "b"]);
+        ring.push("c".to_string());
+        assert_eq!(ring.len(), 3);
+        assert_eq!(contents(&ring), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn ring_drops_the_oldest_line_when_full() {
+        let mut ring = LogRing::new(3);
+        for line in 0..10 {
+            ring.push(line.to_string());
+            assert!(ring.len() <= 3);
+        }
+        assert_eq!(contents(&ring), vec!["7", "8", "9"]);
+    }
+
+    #[test]
+    fn zero_capacity_ring_stays_empty() {
+        let mut ring = LogRing::new(0);
+        ring.push("dropped".to_string());
+        assert!(ring.is_empty());
+    }
+}
@@ -0,0 +1,246 @@
+// Close-up detail. Sections can ask for the cubes nearest the camera to be drawn as 2×2×2
+// half-size cubes with slightly varied colors once the camera comes close, going back to
+// single cubes when it pulls away. The detail cubes are a pool of their own outside the
+// chunks, so picking, the scene export and resets never see them
+
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector3};
+
+use crate::entity::entity::Instance;
+
+// Children are grown this much around the cube's center so their faces sit just outside the
+// cube's own and win the depth test without hiding it
+const INFLATE: f32 = 1.01;
+// Largest brightness change of a child, as a fraction of the cube's color
+const COLOR_VARIATION: f32 = 0.08;
+// Smaller cubes a refined cube is drawn as
+pub const CHILDREN: usize = 8;
+
+// `detail = <refine distance>, <coarsen distance>, <max cubes>` in a section block
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DetailSpec {
+    // Cubes closer to the eye than this get refined
+    pub refine_distance: f32,
+    // Refined cubes go back once farther than this, kept above refine_distance so cubes
+    // on the edge don't flip every frame
+    pub coarsen_distance: f32,
+    // Most cubes refined at once, the nearest win
+    pub max_cubes: usize,
+}
+
+impl DetailSpec {
+    pub fn from_line(line: &str) -> Option<DetailSpec> {
+        let parts = line.split(',').map(str::trim).collect::<Vec<_>>();
+        match parts[..] {
+            [refine, coarsen, max] => {
+                let refine_distance = refine.parse::<f32>().ok()?.max(0.0);
+                Some(DetailSpec {
+                    refine_distance,
+                    coarsen_distance: coarsen.parse::<f32>().ok()?.max(refine_distance),
+                    max_cubes: max.parse().ok()?,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct DetailRefiner {
+    // Indices of the refined cubes, nearest first
+    refined: Vec<usize>,
+}
+
+impl DetailRefiner {
+    // Refines the visible cubes that came within reach of `eye` and coarsens the ones that
+    // left it. No spec coarsens everything. Returns whether the refined set changed
+    pub fn update(
+        &mut self,
+        spec: Option<DetailSpec>,
+        eye: Point3<f32>,
+        instances: &[Instance],
+    ) -> bool {
+        let spec = match spec {
+            Some(spec) => spec,
+            None => {
+                let changed = !self.refined.is_empty();
+                self.refined.clear();
+                return changed;
+            }
+        };
+        let eye = eye.to_vec();
+        let distance = |index: usize| (center(&instances[index]) - eye).magnitude();
+        let visible = |index: usize| instances.get(index).is_some_and(|cube| cube.should_render);
+
+        let mut next = self
+            .refined
+            .iter()
+            .copied()
+            .filter(|index| visible(*index) && distance(*index) <= spec.coarsen_distance)
+            .collect::<Vec<_>>();
+        next.extend((0..instances.len()).filter(|index| {
+            visible(*index)
+                && !self.refined.contains(index)
+                && distance(*index) < spec.refine_distance
+        }));
+        next.sort_by(|a, b| distance(*a).total_cmp(&distance(*b)));
+        next.truncate(spec.max_cubes);
+
+        let changed = next != self.refined;
+        self.refined = next;
+        changed
+    }
+
+    // The children of every refined cube, following the cubes wherever they are animated to
+    pub fn instances(&self, cubes: &[Instance]) -> Vec<Instance> {
+        let mut children = Vec::with_capacity(self.refined.len() * CHILDREN);
+        for &index in &self.refined {
+            let cube = match cubes.get(index) {
+                Some(cube) => cube,
+                None => continue,
+            };
+            for child in 0..CHILDREN {
+                let cell = Vector3::new(
+                    (child & 1) as f32,
+                    (child >> 1 & 1) as f32,
+                    (child >> 2 & 1) as f32,
+                ) * 0.5;
+                let corner =
+                    Vector3::new(0.5, 0.5, 0.5) + (cell - Vector3::new(0.5, 0.5, 0.5)) * INFLATE;
+                let mut instance = cube.subcube(corner, 0.5 * INFLATE);
+                instance.color *= 1.0 + COLOR_VARIATION * variation(index, child);
+                children.push(instance);
+            }
+        }
+        children
+    }
+}
+
+fn center(instance: &Instance) -> Vector3<f32> {
+    instance.position + instance.size * (instance.scale / 2.0)
+}
+
+// Fixed value in -1..1 per cube and child, so the pattern stays put frame to frame
+fn variation(index: usize, child: usize) -> f32 {
+    let mut hash =
+        (index as u32).wrapping_mul(0x9e37_79b9) ^ (child as u32).wrapping_mul(0x85eb_ca6b);
+    hash ^= hash >> 16;
+    hash = hash.wrapping_mul(0x7feb_352d);
+    hash ^= hash >> 15;
+    (hash & 0xffff) as f32 / 0xffff as f32 * 2.0 - 1.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::test_support;
+
+    // Eye level with the centers of a row of cubes along x, `x` along the row
+    fn eye(x: f32) -> Point3<f32> {
+        Point3::new(x, 0.5, 0.5)
+    }
+
+    fn spec(max_cubes: usize) -> Option<DetailSpec> {
+        DetailSpec::from_line(&format!("3, 5, {}", max_cubes))
+    }
+
+    #[test]
+    fn approaching_camera_refines_and_retreating_one_coarsens() {
+        let row = test_support::row(20);
+        let mut refiner = DetailRefiner::default();
+        // Cube 0 is centered at 0.5, the camera comes in along the row and backs off again
+        let script = [
+            (-10.0, false, vec![]),
+            (-2.0, true, vec![0]),
+            (-1.0, true, vec![0, 1]),
+            // Out of refine reach but inside the coarsen distance, the refined ones stay
+            (-3.0, false, vec![0, 1]),
+            (-4.0, true, vec![0]),
+            (-4.4, false, vec![0]),
+            (-4.6, true, vec![]),
+            // Coming back to the same spot doesn't refine again
+            (-4.0, false, vec![]),
+        ];
+        for (x, changes, refined) in script.iter() {
+            assert_eq!(
+                refiner.update(spec(100), eye(*x), &row),
+                *changes,
+                "eye at {}",
+                x
+            );
+            assert_eq!(refiner.refined, *refined, "eye at {}", x);
+        }
+    }
+
+    #[test]
+    fn the_nearest_cubes_win_the_cap() {
+        let row = test_support::row(20);
+        let mut refiner = DetailRefiner::default();
+        for step in 0..=40 {
+            let x = -5.0 + step as f32 * 0.5;
+            refiner.update(spec(4), eye(x), &row);
+            assert!(refiner.refined.len() <= 4);
+            let distances = refiner
+                .refined
+                .iter()
+                .map(|index| (*index as f32 + 0.5 - x).abs())
+                .collect::<Vec<_>>();
+            assert!(distances.windows(2).all(|pair| pair[0] <= pair[1]));
+            assert!(distances.iter().all(|distance| *distance <= 5.0));
+        }
+        // In the middle of the row six cubes are in reach, the four nearest are refined
+        refiner.update(spec(4), eye(10.0), &row);
+        let mut refined = refiner.refined.clone();
+        refined.sort_unstable();
+        assert_eq!(refined, vec![8, 9, 10, 11]);
+        assert_eq!(refiner.instances(&row).len(), 4 * CHILDREN);
+    }
+
+    #[test]
+    fn hidden_cubes_and_sections_without_detail_refine_nothing() {
+        let mut row = test_support::row(4);
+        let mut refiner = DetailRefiner::default();
+        assert!(refiner.update(spec(10), eye(-1.0), &row));
+        assert_eq!(refiner.refined, vec![0, 1]);
+        row[0].should_render = false;
+        assert!(refiner.update(spec(10), eye(-1.0), &row));
+        assert_eq!(refiner.refined, vec![1]);
+        assert!(refiner.update(None, eye(-1.0), &row));
+        assert!(refiner.refined.is_empty());
+        assert!(refiner.instances(&row).is_empty());
+    }
+
+    #[test]
+    fn children_fill_their_cube() {
+        let row = test_support::row(1);
+        let mut refiner = DetailRefiner::default();
+        refiner.update(spec(1), eye(0.5), &row);
+        let children = refiner.instances(&row);
+        assert_eq!(children.len(), CHILDREN);
+        for child in &children {
+            let offset = center(child) - center(&row[0]);
+            assert!((offset.x.abs() - 0.25).abs() < 0.01, "{:?}", offset);
+            assert!((offset.y.abs() - 0.25).abs() < 0.01, "{:?}", offset);
+            assert!((offset.z.abs() - 0.25).abs() < 0.01, "{:?}", offset);
+            let brightness = child.color.x / row[0].color.x;
+            assert!((brightness - 1.0).abs() <= COLOR_VARIATION + 1e-6);
+        }
+    }
+
+    #[test]
+    fn spec_lines_keep_coarsen_beyond_refine() {
+        assert_eq!(
+            DetailSpec::from_line("6, 8, 24"),
+            Some(DetailSpec {
+                refine_distance: 6.0,
+                coarsen_distance: 8.0,
+                max_cubes: 24,
+            })
+        );
+        assert_eq!(
+            DetailSpec::from_line("6, 2, 24").map(|spec| spec.coarsen_distance),
+            Some(6.0)
+        );
+        assert_eq!(DetailSpec::from_line("6, 8"), None);
+        assert_eq!(DetailSpec::from_line("near, 8, 24"), None);
+    }
+}
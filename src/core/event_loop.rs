@@ -1,7 +1,10 @@
 use super::camera::{Camera, CameraController, CameraUniform};
 use cgmath::prelude::*;
 use instant::Instant;
-use std::{iter, sync::Arc};
+use std::{
+    iter,
+    sync::{atomic::Ordering, Arc},
+};
 use wgpu::util::DeviceExt;
 use winit::{
     application::ApplicationHandler,
@@ -15,30 +18,101 @@ use winit::{
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
 
+use crate::core::content::{spawn_content_loader, Manifest};
+use crate::core::game_loop::Chunk;
+use crate::core::instance_builder::{spawn_instance_builder, BuilderHandle};
 use crate::core::state::State;
+use crate::entity::entity::{Instance, Mesh};
+
+pub enum UserEvent {
+    StateReady(Box<State>),
+    DeviceRecreated(wgpu::Device, wgpu::Queue),
+    InstanceBatch(Chunk, Vec<Instance>),
+    Content(Manifest),
+}
+
+// How often a static scene checks for requests from the page and pending idle events
+const STATIC_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(250);
+// Pixels the cursor may drift before the screensaver exits, the cursor reports a position
+// when the window opens and some mice jitter
+const SCREENSAVER_CURSOR_SLACK: f64 = 8.0;
 
 // #[derive(Default)]
 pub struct App {
     #[cfg(target_arch = "wasm32")]
-    proxy: Option<winit::event_loop::EventLoopProxy<State>>,
+    proxy: Option<winit::event_loop::EventLoopProxy<UserEvent>>,
+    #[cfg(target_arch = "wasm32")]
+    device_proxy: winit::event_loop::EventLoopProxy<UserEvent>,
+    instance_proxy: winit::event_loop::EventLoopProxy<UserEvent>,
     state: Option<State>,
+    // Stopped before the state is dropped
+    builder: Option<BuilderHandle>,
     last_time: instant::Instant,
+    // Fullscreen without a cursor, any input exits
+    screensaver: bool,
+    // Where the cursor was first seen in screensaver mode
+    cursor_origin: Option<winit::dpi::PhysicalPosition<f64>>,
 }
 
 impl App {
-    pub fn new(#[cfg(target_arch = "wasm32")] event_loop: &EventLoop<State>) -> Self {
+    pub fn new(event_loop: &EventLoop<UserEvent>, screensaver: bool) -> Self {
         #[cfg(target_arch = "wasm32")]
         let proxy = Some(event_loop.create_proxy());
         Self {
             state: None,
             #[cfg(target_arch = "wasm32")]
             proxy,
+            #[cfg(target_arch = "wasm32")]
+            device_proxy: event_loop.create_proxy(),
+            instance_proxy: event_loop.create_proxy(),
+            builder: None,
             last_time: instant::Instant::now(),
+            screensaver,
+            cursor_origin: None,
+        }
+    }
+}
+
+impl App {
+    fn build_instances(&mut self, state: &State) {
+        self.builder = Some(spawn_instance_builder(
+            state.game_loop.chunk_map.keys().copied().collect(),
+            state.chunk_size,
+            matches!(state.mesh, Mesh::Textured(_)),
+            state.game_loop.settings.jitter,
+            state.game_loop.settings.seed,
+            self.instance_proxy.clone(),
+        ));
+    }
+
+    // Whether the event ends the screensaver, the way every screensaver reacts to input
+    fn wakes_screensaver(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            // Releases don't count, the key that launched it may still be coming up
+            WindowEvent::KeyboardInput { event, .. } => event.state.is_pressed(),
+            WindowEvent::MouseInput { state, .. } => state.is_pressed(),
+            WindowEvent::MouseWheel { .. } | WindowEvent::Touch(_) => true,
+            WindowEvent::CursorMoved { position, .. } => {
+                let origin = *self.cursor_origin.get_or_insert(*position);
+                (position.x - origin.x).hypot(position.y - origin.y) > SCREENSAVER_CURSOR_SLACK
+            }
+            _ => false,
+        }
+    }
+
+    // Stops the builder first so no batch arrives for released resources, then lets the
+    // state finish its GPU work and drops it
+    fn shutdown(&mut self) {
+        if let Some(mut builder) = self.builder.take() {
+            builder.stop();
+        }
+        if let Some(mut state) = self.state.take() {
+            state.shutdown();
         }
     }
 }
 
-impl ApplicationHandler<State> for App {
+impl ApplicationHandler<UserEvent> for App {
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
         #[allow(unused_mut)]
         let mut window_attributes = Window::default_attributes();
@@ -55,18 +129,30 @@ impl ApplicationHandler<State> for App {
             let canvas = document.get_element_by_id(CANVAS_ID).unwrap_throw();
             let html_canvas_element = canvas.unchecked_into();
             window_attributes = window_attributes.with_canvas(Some(html_canvas_element));
+            crate::core::scroll::watch_page_restore();
+        }
+
+        if self.screensaver {
+            window_attributes = window_attributes
+                .with_decorations(false)
+                .with_fullscreen(Some(winit::window::Fullscreen::Borderless(
+                    event_loop.primary_monitor(),
+                )));
         }
 
         // Create window object
         let window = Arc::new(event_loop.create_window(window_attributes).unwrap());
+        if self.screensaver {
+            window.set_cursor_visible(false);
+        }
         #[cfg(target_arch = "wasm32")]
         {
             if let Some(proxy) = self.proxy.take() {
                 wasm_bindgen_futures::spawn_local(async move {
                     assert!(proxy
-                        .send_event(
+                        .send_event(UserEvent::StateReady(Box::new(
                             State::new(window).await // .expect("Unable to create canvas!!!")
-                        )
+                        )))
                         .is_ok())
                 });
             }
@@ -74,37 +160,163 @@ impl ApplicationHandler<State> for App {
 
         #[cfg(not(target_arch = "wasm32"))]
         {
-            let state = pollster::block_on(State::new(window.clone()));
+            let mut state = pollster::block_on(State::new(window.clone()));
+            if self.screensaver {
+                state.game_loop.start_screensaver();
+            }
+            self.build_instances(&state);
+            spawn_content_loader(self.instance_proxy.clone());
             self.state = Some(state);
         }
     }
 
     #[allow(unused_mut)]
-    fn user_event(&mut self, _event_loop: &ActiveEventLoop, mut event: State) {
-        #[cfg(target_arch = "wasm32")]
-        {
-            event.window.request_redraw();
-            event.resize(event.window.inner_size());
+    fn user_event(&mut self, _event_loop: &ActiveEventLoop, event: UserEvent) {
+        match event {
+            UserEvent::StateReady(mut state) => {
+                #[cfg(target_arch = "wasm32")]
+                {
+                    state.window.request_redraw();
+                    state.resize(state.window.inner_size());
+                }
+                self.build_instances(&state);
+                spawn_content_loader(self.instance_proxy.clone());
+                self.state = Some(*state);
+            }
+            UserEvent::InstanceBatch(chunk, instances) => {
+                if let Some(state) = &mut self.state {
+                    state.game_loop.install_instances(chunk, instances);
+                    state.window.request_redraw();
+                }
+            }
+            UserEvent::Content(content) => {
+                if let Some(state) = &mut self.state {
+                    state.game_loop.set_content(content);
+                    state.window.request_redraw();
+                }
+            }
+            UserEvent::DeviceRecreated(device, queue) => {
+                if let Some(state) = &mut self.state {
+                    state.install_device(device, queue);
+                    state.window.request_redraw();
+                }
+            }
         }
-        self.state = Some(event);
     }
-    fn window_event(&mut self, event_loop: &ActiveEventLoop, _id: WindowId, event: WindowEvent) {
+    fn new_events(&mut self, event_loop: &ActiveEventLoop, cause: StartCause) {
+        if !matches!(cause, StartCause::ResumeTimeReached { .. }) {
+            return;
+        }
+        let state = match &mut self.state {
+            Some(state) => state,
+            None => return,
+        };
+        if state.wants_wake(self.last_time.elapsed()) {
+            event_loop.set_control_flow(ControlFlow::Wait);
+            state.window.request_redraw();
+        } else {
+            event_loop.set_control_flow(ControlFlow::wait_duration(STATIC_POLL_INTERVAL));
+        }
+    }
+
+    fn window_event(&mut self, event_loop: &ActiveEventLoop, id: WindowId, event: WindowEvent) {
+        let state = match &mut self.state {
+            Some(canvas) => canvas,
+            None => return,
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            if state
+                .presenter
+                .as_ref()
+                .is_some_and(|presenter| presenter.window.id() == id)
+            {
+                state.presenter_input(&event);
+                return;
+            }
+            if let WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key: PhysicalKey::Code(KeyCode::F11),
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } = event
+            {
+                state.toggle_presenter(event_loop);
+            }
+        }
+        #[cfg(target_arch = "wasm32")]
+        let _ = id;
+        if self.screensaver && self.wakes_screensaver(&event) {
+            log::info!("Input received; leaving the screensaver");
+            self.shutdown();
+            event_loop.exit();
+            return;
+        }
         let state = match &mut self.state {
             Some(canvas) => canvas,
             None => return,
         };
         state.input(&event);
+        // Any input wakes the loop right away, the next update decides whether it keeps drawing
+        if !matches!(event, WindowEvent::RedrawRequested) {
+            state.window.request_redraw();
+        }
         // println!("{event:?}");
         match event {
             WindowEvent::CloseRequested => {
                 println!("The close button was pressed; stopping");
+                self.shutdown();
                 event_loop.exit();
             }
             WindowEvent::RedrawRequested => {
+                if state.device_lost.swap(false, Ordering::SeqCst) {
+                    // Nothing is rendered until the new device is installed
+                    state.surface_configured = false;
+                    #[cfg(not(target_arch = "wasm32"))]
+                    {
+                        let (device, queue) =
+                            pollster::block_on(State::request_device(&state.adapter));
+                        state.install_device(device, queue);
+                        state.window.request_redraw();
+                    }
+                    #[cfg(target_arch = "wasm32")]
+                    {
+                        let adapter = state.adapter.clone();
+                        let proxy = self.device_proxy.clone();
+                        wasm_bindgen_futures::spawn_local(async move {
+                            let (device, queue) = State::request_device(&adapter).await;
+                            assert!(proxy
+                                .send_event(UserEvent::DeviceRecreated(device, queue))
+                                .is_ok())
+                        });
+                    }
+                    return;
+                }
                 let dt = self.last_time.elapsed();
                 self.last_time = instant::Instant::now();
-                state.update(dt);
-                state.render().unwrap();
+                let changed = state.update(dt);
+                let rendered = state.render();
+                #[cfg(not(target_arch = "wasm32"))]
+                state.render_presenter();
+                state.finish_frame(self.last_time.elapsed());
+                match rendered {
+                    Ok(_) if changed => {
+                        event_loop.set_control_flow(ControlFlow::Wait);
+                        state.window.request_redraw();
+                    }
+                    // Nothing moves, sleep and only check for scheduled work now and then
+                    Ok(_) => event_loop
+                        .set_control_flow(ControlFlow::wait_duration(STATIC_POLL_INTERVAL)),
+                    // Reconfigure the surface if it was lost or is outdated
+                    Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                        state.resize(state.size)
+                    }
+                    Err(e) => log::error!("Unable to render {}", e),
+                }
             }
             WindowEvent::Resized(size) => {
                 // Reconfigures the size of the surface. We do not re-render
@@ -117,20 +329,20 @@ impl ApplicationHandler<State> for App {
 }
 
 pub fn run() -> anyhow::Result<()> {
-    #[cfg(not(target_arch = "wasm32"))]
-    {
-        env_logger::init();
-    }
-    #[cfg(target_arch = "wasm32")]
-    {
-        console_log::init_with_level(log::Level::Info).unwrap_throw();
-    }
+    launch(false)
+}
+
+// Borderless fullscreen on the primary monitor, cycling the sections until any input
+pub fn run_screensaver() -> anyhow::Result<()> {
+    launch(true)
+}
+
+fn launch(screensaver: bool) -> anyhow::Result<()> {
+    crate::core::crash::init_logging();
+    crate::core::crash::install_panic_hook();
 
     let event_loop = EventLoop::with_user_event().build()?;
-    let mut app = App::new(
-        #[cfg(target_arch = "wasm32")]
-        &event_loop,
-    );
+    let mut app = App::new(&event_loop, screensaver);
     event_loop.run_app(&mut app)?;
 
     Ok(())
@@ -139,8 +351,65 @@ pub fn run() -> anyhow::Result<()> {
 #[cfg(target_arch = "wasm32")]
 #[wasm_bindgen(start)]
 pub fn run_web() -> Result<(), wasm_bindgen::JsValue> {
-    console_error_panic_hook::set_once();
     run().unwrap_throw();
 
     Ok(())
 }
+
+// Restores the scene after cubes were deleted, painted or imploded
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+pub fn reset_section() {
+    crate::core::game_loop::request_reset();
+}
+
+// Index of the section the scroll progress has settled on
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+pub fn get_current_section() -> Option<u32> {
+    crate::core::status::latest()?
+        .section
+        .map(|section| section as u32)
+}
+
+// The latest SceneStatus as a JSON string, "null" before the first frame
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+pub fn get_scene_status() -> String {
+    crate::core::status::latest()
+        .map(|status| status.to_json())
+        .unwrap_or_else(|| "null".to_string())
+}
+
+// Downloads the current scene as scene.json
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+pub fn export_scene() {
+    crate::core::scene_io::request_export();
+}
+
+// Restores instance positions, colors and visibility from an exported scene
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+pub fn import_scene(json: String) {
+    crate::core::scene_io::request_import(json);
+}
+
+// Lets the page report which parts of the canvas are covered by its own UI, in CSS pixels
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+pub fn set_safe_area(top: f32, right: f32, bottom: f32, left: f32) {
+    crate::core::camera::request_safe_area(crate::core::camera::SafeArea {
+        top,
+        right,
+        bottom,
+        left,
+    });
+}
+
+// Draws the grid with the textured cube instead of the flat one
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+pub fn set_textured_mesh(textured: bool) {
+    crate::core::state::request_mesh(textured);
+}
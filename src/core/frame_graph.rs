@@ -0,0 +1,218 @@
+// The passes of one frame, ordered by the targets they read and write instead of by where
+// State happens to record them. Built fresh every frame, passes borrow what they draw
+
+use std::sync::Arc;
+
+use crate::core::post_process::RenderTarget;
+
+// Named render targets passes declare, the surface is the swapchain image
+pub const DEPTH_TARGET: &str = "depth";
+pub const SSAO_INPUT_TARGET: &str = "ssao_input";
+pub const DOF_INPUT_TARGET: &str = "dof_input";
+pub const MOTION_BLUR_INPUT_TARGET: &str = "motion_blur_input";
+// Screen space motion of the cubes, read by the motion blur
+pub const VELOCITY_TARGET: &str = "velocity";
+pub const SURFACE_TARGET: &str = "surface";
+
+type Record<'a> = Box<dyn FnOnce(&mut wgpu::CommandEncoder) + 'a>;
+
+struct Pass<'a> {
+    name: &'static str,
+    reads: Vec<&'static str>,
+    writes: Vec<&'static str>,
+    record: Record<'a>,
+}
+
+#[derive(Default)]
+pub struct FrameGraph<'a> {
+    passes: Vec<Pass<'a>>,
+}
+
+impl<'a> FrameGraph<'a> {
+    pub fn new() -> FrameGraph<'a> {
+        FrameGraph { passes: Vec::new() }
+    }
+
+    pub fn add_pass(
+        &mut self,
+        name: &'static str,
+        reads: &[&'static str],
+        writes: &[&'static str],
+        record: impl FnOnce(&mut wgpu::CommandEncoder) + 'a,
+    ) {
+        self.passes.push(Pass {
+            name,
+            reads: reads.to_vec(),
+            writes: writes.to_vec(),
+            record: Box::new(record),
+        });
+    }
+
+    // Records every pass into the encoder, dependencies first
+    pub fn execute(self, encoder: &mut wgpu::CommandEncoder) {
+        let order = self.sorted();
+        let mut passes = self.passes.into_iter().map(Some).collect::<Vec<_>>();
+        for index in order {
+            if let Some(pass) = passes[index].take() {
+                (pass.record)(encoder);
+            }
+        }
+    }
+
+    // A pass runs after the passes writing a target it reads, and after earlier registered
+    // passes writing a target it writes. Passes that read and write the same target, like
+    // overlays loading the surface, layer in registration order. A cycle falls back to
+    // registration order entirely
+    fn sorted(&self) -> Vec<usize> {
+        let count = self.passes.len();
+        let mut dependencies = vec![Vec::new(); count];
+        for (index, pass) in self.passes.iter().enumerate() {
+            for (other, writer) in self.passes.iter().enumerate() {
+                if other == index {
+                    continue;
+                }
+                let reads_output = pass.reads.iter().any(|target| {
+                    writer.writes.contains(target)
+                        && (other < index || !pass.writes.contains(target))
+                });
+                let shares_output = other < index
+                    && pass
+                        .writes
+                        .iter()
+                        .any(|target| writer.writes.contains(target));
+                if reads_output || shares_output {
+                    dependencies[index].push(other);
+                }
+            }
+        }
+
+        let mut order = Vec::with_capacity(count);
+        let mut done = vec![false; count];
+        while order.len() < count {
+            let next = (0..count).find(|&index| {
+                !done[index]
+                    && dependencies[index]
+                        .iter()
+                        .all(|&dependency| done[dependency])
+            });
+            match next {
+                Some(index) => {
+                    done[index] = true;
+                    order.push(index);
+                }
+                None => {
+                    let stuck = (0..count)
+                        .filter(|&index| !done[index])
+                        .map(|index| self.passes[index].name)
+                        .collect::<Vec<_>>();
+                    log::error!(
+                        "Frame graph cycle between {:?}, recording passes as registered",
+                        stuck
+                    );
+                    return (0..count).collect();
+                }
+            }
+        }
+        order
+    }
+}
+
+// Offscreen color targets lent out for a frame. Targets handed back by recycle serve the
+// next requests of the same size and format, so a target freed by one effect is picked up by
+// the next effect that needs one instead of being created again
+#[derive(Default)]
+pub struct TransientTargets {
+    free: Vec<Arc<RenderTarget>>,
+    lent: Vec<Arc<RenderTarget>>,
+}
+
+impl TransientTargets {
+    pub fn acquire(
+        &mut self,
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+    ) -> Arc<RenderTarget> {
+        let (width, height) = (width.max(1), height.max(1));
+        let fits = |target: &Arc<RenderTarget>| {
+            target.texture.width() == width
+                && target.texture.height() == height
+                && target.texture.format() == format
+        };
+        let target = match self.free.iter().position(fits) {
+            Some(index) => self.free.remove(index),
+            None => Arc::new(RenderTarget::new(
+                device,
+                width,
+                height,
+                format,
+                "transient_target",
+            )),
+        };
+        self.lent.push(Arc::clone(&target));
+        target
+    }
+
+    // Takes back every lent target for the next requests, the free ones nobody asked for
+    // since the last recycle are dropped
+    pub fn recycle(&mut self) {
+        self.free = std::mem::take(&mut self.lent);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::test_support;
+
+    const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Bgra8UnormSrgb;
+
+    #[test]
+    fn a_freed_target_goes_to_the_next_compatible_pass() {
+        let (device, _) = test_support::device();
+        let mut targets = TransientTargets::default();
+
+        // Frame one, only the first effect is on
+        let ssao = targets.acquire(&device, 800, 600, FORMAT);
+        targets.recycle();
+        // Frame two, the first effect is off and the second one gets its target
+        let dof = targets.acquire(&device, 800, 600, FORMAT);
+        assert!(Arc::ptr_eq(&ssao, &dof));
+        targets.recycle();
+
+        // Both on at once, each needs its own target
+        let first = targets.acquire(&device, 800, 600, FORMAT);
+        let second = targets.acquire(&device, 800, 600, FORMAT);
+        assert!(Arc::ptr_eq(&first, &ssao));
+        assert!(!Arc::ptr_eq(&first, &second));
+        targets.recycle();
+
+        // Same order next frame, same targets, so no bind group gets rebuilt
+        assert!(Arc::ptr_eq(
+            &targets.acquire(&device, 800, 600, FORMAT),
+            &first
+        ));
+        assert!(Arc::ptr_eq(
+            &targets.acquire(&device, 800, 600, FORMAT),
+            &second
+        ));
+    }
+
+    #[test]
+    fn incompatible_or_unused_targets_are_not_reused() {
+        let (device, _) = test_support::device();
+        let mut targets = TransientTargets::default();
+        let small = targets.acquire(&device, 800, 600, FORMAT);
+        targets.recycle();
+
+        let resized = targets.acquire(&device, 1024, 768, FORMAT);
+        let other_format = targets.acquire(&device, 800, 600, wgpu::TextureFormat::Rgba16Float);
+        assert!(!Arc::ptr_eq(&small, &resized));
+        assert!(!Arc::ptr_eq(&small, &other_format));
+        targets.recycle();
+        // Nobody asked for the small target for a whole frame, the pool let go of it
+        targets.recycle();
+        assert_eq!(Arc::strong_count(&small), 1);
+    }
+}
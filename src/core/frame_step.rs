@@ -0,0 +1,70 @@
+// Debug stepping. Pause freezes the simulation while the scene keeps rendering, each step
+// then advances it by exactly one fixed timestep. Only built with the frame-step feature
+
+use std::time::Duration;
+
+use winit::{event::WindowEvent, keyboard::KeyCode};
+
+// The timestep every step advances by, a 60 Hz frame
+pub const STEP: Duration = Duration::from_nanos(16_666_667);
+
+#[derive(Default)]
+pub struct FrameStep {
+    paused: bool,
+    // Steps requested but not yet run, one runs per frame
+    pending: u32,
+    // Simulated time since pausing
+    simulated: Duration,
+    // Input received while paused, replayed before the next step
+    queued: Vec<WindowEvent>,
+}
+
+impl FrameStep {
+    // Pause toggles, while paused . steps once and / ten times. Returns whether the key
+    // was taken
+    pub fn handle_key(&mut self, code: KeyCode) -> bool {
+        match code {
+            KeyCode::Pause => {
+                self.paused = !self.paused;
+                self.pending = 0;
+                self.simulated = Duration::ZERO;
+                log::info!("Frame stepping {}", if self.paused { "on" } else { "off" });
+            }
+            KeyCode::Period if self.paused => self.pending += 1,
+            KeyCode::Slash if self.paused => self.pending += 10,
+            _ => return false,
+        }
+        true
+    }
+
+    // Holds on to the event while paused, returns whether it was queued
+    pub fn queue(&mut self, event: &WindowEvent) -> bool {
+        if self.paused {
+            self.queued.push(event.clone());
+        }
+        self.paused
+    }
+
+    // The dt to simulate this frame with, None while paused without a pending step
+    pub fn advance(&mut self, dt: Duration) -> Option<Duration> {
+        if !self.paused {
+            return Some(dt);
+        }
+        if self.pending == 0 {
+            return None;
+        }
+        self.pending -= 1;
+        self.simulated += STEP;
+        Some(STEP)
+    }
+
+    // Input queued while paused, oldest first. Unpausing hands it over on the next frame
+    pub fn take_queued(&mut self) -> Vec<WindowEvent> {
+        std::mem::take(&mut self.queued)
+    }
+
+    // Seconds simulated since pausing, None while running normally
+    pub fn simulated_time(&self) -> Option<f32> {
+        self.paused.then_some(self.simulated.as_secs_f32())
+    }
+}
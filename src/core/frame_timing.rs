@@ -0,0 +1,255 @@
+// Where the time of a frame went. Subsystems time their work with `scope`, State closes the
+// frame and logs a breakdown of frames that took much longer than usual
+
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+// Frame times the median is taken over
+const HISTORY: usize = 120;
+// Frames recorded before long frames are reported, the first ones compile pipelines
+const WARMUP: usize = 30;
+// Long frames kept for the F1 dump
+const LONG_FRAME_RECORDS: usize = 50;
+// Frames shorter than this are never long, a 1 ms frame next to 0.4 ms ones isn't a stutter
+const MIN_LONG_FRAME: Duration = Duration::from_millis(8);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    Input,
+    Animate,
+    Upload,
+    Encode,
+    // Acquiring and presenting the surface texture, where waiting on the GPU shows up
+    Present,
+}
+
+pub const PHASES: [Phase; 5] = [
+    Phase::Input,
+    Phase::Animate,
+    Phase::Upload,
+    Phase::Encode,
+    Phase::Present,
+];
+
+impl Phase {
+    pub fn name(self) -> &'static str {
+        match self {
+            Phase::Input => "input",
+            Phase::Animate => "animate",
+            Phase::Upload => "upload",
+            Phase::Encode => "encode",
+            Phase::Present => "present",
+        }
+    }
+}
+
+thread_local! {
+    // Time per phase of the frame in progress
+    static CURRENT: Cell<[Duration; PHASES.len()]> = const { Cell::new([Duration::ZERO; PHASES.len()]) };
+}
+
+// Adds the time until it is dropped to its phase of the current frame
+pub struct PhaseTimer {
+    phase: Phase,
+    started: instant::Instant,
+}
+
+impl Drop for PhaseTimer {
+    fn drop(&mut self) {
+        let elapsed = self.started.elapsed();
+        CURRENT.with(|current| {
+            let mut phases = current.get();
+            phases[self.phase as usize] += elapsed;
+            current.set(phases);
+        });
+    }
+}
+
+pub fn scope(phase: Phase) -> PhaseTimer {
+    PhaseTimer {
+        phase,
+        started: instant::Instant::now(),
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct LongFrame {
+    pub total: Duration,
+    pub median: Duration,
+    pub phases: [Duration; PHASES.len()],
+    pub instances: usize,
+    pub animations: usize,
+}
+
+impl LongFrame {
+    pub fn log(&self) {
+        let phases = PHASES
+            .iter()
+            .zip(&self.phases)
+            .map(|(phase, time)| format!("{}={:.2}ms", phase.name(), millis(*time)))
+            .collect::<Vec<_>>()
+            .join(" ");
+        log::warn!(
+            "Long frame total={:.2}ms median={:.2}ms {} instances={} animations={}",
+            millis(self.total),
+            millis(self.median),
+            phases,
+            self.instances,
+            self.animations
+        );
+    }
+}
+
+#[derive(Default)]
+pub struct FrameTimer {
+    history: VecDeque<Duration>,
+    // Reused for the median so timing doesn't allocate every frame
+    sorted: Vec<Duration>,
+    long_frames: VecDeque<LongFrame>,
+}
+
+impl FrameTimer {
+    // Closes the frame that took `total`. A frame longer than `factor` times the median of
+    // the recent ones is logged and kept, `counts` gives its instance and animation counts
+    pub fn finish(
+        &mut self,
+        total: Duration,
+        factor: f32,
+        counts: impl FnOnce() -> (usize, usize),
+    ) -> Option<LongFrame> {
+        let phases = CURRENT.with(|current| current.replace([Duration::ZERO; PHASES.len()]));
+        let median = if total >= MIN_LONG_FRAME {
+            self.median()
+        } else {
+            None
+        };
+        if self.history.len() == HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back(total);
+        let median = median?;
+        if total.as_secs_f32() <= median.as_secs_f32() * factor {
+            return None;
+        }
+        let (instances, animations) = counts();
+        let frame = LongFrame {
+            total,
+            median,
+            phases,
+            instances,
+            animations,
+        };
+        frame.log();
+        if self.long_frames.len() == LONG_FRAME_RECORDS {
+            self.long_frames.pop_front();
+        }
+        self.long_frames.push_back(frame);
+        Some(frame)
+    }
+
+    // Median of the recent frames, None while warming up
+    pub fn median(&mut self) -> Option<Duration> {
+        if self.history.len() < WARMUP {
+            return None;
+        }
+        self.sorted.clear();
+        self.sorted.extend(self.history.iter().copied());
+        self.sorted.sort_unstable();
+        Some(self.sorted[self.sorted.len() / 2])
+    }
+
+    // Oldest first
+    pub fn long_frames(&self) -> impl Iterator<Item = &LongFrame> {
+        self.long_frames.iter()
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NORMAL: Duration = Duration::from_millis(10);
+
+    fn warmed_up() -> FrameTimer {
+        let mut timer = FrameTimer::default();
+        for _ in 0..WARMUP {
+            assert!(timer.finish(NORMAL, 2.0, || (0, 0)).is_none());
+        }
+        timer
+    }
+
+    #[test]
+    fn history_wraps_to_the_recent_frames() {
+        let mut timer = FrameTimer::default();
+        for _ in 0..WARMUP - 1 {
+            timer.finish(Duration::from_millis(100), 2.0, || (0, 0));
+        }
+        assert_eq!(timer.median(), None);
+        for _ in 0..HISTORY {
+            timer.finish(Duration::from_millis(100), 2.0, || (0, 0));
+        }
+        assert_eq!(timer.history.len(), HISTORY);
+        assert_eq!(timer.median(), Some(Duration::from_millis(100)));
+
+        // A full history of faster frames pushes every old one out
+        for _ in 0..HISTORY {
+            timer.finish(NORMAL, 2.0, || (0, 0));
+        }
+        assert_eq!(timer.history.len(), HISTORY);
+        assert_eq!(timer.median(), Some(NORMAL));
+    }
+
+    #[test]
+    fn frames_over_the_factor_are_long() {
+        let mut timer = warmed_up();
+        assert!(timer.finish(NORMAL * 2, 2.0, || (0, 0)).is_none());
+        {
+            let _upload = scope(Phase::Upload);
+            std::thread::sleep(Duration::from_millis(2));
+        }
+        let frame = timer
+            .finish(NORMAL * 3, 2.0, || (400, 7))
+            .expect("long frame");
+        assert_eq!(frame.median, NORMAL);
+        assert_eq!((frame.instances, frame.animations), (400, 7));
+        assert!(frame.phases[Phase::Upload as usize] >= Duration::from_millis(2));
+        assert_eq!(frame.phases[Phase::Encode as usize], Duration::ZERO);
+
+        // The phases start over with the next frame
+        let frame = timer.finish(NORMAL * 3, 2.0, || (0, 0)).unwrap();
+        assert_eq!(frame.phases, [Duration::ZERO; PHASES.len()]);
+    }
+
+    #[test]
+    fn short_frames_are_never_long() {
+        let mut timer = FrameTimer::default();
+        for _ in 0..WARMUP {
+            timer.finish(Duration::from_millis(1), 2.0, || (0, 0));
+        }
+        assert!(timer
+            .finish(MIN_LONG_FRAME - Duration::from_millis(1), 2.0, || (0, 0))
+            .is_none());
+        assert!(timer.finish(MIN_LONG_FRAME * 2, 2.0, || (0, 0)).is_some());
+    }
+
+    #[test]
+    fn only_the_latest_long_frames_are_kept() {
+        let mut timer = warmed_up();
+        for long in 0..LONG_FRAME_RECORDS + 10 {
+            for _ in 0..10 {
+                timer.finish(NORMAL, 2.0, || (0, 0));
+            }
+            assert!(timer.finish(NORMAL * 3, 2.0, || (long, 0)).is_some());
+        }
+        let kept = timer
+            .long_frames()
+            .map(|frame| frame.instances)
+            .collect::<Vec<_>>();
+        assert_eq!(kept, (10..LONG_FRAME_RECORDS + 10).collect::<Vec<_>>());
+    }
+}
@@ -1,18 +1,64 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    io::Write,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 
-use cgmath::{InnerSpace, Rotation3, Vector2, Vector3};
+use cgmath::{Deg, InnerSpace, Point3, Quaternion, Rotation, Rotation3, Vector2, Vector3};
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use winit::{
-    dpi::{PhysicalPosition, PhysicalSize},
-    event::{KeyEvent, WindowEvent},
-    keyboard::{KeyCode, PhysicalKey},
+    dpi::PhysicalPosition,
+    event::{KeyEvent, MouseScrollDelta, WindowEvent},
+    keyboard::{KeyCode, ModifiersState, PhysicalKey},
 };
 
 use crate::{
-    core::{camera::Camera, state::State},
-    entity::entity::{Instance, InstanceController},
+    core::{
+        a11y::{section_announcement, Announcer},
+        camera::{Camera, ScreenMapper},
+        camera_attachment::{cube_logo, CameraAttachment},
+        camera_script::CameraCue,
+        capabilities::Capabilities,
+        clipboard::copy_text,
+        content::Manifest,
+        detail::DetailRefiner,
+        frame_timing::{self, Phase},
+        idle_events::{IdleEvent, IdleEventScheduler},
+        reveal::GridReveal,
+        scene_io,
+        scroll::{take_restore_request, ScrollSnap},
+        section::{dispatch_section, InteractionPolicy, SectionTracker},
+        sequencer::{
+            finale_script, screensaver_script, story_requested, story_script, Cue, Sequencer,
+        },
+        settings::Settings,
+        state::State,
+        status::{gpu_memory_usage, SceneStatus},
+        tooltip::Tooltip,
+        upload::UploadScheduler,
+    },
+    entity::{
+        entity::{Instance, InstanceController, MeshBuffer, Renderer},
+        progress_bar::PROGRESS_CUBES,
+    },
     helpers::{
-        animation::{ease_in_ease_out_loop, get_height_color, AnimationHandler},
-        line_trace::{line_trace_animate_hit, line_trace_cursor, line_trace_remove},
+        animation::{
+            color_groups, get_height_color, AnimationHandler, EaseInEaseOut, TrajectoryMode,
+        },
+        drift::DriftSnapshot,
+        effects::{EffectOptions, EffectPreset, EffectTemplate},
+        grid::{cube_info, grid_cell},
+        line_trace::{
+            implode_at, lift_at, line_trace_animate_hit, line_trace_cursor, line_trace_hit,
+            line_trace_implode, line_trace_index, line_trace_remove, shake_at,
+        },
+        selection::Selection,
+        sequence::{SequenceDetector, KONAMI_CODE},
+        tool::{Palette, Tool},
+        vector::{is_finite, safe_normalize},
     },
 };
 
@@ -31,30 +77,257 @@ pub struct Gameloop {
     pub elapsed_time: f32,
     pub chunk_size: Vector2<u32>,
     pub animation_handler: AnimationHandler,
+    pub scroll_progress: f32,
+    pub scroll_snap: ScrollSnap,
+    pub section_tracker: SectionTracker,
+    pub content: Manifest,
+    // Eased toward 1.0 while the jitter is enabled and 0.0 while it isn't
+    pub jitter_amount: f32,
+    // Instances as the builder delivered them, what reset_section restores
+    pub canonical: HashMap<Chunk, Vec<Instance>>,
+    pub tool: Tool,
+    pub palette: Palette,
+    pub painting: bool,
+    pub settings: Settings,
+    pub sequencer: Sequencer,
+    pub idle_events: IdleEventScheduler,
+    pub modifiers: ModifiersState,
+    // Start and current cursor position of a ctrl + drag selection
+    pub box_select: Option<(PhysicalPosition<f32>, PhysicalPosition<f32>)>,
+    // Instance copied by the stamp tool
+    pub stamp: Option<Instance>,
+    // Cubes toggled with ctrl + click, the selection commands act on all of them
+    pub selection: Selection,
+    // Set by input and new instances, makes the next update report a change
+    pub dirty: bool,
+    pub capabilities: Capabilities,
+    pub uploads: UploadScheduler,
+    // Chunk under the camera target, uploaded first
+    pub focus: Chunk,
+    // Interactions the active section allows, replaced when a section settles
+    pub policy: InteractionPolicy,
+    pub announcer: Announcer,
+    // Taken with F9, F10 reports what moved since
+    pub drift_snapshot: Option<DriftSnapshot>,
+    pub konami: SequenceDetector<KeyCode>,
+    pub tooltip: Tooltip,
+    // First load entrance, None once every cube showed or when it is turned off
+    pub reveal: Option<GridReveal>,
+    // Hits taken by cubes of the first chunk in multi-hit mode, by instance index
+    pub hits: HashMap<usize, u32>,
+    // Removals from input, applied at the start of the next update so they land in that
+    // frame's upload
+    pub pending_removals: Vec<(Chunk, usize)>,
+    pub finale: Sequencer,
+    // The finale started since the scroll last left the end, it plays once per visit
+    pub finale_played: bool,
+    // Cycles the sections while Settings.screensaver is on, restarted after every lap
+    pub screensaver: Sequencer,
+    // Groups drawn pinned to the screen instead of placed in the world
+    pub camera_attachments: Vec<CameraAttachment>,
+    // Cubes of the first chunk the active section draws split up near the camera
+    pub detail: DetailRefiner,
+    // Their smaller stand-ins, a pool of its own drawn with the grid's mesh
+    pub detail_cubes: InstanceController,
+}
+
+#[cfg(target_arch = "wasm32")]
+use crate::core::scroll::scroll_page_to;
+
+const SCROLL_LINE_STEP: f32 = 0.05;
+const SCROLL_PIXEL_STEP: f32 = 0.001;
+// Seconds to fade the jitter in or out when it is toggled
+const JITTER_FADE: f32 = 0.4;
+// Glow added to selected cubes
+const SELECTION_EMISSIVE: f32 = 0.35;
+// A ctrl + drag shorter than this in pixels is a click and toggles the selection
+const CLICK_DRAG_PIXELS: f32 = 4.0;
+// Smallest per-frame change in position or color that still needs a redraw
+const CHANGE_EPSILON: f32 = 1e-4;
+const DRIFT_REPORT_LIMIT: usize = 10;
+// Arc height of cubes flying home on a reset, as a fraction of their distance
+const RESET_ARC_HEIGHT: (f32, f32) = (0.1, 0.3);
+// Seconds painting the selection takes to fade to the new color
+const PAINT_FADE: f32 = 0.25;
+// Seconds between two keys of the konami code before it has to be typed again
+const KONAMI_TIMEOUT: f32 = 1.5;
+// Hits a cube takes in multi-hit mode, the last one removes it
+const CUBE_HIT_POINTS: u32 = 3;
+// Color kept per hit that cracks a cube
+const CRACK_DARKEN: f32 = 0.7;
+const CRACK_FADE: f32 = 0.15;
+// Range of distances the finale throws cubes outward
+const FINALE_DISTANCE: (f32, f32) = (8.0, 20.0);
+// Arc height of the finale throw, as a fraction of the distance
+const FINALE_ARC_HEIGHT: (f32, f32) = (0.3, 0.8);
+// Units below their place cubes rise from when the first load reveal reaches them
+const REVEAL_RISE: f32 = 1.0;
+// Cubes along each side of the HUD logo
+const LOGO_SIZE: usize = 6;
+// Center of the HUD logo in normalized device coordinates, near the top right corner
+const LOGO_ANCHOR: Vector2<f32> = Vector2::new(0.82, 0.72);
+// Fraction of the view height each logo cube takes up
+const LOGO_SCALE: f32 = 0.012;
+// Degrees per second the logo turns
+const LOGO_SPIN: f32 = 30.0;
+
+fn overlaps(
+    lower: Vector3<f32>,
+    upper: Vector3<f32>,
+    min: Vector3<f32>,
+    max: Vector3<f32>,
+) -> bool {
+    lower.x <= max.x
+        && upper.x >= min.x
+        && lower.y <= max.y
+        && upper.y >= min.y
+        && lower.z <= max.z
+        && upper.z >= min.z
+}
+
+// Section an Alt + digit jumps to
+fn section_slot(code: KeyCode) -> Option<usize> {
+    let slot = match code {
+        KeyCode::Digit1 => 0,
+        KeyCode::Digit2 => 1,
+        KeyCode::Digit3 => 2,
+        KeyCode::Digit4 => 3,
+        KeyCode::Digit5 => 4,
+        KeyCode::Digit6 => 5,
+        KeyCode::Digit7 => 6,
+        KeyCode::Digit8 => 7,
+        KeyCode::Digit9 => 8,
+        _ => return None,
+    };
+    Some(slot)
+}
+
+// Set by the page through the wasm api, picked up on the next frame
+static RESET_REQUEST: AtomicBool = AtomicBool::new(false);
+
+pub fn request_reset() {
+    RESET_REQUEST.store(true, Ordering::SeqCst);
+}
+
+pub fn reset_pending() -> bool {
+    RESET_REQUEST.load(Ordering::SeqCst)
 }
 
 impl Gameloop {
-    pub fn update(&mut self, dt: std::time::Duration) {
+    // Advances the scene, returns whether anything visible changed
+    pub fn update(&mut self, dt: std::time::Duration) -> bool {
         let dts = dt.as_secs_f32();
+        let previous_progress = self.scroll_progress;
+        // Delayed and slow animations barely move some frames but must keep the loop running
+        let mut changed = std::mem::take(&mut self.dirty)
+            || self.sequencer.active
+            || self.finale.active
+            || self.screensaver.active
+            || self.animation_handler.is_active()
+            || self.announcer.is_pending();
+        changed |= self.apply_pending_removals();
+        for attachment in &mut self.camera_attachments {
+            attachment.update(dts);
+            changed |= attachment.spin != 0.0;
+        }
+        for cue in self.sequencer.update(dts) {
+            self.apply_cue(cue);
+        }
+        if RESET_REQUEST.swap(false, Ordering::SeqCst) {
+            self.reset_section();
+        }
+        if let Some(progress) = take_restore_request() {
+            self.restore_scroll(progress);
+        }
+        #[cfg(target_arch = "wasm32")]
+        let (was_snapping, was_jumping) = (
+            self.scroll_snap.is_snapping(),
+            self.scroll_snap.is_jumping(),
+        );
+        self.scroll_snap
+            .update(&mut self.scroll_progress, dts, &self.settings);
+        // Keyboard jumps always carry the page along, nothing else scrolls it there
+        #[cfg(target_arch = "wasm32")]
+        if was_jumping
+            || self.settings.scroll_snap_page && (was_snapping || self.scroll_snap.is_snapping())
+        {
+            scroll_page_to(self.scroll_progress);
+        }
+        let settled = !self.scroll_snap.is_snapping();
+        if let Some(index) = self.section_tracker.update(self.scroll_progress, settled) {
+            self.activate_section(index);
+        }
+        self.update_finale(settled);
+        for cue in self.finale.update(dts) {
+            self.apply_cue(cue);
+        }
+        if self.settings.screensaver && !self.screensaver.active {
+            self.screensaver.start();
+        }
+        for cue in self.screensaver.update(dts) {
+            self.apply_cue(cue);
+        }
+        self.announcer.update(dts);
+        self.konami.update(dts);
+        // Idle events only play on the first section, or on any in the screensaver, and never
+        // during story mode
+        if (self.scroll_progress <= 0.0 || self.settings.screensaver) && !self.sequencer.active {
+            if let Some(event) = self.idle_events.update(dts, &self.settings) {
+                self.apply_idle_event(event);
+            }
+        }
+        let jitter_target = if self.settings.jitter.enabled {
+            1.0
+        } else {
+            0.0
+        };
+        let jitter_fading = self.jitter_amount != jitter_target;
+        changed |= jitter_fading;
+        let jitter_step = dts / JITTER_FADE;
+        self.jitter_amount += (jitter_target - self.jitter_amount).clamp(-jitter_step, jitter_step);
+        let jitter_amount = EaseInEaseOut::ease_in_ease_out_cubic(self.jitter_amount);
+        let origin = Chunk { x: 0, y: 0 };
+        if let Some(controller) = self.chunk_map.get(&origin) {
+            self.selection.prune(&controller.instances);
+        }
+        let animate_timer = frame_timing::scope(Phase::Animate);
+        // Advanced once per frame however many chunks there are, the loop below only applies it
+        self.animation_handler.animate(dts);
         for (chunk, instance_controller) in self.chunk_map.iter_mut() {
-            self.animation_handler.animate(dt.as_secs_f32());
+            let mut chunk_changed = jitter_fading;
 
             for (i, instance) in instance_controller.instances.iter_mut().enumerate() {
+                // Hidden cubes are parked, their animations wait until they show again
+                if *chunk == origin {
+                    self.animation_handler.set_active(i, instance.should_render);
+                }
+                if !instance.should_render {
+                    continue;
+                }
+                let before = (instance.position, instance.color, instance.emissive);
                 let local_x = (i % self.chunk_size.x as usize) as u64;
                 let local_y = (i / self.chunk_size.y as usize) as u64;
                 let delay = ((chunk.x as f32 + chunk.y as f32) * 5.0)
                     + ((local_x as f32 + local_y as f32) * 0.05);
                 // Diagonal wave offset for this tile
-                let lerp = 1.0 * ease_in_ease_out_loop(self.elapsed_time, delay as f32, 1.0);
+                let lerp = self.settings.wave_motion.sample(self.elapsed_time, delay);
+                let height = self.settings.wave_color.sample(self.elapsed_time, delay);
                 if (i == 1) {
                     println!("{:?}", lerp);
                 }
                 self.animation_handler.update_instance(i, instance);
+                instance.jitter.amount = jitter_amount;
+                instance.emissive = if *chunk == origin && self.selection.contains(i) {
+                    SELECTION_EMISSIVE
+                } else {
+                    0.0
+                };
 
                 // if (i == 200) {
                 //     println!("{:?}", height);
                 // }
-                if self.animation_handler.disabled {
+                let pinned = *chunk == origin && self.animation_handler.is_pinned(i);
+                if self.animation_handler.disabled && !pinned {
                     let pos = Vector3::new(0.0, lerp, 0.0);
 
                     if let Some(animation) = self.animation_handler.movement_list.get_mut(i) {
@@ -62,22 +335,270 @@ impl Gameloop {
                         instance.bounding = instance.size + animation.current_pos + pos;
                     }
                 }
-                instance.color = get_height_color(lerp)
+                let color_vision = self.settings.color_vision;
+                instance.color = self.animation_handler.smoothed_color(
+                    i,
+                    get_height_color(height),
+                    |color| color_vision.remap(color),
+                    dts,
+                    self.settings.color_smoothing,
+                );
+                changed |= (instance.position - before.0).magnitude2() > CHANGE_EPSILON
+                    || (instance.color - before.1).magnitude2() > CHANGE_EPSILON
+                    || instance.emissive != before.2;
+                chunk_changed |= (instance.position, instance.color, instance.emissive) != before;
                 // test += 15;
             }
 
-            instance_controller.update_buffer(&self.queue);
+            if chunk_changed {
+                self.uploads.touch(*chunk, instance_controller);
+            }
         }
-        if self.animation_handler.disabled {
+        drop(animate_timer);
+        debug_assert!(
+            self.chunk_map.values().all(|controller| controller
+                .instances
+                .iter()
+                .all(|instance| is_finite(instance.position))),
+            "Instance position went non-finite"
+        );
+        let upload_timer = frame_timing::scope(Phase::Upload);
+        self.uploads
+            .upload(&mut self.chunk_map, self.focus, &self.queue);
+        drop(upload_timer);
+        // The looping wave keeps the scene moving unless reduced motion freezes it
+        if self.animation_handler.disabled && !self.settings.reduced_motion {
             self.elapsed_time += dt.as_secs_f32();
+            changed = true;
         }
+        changed
+            || self.scroll_progress != previous_progress
+            || self.scroll_snap.is_snapping()
+            || self
+                .chunk_map
+                .values()
+                .any(InstanceController::needs_upload)
     }
-    pub fn process_event(
-        &mut self,
-        event: &WindowEvent,
-        camera: &Camera,
-        screen: &PhysicalSize<u32>,
-    ) {
+
+    // Starts the finale once the scroll settles at the end. Scrolling back cancels it and
+    // flies the cubes home
+    fn update_finale(&mut self, settled: bool) {
+        let at_end = self.scroll_progress >= 1.0;
+        if !at_end {
+            self.finale_played = false;
+            if self.finale.active {
+                self.finale.stop();
+                self.reset_section();
+            }
+            return;
+        }
+        if self.finale.is_held() && !self.animation_handler.is_active() {
+            self.finale.release();
+        }
+        if settled && self.content.finale && !self.finale_played && !self.sequencer.active {
+            self.finale.start();
+            self.finale_played = true;
+        }
+    }
+
+    // Throws every visible cube of the first chunk outward from the grid center, each on
+    // an arc of its own
+    fn explode_all(&mut self) {
+        let targets = match self.chunk_map.get(&Chunk { x: 0, y: 0 }) {
+            Some(controller) => controller
+                .instances
+                .iter()
+                .enumerate()
+                .filter(|(_, instance)| instance.should_render)
+                .map(|(index, instance)| (index, instance.position))
+                .collect::<Vec<_>>(),
+            None => return,
+        };
+        if targets.is_empty() {
+            return;
+        }
+        let center = targets
+            .iter()
+            .fold(Vector3::new(0.0, 0.0, 0.0), |sum, (_, position)| {
+                sum + position
+            })
+            / targets.len() as f32;
+        // Seeded so the finale always scatters the same way
+        let mut rng = StdRng::seed_from_u64(self.settings.seed);
+        for (index, position) in targets {
+            let offset = Vector3::new(position.x - center.x, 0.0, position.z - center.z);
+            let direction = safe_normalize(offset, Vector3::unit_x());
+            let distance = rng.gen_range(FINALE_DISTANCE.0..FINALE_DISTANCE.1);
+            let height = rng.gen_range(FINALE_ARC_HEIGHT.0..FINALE_ARC_HEIGHT.1);
+            self.animation_handler
+                .play(index, position, position + direction * distance, 0.0);
+            self.animation_handler
+                .set_trajectory(index, TrajectoryMode::Arc { height });
+        }
+    }
+
+    fn activate_section(&mut self, index: usize) {
+        self.tooltip.hide();
+        dispatch_section(index, self.content.section(index));
+        self.announcer
+            .announce(section_announcement(index, self.content.section(index)));
+        self.policy = self
+            .content
+            .sections
+            .get(index)
+            .map(|section| section.policy)
+            .unwrap_or_default();
+        let entrance = self
+            .content
+            .sections
+            .get(index)
+            .and_then(|section| section.entrance.as_deref())
+            .and_then(|name| self.content.animation(name));
+        if let Some(template) = entrance {
+            self.play_template(&template);
+        }
+    }
+
+    // Plays an animation template on the visible cubes of the first chunk, into where they are
+    fn play_template(&mut self, template: &EffectTemplate) {
+        let targets = match self.chunk_map.get(&Chunk { x: 0, y: 0 }) {
+            Some(controller) => controller
+                .instances
+                .iter()
+                .enumerate()
+                .filter(|(_, instance)| instance.should_render)
+                .map(|(index, instance)| (index, instance.position))
+                .collect::<Vec<_>>(),
+            None => return,
+        };
+        template.play(&mut self.animation_handler, &targets);
+    }
+
+    // The active section and how far the scroll has moved on from it toward the next one,
+    // 0 at its anchor and 1 at the next
+    pub fn section_progress(&self) -> Option<(usize, f32)> {
+        let index = self.section_tracker.active()?;
+        let sections = (PROGRESS_CUBES - 1) as f32;
+        Some((
+            index,
+            (self.scroll_progress * sections - index as f32).clamp(0.0, 1.0),
+        ))
+    }
+
+    // Camera cues of the active section, none with reduced motion
+    pub fn camera_cues(&self) -> &[CameraCue] {
+        let section = self
+            .section_tracker
+            .active()
+            .and_then(|index| self.content.sections.get(index));
+        match section {
+            Some(section) if !self.settings.reduced_motion => &section.camera,
+            _ => &[],
+        }
+    }
+
+    // Refines and coarsens the first chunk's cubes for the camera at `eye` and places the
+    // detail cubes where the refined ones are this frame. Runs after update so they follow
+    // the animations. Returns whether the refined set changed
+    pub fn update_detail(&mut self, eye: Point3<f32>) -> bool {
+        let spec = self
+            .section_tracker
+            .active()
+            .and_then(|index| self.content.sections.get(index))
+            .and_then(|section| section.detail);
+        let instances = self
+            .chunk_map
+            .get(&Chunk { x: 0, y: 0 })
+            .map_or(&[][..], |controller| &controller.instances);
+        let changed = self.detail.update(spec, eye, instances);
+        if changed || !self.detail_cubes.instances.is_empty() {
+            let cubes = self.detail.instances(instances);
+            self.detail_cubes.instances.clear();
+            self.detail_cubes
+                .add_instances(cubes, &self.queue, &self.device);
+        }
+        changed
+    }
+
+    // Section the scroll is on or headed to
+    fn current_section(&self) -> usize {
+        let progress = self.scroll_snap.target().unwrap_or(self.scroll_progress);
+        (progress * (PROGRESS_CUBES - 1) as f32).round() as usize
+    }
+
+    // Eases the scroll onto the anchor of section `index` the way a snap does, so the section
+    // activates, announces itself and moves the camera as if it was scrolled to. Reduced
+    // motion lands on it right away
+    pub fn go_to_section(&mut self, index: usize) {
+        let target = index.min(PROGRESS_CUBES - 1) as f32 / (PROGRESS_CUBES - 1) as f32;
+        if self.settings.reduced_motion {
+            self.restore_scroll(target);
+        } else {
+            self.scroll_snap.jump(self.scroll_progress, target);
+        }
+        self.dirty = true;
+    }
+
+    // Lands on the section a restored page scroll falls in without easing or snapping
+    // through the sections in between, the way reduced motion jumps
+    pub fn restore_scroll(&mut self, progress: f32) {
+        self.scroll_snap.reset();
+        let index = self.section_tracker.restore(progress);
+        self.scroll_progress = index as f32 / (PROGRESS_CUBES - 1) as f32;
+        #[cfg(target_arch = "wasm32")]
+        scroll_page_to(self.scroll_progress);
+        self.activate_section(index);
+        self.dirty = true;
+    }
+
+    // Chunk whose grid contains the world position
+    pub fn chunk_at(&self, position: cgmath::Point3<f32>) -> Chunk {
+        Chunk {
+            x: (position.x / self.chunk_size.x as f32).floor() as i32,
+            y: (position.z / self.chunk_size.y as f32).floor() as i32,
+        }
+    }
+
+    // Whether an idle event would fire if the scene was updated `elapsed` seconds from now
+    pub fn idle_event_due(&self, elapsed: f32) -> bool {
+        self.scroll_progress <= 0.0
+            && !self.sequencer.active
+            && self.idle_events.is_due(elapsed, &self.settings)
+    }
+    pub fn process_event(&mut self, event: &WindowEvent, camera: &Camera, screen: &ScreenMapper) {
+        let user_input = match event {
+            WindowEvent::KeyboardInput { event, .. } => event.state.is_pressed(),
+            WindowEvent::MouseInput { state, .. } => state.is_pressed(),
+            WindowEvent::MouseWheel { .. } => true,
+            _ => false,
+        };
+        if user_input {
+            self.idle_events.reset();
+            self.skip_reveal();
+        }
+        if user_input || matches!(event, WindowEvent::CursorMoved { .. }) {
+            self.dirty = true;
+        }
+        if user_input && self.sequencer.active {
+            // Any input hands control back to the user
+            self.sequencer.stop();
+            log::info!("Story mode stopped");
+        }
+        if let WindowEvent::KeyboardInput {
+            event:
+                KeyEvent {
+                    state: winit::event::ElementState::Pressed,
+                    physical_key: PhysicalKey::Code(keycode),
+                    repeat: false,
+                    ..
+                },
+            ..
+        } = event
+        {
+            if self.konami.push(*keycode) {
+                self.play_easter_egg();
+            }
+        }
         match event {
             WindowEvent::KeyboardInput {
                 event:
@@ -88,13 +609,106 @@ impl Gameloop {
                     },
                 ..
             } => match keycode {
-                KeyCode::Delete => {
+                KeyCode::Escape if state.is_pressed() => self.selection.clear(),
+                KeyCode::Delete if state.is_pressed() && !self.selection.is_empty() => {
+                    if self.policy.allow_delete {
+                        self.delete_selection()
+                    } else {
+                        self.shake(self.selection.indices())
+                    }
+                }
+                KeyCode::KeyC if state.is_pressed() && self.modifiers.control_key() => {
+                    self.copy_hovered(camera, screen)
+                }
+                KeyCode::KeyC if state.is_pressed() => {
+                    if self.policy.allow_paint {
+                        self.paint_selection()
+                    } else {
+                        self.shake(self.selection.indices())
+                    }
+                }
+                KeyCode::KeyL if state.is_pressed() => self.lift_selection(),
+                KeyCode::KeyQ if state.is_pressed() => self.rotate_selection(1),
+                KeyCode::KeyE if state.is_pressed() => self.rotate_selection(-1),
+                KeyCode::Delete if state.is_pressed() && self.policy.allow_delete => {
                     let target_chunk = Chunk { x: 0, y: 0 };
 
-                    if let Some(controller) = self.chunk_map.get_mut(&target_chunk) {
-                        controller.remove_instance(controller.instances.len() - 50, &self.queue);
+                    if let Some(controller) = self.chunk_map.get(&target_chunk) {
+                        if let Some(index) = controller.instances.len().checked_sub(50) {
+                            self.pending_removals.push((target_chunk, index));
+                        }
                     }
                 }
+                KeyCode::F5 if state.is_pressed() => {
+                    self.sequencer.start();
+                    log::info!("Story mode started");
+                }
+                KeyCode::KeyR if state.is_pressed() => {
+                    self.reset_section();
+                    log::info!("Section reset");
+                }
+                KeyCode::KeyJ if state.is_pressed() => {
+                    self.settings.jitter.enabled = !self.settings.jitter.enabled;
+                    log::info!("Jitter: {}", self.settings.jitter.enabled);
+                }
+                KeyCode::KeyP if state.is_pressed() => {
+                    self.tool = self.tool.next();
+                    log::info!("Tool: {:?}", self.tool);
+                }
+                KeyCode::F6 if state.is_pressed() => scene_io::request_export(),
+                #[cfg(not(target_arch = "wasm32"))]
+                KeyCode::F7 if state.is_pressed() => scene_io::request_import_exported(),
+                KeyCode::KeyH if state.is_pressed() => {
+                    self.settings.multi_hit = !self.settings.multi_hit;
+                    self.hits.clear();
+                    log::info!("Multi-hit: {}", self.settings.multi_hit);
+                }
+                KeyCode::KeyM if state.is_pressed() => {
+                    // State swaps the mesh on the next update
+                    self.settings.textured_mesh = !self.settings.textured_mesh;
+                    log::info!("Textured mesh: {}", self.settings.textured_mesh);
+                }
+                KeyCode::F8 if state.is_pressed() => {
+                    let captions = !self.announcer.captions();
+                    self.announcer.set_captions(captions);
+                    log::info!("Captions: {}", captions);
+                }
+                KeyCode::F9 if state.is_pressed() => {
+                    self.drift_snapshot = Some(DriftSnapshot::take(
+                        &self.chunk_map,
+                        &self.animation_handler,
+                    ));
+                    log::info!("Drift snapshot taken");
+                }
+                KeyCode::F10 if state.is_pressed() => self.report_drift(),
+                KeyCode::F4 if state.is_pressed() => {
+                    // Existing instances re-tint through the color smoothing
+                    self.settings.color_vision = self.settings.color_vision.next();
+                    log::info!("Color vision: {:?}", self.settings.color_vision);
+                }
+                KeyCode::F3 if state.is_pressed() => {
+                    self.settings.ssao_enabled = !self.settings.ssao_enabled;
+                    log::info!("Ambient occlusion: {}", self.settings.ssao_enabled);
+                }
+                KeyCode::F2 if state.is_pressed() => {
+                    self.settings.dof_enabled = !self.settings.dof_enabled;
+                    log::info!("Depth of field: {}", self.settings.dof_enabled);
+                }
+                KeyCode::KeyV if state.is_pressed() => {
+                    self.settings.motion_blur_enabled = !self.settings.motion_blur_enabled;
+                    log::info!("Motion blur: {}", self.settings.motion_blur_enabled);
+                }
+                // Keyboard navigation of the sections, Alt + 1-9 further down jumps to the nth
+                KeyCode::PageDown if state.is_pressed() => {
+                    self.go_to_section(self.current_section() + 1)
+                }
+                KeyCode::PageUp if state.is_pressed() => {
+                    self.go_to_section(self.current_section().saturating_sub(1))
+                }
+                KeyCode::Home if state.is_pressed() => self.go_to_section(0),
+                KeyCode::End if state.is_pressed() => self.go_to_section(PROGRESS_CUBES - 1),
+                KeyCode::BracketLeft if state.is_pressed() => self.palette.previous(),
+                KeyCode::BracketRight if state.is_pressed() => self.palette.next(),
                 KeyCode::Insert => match state {
                     winit::event::ElementState::Pressed => {
                         if (self.animation_handler.disabled) {
@@ -107,6 +721,11 @@ impl Gameloop {
                     }
                     _ => {}
                 },
+                code if state.is_pressed() && self.modifiers.alt_key() => {
+                    if let Some(index) = section_slot(*code) {
+                        self.go_to_section(index);
+                    }
+                }
                 _ => {}
             },
             WindowEvent::MouseInput {
@@ -115,15 +734,68 @@ impl Gameloop {
                 button,
             } => {
                 match button {
+                    winit::event::MouseButton::Left
+                        if state.is_pressed() && self.modifiers.control_key() =>
+                    {
+                        self.box_select = Some((self.cursor_position, self.cursor_position));
+                    }
+                    winit::event::MouseButton::Left
+                        if !state.is_pressed() && self.box_select.is_some() =>
+                    {
+                        if let Some((start, end)) = self.box_select.take() {
+                            let drag = (end.x - start.x).hypot(end.y - start.y);
+                            if drag < CLICK_DRAG_PIXELS {
+                                self.select_at_cursor(camera, screen);
+                            } else if self.policy.allow_delete {
+                                self.delete_in_rect(camera, screen, start, end);
+                            } else {
+                                self.shake_at_cursor(camera, screen);
+                            }
+                        }
+                    }
+                    winit::event::MouseButton::Left
+                        if self.tool == Tool::Stamp && state.is_pressed() =>
+                    {
+                        // Copying the template is always fine, placing copies may not be
+                        if self.stamp.is_none() || self.policy.allow_place {
+                            self.stamp_at_cursor(camera, screen);
+                        } else {
+                            self.shake_at_cursor(camera, screen);
+                        }
+                    }
+                    winit::event::MouseButton::Right
+                        if self.tool == Tool::Stamp && state.is_pressed() =>
+                    {
+                        self.stamp = None;
+                        log::info!("Stamp cleared");
+                    }
+                    winit::event::MouseButton::Left
+                        if self.tool == Tool::Delete && state.is_pressed() =>
+                    {
+                        if self.policy.allow_delete {
+                            self.hit_at_cursor(camera, screen);
+                        } else {
+                            self.shake_at_cursor(camera, screen);
+                        }
+                    }
+                    winit::event::MouseButton::Left
+                        if self.tool == Tool::Paint && !self.policy.allow_paint =>
+                    {
+                        self.painting = false;
+                        if state.is_pressed() {
+                            self.shake_at_cursor(camera, screen);
+                        }
+                    }
+                    winit::event::MouseButton::Left if self.tool == Tool::Paint => {
+                        self.painting = state.is_pressed();
+                        if self.painting {
+                            self.paint_at_cursor(camera, screen);
+                        }
+                    }
                     winit::event::MouseButton::Left => {
                         match state {
                             winit::event::ElementState::Pressed => {
-                                let test = camera.screen_to_world_ray(
-                                    self.cursor_position.x,
-                                    self.cursor_position.y,
-                                    screen.width as f32,
-                                    screen.height as f32,
-                                );
+                                let test = screen.cursor_ray(camera, self.cursor_position);
                                 println!("{:?}", test);
                                 // line_trace(&mut self.instance_controller2, camera, &self.queue, &self.device, test);
                                 let target_chunk = Chunk { x: 0, y: 0 };
@@ -138,6 +810,7 @@ impl Gameloop {
                                     line_trace_animate_hit(
                                         controller,
                                         &mut self.animation_handler,
+                                        self.settings.pick_policy,
                                         &self.queue,
                                         test,
                                     )
@@ -148,6 +821,24 @@ impl Gameloop {
                             _ => {}
                         }
                     }
+                    winit::event::MouseButton::Middle
+                        if state.is_pressed() && !self.policy.allow_explode =>
+                    {
+                        self.shake_at_cursor(camera, screen);
+                    }
+                    winit::event::MouseButton::Middle if state.is_pressed() => {
+                        let ray = screen.cursor_ray(camera, self.cursor_position);
+                        let target_chunk = Chunk { x: 0, y: 0 };
+                        if let Some(controller) = self.chunk_map.get_mut(&target_chunk) {
+                            line_trace_implode(
+                                controller,
+                                &mut self.animation_handler,
+                                &self.settings,
+                                &self.queue,
+                                ray,
+                            );
+                        }
+                    }
                     winit::event::MouseButton::Right => match state {
                         winit::event::ElementState::Pressed => {}
                         _ => {}
@@ -160,15 +851,896 @@ impl Gameloop {
                     _ => {}
                 }
             }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let step = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y * SCROLL_LINE_STEP,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 * SCROLL_PIXEL_STEP,
+                };
+                let previous = self.scroll_progress;
+                self.scroll_progress = (self.scroll_progress - step).clamp(0.0, 1.0);
+                self.scroll_snap.on_scroll(self.scroll_progress - previous);
+            }
             WindowEvent::CursorMoved {
                 device_id,
                 position,
             } => {
-                self.cursor_position = PhysicalPosition::new(position.x as f32, position.y as f32);
+                self.cursor_position = screen.to_surface(*position);
+                if self.painting {
+                    self.paint_at_cursor(camera, screen);
+                }
+                if let Some((_, end)) = &mut self.box_select {
+                    *end = self.cursor_position;
+                }
             }
+            WindowEvent::ModifiersChanged(modifiers) => self.modifiers = modifiers.state(),
             _ => {}
         }
     }
+    fn apply_cue(&mut self, cue: Cue) {
+        let target_chunk = Chunk { x: 0, y: 0 };
+        let grid_index = |x: u32, z: u32| (z * self.chunk_size.y + x) as usize;
+        match cue {
+            Cue::SetProgress(progress) => self.scroll_progress = progress.clamp(0.0, 1.0),
+            Cue::Lift { x, z } => {
+                let index = grid_index(x, z);
+                if let Some(controller) = self.chunk_map.get_mut(&target_chunk) {
+                    lift_at(controller, &mut self.animation_handler, index);
+                }
+            }
+            Cue::Implode { x, z } => {
+                let index = grid_index(x, z);
+                if let Some(controller) = self.chunk_map.get_mut(&target_chunk) {
+                    implode_at(
+                        controller,
+                        &mut self.animation_handler,
+                        &self.settings,
+                        &self.queue,
+                        index,
+                    );
+                }
+            }
+            Cue::EnableAnimations(true) => self.animation_handler.enable(),
+            Cue::EnableAnimations(false) => self.animation_handler.disable(),
+            Cue::Explode => self.explode_all(),
+            Cue::Reassemble => self.reset_section(),
+            Cue::AwaitAnimations => {}
+            Cue::ToggleMesh => self.settings.textured_mesh = !self.settings.textured_mesh,
+        }
+    }
+
+    // Turns the scene into a screensaver, cycling the configured sections or all of them
+    pub fn start_screensaver(&mut self) {
+        self.settings.enable_screensaver();
+        let last = (PROGRESS_CUBES - 1) as f32;
+        let progresses = if self.settings.screensaver_sections.is_empty() {
+            (0..PROGRESS_CUBES)
+                .map(|index| index as f32 / last)
+                .collect::<Vec<_>>()
+        } else {
+            self.settings
+                .screensaver_sections
+                .iter()
+                .map(|index| (*index as f32 / last).min(1.0))
+                .collect()
+        };
+        self.screensaver = Sequencer::new(screensaver_script(
+            &progresses,
+            self.settings.screensaver_period,
+        ));
+    }
+
+    // Every visible cube spirals back into place
+    fn play_easter_egg(&mut self) {
+        let controller = match self.chunk_map.get(&Chunk { x: 0, y: 0 }) {
+            Some(controller) => controller,
+            None => return,
+        };
+        let targets: Vec<(usize, Vector3<f32>)> = controller
+            .instances
+            .iter()
+            .enumerate()
+            .filter(|(_, instance)| instance.should_render)
+            .map(|(index, instance)| (index, instance.position))
+            .collect();
+        EffectPreset::SpiralIn.play(
+            &mut self.animation_handler,
+            &targets,
+            &EffectOptions::default(),
+        );
+        self.announcer.announce("You found the secret".to_string());
+        log::debug!("Konami code");
+    }
+
+    fn apply_idle_event(&mut self, event: IdleEvent) {
+        let target_chunk = Chunk { x: 0, y: 0 };
+        let controller = match self.chunk_map.get_mut(&target_chunk) {
+            Some(controller) => controller,
+            None => return,
+        };
+        match event {
+            IdleEvent::Wave => {
+                // Lift one row and let it fall back from one end to the other
+                let z = self.idle_events.rng.gen_range(0..self.chunk_size.y);
+                for x in 0..self.chunk_size.x {
+                    let index = (z * self.chunk_size.y + x) as usize;
+                    // Skips rows that weren't built yet
+                    if !controller
+                        .instances
+                        .get(index)
+                        .is_some_and(|instance| instance.should_render)
+                    {
+                        continue;
+                    }
+                    lift_at(controller, &mut self.animation_handler, index);
+                    self.animation_handler
+                        .set_rebound(index, 0.1 + x as f32 * 0.03);
+                }
+            }
+            IdleEvent::Implode => {
+                let visible: Vec<usize> = controller
+                    .instances
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, instance)| instance.should_render)
+                    .map(|(index, _)| index)
+                    .collect();
+                if visible.is_empty() {
+                    return;
+                }
+                let index = visible[self.idle_events.rng.gen_range(0..visible.len())];
+                implode_at(
+                    controller,
+                    &mut self.animation_handler,
+                    &self.settings,
+                    &self.queue,
+                    index,
+                );
+            }
+        }
+    }
+
+    fn paint_at_cursor(&mut self, camera: &Camera, screen: &ScreenMapper) {
+        let ray = screen.cursor_ray(camera, self.cursor_position);
+        let target_chunk = Chunk { x: 0, y: 0 };
+        if let Some(controller) = self.chunk_map.get_mut(&target_chunk) {
+            if let Some(index) = line_trace_index(
+                controller,
+                Some(&self.animation_handler),
+                self.settings.pick_policy,
+                ray,
+            ) {
+                let color = self.palette.color();
+                self.animation_handler.set_manual_color(index, color);
+                controller.instances[index].color = color;
+            }
+        }
+    }
+
+    // First click copies the hit instance, later clicks place copies against the hit face
+    fn stamp_at_cursor(&mut self, camera: &Camera, screen: &ScreenMapper) {
+        let ray = screen.cursor_ray(camera, self.cursor_position);
+        let target_chunk = Chunk { x: 0, y: 0 };
+        let controller = match self.chunk_map.get_mut(&target_chunk) {
+            Some(controller) => controller,
+            None => return,
+        };
+        let (index, normal) = match line_trace_hit(
+            controller,
+            Some(&self.animation_handler),
+            self.settings.pick_policy,
+            ray,
+        ) {
+            Some(hit) => hit,
+            None => return,
+        };
+        let template = match &self.stamp {
+            Some(template) => template.clone(),
+            None => {
+                self.stamp = Some(controller.instances[index].clone());
+                log::info!("Stamp copied");
+                return;
+            }
+        };
+        let base = controller.instances[index].position + normal;
+        let height = if self.modifiers.shift_key() {
+            self.settings.stamp_column_height.max(1)
+        } else {
+            1
+        };
+        let chunk_size = self.chunk_size;
+        for level in 0..height {
+            if controller.instances.len() >= self.capabilities.max_instances_hint as usize {
+                log::warn!("Instance limit reached, stamp skipped");
+                break;
+            }
+            let position = base + Vector3::unit_y() * level as f32;
+            // Cells rather than exact positions, animated cubes come to rest a few ulps off
+            let cell = (grid_cell(position, chunk_size), position.y.round());
+            let occupied = controller.instances.iter().any(|instance| {
+                instance.should_render
+                    && (
+                        grid_cell(instance.position, chunk_size),
+                        instance.position.y.round(),
+                    ) == cell
+            });
+            if occupied {
+                continue;
+            }
+            let instance = Instance {
+                position,
+                bounding: position + template.size,
+                should_render: true,
+                ..template.clone()
+            };
+            let new_index = controller.instances.len();
+            self.animation_handler.add_animation(&instance);
+            self.animation_handler
+                .set_manual_color(new_index, template.color);
+            controller.add_instance(instance, &self.queue, &self.device);
+        }
+    }
+
+    // Where the next stamp would be placed, for the ghost preview
+    pub fn stamp_preview(
+        &self,
+        camera: &Camera,
+        screen: &ScreenMapper,
+    ) -> Option<(Instance, Vector3<f32>)> {
+        if self.tool != Tool::Stamp {
+            return None;
+        }
+        let template = self.stamp.as_ref()?;
+        let ray = screen.cursor_ray(camera, self.cursor_position);
+        let controller = self.chunk_map.get(&Chunk { x: 0, y: 0 })?;
+        let (index, normal) = line_trace_hit(
+            controller,
+            Some(&self.animation_handler),
+            self.settings.pick_policy,
+            ray,
+        )?;
+        Some((
+            template.clone(),
+            controller.instances[index].position + normal,
+        ))
+    }
+
+    // Removes every visible instance whose center projects inside the screen rectangle
+    fn delete_in_rect(
+        &mut self,
+        camera: &Camera,
+        screen: &ScreenMapper,
+        start: PhysicalPosition<f32>,
+        end: PhysicalPosition<f32>,
+    ) {
+        let (min_x, max_x) = (start.x.min(end.x), start.x.max(end.x));
+        let (min_y, max_y) = (start.y.min(end.y), start.y.max(end.y));
+        let mut removed = 0;
+        for (chunk, controller) in &self.chunk_map {
+            for (index, instance) in controller.instances.iter().enumerate() {
+                if !instance.should_render {
+                    continue;
+                }
+                let center = instance.position + instance.size * (instance.scale / 2.0);
+                if let Some((x, y)) = camera.project_world_to_screen(
+                    center,
+                    screen.surface.width as f32,
+                    screen.surface.height as f32,
+                ) {
+                    if x >= min_x && x <= max_x && y >= min_y && y <= max_y {
+                        self.pending_removals.push((*chunk, index));
+                        removed += 1;
+                    }
+                }
+            }
+        }
+        log::info!("Removed {} instances", removed);
+    }
+
+    // Prints the cubes that moved most since the F9 snapshot
+    fn report_drift(&self) {
+        let snapshot = match &self.drift_snapshot {
+            Some(snapshot) => snapshot,
+            None => {
+                log::info!("No drift snapshot, take one with F9");
+                return;
+            }
+        };
+        let drifts = snapshot.diff(&self.chunk_map, &self.animation_handler, DRIFT_REPORT_LIMIT);
+        log::info!("{} drifted values", drifts.len());
+        for drift in drifts {
+            log::info!(
+                "chunk ({}, {}) #{} {}: {:?} ({:.5})",
+                drift.chunk.x,
+                drift.chunk.y,
+                drift.index,
+                if drift.start { "start" } else { "position" },
+                drift.offset,
+                drift.offset.magnitude()
+            );
+        }
+    }
+
+    // Shows the label of the hovered cube's group once the cursor rests, returns whether
+    // it is still waiting to
+    pub fn update_tooltip(&mut self, camera: &Camera, screen: &ScreenMapper, dt: f32) -> bool {
+        if !self.tooltip.update(self.cursor_position, dt) {
+            return self.tooltip.is_waiting();
+        }
+        if let Some(label) = self.hovered_label(camera, screen) {
+            let css_scale = screen.window.width as f32
+                / screen.surface.width.max(1) as f32
+                / screen.scale_factor as f32;
+            self.tooltip.show(&label, self.cursor_position, css_scale);
+        }
+        false
+    }
+
+    fn hovered_label(&self, camera: &Camera, screen: &ScreenMapper) -> Option<String> {
+        let labels = &self.content.section(self.section_tracker.active()?)?.labels;
+        if labels.is_empty() {
+            return None;
+        }
+        let origin = Chunk { x: 0, y: 0 };
+        let index = line_trace_index(
+            self.chunk_map.get(&origin)?,
+            Some(&self.animation_handler),
+            self.settings.pick_policy,
+            screen.cursor_ray(camera, self.cursor_position),
+        )?;
+        // Groups of the cubes as built, painting and the wave don't move a cube between them
+        let group = *color_groups(self.canonical.get(&origin)?).get(index)?;
+        labels.get(group).cloned()
+    }
+
+    // Copies where the cube under the cursor is, for tuning poses and writing the manifest
+    fn copy_hovered(&self, camera: &Camera, screen: &ScreenMapper) {
+        let ray = screen.cursor_ray(camera, self.cursor_position);
+        let controller = match self.chunk_map.get(&Chunk { x: 0, y: 0 }) {
+            Some(controller) => controller,
+            None => return,
+        };
+        let index = match line_trace_index(
+            controller,
+            Some(&self.animation_handler),
+            self.settings.pick_policy,
+            ray,
+        ) {
+            Some(index) => index,
+            None => return,
+        };
+        let position = controller.instances[index].position;
+        let (chunk, cell) = grid_cell(position, self.chunk_size);
+        let section = self.section_tracker.active().map(|section| {
+            let title = self
+                .content
+                .section(section)
+                .map_or("", |info| info.title.as_str());
+            (section, title)
+        });
+        copy_text(&cube_info(position, chunk, cell, section));
+    }
+
+    // Answers a denied interaction by shaking the cube under the cursor
+    fn shake_at_cursor(&mut self, camera: &Camera, screen: &ScreenMapper) {
+        let ray = screen.cursor_ray(camera, self.cursor_position);
+        let index = match self
+            .chunk_map
+            .get(&Chunk { x: 0, y: 0 })
+            .and_then(|controller| {
+                line_trace_index(
+                    controller,
+                    Some(&self.animation_handler),
+                    self.settings.pick_policy,
+                    ray,
+                )
+            }) {
+            Some(index) => index,
+            None => return,
+        };
+        self.shake(vec![index]);
+    }
+
+    // Removes the cube under the cursor. In multi-hit mode the first hits only crack it,
+    // darkening it a step and shaking it
+    fn hit_at_cursor(&mut self, camera: &Camera, screen: &ScreenMapper) {
+        let ray = screen.cursor_ray(camera, self.cursor_position);
+        let controller = match self.chunk_map.get_mut(&Chunk { x: 0, y: 0 }) {
+            Some(controller) => controller,
+            None => return,
+        };
+        let index = match line_trace_index(
+            controller,
+            Some(&self.animation_handler),
+            self.settings.pick_policy,
+            ray,
+        ) {
+            Some(index) => index,
+            None => return,
+        };
+        let hits = self.hits.entry(index).or_insert(0);
+        *hits += 1;
+        if !self.settings.multi_hit || *hits >= CUBE_HIT_POINTS {
+            self.hits.remove(&index);
+            self.pending_removals.push((Chunk { x: 0, y: 0 }, index));
+            return;
+        }
+        let cracked = controller.instances[index].color * CRACK_DARKEN;
+        self.animation_handler
+            .set_colors_bulk([(index, cracked)], CRACK_FADE);
+        self.shake(vec![index]);
+    }
+
+    fn shake(&mut self, indices: Vec<usize>) {
+        if let Some(controller) = self.chunk_map.get_mut(&Chunk { x: 0, y: 0 }) {
+            for index in indices {
+                shake_at(controller, &mut self.animation_handler, index);
+            }
+        }
+    }
+
+    fn select_at_cursor(&mut self, camera: &Camera, screen: &ScreenMapper) {
+        let ray = screen.cursor_ray(camera, self.cursor_position);
+        let controller = match self.chunk_map.get(&Chunk { x: 0, y: 0 }) {
+            Some(controller) => controller,
+            None => return,
+        };
+        if let Some(index) = line_trace_index(
+            controller,
+            Some(&self.animation_handler),
+            self.settings.pick_policy,
+            ray,
+        ) {
+            self.selection.toggle(index);
+            log::info!("Selected {} cubes", self.selection.len());
+        }
+    }
+
+    // Adds every visible cube of chunk (0, 0) whose bounds overlap the world space box
+    pub fn select_region(&mut self, min: Vector3<f32>, max: Vector3<f32>) -> usize {
+        let controller = match self.chunk_map.get(&Chunk { x: 0, y: 0 }) {
+            Some(controller) => controller,
+            None => return 0,
+        };
+        let mut added = 0;
+        for (index, instance) in controller.instances.iter().enumerate() {
+            let (lower, upper) = instance.bounds();
+            let overlaps = overlaps(lower, upper, min, max);
+            if instance.should_render && overlaps && !self.selection.contains(index) {
+                self.selection.insert(index);
+                added += 1;
+            }
+        }
+        added
+    }
+
+    // Makes cubes of chunk (0, 0) signature cubes that keep their place and color through
+    // every section, effect and idle event
+    pub fn pin_instances(&mut self, indices: &[usize]) {
+        let controller = match self.chunk_map.get(&Chunk { x: 0, y: 0 }) {
+            Some(controller) => controller,
+            None => return,
+        };
+        for index in indices {
+            if let Some(instance) = controller.instances.get(*index) {
+                self.animation_handler.pin(*index, instance.color);
+            }
+        }
+    }
+
+    // Pins every cube of chunk (0, 0) whose bounds overlap the world space box
+    pub fn pin_region(&mut self, min: Vector3<f32>, max: Vector3<f32>) -> usize {
+        let indices = match self.chunk_map.get(&Chunk { x: 0, y: 0 }) {
+            Some(controller) => controller
+                .instances
+                .iter()
+                .enumerate()
+                .filter(|(_, instance)| {
+                    let (lower, upper) = instance.bounds();
+                    overlaps(lower, upper, min, max)
+                })
+                .map(|(index, _)| index)
+                .collect::<Vec<_>>(),
+            None => return 0,
+        };
+        self.pin_instances(&indices);
+        indices.len()
+    }
+
+    pub fn delete_selection(&mut self) {
+        let chunk = Chunk { x: 0, y: 0 };
+        self.pending_removals.extend(
+            self.selection
+                .indices()
+                .into_iter()
+                .map(|index| (chunk, index)),
+        );
+        log::info!("Removed {} instances", self.selection.len());
+        self.selection.clear();
+    }
+
+    // Hides the cubes queued by input since the last update, each touched chunk is written
+    // out once. Returns whether anything was removed
+    fn apply_pending_removals(&mut self) -> bool {
+        if self.pending_removals.is_empty() {
+            return false;
+        }
+        let mut touched = HashSet::new();
+        for (chunk, index) in std::mem::take(&mut self.pending_removals) {
+            if let Some(instance) = self
+                .chunk_map
+                .get_mut(&chunk)
+                .and_then(|controller| controller.instances.get_mut(index))
+            {
+                instance.should_render = false;
+                touched.insert(chunk);
+            }
+        }
+        for chunk in &touched {
+            if let Some(controller) = self.chunk_map.get_mut(chunk) {
+                controller.update_buffer(&self.queue);
+            }
+        }
+        !touched.is_empty()
+    }
+
+    pub fn paint_selection(&mut self) {
+        let color = self.palette.color();
+        let colors = self
+            .selection
+            .indices()
+            .into_iter()
+            .map(|index| (index, color));
+        self.animation_handler.set_colors_bulk(colors, PAINT_FADE);
+    }
+
+    // Raises every selected cube by one unit, all in the same step
+    pub fn lift_selection(&mut self) {
+        let moves = match self.chunk_map.get(&Chunk { x: 0, y: 0 }) {
+            Some(controller) => self
+                .selection
+                .indices()
+                .into_iter()
+                .filter_map(|index| {
+                    let position = controller.instances.get(index)?.position;
+                    Some((index, position + Vector3::unit_y()))
+                })
+                .collect::<Vec<_>>(),
+            None => return,
+        };
+        self.move_instances(&moves);
+    }
+
+    // Turns the selection by quarter turns around the y axis through its centroid,
+    // the pivot is snapped to the grid so cubes land on grid positions
+    pub fn rotate_selection(&mut self, quarter_turns: i32) {
+        let controller = match self.chunk_map.get(&Chunk { x: 0, y: 0 }) {
+            Some(controller) => controller,
+            None => return,
+        };
+        let pivot = match self.selection.centroid(&controller.instances) {
+            Some(centroid) => Vector3::new(centroid.x.round(), 0.0, centroid.z.round()),
+            None => return,
+        };
+        let rotation = Quaternion::from_angle_y(Deg(90.0 * quarter_turns as f32));
+        let moves = self
+            .selection
+            .indices()
+            .into_iter()
+            .filter_map(|index| {
+                let position = controller.instances.get(index)?.position;
+                let offset = rotation.rotate_vector(position - pivot);
+                let rotated = pivot + offset;
+                Some((
+                    index,
+                    Vector3::new(rotated.x.round(), position.y, rotated.z.round()),
+                ))
+            })
+            .collect::<Vec<_>>();
+        self.move_instances(&moves);
+    }
+
+    // Animates instances of chunk (0, 0) to new positions, or moves them right away
+    // while animations are disabled
+    fn move_instances(&mut self, moves: &[(usize, Vector3<f32>)]) {
+        let controller = match self.chunk_map.get_mut(&Chunk { x: 0, y: 0 }) {
+            Some(controller) => controller,
+            None => return,
+        };
+        for (index, target) in moves {
+            let instance = match controller.instances.get_mut(*index) {
+                Some(instance) => instance,
+                None => continue,
+            };
+            if self.animation_handler.is_pinned(*index) {
+                continue;
+            }
+            if self.animation_handler.disabled {
+                if let Some(animation) = self.animation_handler.movement_list.get_mut(*index) {
+                    animation.current_pos = *target;
+                }
+                instance.position = *target;
+                instance.bounding = instance.size + *target;
+            } else {
+                self.animation_handler
+                    .play(*index, instance.position, *target, 0.0);
+            }
+        }
+        controller.update_buffer(&self.queue);
+    }
+
+    // Restarts every random sequence from `seed`, same seed and input give the same run
+    pub fn reseed(&mut self, seed: u64) {
+        self.settings.seed = seed;
+        self.idle_events.reseed(seed);
+    }
+
+    // Cheap enough for every frame, only walks the instances to count the visible ones
+    pub fn status(&self, camera: &Camera) -> SceneStatus {
+        SceneStatus {
+            section: self.section_tracker.active(),
+            scroll_progress: self.scroll_progress,
+            transition_active: self.animation_handler.is_active() || self.scroll_snap.is_snapping(),
+            transition_progress: self.animation_handler.progress(),
+            camera_eye: camera.eye.into(),
+            camera_target: camera.target.into(),
+            camera_fovy: camera.fovy,
+            story_mode: self.sequencer.active,
+            visible_instances: self
+                .chunk_map
+                .values()
+                .flat_map(|controller| controller.instances.iter())
+                .filter(|instance| instance.should_render)
+                .count(),
+            active_animations: self.animation_handler.animating_count(),
+            simulated_time: None,
+            allocations: None,
+            gpu_memory: gpu_memory_usage(),
+        }
+    }
+
+    // The main scene pass, every chunk into a pass State has set up
+    pub fn render(&self, render_pass: &mut wgpu::RenderPass) {
+        let slice = self
+            .capabilities
+            .draw_slice(self.settings.draw_slice_instances);
+        let first_instance = self.capabilities.first_instance;
+        for instance_controller in self.chunk_map.values() {
+            instance_controller.render(render_pass, slice, first_instance);
+        }
+        self.detail_cubes.render(render_pass, slice, first_instance);
+    }
+
+    // Streams the camera, active section and every instance as JSON
+    pub fn export_scene_json(&self, camera: &Camera, out: &mut impl Write) -> std::io::Result<()> {
+        scene_io::write_scene(self, camera, out)
+    }
+
+    // Restores instance positions, colors and visibility from an export
+    pub fn import_scene_json(&mut self, json: &str) -> Result<usize, String> {
+        self.skip_reveal();
+        self.hits.clear();
+        scene_io::read_scene(self, json)
+    }
+
+    // Every section shares the grid, so its canonical state is the grid as it was built.
+    // Undoes deletes, paint, stamps and implosions, deleted cubes assemble back from below
+    pub fn reset_section(&mut self) {
+        self.skip_reveal();
+        self.hits.clear();
+        let origin = Chunk { x: 0, y: 0 };
+        // Seeded so a reset always fans out the same way
+        let mut rng = StdRng::seed_from_u64(self.settings.seed);
+        for (chunk, controller) in self.chunk_map.iter_mut() {
+            let canonical = match self.canonical.get(chunk) {
+                Some(canonical) => canonical,
+                None => continue,
+            };
+            let animate = *chunk == origin && !self.animation_handler.disabled;
+            let mut restored = Vec::new();
+            for (index, instance) in controller.instances.iter_mut().enumerate() {
+                let clean = match canonical.get(index) {
+                    Some(clean) => clean,
+                    // Added after the build, e.g. by the stamp tool
+                    None => {
+                        instance.should_render = false;
+                        continue;
+                    }
+                };
+                let was_hidden = !instance.should_render;
+                instance.color = clean.color;
+                instance.should_render = clean.should_render;
+                if *chunk == origin {
+                    self.animation_handler.clear_manual_color(index);
+                }
+                if animate && clean.should_render && was_hidden {
+                    restored.push((index, clean.position));
+                } else if animate && instance.position != clean.position {
+                    // Arcs of slightly different heights keep cubes crossing the grid apart
+                    self.animation_handler
+                        .play(index, instance.position, clean.position, 0.0);
+                    let height = rng.gen_range(RESET_ARC_HEIGHT.0..RESET_ARC_HEIGHT.1);
+                    self.animation_handler
+                        .set_trajectory(index, TrajectoryMode::Arc { height });
+                } else {
+                    instance.position = clean.position;
+                    instance.bounding = clean.bounding;
+                }
+            }
+            if animate {
+                EffectPreset::AssembleFromGround.play(
+                    &mut self.animation_handler,
+                    &restored,
+                    &EffectOptions::default(),
+                );
+            }
+            controller.update_buffer(&self.queue);
+        }
+    }
+
+    // Swaps in newer section content, the current section is reported again
+    pub fn set_content(&mut self, content: Manifest) {
+        // The old manifest's signature goes with it
+        for index in 0..self.animation_handler.movement_list.len() {
+            self.animation_handler.unpin(index);
+        }
+        for (min, max) in &content.pinned {
+            self.pin_region(*min, *max);
+        }
+        self.content = content;
+        self.section_tracker = SectionTracker::default();
+    }
+
+    // Appends a batch from the instance builder, keeping the animations of chunk (0, 0) in step
+    pub fn install_instances(&mut self, chunk: Chunk, mut instances: Vec<Instance>) {
+        self.dirty = true;
+        self.canonical
+            .entry(chunk)
+            .or_default()
+            .extend(instances.iter().cloned());
+        if let (Some(reveal), Some(controller)) = (&mut self.reveal, self.chunk_map.get(&chunk)) {
+            let first = controller.instances.len();
+            for (n, instance) in instances.iter_mut().enumerate() {
+                if instance.should_render {
+                    instance.should_render = false;
+                    reveal.hide(chunk, first + n, instance.position);
+                }
+            }
+        }
+        if chunk == (Chunk { x: 0, y: 0 }) {
+            let first = self.animation_handler.movement_list.len();
+            for (n, instance) in instances.iter().enumerate() {
+                self.animation_handler.add_animation(instance);
+                if self.content.pinned.iter().any(|(min, max)| {
+                    let (lower, upper) = instance.bounds();
+                    overlaps(lower, upper, *min, *max)
+                }) {
+                    self.animation_handler.pin(first + n, instance.color);
+                }
+            }
+            if let Some(effect) = self.settings.entrance_effect {
+                let targets = instances
+                    .iter()
+                    .enumerate()
+                    .map(|(n, instance)| (first + n, instance.position))
+                    .collect::<Vec<_>>();
+                effect.play(
+                    &mut self.animation_handler,
+                    &targets,
+                    &EffectOptions::default(),
+                );
+            }
+        }
+        if let Some(controller) = self.chunk_map.get_mut(&chunk) {
+            controller.add_instances(instances, &self.queue, &self.device);
+        }
+    }
+
+    // Starts the first load reveal once the loading screen is gone and shows the cubes its
+    // ring reached, returns whether it is still running
+    pub fn update_reveal(&mut self, dt: f32, loading: bool) -> bool {
+        let reveal = match &mut self.reveal {
+            Some(reveal) => reveal,
+            None => return false,
+        };
+        if !loading {
+            reveal.start(self.scroll_progress);
+        }
+        let reached = reveal.update(dt, self.scroll_progress);
+        let running = reveal.is_running();
+        if !reveal.is_pending() {
+            self.reveal = None;
+        }
+        self.show_revealed(reached, true);
+        running
+    }
+
+    // Shows every cube the reveal still holds back at once, so input isn't kept waiting
+    pub fn skip_reveal(&mut self) {
+        if let Some(mut reveal) = self.reveal.take() {
+            let hidden = reveal.finish();
+            self.show_revealed(hidden, false);
+        }
+    }
+
+    fn show_revealed(&mut self, cubes: Vec<(Chunk, usize)>, rise: bool) {
+        let origin = Chunk { x: 0, y: 0 };
+        for (chunk, index) in cubes {
+            let controller = match self.chunk_map.get_mut(&chunk) {
+                Some(controller) => controller,
+                None => continue,
+            };
+            if let Some(instance) = controller.instances.get_mut(index) {
+                instance.should_render = true;
+                // Only the first chunk has animations
+                if rise && chunk == origin {
+                    let start = instance.position - Vector3::unit_y() * REVEAL_RISE;
+                    self.animation_handler
+                        .play(index, start, instance.position, 0.0);
+                }
+            }
+            self.uploads.touch(chunk, controller);
+            self.dirty = true;
+        }
+    }
+
+    // Switches every chunk to its spare mesh, instances and animations are left alone
+    pub fn swap_meshes(&mut self, mut mesh_buffer: impl FnMut() -> (MeshBuffer, Renderer)) {
+        for instance_controller in self.chunk_map.values_mut() {
+            instance_controller.swap_mesh(&mut mesh_buffer);
+        }
+        self.detail_cubes.swap_mesh(&mut mesh_buffer);
+        self.dirty = true;
+    }
+
+    // Pins `group` to the screen. Its instances are in group units around the group's center,
+    // which sits at `screen_anchor` in normalized device coordinates, `depth` of the way from
+    // the near to the far plane, one unit taking up `scale` of the view height
+    pub fn attach_group_to_camera(
+        &mut self,
+        group: Vec<Instance>,
+        screen_anchor: Vector2<f32>,
+        depth: f32,
+        scale: f32,
+    ) -> &mut CameraAttachment {
+        self.camera_attachments
+            .push(CameraAttachment::new(group, screen_anchor, depth, scale));
+        self.camera_attachments.last_mut().unwrap()
+    }
+
+    // Every camera attached cube placed in the world for `camera`
+    pub fn attached_instances(&self, camera: &Camera) -> Vec<Instance> {
+        self.camera_attachments
+            .iter()
+            .flat_map(|attachment| attachment.world_instances(camera))
+            .collect()
+    }
+
+    // Keeps the previous transforms of every chunk while motion blur needs their velocities,
+    // chunks the builder delivers later pick it up on the next call
+    pub fn set_track_previous(&mut self, track: bool) {
+        for instance_controller in self.chunk_map.values_mut() {
+            instance_controller.set_track_previous(track, &self.queue, &self.device);
+        }
+    }
+
+    pub fn recreate_gpu_resources(
+        &mut self,
+        device: Arc<wgpu::Device>,
+        queue: Arc<wgpu::Queue>,
+        mut mesh_buffer: impl FnMut() -> (MeshBuffer, Renderer),
+    ) {
+        for instance_controller in self.chunk_map.values_mut() {
+            let (mb, renderer) = mesh_buffer();
+            instance_controller.recreate_gpu_resources(mb, renderer, &device, &queue);
+        }
+        let (mb, renderer) = mesh_buffer();
+        self.detail_cubes
+            .recreate_gpu_resources(mb, renderer, &device, &queue);
+        self.device = device;
+        self.queue = queue;
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         name: String,
         cursor_position: PhysicalPosition<f32>,
@@ -176,13 +1748,30 @@ impl Gameloop {
         queue: Arc<wgpu::Queue>,
         chunk_size: Vector2<u32>,
         chunk_map: HashMap<Chunk, InstanceController>,
+        detail_cubes: InstanceController,
+        capabilities: Capabilities,
     ) -> Self {
         // Create a merged AnimationHandler based on all instances in chunk_map
         let instance_controller = &chunk_map.get(&Chunk { x: 0, y: 0 }).unwrap();
 
-        let animation_handler = AnimationHandler::new(&instance_controller);
+        let mut animation_handler = AnimationHandler::new(&instance_controller);
 
-        Gameloop {
+        let settings = Settings::from_url();
+        animation_handler.grid_snap = settings.grid_snap_epsilon;
+        let idle_events = IdleEventScheduler::new(settings.seed);
+        let uploads = UploadScheduler::new(settings.upload_budget_bytes);
+        let mut sequencer = Sequencer::new(story_script());
+        if story_requested() {
+            sequencer.start();
+        }
+
+        let captions = settings.captions;
+        // An entrance effect already animates the cubes in, and reduced motion skips both
+        let reveal = (settings.first_load_reveal
+            && !settings.reduced_motion
+            && settings.entrance_effect.is_none())
+        .then(|| GridReveal::new(settings.reveal_duration));
+        let mut game_loop = Gameloop {
             name,
             cursor_position,
             device,
@@ -192,6 +1781,712 @@ impl Gameloop {
 
             chunk_size,
             animation_handler,
+            scroll_progress: 0.0,
+            scroll_snap: ScrollSnap::default(),
+            section_tracker: SectionTracker::default(),
+            policy: InteractionPolicy::default(),
+            announcer: Announcer::new(captions),
+            drift_snapshot: None,
+            konami: SequenceDetector::new(&KONAMI_CODE, KONAMI_TIMEOUT),
+            tooltip: Tooltip::default(),
+            reveal,
+            hits: HashMap::new(),
+            pending_removals: Vec::new(),
+            finale: Sequencer::new(finale_script()),
+            finale_played: false,
+            screensaver: Sequencer::new(Vec::new()),
+            camera_attachments: Vec::new(),
+            detail: DetailRefiner::default(),
+            detail_cubes,
+            content: Manifest::embedded(),
+            jitter_amount: if settings.jitter.enabled { 1.0 } else { 0.0 },
+            canonical: HashMap::new(),
+            tool: Tool::Animate,
+            palette: Palette::from_theme(),
+            painting: false,
+            settings,
+            sequencer,
+            idle_events,
+            modifiers: ModifiersState::empty(),
+            box_select: None,
+            stamp: None,
+            selection: Selection::default(),
+            dirty: true,
+            uploads,
+            focus: Chunk { x: 0, y: 0 },
+            capabilities,
+        };
+        for (min, max) in game_loop.content.pinned.clone() {
+            game_loop.pin_region(min, max);
+        }
+        if game_loop.settings.hud_logo {
+            let spin = if game_loop.settings.reduced_motion {
+                0.0
+            } else {
+                LOGO_SPIN
+            };
+            game_loop
+                .attach_group_to_camera(cube_logo(LOGO_SIZE), LOGO_ANCHOR, 0.5, LOGO_SCALE)
+                .spin = spin;
+        }
+        game_loop
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use cgmath::EuclideanSpace;
+    use winit::event::{DeviceId, ElementState, MouseButton};
+
+    use super::*;
+    use crate::{
+        core::{content::parse_manifest, section::SectionInfo, state, test_support},
+        entity::entity::make_cube_textured,
+    };
+
+    const FRAME: Duration = Duration::from_millis(16);
+
+    fn run(game_loop: &mut Gameloop, seconds: f32) {
+        for _ in 0..(seconds / FRAME.as_secs_f32()).ceil() as usize {
+            game_loop.update(FRAME);
+        }
+    }
+
+    #[test]
+    fn story_mode_ends_on_the_last_section() {
+        let mut game_loop = test_support::game_loop(test_support::grid());
+        game_loop.sequencer.start();
+        run(&mut game_loop, 20.0);
+        assert!(!game_loop.sequencer.active);
+        assert_eq!(game_loop.scroll_progress, 1.0);
+        assert!(!game_loop.animation_handler.disabled);
+    }
+
+    #[test]
+    fn swapping_meshes_mid_transition_keeps_the_animations_going() {
+        let mut swapped = test_support::game_loop(test_support::grid());
+        let mut untouched = test_support::game_loop(test_support::grid());
+        for game_loop in [&mut swapped, &mut untouched].iter_mut() {
+            game_loop.go_to_section(2);
+            run(game_loop, 0.5);
+        }
+        let layout = swapped
+            .device
+            .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+                label: Some("camera_bind_group_layout"),
+            });
+        let shader = swapped
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("Shader"),
+                source: wgpu::ShaderSource::Wgsl(state::SHADER_SOURCE.into()),
+            });
+        let (device, queue) = (swapped.device.clone(), swapped.queue.clone());
+        let mut built = 0;
+        swapped.swap_meshes(|| {
+            built += 1;
+            make_cube_textured([1.0, 1.0, 1.0], None).get_mesh_buffer(
+                &device,
+                &shader,
+                wgpu::TextureFormat::Bgra8UnormSrgb,
+                &queue,
+                layout.clone(),
+            )
+        });
+        assert!(swapped
+            .chunk_map
+            .values()
+            .all(InstanceController::is_textured));
+        assert!(swapped.detail_cubes.is_textured());
+
+        run(&mut swapped, 1.0);
+        run(&mut untouched, 1.0);
+        assert_eq!(visible(&swapped), visible(&untouched));
+
+        // Back again with the mesh kept from the first swap
+        swapped.swap_meshes(|| unreachable!());
+        assert!(!swapped
+            .chunk_map
+            .values()
+            .any(InstanceController::is_textured));
+        // One spare per controller, the chunk's and the detail cubes'
+        assert_eq!(built, swapped.chunk_map.len() + 1);
+        run(&mut swapped, 2.0);
+        run(&mut untouched, 2.0);
+        assert_eq!(visible(&swapped), visible(&untouched));
+        assert_eq!(swapped.section_tracker.active(), Some(2));
+    }
+
+    #[test]
+    fn screensaver_cycles_its_sections_lap_after_lap() {
+        let mut game_loop = test_support::game_loop(test_support::grid());
+        game_loop.settings.screensaver_sections = vec![2, 4];
+        game_loop.settings.screensaver_period = 1.0;
+        game_loop.start_screensaver();
+        let textured = game_loop.settings.textured_mesh;
+        let last = (PROGRESS_CUBES - 1) as f32;
+
+        run(&mut game_loop, 0.5);
+        assert_eq!(game_loop.scroll_progress, 2.0 / last);
+        run(&mut game_loop, 1.0);
+        assert_eq!(game_loop.scroll_progress, 4.0 / last);
+        run(&mut game_loop, 1.0);
+        assert_ne!(game_loop.settings.textured_mesh, textured);
+        // The next lap starts over on the first section
+        run(&mut game_loop, 0.1);
+        assert_eq!(game_loop.scroll_progress, 2.0 / last);
+        assert!(game_loop.screensaver.active);
+    }
+
+    #[test]
+    fn alt_digits_pick_the_nth_section() {
+        assert_eq!(section_slot(KeyCode::Digit1), Some(0));
+        assert_eq!(section_slot(KeyCode::Digit5), Some(4));
+        assert_eq!(section_slot(KeyCode::Digit9), Some(8));
+        assert_eq!(section_slot(KeyCode::Digit0), None);
+        assert_eq!(section_slot(KeyCode::KeyA), None);
+    }
+
+    #[test]
+    fn go_to_section_eases_onto_the_section_and_activates_it() {
+        let mut game_loop = test_support::game_loop(test_support::grid());
+        game_loop.go_to_section(3);
+        // PageDown and PageUp step from where a running jump is headed
+        assert_eq!(game_loop.current_section(), 3);
+        assert_eq!(game_loop.scroll_progress, 0.0);
+        run(&mut game_loop, 3.0);
+        assert_eq!(game_loop.section_tracker.active(), Some(3));
+        assert_eq!(game_loop.scroll_progress, 3.0 / (PROGRESS_CUBES - 1) as f32);
+
+        // Past the last section clamps onto it
+        game_loop.go_to_section(PROGRESS_CUBES + 4);
+        run(&mut game_loop, 3.0);
+        assert_eq!(game_loop.section_tracker.active(), Some(PROGRESS_CUBES - 1));
+        assert_eq!(game_loop.scroll_progress, 1.0);
+
+        // Reduced motion lands right away
+        game_loop.settings.reduced_motion = true;
+        game_loop.go_to_section(1);
+        assert_eq!(game_loop.scroll_progress, 1.0 / (PROGRESS_CUBES - 1) as f32);
+        run(&mut game_loop, 0.1);
+        assert_eq!(game_loop.section_tracker.active(), Some(1));
+    }
+
+    #[test]
+    fn imploded_cubes_return_to_the_grid_after_a_transition() {
+        let mut game_loop = test_support::game_loop(test_support::grid());
+        let origin = Chunk { x: 0, y: 0 };
+        let snapshot = DriftSnapshot::take(&game_loop.chunk_map, &game_loop.animation_handler);
+        let drift = |game_loop: &Gameloop| {
+            snapshot
+                .diff(
+                    &game_loop.chunk_map,
+                    &game_loop.animation_handler,
+                    usize::MAX,
+                )
+                .iter()
+                .filter(|drift| !drift.start)
+                .map(|drift| drift.offset.magnitude())
+                .fold(0.0, f32::max)
+        };
+        let size = test_support::CHUNK_SIZE;
+        let middle = (size.y / 2 * size.y + size.x / 2) as usize;
+        let hit = (middle..)
+            .find(|index| game_loop.chunk_map[&origin].instances[*index].should_render)
+            .unwrap();
+
+        implode_at(
+            game_loop.chunk_map.get_mut(&origin).unwrap(),
+            &mut game_loop.animation_handler,
+            &game_loop.settings,
+            &game_loop.queue,
+            hit,
+        );
+        run(&mut game_loop, 0.8);
+        assert!(drift(&game_loop) > 0.5);
+        // Pulled in, held, and back out again
+        run(&mut game_loop, 3.0);
+
+        game_loop.go_to_section(1);
+        run(&mut game_loop, 3.0);
+        game_loop.go_to_section(0);
+        run(&mut game_loop, 3.0);
+        assert!(drift(&game_loop) < game_loop.settings.grid_snap_epsilon.unwrap());
+    }
+
+    fn visible(game_loop: &Gameloop) -> Vec<(Vector3<f32>, Vector3<f32>)> {
+        game_loop.chunk_map[&Chunk { x: 0, y: 0 }]
+            .instances
+            .iter()
+            .filter(|instance| instance.should_render)
+            .map(|instance| (instance.position, instance.color))
+            .collect()
+    }
+
+    #[test]
+    fn stamps_stack_up_and_reset_removes_them() {
+        let mut game_loop = test_support::game_loop(test_support::grid());
+        let before = visible(&game_loop);
+        let base = game_loop.chunk_map[&Chunk { x: 0, y: 0 }]
+            .instances
+            .iter()
+            .find(|instance| instance.should_render)
+            .unwrap()
+            .position;
+        let camera =
+            test_support::camera_above(Point3::from_vec(base + Vector3::new(0.5, 0.5, 0.5)));
+        let screen = test_support::screen();
+        game_loop.cursor_position = test_support::screen_center();
+
+        // The first click copies the cube, every later one lands on top of the last stamp
+        game_loop.stamp_at_cursor(&camera, &screen);
+        for _ in 0..10 {
+            game_loop.stamp_at_cursor(&camera, &screen);
+        }
+        let stacked = visible(&game_loop);
+        assert_eq!(stacked.len(), before.len() + 10);
+        for level in 1..=10 {
+            let position = base + Vector3::unit_y() * level as f32;
+            assert_eq!(
+                stacked.iter().filter(|(at, _)| *at == position).count(),
+                1,
+                "level {}",
+                level
+            );
+        }
+
+        game_loop.reset_section();
+        assert_eq!(visible(&game_loop), before);
+    }
+
+    #[test]
+    fn stamps_skip_cells_held_by_slightly_drifted_cubes() {
+        // The top of the column already holds a cube that came to rest a little off its cell
+        let mut game_loop = test_support::game_loop(vec![
+            test_support::cube(Vector3::new(0.0, 0.0, 0.0)),
+            test_support::cube(Vector3::new(1.0 + 1e-4, 2.0 - 1e-4, 0.0)),
+        ]);
+        // Side on, so the cursor ray hits the +x face of the first cube and misses the other
+        let camera = Camera {
+            eye: Point3::new(10.0, 0.5, 0.5),
+            target: Point3::new(0.5, 0.5, 0.5),
+            up: Vector3::unit_y(),
+            zfar: 100.0,
+            ..test_support::camera_above(Point3::new(0.0, 0.0, 0.0))
+        };
+        let screen = test_support::screen();
+        game_loop.cursor_position = test_support::screen_center();
+        game_loop.settings.stamp_column_height = 3;
+        game_loop.modifiers = ModifiersState::SHIFT;
+
+        game_loop.stamp_at_cursor(&camera, &screen);
+        game_loop.stamp_at_cursor(&camera, &screen);
+        let cubes = visible(&game_loop);
+        assert_eq!(cubes.len(), 4);
+        for level in 0..3 {
+            let cell = Vector3::new(1.0, level as f32, 0.0);
+            assert_eq!(
+                cubes
+                    .iter()
+                    .filter(|(at, _)| (at - cell).magnitude() < 1e-3)
+                    .count(),
+                1,
+                "level {}",
+                level
+            );
         }
     }
+
+    // Every transform and color of chunk (0, 0) as uploaded
+    fn snapshot(game_loop: &Gameloop) -> Vec<u8> {
+        let raw = game_loop.chunk_map[&Chunk { x: 0, y: 0 }]
+            .instances
+            .iter()
+            .map(Instance::to_slot)
+            .collect::<Vec<_>>();
+        bytemuck::cast_slice(&raw).to_vec()
+    }
+
+    // Forty seconds of idling with the idle events rolling, then a section reset
+    fn seeded_run(seed: u64) -> Vec<Vec<u8>> {
+        let mut game_loop = test_support::game_loop(test_support::grid());
+        game_loop.reseed(seed);
+        let mut snapshots = Vec::new();
+        for _ in 0..4 {
+            run(&mut game_loop, 10.0);
+            snapshots.push(snapshot(&game_loop));
+        }
+        game_loop.reset_section();
+        run(&mut game_loop, 1.0);
+        snapshots.push(snapshot(&game_loop));
+        snapshots
+    }
+
+    #[test]
+    fn same_seed_same_run() {
+        assert!(seeded_run(7) == seeded_run(7));
+        assert!(seeded_run(7) != seeded_run(8));
+    }
+
+    // Frames the event loop would redraw, it only asks for one when update saw a change
+    fn redraws(game_loop: &mut Gameloop, seconds: f32) -> usize {
+        (0..(seconds / FRAME.as_secs_f32()).ceil() as usize)
+            .filter(|_| game_loop.update(FRAME))
+            .count()
+    }
+
+    #[test]
+    fn settled_scene_stops_redrawing() {
+        let mut game_loop = test_support::game_loop(test_support::grid());
+        // The spinning logo keeps every frame busy on purpose
+        game_loop.camera_attachments.clear();
+        run(&mut game_loop, 5.0);
+        assert_eq!(redraws(&mut game_loop, 10.0), 0);
+
+        game_loop.go_to_section(2);
+        assert!(redraws(&mut game_loop, 3.0) > 0);
+        assert_eq!(redraws(&mut game_loop, 1.0), 0);
+    }
+
+    // A game loop looking straight down at its first visible cube, with the cursor on it
+    fn aimed() -> (Gameloop, usize, Camera, ScreenMapper) {
+        let mut game_loop = test_support::game_loop(test_support::grid());
+        let (index, base) = game_loop.chunk_map[&Chunk { x: 0, y: 0 }]
+            .instances
+            .iter()
+            .enumerate()
+            .find(|(_, instance)| instance.should_render)
+            .map(|(index, instance)| (index, instance.position))
+            .unwrap();
+        let camera =
+            test_support::camera_above(Point3::from_vec(base + Vector3::new(0.5, 0.5, 0.5)));
+        game_loop.cursor_position = test_support::screen_center();
+        (game_loop, index, camera, test_support::screen())
+    }
+
+    fn press(
+        game_loop: &mut Gameloop,
+        button: MouseButton,
+        camera: &Camera,
+        screen: &ScreenMapper,
+    ) {
+        for state in [ElementState::Pressed, ElementState::Released].iter() {
+            let event = WindowEvent::MouseInput {
+                device_id: DeviceId::dummy(),
+                state: *state,
+                button,
+            };
+            game_loop.process_event(&event, camera, screen);
+        }
+    }
+
+    fn denying(deny: impl Fn(&mut InteractionPolicy)) -> InteractionPolicy {
+        let mut policy = InteractionPolicy::default();
+        deny(&mut policy);
+        policy
+    }
+
+    fn visible_count(game_loop: &Gameloop) -> usize {
+        visible(game_loop).len()
+    }
+
+    #[test]
+    fn denied_delete_shakes_instead() {
+        for allowed in [true, false].iter().copied() {
+            let (mut game_loop, index, camera, screen) = aimed();
+            game_loop.policy = denying(|policy| policy.allow_delete = allowed);
+            game_loop.tool = Tool::Delete;
+            press(&mut game_loop, MouseButton::Left, &camera, &screen);
+            run(&mut game_loop, 0.1);
+            let cube = &game_loop.chunk_map[&Chunk { x: 0, y: 0 }].instances[index];
+            assert_eq!(cube.should_render, !allowed);
+            if !allowed {
+                assert!(game_loop.animation_handler.is_animating(index));
+            }
+        }
+    }
+
+    #[test]
+    fn denied_explode_shakes_instead() {
+        for allowed in [true, false].iter().copied() {
+            let (mut game_loop, index, camera, screen) = aimed();
+            game_loop.policy = denying(|policy| policy.allow_explode = allowed);
+            press(&mut game_loop, MouseButton::Middle, &camera, &screen);
+            // The implosion pulls the neighbours in, a shake only moves the clicked cube
+            let handler = &game_loop.animation_handler;
+            let cubes = game_loop.chunk_map[&Chunk { x: 0, y: 0 }].instances.len();
+            let neighbours = (0..cubes)
+                .filter(|other| *other != index && handler.is_animating(*other))
+                .count();
+            assert_eq!(neighbours > 0, allowed);
+            if !allowed {
+                assert!(handler.is_animating(index));
+            }
+        }
+    }
+
+    #[test]
+    fn denied_place_shakes_instead() {
+        for allowed in [true, false].iter().copied() {
+            let (mut game_loop, index, camera, screen) = aimed();
+            game_loop.policy = denying(|policy| policy.allow_place = allowed);
+            game_loop.tool = Tool::Stamp;
+            let before = visible_count(&game_loop);
+            // Copying the template always works, only the placing click is checked
+            press(&mut game_loop, MouseButton::Left, &camera, &screen);
+            assert!(game_loop.stamp.is_some());
+            press(&mut game_loop, MouseButton::Left, &camera, &screen);
+            assert_eq!(visible_count(&game_loop), before + allowed as usize);
+            if !allowed {
+                assert!(game_loop.animation_handler.is_animating(index));
+            }
+        }
+    }
+
+    #[test]
+    fn denied_paint_shakes_instead() {
+        for allowed in [true, false].iter().copied() {
+            let (mut game_loop, index, camera, screen) = aimed();
+            game_loop.policy = denying(|policy| policy.allow_paint = allowed);
+            game_loop.tool = Tool::Paint;
+            let before = game_loop.chunk_map[&Chunk { x: 0, y: 0 }].instances[index].color;
+            press(&mut game_loop, MouseButton::Left, &camera, &screen);
+            let after = game_loop.chunk_map[&Chunk { x: 0, y: 0 }].instances[index].color;
+            assert_eq!(after == game_loop.palette.color(), allowed);
+            if !allowed {
+                assert_eq!(after, before);
+                assert!(game_loop.animation_handler.is_animating(index));
+            }
+        }
+    }
+
+    #[test]
+    fn policy_follows_the_section_transition() {
+        let mut game_loop = test_support::game_loop(test_support::grid());
+        let locked = denying(|policy| {
+            policy.allow_delete = false;
+            policy.allow_paint = false;
+        });
+        game_loop.content.sections = vec![SectionInfo::default(); 3];
+        game_loop.content.sections[2].policy = locked;
+        assert_eq!(game_loop.policy, InteractionPolicy::default());
+
+        game_loop.go_to_section(2);
+        run(&mut game_loop, 3.0);
+        assert_eq!(game_loop.policy, locked);
+        game_loop.go_to_section(0);
+        run(&mut game_loop, 3.0);
+        assert_eq!(game_loop.policy, InteractionPolicy::default());
+    }
+
+    #[test]
+    fn new_content_replaces_the_old_pins() {
+        let mut game_loop = test_support::game_loop(test_support::row(6));
+        let manifest = |min: Vector3<f32>, max: Vector3<f32>| Manifest {
+            pinned: vec![(min, max)],
+            ..Manifest::default()
+        };
+        let pinned = |game_loop: &Gameloop| {
+            (0..6)
+                .filter(|index| game_loop.animation_handler.is_pinned(*index))
+                .collect::<Vec<_>>()
+        };
+        game_loop.set_content(manifest(
+            Vector3::new(0.0, 0.0, 0.0),
+            Vector3::new(1.5, 1.0, 1.0),
+        ));
+        assert_eq!(pinned(&game_loop), vec![0, 1]);
+
+        game_loop.set_content(manifest(
+            Vector3::new(4.5, 0.0, 0.0),
+            Vector3::new(6.0, 1.0, 1.0),
+        ));
+        assert_eq!(pinned(&game_loop), vec![4, 5]);
+        // The released cubes take animations again
+        game_loop
+            .animation_handler
+            .set_manual_color(0, Vector3::new(1.0, 0.0, 0.0));
+        assert_eq!(
+            game_loop.animation_handler.movement_list[0].manual_color,
+            Some(Vector3::new(1.0, 0.0, 0.0))
+        );
+    }
+
+    #[test]
+    fn pinned_cubes_sit_out_transitions() {
+        let mut game_loop = test_support::game_loop(test_support::grid());
+        let cubes = |game_loop: &Gameloop, indices: &[usize]| {
+            let instances = &game_loop.chunk_map[&Chunk { x: 0, y: 0 }].instances;
+            indices
+                .iter()
+                .map(|index| (instances[*index].position, instances[*index].color))
+                .collect::<Vec<_>>()
+        };
+        let shown = game_loop.chunk_map[&Chunk { x: 0, y: 0 }]
+            .instances
+            .iter()
+            .enumerate()
+            .filter(|(_, instance)| instance.should_render)
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+        let (pinned, free) = shown.split_at(5);
+        game_loop.pin_instances(pinned);
+        let before = cubes(&game_loop, pinned);
+        let free_before = cubes(&game_loop, free);
+
+        for section in 1..=3 {
+            game_loop.go_to_section(section);
+            run(&mut game_loop, 3.0);
+        }
+        assert_eq!(cubes(&game_loop, pinned), before);
+        assert!(cubes(&game_loop, free) != free_before);
+    }
+
+    // Sections the tracker moves through over `seconds`, every change is one activation
+    fn activations(game_loop: &mut Gameloop, seconds: f32) -> Vec<Option<usize>> {
+        let mut seen = Vec::new();
+        for _ in 0..(seconds / FRAME.as_secs_f32()).ceil() as usize {
+            game_loop.update(FRAME);
+            let active = game_loop.section_tracker.active();
+            if seen.last() != Some(&active) {
+                seen.push(active);
+            }
+        }
+        seen
+    }
+
+    #[test]
+    fn cold_restore_activates_one_section() {
+        let mut game_loop = test_support::game_loop(test_support::grid());
+        let before = activations(&mut game_loop, 0.5);
+        assert_eq!(before.len(), 1);
+
+        // 2300 pixels down a page that scrolls 4600, halfway
+        let progress = crate::core::scroll::page_progress(2300.0, 4600.0);
+        let section = (progress * (PROGRESS_CUBES - 1) as f32).round() as usize;
+        game_loop.restore_scroll(progress);
+        assert_eq!(game_loop.section_tracker.active(), Some(section));
+        assert!(!game_loop.scroll_snap.is_snapping());
+        assert_eq!(activations(&mut game_loop, 5.0), vec![Some(section)]);
+        assert_eq!(
+            game_loop.scroll_progress,
+            section as f32 / (PROGRESS_CUBES - 1) as f32
+        );
+    }
+
+    #[test]
+    fn animations_advance_once_per_frame_for_any_chunk_count() {
+        let flight = |chunks: usize| {
+            let mut game_loop = test_support::game_loop(test_support::grid());
+            let (index, start) = game_loop.chunk_map[&Chunk { x: 0, y: 0 }]
+                .instances
+                .iter()
+                .enumerate()
+                .find(|(_, instance)| instance.should_render)
+                .map(|(index, instance)| (index, instance.position))
+                .unwrap();
+            match chunks {
+                0 => game_loop.chunk_map.clear(),
+                1 => {}
+                _ => {
+                    let neighbor = test_support::controller(
+                        &game_loop.device,
+                        &game_loop.queue,
+                        test_support::grid(),
+                    );
+                    game_loop.chunk_map.insert(Chunk { x: 1, y: 0 }, neighbor);
+                }
+            }
+            let end = start + Vector3::new(0.0, 10.0, 0.0);
+            game_loop.animation_handler.play(index, start, end, 0.0);
+            run(&mut game_loop, 0.2);
+            game_loop.animation_handler.movement_list[index].current_pos - start
+        };
+        let single = flight(1);
+        assert!(single.y > 0.0 && single.y < 10.0);
+        assert_eq!(flight(0), single);
+        assert_eq!(flight(2), single);
+    }
+
+    #[test]
+    fn manifest_entrance_follows_its_template_curve() {
+        let mut game_loop = test_support::game_loop(test_support::grid());
+        game_loop.content = parse_manifest(include_str!("content/fixtures/entrance.txt")).unwrap();
+        let template = game_loop.content.animation("rise").unwrap();
+        assert_eq!(template.preset, EffectPreset::AssembleFromGround);
+        assert_eq!(template.trajectory, TrajectoryMode::Arc { height: 0.5 });
+        // Lowest cubes start right away, the stagger is zero anyway
+        let (index, target) = game_loop.chunk_map[&Chunk { x: 0, y: 0 }]
+            .instances
+            .iter()
+            .enumerate()
+            .filter(|(_, instance)| instance.should_render)
+            .map(|(index, instance)| (index, instance.position))
+            .min_by(|a, b| a.1.y.total_cmp(&b.1.y))
+            .unwrap();
+
+        run(&mut game_loop, 0.1);
+        game_loop.activate_section(1);
+        let start = target - Vector3::unit_y() * 4.0;
+        let duration = game_loop.animation_handler.duration(index, start, target);
+        let mut elapsed = 0.0;
+        for frames in [15, 30, 15].iter() {
+            for _ in 0..*frames {
+                game_loop.update(FRAME);
+            }
+            elapsed += *frames as f32 * FRAME.as_secs_f32();
+            // Rises 4 units, bowing up by half the distance at the peak
+            let eased = EaseInEaseOut::ease_in_ease_out_cubic(elapsed / duration);
+            let expected = target.y - 4.0 * (1.0 - eased) + 0.5 * 4.0 * 4.0 * eased * (1.0 - eased);
+            let position = game_loop.animation_handler.movement_list[index].current_pos;
+            assert!(
+                (position.y - expected).abs() < 1e-3,
+                "{} at {}s",
+                position.y,
+                elapsed
+            );
+            assert_eq!((position.x, position.z), (target.x, target.z));
+        }
+    }
+
+    #[test]
+    fn click_before_a_pending_upload_still_hides_the_cube() {
+        let (mut game_loop, index, camera, screen) = aimed();
+        let origin = Chunk { x: 0, y: 0 };
+        // A neighbor mid-flight keeps the chunk's upload pending from before the click
+        let neighbor = (0..index)
+            .chain(index + 1..game_loop.chunk_map[&origin].instances.len())
+            .find(|other| game_loop.chunk_map[&origin].instances[*other].should_render)
+            .unwrap();
+        let from = game_loop.chunk_map[&origin].instances[neighbor].position;
+        game_loop
+            .animation_handler
+            .play(neighbor, from, from + Vector3::new(0.0, 3.0, 0.0), 0.0);
+        game_loop.update(FRAME);
+        game_loop.chunk_map.get_mut(&origin).unwrap().mark_dirty();
+
+        game_loop.tool = Tool::Delete;
+        press(&mut game_loop, MouseButton::Left, &camera, &screen);
+        // Input only queues the removal, the next update applies it ahead of the upload
+        assert!(game_loop.chunk_map[&origin].instances[index].should_render);
+        assert!(game_loop.pending_removals == vec![(origin, index)]);
+
+        game_loop.update(FRAME);
+        let controller = &game_loop.chunk_map[&origin];
+        assert!(!controller.instances[index].should_render);
+        assert!(!controller.needs_upload());
+        assert!(game_loop.animation_handler.is_animating(neighbor));
+    }
 }
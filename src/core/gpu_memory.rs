@@ -0,0 +1,258 @@
+// Bytes of GPU memory the crate holds, by category. Buffers and textures are created
+// through the helpers here, the Tracked wrapper counts them until it is dropped
+
+use std::{
+    ops::Deref,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use wgpu::util::DeviceExt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryCategory {
+    Instances,
+    Meshes,
+    Uniforms,
+    Textures,
+    RenderTargets,
+}
+
+pub const CATEGORIES: [MemoryCategory; 5] = [
+    MemoryCategory::Instances,
+    MemoryCategory::Meshes,
+    MemoryCategory::Uniforms,
+    MemoryCategory::Textures,
+    MemoryCategory::RenderTargets,
+];
+
+static ALLOCATED: [AtomicU64; CATEGORIES.len()] = [const { AtomicU64::new(0) }; CATEGORIES.len()];
+
+impl MemoryCategory {
+    pub fn name(self) -> &'static str {
+        match self {
+            MemoryCategory::Instances => "instances",
+            MemoryCategory::Meshes => "meshes",
+            MemoryCategory::Uniforms => "uniforms",
+            MemoryCategory::Textures => "textures",
+            MemoryCategory::RenderTargets => "renderTargets",
+        }
+    }
+
+    fn counter(self) -> &'static AtomicU64 {
+        &ALLOCATED[self as usize]
+    }
+}
+
+// A buffer or texture counted against its category while it lives
+pub struct Tracked<T> {
+    resource: T,
+    size: u64,
+    counter: &'static AtomicU64,
+}
+
+impl<T> Tracked<T> {
+    fn new(resource: T, size: u64, category: MemoryCategory, label: Option<&str>) -> Tracked<T> {
+        log::debug!(
+            "GPU alloc {} bytes of {} ({})",
+            size,
+            category.name(),
+            label.unwrap_or("unlabeled")
+        );
+        Tracked::counted(resource, size, category.counter())
+    }
+
+    fn counted(resource: T, size: u64, counter: &'static AtomicU64) -> Tracked<T> {
+        counter.fetch_add(size, Ordering::Relaxed);
+        Tracked {
+            resource,
+            size,
+            counter,
+        }
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+impl<T> Deref for Tracked<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.resource
+    }
+}
+
+impl<T> Drop for Tracked<T> {
+    fn drop(&mut self) {
+        self.counter.fetch_sub(self.size, Ordering::Relaxed);
+    }
+}
+
+pub fn create_buffer(
+    device: &wgpu::Device,
+    desc: &wgpu::BufferDescriptor,
+    category: MemoryCategory,
+) -> Tracked<wgpu::Buffer> {
+    Tracked::new(device.create_buffer(desc), desc.size, category, desc.label)
+}
+
+pub fn create_buffer_init(
+    device: &wgpu::Device,
+    desc: &wgpu::util::BufferInitDescriptor,
+    category: MemoryCategory,
+) -> Tracked<wgpu::Buffer> {
+    let buffer = device.create_buffer_init(desc);
+    let size = buffer.size();
+    Tracked::new(buffer, size, category, desc.label)
+}
+
+pub fn create_texture(
+    device: &wgpu::Device,
+    desc: &wgpu::TextureDescriptor,
+    category: MemoryCategory,
+) -> Tracked<wgpu::Texture> {
+    Tracked::new(
+        device.create_texture(desc),
+        texture_size(desc),
+        category,
+        desc.label,
+    )
+}
+
+// Estimated bytes of every mip level and sample, drivers may pad on top
+fn texture_size(desc: &wgpu::TextureDescriptor) -> u64 {
+    let texel = desc
+        .format
+        .block_copy_size(Some(wgpu::TextureAspect::All))
+        .or_else(|| {
+            desc.format
+                .block_copy_size(Some(wgpu::TextureAspect::DepthOnly))
+        })
+        .unwrap_or(4) as u64;
+    let (block_width, block_height) = desc.format.block_dimensions();
+    (0..desc.mip_level_count)
+        .filter_map(|level| desc.mip_level_size(level))
+        .map(|size| {
+            let blocks_x = size.width.div_ceil(block_width) as u64;
+            let blocks_y = size.height.div_ceil(block_height) as u64;
+            blocks_x * blocks_y * size.depth_or_array_layers as u64 * texel
+        })
+        .sum::<u64>()
+        * desc.sample_count as u64
+}
+
+pub fn allocated(category: MemoryCategory) -> u64 {
+    category.counter().load(Ordering::Relaxed)
+}
+
+pub fn total() -> u64 {
+    CATEGORIES.iter().map(|category| allocated(*category)).sum()
+}
+
+// Warns once each time the total crosses the budget
+#[derive(Default)]
+pub struct BudgetWatch {
+    over: bool,
+}
+
+impl BudgetWatch {
+    // Whether the total went over the budget since the last check
+    pub fn check(&mut self, budget: u64) -> bool {
+        self.observe(total(), budget)
+    }
+
+    fn observe(&mut self, total: u64, budget: u64) -> bool {
+        let over = total > budget;
+        let crossed = over && !self.over;
+        if crossed {
+            let usage = CATEGORIES
+                .iter()
+                .map(|category| format!("{} {}", category.name(), allocated(*category)))
+                .collect::<Vec<_>>()
+                .join(", ");
+            log::warn!(
+                "GPU memory {} bytes is over the {} byte budget: {}",
+                total,
+                budget,
+                usage
+            );
+        }
+        self.over = over;
+        crossed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Counters of their own, the category ones are shared with every test creating buffers
+    static FIRST: AtomicU64 = AtomicU64::new(0);
+    static SECOND: AtomicU64 = AtomicU64::new(0);
+
+    #[test]
+    fn tracked_resources_are_counted_until_dropped() {
+        let a = Tracked::counted((), 100, &FIRST);
+        let b = Tracked::counted((), 50, &FIRST);
+        let c = Tracked::counted((), 7, &SECOND);
+        assert_eq!(FIRST.load(Ordering::Relaxed), 150);
+        assert_eq!(SECOND.load(Ordering::Relaxed), 7);
+        assert_eq!(b.size(), 50);
+
+        drop(a);
+        assert_eq!(FIRST.load(Ordering::Relaxed), 50);
+        drop(c);
+        assert_eq!(SECOND.load(Ordering::Relaxed), 0);
+        drop(b);
+        assert_eq!(FIRST.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn texture_size_counts_mips_and_samples() {
+        let desc = |format, mip_level_count, sample_count| wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d {
+                width: 64,
+                height: 64,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        };
+        assert_eq!(
+            texture_size(&desc(wgpu::TextureFormat::Rgba8Unorm, 1, 1)),
+            64 * 64 * 4
+        );
+        assert_eq!(
+            texture_size(&desc(wgpu::TextureFormat::Rgba8Unorm, 7, 1)),
+            (64 * 64 + 32 * 32 + 16 * 16 + 8 * 8 + 4 * 4 + 2 * 2 + 1) * 4
+        );
+        assert_eq!(
+            texture_size(&desc(wgpu::TextureFormat::Depth32Float, 1, 4)),
+            64 * 64 * 4 * 4
+        );
+        assert_eq!(
+            texture_size(&desc(wgpu::TextureFormat::Bc1RgbaUnorm, 1, 1)),
+            16 * 16 * 8
+        );
+    }
+
+    #[test]
+    fn budget_triggers_once_per_crossing() {
+        let mut watch = BudgetWatch::default();
+        let budget = 1000;
+        let triggers = [500, 1000, 1001, 2000, 1500, 900, 1200, 1200]
+            .iter()
+            .map(|total| watch.observe(*total, budget))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            triggers,
+            [false, false, true, false, false, false, true, false]
+        );
+    }
+}
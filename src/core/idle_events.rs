@@ -0,0 +1,71 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::core::settings::Settings;
+
+// Small ambient events that keep the scene alive while nobody interacts
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IdleEvent {
+    Wave,
+    Implode,
+}
+
+pub struct IdleEventScheduler {
+    pub rng: StdRng,
+    idle_time: f32,
+    cooldown: f32,
+}
+
+impl IdleEventScheduler {
+    pub fn new(seed: u64) -> IdleEventScheduler {
+        IdleEventScheduler {
+            rng: StdRng::seed_from_u64(seed),
+            idle_time: 0.0,
+            cooldown: 0.0,
+        }
+    }
+
+    pub fn reseed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    // Called on real input, restarts the idle timer
+    pub fn reset(&mut self) {
+        self.idle_time = 0.0;
+        self.cooldown = 0.0;
+    }
+
+    // Whether `update` would roll for an event after another `dt` seconds
+    pub fn is_due(&self, dt: f32, settings: &Settings) -> bool {
+        self.idle_time + dt >= settings.idle_timeout && self.cooldown - dt <= 0.0
+    }
+
+    pub fn update(&mut self, dt: f32, settings: &Settings) -> Option<IdleEvent> {
+        self.idle_time += dt;
+        if self.idle_time < settings.idle_timeout {
+            return None;
+        }
+        self.cooldown -= dt;
+        if self.cooldown > 0.0 {
+            return None;
+        }
+        self.cooldown = settings.idle_cooldown;
+
+        let weights = [
+            (IdleEvent::Wave, settings.idle_wave_weight),
+            (IdleEvent::Implode, settings.idle_implode_weight),
+        ];
+        let total: f32 = weights.iter().map(|(_, weight)| weight.max(0.0)).sum();
+        if total <= 0.0 {
+            return None;
+        }
+        let mut pick = self.rng.gen_range(0.0..total);
+        for (event, weight) in weights {
+            let weight = weight.max(0.0);
+            if pick < weight {
+                return Some(event);
+            }
+            pick -= weight;
+        }
+        None
+    }
+}
@@ -0,0 +1,334 @@
+// Builds the instance grids away from the main loop and hands them over in batches
+
+use std::{
+    collections::HashSet,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use cgmath::{Vector2, Vector3};
+use rand::{rngs::StdRng, SeedableRng};
+use winit::event_loop::EventLoopProxy;
+
+use crate::{
+    core::{event_loop::UserEvent, game_loop::Chunk, settings::JitterSpec},
+    entity::entity::{instances_list, instances_list_circle, Instance},
+};
+
+const BATCH_SIZE: usize = 256;
+// Darkening of a cube with all 26 neighbors occupied
+const AO_STRENGTH: f32 = 0.45;
+
+// Same layouts State::new used to build synchronously. Each chunk gets its own jitter
+// sequence so the result doesn't depend on build order
+pub fn chunk_instances(
+    chunk: Chunk,
+    chunk_size: Vector2<u32>,
+    textured: bool,
+    jitter: JitterSpec,
+    seed: u64,
+) -> Vec<Instance> {
+    let mut instances = if textured {
+        instances_list(chunk, chunk_size)
+    } else {
+        instances_list_circle(chunk, chunk_size)
+    };
+    let chunk_seed = ((chunk.x as u32 as u64) << 32) | chunk.y as u32 as u64;
+    let mut rng = StdRng::seed_from_u64(seed ^ chunk_seed);
+    for instance in instances.iter_mut() {
+        instance.jitter = jitter.sample(&mut rng);
+    }
+    bake_ao(&mut instances);
+    instances
+}
+
+// Ambient term for a cube with `occupied` of its 26 neighbors filled
+pub fn neighbor_ao(occupied: usize) -> f32 {
+    1.0 - AO_STRENGTH * occupied.min(26) as f32 / 26.0
+}
+
+// Counts the occupied grid cells around every visible instance
+pub fn bake_ao(instances: &mut [Instance]) {
+    let cell = |position: Vector3<f32>| {
+        (
+            position.x.round() as i32,
+            position.y.round() as i32,
+            position.z.round() as i32,
+        )
+    };
+    let occupied: HashSet<(i32, i32, i32)> = instances
+        .iter()
+        .filter(|instance| instance.should_render)
+        .map(|instance| cell(instance.position))
+        .collect();
+    for instance in instances.iter_mut() {
+        let (x, y, z) = cell(instance.position);
+        let mut count = 0;
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    if (dx, dy, dz) != (0, 0, 0) && occupied.contains(&(x + dx, y + dy, z + dz)) {
+                        count += 1;
+                    }
+                }
+            }
+        }
+        instance.ao = neighbor_ao(count);
+    }
+}
+
+// The running builder, stopping it drops the batches it hasn't sent yet
+pub struct BuilderHandle {
+    cancelled: Arc<AtomicBool>,
+    #[cfg(not(target_arch = "wasm32"))]
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl BuilderHandle {
+    // Returns once the builder sends nothing more, on native after its thread has ended
+    pub fn stop(&mut self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                log::error!("Instance builder panicked");
+            }
+        }
+    }
+}
+
+// Batches arrive in order, so the final instance lists match the synchronous build
+pub fn spawn_instance_builder(
+    mut chunks: Vec<Chunk>,
+    chunk_size: Vector2<u32>,
+    textured: bool,
+    jitter: JitterSpec,
+    seed: u64,
+    proxy: EventLoopProxy<UserEvent>,
+) -> BuilderHandle {
+    // Chunk (0, 0) holds the animations, build it first
+    chunks.sort_by_key(|chunk| (chunk.x.abs() + chunk.y.abs(), chunk.x, chunk.y));
+    let cancelled = Arc::new(AtomicBool::new(false));
+
+    #[cfg(not(target_arch = "wasm32"))]
+    let thread = spawn_thread(
+        chunks,
+        chunk_size,
+        textured,
+        jitter,
+        seed,
+        cancelled.clone(),
+        move |chunk, batch| {
+            proxy
+                .send_event(UserEvent::InstanceBatch(chunk, batch))
+                .is_ok()
+        },
+    );
+
+    // No threads on the web, build a batch at a time and let the browser breathe in between
+    #[cfg(target_arch = "wasm32")]
+    {
+        let cancelled = cancelled.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            for chunk in chunks {
+                let instances = chunk_instances(chunk, chunk_size, textured, jitter, seed);
+                for batch in instances.chunks(BATCH_SIZE) {
+                    if cancelled.load(Ordering::SeqCst) {
+                        return;
+                    }
+                    let event = UserEvent::InstanceBatch(chunk, batch.to_vec());
+                    if proxy.send_event(event).is_err() {
+                        return;
+                    }
+                    crate::core::loading::yield_to_browser().await;
+                }
+            }
+        });
+    }
+
+    BuilderHandle {
+        cancelled,
+        #[cfg(not(target_arch = "wasm32"))]
+        thread: Some(thread),
+    }
+}
+
+// Builds `chunks` in order on a thread of its own, handing every batch to `send` until it
+// returns false or the build is cancelled
+#[cfg(not(target_arch = "wasm32"))]
+fn spawn_thread(
+    chunks: Vec<Chunk>,
+    chunk_size: Vector2<u32>,
+    textured: bool,
+    jitter: JitterSpec,
+    seed: u64,
+    cancelled: Arc<AtomicBool>,
+    mut send: impl FnMut(Chunk, Vec<Instance>) -> bool + Send + 'static,
+) -> std::thread::JoinHandle<()> {
+    std::thread::spawn(move || {
+        for chunk in chunks {
+            let instances = chunk_instances(chunk, chunk_size, textured, jitter, seed);
+            for batch in instances.chunks(BATCH_SIZE) {
+                if cancelled.load(Ordering::SeqCst) {
+                    return;
+                }
+                if !send(chunk, batch.to_vec()) {
+                    return;
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+    use crate::core::{settings::Settings, test_support};
+
+    // Everything the GPU sees of the instances, plus visibility
+    fn uploaded(instances: &[Instance]) -> (Vec<u8>, Vec<bool>) {
+        let raw = instances.iter().map(Instance::to_raw).collect::<Vec<_>>();
+        (
+            bytemuck::cast_slice(&raw).to_vec(),
+            instances
+                .iter()
+                .map(|instance| instance.should_render)
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn threaded_build_matches_inline_build() {
+        let settings = Settings::default();
+        let chunk_size = Vector2::new(35, 35);
+        let chunks = vec![
+            Chunk { x: 0, y: 0 },
+            Chunk { x: 1, y: 0 },
+            Chunk { x: 0, y: 1 },
+            Chunk { x: -1, y: 2 },
+        ];
+        for textured in [false, true].iter().copied() {
+            let (sender, receiver) = mpsc::channel();
+            spawn_thread(
+                chunks.clone(),
+                chunk_size,
+                textured,
+                settings.jitter,
+                settings.seed,
+                Arc::new(AtomicBool::new(false)),
+                move |chunk, batch| sender.send((chunk, batch)).is_ok(),
+            )
+            .join()
+            .unwrap();
+            let batches = receiver.try_iter().collect::<Vec<_>>();
+            for chunk in &chunks {
+                let threaded = batches
+                    .iter()
+                    .filter(|(from, _)| from == chunk)
+                    .flat_map(|(_, batch)| batch.iter().cloned())
+                    .collect::<Vec<_>>();
+                let inline =
+                    chunk_instances(*chunk, chunk_size, textured, settings.jitter, settings.seed);
+                assert!(uploaded(&threaded) == uploaded(&inline));
+            }
+        }
+    }
+
+    // Ambient term bake_ao gives the cube at (x, y, z) of a full 3×3×3 block
+    fn block_ao(x: usize, y: usize, z: usize) -> f32 {
+        let mut block = (0..27)
+            .map(|n| {
+                test_support::cube(Vector3::new(
+                    (n % 3) as f32,
+                    (n / 9) as f32,
+                    (n / 3 % 3) as f32,
+                ))
+            })
+            .collect::<Vec<_>>();
+        bake_ao(&mut block);
+        block[y * 9 + z * 3 + x].ao
+    }
+
+    #[test]
+    fn ao_follows_the_occupied_neighbors() {
+        // Corner, edge, face and interior cubes of a 3×3×3 block
+        assert_eq!(block_ao(0, 0, 0), neighbor_ao(7));
+        assert_eq!(block_ao(1, 0, 0), neighbor_ao(11));
+        assert_eq!(block_ao(1, 1, 0), neighbor_ao(17));
+        assert_eq!(block_ao(1, 1, 1), neighbor_ao(26));
+    }
+
+    #[test]
+    fn ao_mapping_is_open_alone_and_darkest_surrounded() {
+        assert_eq!(neighbor_ao(0), 1.0);
+        assert_eq!(neighbor_ao(26), 1.0 - AO_STRENGTH);
+        assert_eq!(neighbor_ao(40), neighbor_ao(26));
+        for occupied in 0..26 {
+            assert!(neighbor_ao(occupied + 1) < neighbor_ao(occupied));
+        }
+    }
+
+    #[test]
+    fn hidden_cubes_do_not_occlude() {
+        let mut pair = vec![
+            test_support::cube(Vector3::new(0.0, 0.0, 0.0)),
+            test_support::cube(Vector3::new(1.0, 0.0, 0.0)),
+        ];
+        pair[1].should_render = false;
+        bake_ao(&mut pair);
+        assert_eq!(pair[0].ao, 1.0);
+    }
+
+    // Sets its flag once the builder thread has dropped it
+    struct Finished(Arc<AtomicBool>);
+
+    impl Drop for Finished {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn stop_joins_the_builder_before_returning() {
+        let settings = Settings::default();
+        let chunk_size = Vector2::new(35, 35);
+        let chunks = (0..8).map(|x| Chunk { x, y: 0 }).collect::<Vec<_>>();
+        let batches = chunks.len() * (35 * 35usize).div_ceil(BATCH_SIZE);
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let finished = Arc::new(AtomicBool::new(false));
+        let (sender, receiver) = mpsc::channel();
+        let guard = Finished(finished.clone());
+        let thread = spawn_thread(
+            chunks,
+            chunk_size,
+            false,
+            settings.jitter,
+            settings.seed,
+            cancelled.clone(),
+            move |chunk, batch| {
+                let _ = &guard;
+                // A slow consumer, so the stop lands mid-build
+                std::thread::sleep(std::time::Duration::from_millis(5));
+                sender.send((chunk, batch)).is_ok()
+            },
+        );
+        let mut handle = BuilderHandle {
+            cancelled,
+            thread: Some(thread),
+        };
+        receiver.recv().unwrap();
+
+        handle.stop();
+        assert!(finished.load(Ordering::SeqCst));
+        let sent = 1 + receiver.try_iter().count();
+        assert!(sent < batches);
+        // Nothing more arrives once stop has returned
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        assert_eq!(receiver.try_iter().count(), 0);
+        handle.stop();
+    }
+}
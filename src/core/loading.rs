@@ -0,0 +1,181 @@
+use crate::core::gpu_memory::{self, MemoryCategory, Tracked};
+use crate::core::post_process::{create_fullscreen_pipeline, fullscreen_pass};
+
+const FADE_DURATION: f32 = 0.6;
+// The first frame after loading has a long dt, which would skip the fade entirely
+const MAX_STEP: f32 = 1.0 / 30.0;
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct LoadingUniform {
+    progress: f32,
+    time: f32,
+    opacity: f32,
+    aspect: f32,
+}
+
+// Shown while State::new builds everything else, then faded out over the scene.
+// It only depends on the device so it can be drawn as soon as the surface exists
+pub struct LoadingScreen {
+    pipeline: wgpu::RenderPipeline,
+    params_buffer: Tracked<wgpu::Buffer>,
+    bind_group: wgpu::BindGroup,
+    pub progress: f32,
+    time: f32,
+    opacity: f32,
+}
+
+impl LoadingScreen {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("LoadingShader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/loading.wgsl").into()),
+        });
+        let params_buffer = gpu_memory::create_buffer_init(
+            device,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Loading Params Buffer"),
+                contents: bytemuck::cast_slice(&[<LoadingUniform as bytemuck::Zeroable>::zeroed()]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            },
+            MemoryCategory::Uniforms,
+        );
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            label: Some("loading_bind_group_layout"),
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: params_buffer.as_entire_binding(),
+            }],
+            label: Some("loading_bind_group"),
+        });
+        let pipeline = create_fullscreen_pipeline(
+            device,
+            &shader,
+            &layout,
+            "fs_main",
+            format,
+            wgpu::BlendState::ALPHA_BLENDING,
+            "Loading Pipeline Layout",
+        );
+        Self {
+            pipeline,
+            params_buffer,
+            bind_group,
+            progress: 0.0,
+            time: 0.0,
+            opacity: 1.0,
+        }
+    }
+
+    // Draws a frame straight to the surface for a loading milestone
+    pub fn show(
+        &mut self,
+        surface: &wgpu::Surface,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        config: &wgpu::SurfaceConfiguration,
+        progress: f32,
+        milestone: &str,
+    ) {
+        log::warn!("Loading {:.0}%: {}", progress * 100.0, milestone);
+        self.progress = progress;
+        if config.width == 0 || config.height == 0 {
+            return;
+        }
+        let output = match surface.get_current_texture() {
+            Ok(output) => output,
+            Err(_) => return,
+        };
+        let view = output
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        self.write_params(queue, config.width as f32 / config.height as f32);
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Loading Encoder"),
+        });
+        fullscreen_pass(
+            &mut encoder,
+            "Loading Pass",
+            &view,
+            &self.pipeline,
+            &self.bind_group,
+        );
+        queue.submit(std::iter::once(encoder.finish()));
+        output.present();
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.opacity > 0.0
+    }
+
+    // Called once loading is done, fades the loader out over the scene
+    pub fn update(&mut self, queue: &wgpu::Queue, aspect: f32, dt: f32) {
+        if !self.is_visible() {
+            return;
+        }
+        let dt = dt.min(MAX_STEP);
+        self.time += dt;
+        self.opacity = (self.opacity - dt / FADE_DURATION).max(0.0);
+        self.write_params(queue, aspect);
+    }
+
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        if !self.is_visible() {
+            return;
+        }
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Loading Fade Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                depth_slice: None,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    fn write_params(&self, queue: &wgpu::Queue, aspect: f32) {
+        let params = LoadingUniform {
+            progress: self.progress,
+            time: self.time,
+            opacity: self.opacity,
+            aspect,
+        };
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[params]));
+    }
+}
+
+// Gives the browser a chance to present the loading frame between init steps
+#[cfg(target_arch = "wasm32")]
+pub async fn yield_to_browser() {
+    let window = match wgpu::web_sys::window() {
+        Some(window) => window,
+        None => return,
+    };
+    let promise = wasm_bindgen_futures::js_sys::Promise::new(&mut |resolve, _| {
+        let _ = window.set_timeout_with_callback(&resolve);
+    });
+    let _ = wasm_bindgen_futures::JsFuture::from(promise).await;
+}
@@ -1,4 +1,41 @@
+pub mod a11y;
+#[cfg(feature = "alloc-counter")]
+pub mod alloc_counter;
 pub mod camera;
+pub mod camera_attachment;
+pub mod camera_script;
+pub mod capabilities;
+pub mod clipboard;
+pub mod content;
+pub mod crash;
+pub mod detail;
 pub mod event_loop;
+pub mod frame_graph;
+#[cfg(feature = "frame-step")]
+pub mod frame_step;
+pub mod frame_timing;
 pub mod game_loop;
+pub mod gpu_memory;
+pub mod idle_events;
+pub mod instance_builder;
+pub mod loading;
+pub mod overlay;
+pub mod post_process;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod presenter;
+pub mod reveal;
+pub mod scene_io;
+pub mod scroll;
+pub mod section;
+pub mod sequencer;
+pub mod settings;
+#[cfg(all(feature = "shader-hot-reload", not(target_arch = "wasm32")))]
+pub mod shader_reload;
 pub mod state;
+pub mod status;
+pub mod storage;
+#[cfg(test)]
+pub mod test_support;
+pub mod tooltip;
+pub mod upload;
+pub mod validate;
@@ -0,0 +1,140 @@
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+
+use crate::core::gpu_memory::{self, MemoryCategory, Tracked};
+use crate::entity::texture::Texture;
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct OverlayRect {
+    min: [f32; 2],
+    max: [f32; 2],
+    color: [f32; 4],
+}
+
+const SELECTION_COLOR: [f32; 4] = [0.9, 0.4, 0.702, 0.25];
+
+// Translucent rectangle drawn on top of the scene while box selecting
+pub struct SelectionOverlay {
+    pipeline: wgpu::RenderPipeline,
+    rect_buffer: Tracked<wgpu::Buffer>,
+    bind_group: wgpu::BindGroup,
+    visible: bool,
+}
+
+impl SelectionOverlay {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("OverlayShader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/overlay.wgsl").into()),
+        });
+        let rect_buffer = gpu_memory::create_buffer_init(
+            device,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Overlay Rect Buffer"),
+                contents: bytemuck::cast_slice(&[<OverlayRect as bytemuck::Zeroable>::zeroed()]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            },
+            MemoryCategory::Uniforms,
+        );
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            label: Some("overlay_bind_group_layout"),
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: rect_buffer.as_entire_binding(),
+            }],
+            label: Some("overlay_bind_group"),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Overlay Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Overlay Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                cull_mode: None,
+                ..Default::default()
+            },
+            // Drawn in the overlay pass, which has a depth attachment, but ignores depth
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Texture::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Always,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+        Self {
+            pipeline,
+            rect_buffer,
+            bind_group,
+            visible: false,
+        }
+    }
+
+    pub fn update(
+        &mut self,
+        queue: &wgpu::Queue,
+        selection: Option<(PhysicalPosition<f32>, PhysicalPosition<f32>)>,
+        screen: &PhysicalSize<u32>,
+    ) {
+        self.visible = selection.is_some();
+        if let Some((start, end)) = selection {
+            let to_ndc = |position: PhysicalPosition<f32>| {
+                [
+                    position.x / screen.width as f32 * 2.0 - 1.0,
+                    (1.0 - position.y / screen.height as f32) * 2.0 - 1.0,
+                ]
+            };
+            let (a, b) = (to_ndc(start), to_ndc(end));
+            let rect = OverlayRect {
+                min: [a[0].min(b[0]), a[1].min(b[1])],
+                max: [a[0].max(b[0]), a[1].max(b[1])],
+                color: SELECTION_COLOR,
+            };
+            queue.write_buffer(&self.rect_buffer, 0, bytemuck::cast_slice(&[rect]));
+        }
+    }
+
+    pub fn render(&self, render_pass: &mut wgpu::RenderPass) {
+        if !self.visible {
+            return;
+        }
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..6, 0..1);
+    }
+}
@@ -0,0 +1,1447 @@
+use std::sync::Arc;
+
+use cgmath::{InnerSpace, Vector3};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    core::{
+        camera::Camera,
+        capabilities::Capabilities,
+        gpu_memory::{self, MemoryCategory, Tracked},
+        settings::Settings,
+    },
+    entity::entity::{InstanceController, InstanceRaw, PrimitiveVertex, TexturedVertex},
+};
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct DofUniform {
+    focus_distance: f32,
+    aperture: f32,
+    znear: f32,
+    zfar: f32,
+    texel_size: [f32; 2],
+    max_radius: f32,
+    _padding: f32,
+}
+
+pub struct RenderTarget {
+    pub texture: Tracked<wgpu::Texture>,
+    pub view: wgpu::TextureView,
+}
+
+impl RenderTarget {
+    pub fn new(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        label: &str,
+    ) -> Self {
+        let texture = gpu_memory::create_texture(
+            device,
+            &wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width: width.max(1),
+                    height: height.max(1),
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            },
+            MemoryCategory::RenderTargets,
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Self { texture, view }
+    }
+}
+
+pub struct DepthOfField {
+    // Lent by the frame's transient targets, see set_scene
+    pub scene: Arc<RenderTarget>,
+    half: RenderTarget,
+    sampler: wgpu::Sampler,
+    params_buffer: Tracked<wgpu::Buffer>,
+    blur_layout: wgpu::BindGroupLayout,
+    composite_layout: wgpu::BindGroupLayout,
+    blur_pipeline: wgpu::RenderPipeline,
+    composite_pipeline: wgpu::RenderPipeline,
+    blur_bind_group: wgpu::BindGroup,
+    composite_bind_group: wgpu::BindGroup,
+    texel_size: [f32; 2],
+}
+
+impl DepthOfField {
+    // Depth textures can't be sampled reliably on WebGL2, the effect is skipped there
+    pub fn is_supported(capabilities: &Capabilities) -> bool {
+        capabilities.samples_depth
+    }
+
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        depth_view: &wgpu::TextureView,
+        scene: Arc<RenderTarget>,
+    ) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("DofShader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/dof.wgsl").into()),
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+        let params_buffer = gpu_memory::create_buffer_init(
+            device,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Dof Params Buffer"),
+                contents: bytemuck::cast_slice(&[<DofUniform as bytemuck::Zeroable>::zeroed()]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            },
+            MemoryCategory::Uniforms,
+        );
+
+        let blur_layout = Self::create_layout(device, false);
+        let composite_layout = Self::create_layout(device, true);
+        let blur_pipeline = create_fullscreen_pipeline(
+            device,
+            &shader,
+            &blur_layout,
+            "fs_blur",
+            config.format,
+            wgpu::BlendState::REPLACE,
+            "Dof Pipeline Layout",
+        );
+        let composite_pipeline = create_fullscreen_pipeline(
+            device,
+            &shader,
+            &composite_layout,
+            "fs_composite",
+            config.format,
+            wgpu::BlendState::REPLACE,
+            "Dof Pipeline Layout",
+        );
+
+        let half = RenderTarget::new(
+            device,
+            config.width / 2,
+            config.height / 2,
+            config.format,
+            "dof_half",
+        );
+        let (blur_bind_group, composite_bind_group) = Self::create_bind_groups(
+            device,
+            &blur_layout,
+            &composite_layout,
+            &scene,
+            &half,
+            depth_view,
+            &sampler,
+            &params_buffer,
+        );
+
+        Self {
+            scene,
+            half,
+            sampler,
+            params_buffer,
+            blur_layout,
+            composite_layout,
+            blur_pipeline,
+            composite_pipeline,
+            blur_bind_group,
+            composite_bind_group,
+            texel_size: [
+                1.0 / config.width.max(1) as f32,
+                1.0 / config.height.max(1) as f32,
+            ],
+        }
+    }
+
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        depth_view: &wgpu::TextureView,
+        scene: Arc<RenderTarget>,
+    ) {
+        self.scene = scene;
+        self.half = RenderTarget::new(
+            device,
+            config.width / 2,
+            config.height / 2,
+            config.format,
+            "dof_half",
+        );
+        self.create_bind_groups_for(device, depth_view);
+        self.texel_size = [
+            1.0 / config.width.max(1) as f32,
+            1.0 / config.height.max(1) as f32,
+        ];
+    }
+
+    // Reads the scene from `scene` from now on, the bind groups are only rebuilt when it is
+    // another target than last frame's
+    pub fn set_scene(
+        &mut self,
+        device: &wgpu::Device,
+        scene: Arc<RenderTarget>,
+        depth_view: &wgpu::TextureView,
+    ) {
+        if !Arc::ptr_eq(&self.scene, &scene) {
+            self.scene = scene;
+            self.create_bind_groups_for(device, depth_view);
+        }
+    }
+
+    fn create_bind_groups_for(&mut self, device: &wgpu::Device, depth_view: &wgpu::TextureView) {
+        let (blur_bind_group, composite_bind_group) = Self::create_bind_groups(
+            device,
+            &self.blur_layout,
+            &self.composite_layout,
+            &self.scene,
+            &self.half,
+            depth_view,
+            &self.sampler,
+            &self.params_buffer,
+        );
+        self.blur_bind_group = blur_bind_group;
+        self.composite_bind_group = composite_bind_group;
+    }
+
+    pub fn update(&self, queue: &wgpu::Queue, camera: &Camera, settings: &Settings) {
+        // Focus on the orbit target unless a fixed distance is configured
+        let focus_distance = settings
+            .dof_focus_distance
+            .unwrap_or_else(|| cgmath::MetricSpace::distance(camera.eye, camera.target));
+        let params = DofUniform {
+            focus_distance,
+            aperture: settings.dof_aperture,
+            znear: camera.znear,
+            zfar: camera.zfar,
+            texel_size: self.texel_size,
+            max_radius: settings.dof_max_radius,
+            _padding: 0.0,
+        };
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[params]));
+    }
+
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder, output: &wgpu::TextureView) {
+        fullscreen_pass(
+            encoder,
+            "Dof Blur Pass",
+            &self.half.view,
+            &self.blur_pipeline,
+            &self.blur_bind_group,
+        );
+        fullscreen_pass(
+            encoder,
+            "Dof Composite Pass",
+            output,
+            &self.composite_pipeline,
+            &self.composite_bind_group,
+        );
+    }
+
+    fn create_layout(device: &wgpu::Device, composite: bool) -> wgpu::BindGroupLayout {
+        let texture = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+        };
+        let mut entries = vec![
+            texture(0),
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    sample_type: wgpu::TextureSampleType::Depth,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 3,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ];
+        if composite {
+            entries.push(texture(4));
+        }
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &entries,
+            label: Some("dof_bind_group_layout"),
+        })
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn create_bind_groups(
+        device: &wgpu::Device,
+        blur_layout: &wgpu::BindGroupLayout,
+        composite_layout: &wgpu::BindGroupLayout,
+        scene: &RenderTarget,
+        half: &RenderTarget,
+        depth_view: &wgpu::TextureView,
+        sampler: &wgpu::Sampler,
+        params_buffer: &wgpu::Buffer,
+    ) -> (wgpu::BindGroup, wgpu::BindGroup) {
+        let shared = [
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&scene.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(depth_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: params_buffer.as_entire_binding(),
+            },
+        ];
+        let blur = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: blur_layout,
+            entries: &shared,
+            label: Some("dof_blur_bind_group"),
+        });
+        let mut entries = shared.to_vec();
+        entries.push(wgpu::BindGroupEntry {
+            binding: 4,
+            resource: wgpu::BindingResource::TextureView(&half.view),
+        });
+        let composite = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: composite_layout,
+            entries: &entries,
+            label: Some("dof_composite_bind_group"),
+        });
+        (blur, composite)
+    }
+}
+
+const SSAO_KERNEL_SIZE: usize = 16;
+const SSAO_NOISE_SIZE: u32 = 4;
+const AO_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R8Unorm;
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct SsaoUniform {
+    kernel: [[f32; 4]; SSAO_KERNEL_SIZE],
+    znear: f32,
+    zfar: f32,
+    tan_half_fovy: f32,
+    aspect: f32,
+    radius: f32,
+    intensity: f32,
+    center_offset: [f32; 2],
+}
+
+pub struct AmbientOcclusion {
+    // Lent by the frame's transient targets, see set_scene
+    pub scene: Arc<RenderTarget>,
+    occlusion: RenderTarget,
+    blurred: RenderTarget,
+    noise: RenderTarget,
+    sampler: wgpu::Sampler,
+    params_buffer: Tracked<wgpu::Buffer>,
+    kernel: [[f32; 4]; SSAO_KERNEL_SIZE],
+    layout: wgpu::BindGroupLayout,
+    occlusion_pipeline: wgpu::RenderPipeline,
+    blur_pipeline: wgpu::RenderPipeline,
+    composite_pipeline: wgpu::RenderPipeline,
+    occlusion_bind_group: wgpu::BindGroup,
+    blur_bind_group: wgpu::BindGroup,
+    composite_bind_group: wgpu::BindGroup,
+}
+
+impl AmbientOcclusion {
+    // Same restriction as depth of field, the depth buffer is sampled
+    pub fn is_supported(capabilities: &Capabilities) -> bool {
+        capabilities.samples_depth
+    }
+
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        config: &wgpu::SurfaceConfiguration,
+        depth_view: &wgpu::TextureView,
+        seed: u64,
+        scene: Arc<RenderTarget>,
+    ) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("SsaoShader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/ssao.wgsl").into()),
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+        let params_buffer = gpu_memory::create_buffer_init(
+            device,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Ssao Params Buffer"),
+                contents: bytemuck::cast_slice(&[<SsaoUniform as bytemuck::Zeroable>::zeroed()]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            },
+            MemoryCategory::Uniforms,
+        );
+
+        let mut rng = StdRng::seed_from_u64(seed);
+        let kernel = Self::create_kernel(&mut rng);
+        let noise = Self::create_noise(device, queue, &mut rng);
+
+        let layout = Self::create_layout(device);
+        let pipeline = |entry_point, format| {
+            create_fullscreen_pipeline(
+                device,
+                &shader,
+                &layout,
+                entry_point,
+                format,
+                wgpu::BlendState::REPLACE,
+                "Ssao Pipeline Layout",
+            )
+        };
+        let occlusion_pipeline = pipeline("fs_occlusion", AO_FORMAT);
+        let blur_pipeline = pipeline("fs_blur", AO_FORMAT);
+        let composite_pipeline = pipeline("fs_composite", config.format);
+
+        let (occlusion, blurred) = Self::create_targets(device, config);
+        let (occlusion_bind_group, blur_bind_group, composite_bind_group) =
+            Self::create_bind_groups(
+                device,
+                &layout,
+                [&scene, &occlusion, &blurred, &noise],
+                depth_view,
+                &sampler,
+                &params_buffer,
+            );
+
+        Self {
+            scene,
+            occlusion,
+            blurred,
+            noise,
+            sampler,
+            params_buffer,
+            kernel,
+            layout,
+            occlusion_pipeline,
+            blur_pipeline,
+            composite_pipeline,
+            occlusion_bind_group,
+            blur_bind_group,
+            composite_bind_group,
+        }
+    }
+
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        depth_view: &wgpu::TextureView,
+        scene: Arc<RenderTarget>,
+    ) {
+        let (occlusion, blurred) = Self::create_targets(device, config);
+        self.scene = scene;
+        self.occlusion = occlusion;
+        self.blurred = blurred;
+        self.create_bind_groups_for(device, depth_view);
+    }
+
+    // Same as for depth of field, rebuilds the bind groups only for another target
+    pub fn set_scene(
+        &mut self,
+        device: &wgpu::Device,
+        scene: Arc<RenderTarget>,
+        depth_view: &wgpu::TextureView,
+    ) {
+        if !Arc::ptr_eq(&self.scene, &scene) {
+            self.scene = scene;
+            self.create_bind_groups_for(device, depth_view);
+        }
+    }
+
+    fn create_bind_groups_for(&mut self, device: &wgpu::Device, depth_view: &wgpu::TextureView) {
+        let (occlusion_bind_group, blur_bind_group, composite_bind_group) =
+            Self::create_bind_groups(
+                device,
+                &self.layout,
+                [&self.scene, &self.occlusion, &self.blurred, &self.noise],
+                depth_view,
+                &self.sampler,
+                &self.params_buffer,
+            );
+        self.occlusion_bind_group = occlusion_bind_group;
+        self.blur_bind_group = blur_bind_group;
+        self.composite_bind_group = composite_bind_group;
+    }
+
+    pub fn update(&self, queue: &wgpu::Queue, camera: &Camera, settings: &Settings) {
+        let params = SsaoUniform {
+            kernel: self.kernel,
+            znear: camera.znear,
+            zfar: camera.zfar,
+            tan_half_fovy: (camera.fovy.to_radians() / 2.0).tan(),
+            aspect: camera.aspect,
+            radius: settings.ssao_radius,
+            intensity: settings.ssao_intensity,
+            center_offset: camera.center_offset.into(),
+        };
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[params]));
+    }
+
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder, output: &wgpu::TextureView) {
+        fullscreen_pass(
+            encoder,
+            "Ssao Occlusion Pass",
+            &self.occlusion.view,
+            &self.occlusion_pipeline,
+            &self.occlusion_bind_group,
+        );
+        fullscreen_pass(
+            encoder,
+            "Ssao Blur Pass",
+            &self.blurred.view,
+            &self.blur_pipeline,
+            &self.blur_bind_group,
+        );
+        fullscreen_pass(
+            encoder,
+            "Ssao Composite Pass",
+            output,
+            &self.composite_pipeline,
+            &self.composite_bind_group,
+        );
+    }
+
+    // Samples in the +z hemisphere, packed closer to the origin
+    fn create_kernel(rng: &mut StdRng) -> [[f32; 4]; SSAO_KERNEL_SIZE] {
+        let mut kernel = [[0.0; 4]; SSAO_KERNEL_SIZE];
+        for (index, sample) in kernel.iter_mut().enumerate() {
+            let direction = Vector3::new(
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(0.05..1.0),
+            )
+            .normalize();
+            let t = index as f32 / SSAO_KERNEL_SIZE as f32;
+            let length = rng.gen_range(0.0..1.0) * (0.1 + 0.9 * t * t);
+            let point = direction * length;
+            *sample = [point.x, point.y, point.z, 0.0];
+        }
+        kernel
+    }
+
+    // Small tiled texture of random rotations around the surface normal
+    fn create_noise(device: &wgpu::Device, queue: &wgpu::Queue, rng: &mut StdRng) -> RenderTarget {
+        let noise = RenderTarget::new(
+            device,
+            SSAO_NOISE_SIZE,
+            SSAO_NOISE_SIZE,
+            wgpu::TextureFormat::Rgba8Unorm,
+            "ssao_noise",
+        );
+        let data: Vec<u8> = (0..SSAO_NOISE_SIZE * SSAO_NOISE_SIZE)
+            .flat_map(|_| [rng.gen(), rng.gen(), 0, 255])
+            .collect();
+        queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                aspect: wgpu::TextureAspect::All,
+                texture: &noise.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            &data,
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * SSAO_NOISE_SIZE),
+                rows_per_image: Some(SSAO_NOISE_SIZE),
+            },
+            wgpu::Extent3d {
+                width: SSAO_NOISE_SIZE,
+                height: SSAO_NOISE_SIZE,
+                depth_or_array_layers: 1,
+            },
+        );
+        noise
+    }
+
+    fn create_targets(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+    ) -> (RenderTarget, RenderTarget) {
+        (
+            RenderTarget::new(
+                device,
+                config.width / 2,
+                config.height / 2,
+                AO_FORMAT,
+                "ssao_occlusion",
+            ),
+            RenderTarget::new(
+                device,
+                config.width / 2,
+                config.height / 2,
+                AO_FORMAT,
+                "ssao_blurred",
+            ),
+        )
+    }
+
+    fn create_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        let texture = |binding, sample_type| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type,
+            },
+            count: None,
+        };
+        let color = wgpu::TextureSampleType::Float { filterable: true };
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                texture(0, color),
+                texture(1, wgpu::TextureSampleType::Depth),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                texture(4, color),
+                texture(5, color),
+            ],
+            label: Some("ssao_bind_group_layout"),
+        })
+    }
+
+    // A pass may not read the texture it renders to, so each pass binds its own input as t_ao
+    fn create_bind_groups(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        [scene, occlusion, blurred, noise]: [&RenderTarget; 4],
+        depth_view: &wgpu::TextureView,
+        sampler: &wgpu::Sampler,
+        params_buffer: &wgpu::Buffer,
+    ) -> (wgpu::BindGroup, wgpu::BindGroup, wgpu::BindGroup) {
+        let bind_group = |ao: &RenderTarget, label| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&scene.view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(depth_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::Sampler(sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: params_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 4,
+                        resource: wgpu::BindingResource::TextureView(&noise.view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 5,
+                        resource: wgpu::BindingResource::TextureView(&ao.view),
+                    },
+                ],
+                label: Some(label),
+            })
+        };
+        (
+            bind_group(blurred, "ssao_occlusion_bind_group"),
+            bind_group(occlusion, "ssao_blur_bind_group"),
+            bind_group(blurred, "ssao_composite_bind_group"),
+        )
+    }
+}
+
+const VELOCITY_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rg16Float;
+// The velocity pass reads the same instance inputs as the mesh shaders
+pub(super) const VELOCITY_SOURCE: &str = concat!(
+    include_str!("shaders/common.wgsl"),
+    include_str!("shaders/velocity.wgsl")
+);
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct MotionBlurUniform {
+    texel_size: [f32; 2],
+    strength: f32,
+    max_pixels: f32,
+}
+
+pub struct MotionBlur {
+    // Lent by the frame's transient targets, see set_scene
+    pub scene: Arc<RenderTarget>,
+    velocity: RenderTarget,
+    sampler: wgpu::Sampler,
+    params_buffer: Tracked<wgpu::Buffer>,
+    layout: wgpu::BindGroupLayout,
+    blur_pipeline: wgpu::RenderPipeline,
+    blur_bind_group: wgpu::BindGroup,
+    // One per mesh, their vertex strides differ
+    primitive_velocity_pipeline: wgpu::RenderPipeline,
+    textured_velocity_pipeline: wgpu::RenderPipeline,
+    texel_size: [f32; 2],
+}
+
+impl MotionBlur {
+    // The velocity pass is depth tested against the scene and draws the visible runs with a
+    // first instance, neither works on WebGL2
+    pub fn is_supported(capabilities: &Capabilities) -> bool {
+        capabilities.samples_depth && capabilities.first_instance
+    }
+
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        scene: Arc<RenderTarget>,
+    ) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("MotionBlurShader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/motion_blur.wgsl").into()),
+        });
+        let velocity_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("VelocityShader"),
+            source: wgpu::ShaderSource::Wgsl(VELOCITY_SOURCE.into()),
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+        let params_buffer = gpu_memory::create_buffer_init(
+            device,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Motion Blur Params Buffer"),
+                contents: bytemuck::cast_slice(&[
+                    <MotionBlurUniform as bytemuck::Zeroable>::zeroed(),
+                ]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            },
+            MemoryCategory::Uniforms,
+        );
+
+        let layout = Self::create_layout(device);
+        let blur_pipeline = create_fullscreen_pipeline(
+            device,
+            &shader,
+            &layout,
+            "fs_blur",
+            config.format,
+            wgpu::BlendState::REPLACE,
+            "Motion Blur Pipeline Layout",
+        );
+        let velocity_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Velocity Pipeline Layout"),
+            bind_group_layouts: &[camera_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let primitive_velocity_pipeline = Self::create_velocity_pipeline(
+            device,
+            &velocity_shader,
+            &velocity_layout,
+            PrimitiveVertex::desc(),
+        );
+        let textured_velocity_pipeline = Self::create_velocity_pipeline(
+            device,
+            &velocity_shader,
+            &velocity_layout,
+            TexturedVertex::desc(),
+        );
+
+        let velocity = Self::create_velocity(device, config);
+        let blur_bind_group =
+            Self::create_bind_group(device, &layout, &scene, &velocity, &sampler, &params_buffer);
+
+        Self {
+            scene,
+            velocity,
+            sampler,
+            params_buffer,
+            layout,
+            blur_pipeline,
+            blur_bind_group,
+            primitive_velocity_pipeline,
+            textured_velocity_pipeline,
+            texel_size: [
+                1.0 / config.width.max(1) as f32,
+                1.0 / config.height.max(1) as f32,
+            ],
+        }
+    }
+
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        scene: Arc<RenderTarget>,
+    ) {
+        self.scene = scene;
+        self.velocity = Self::create_velocity(device, config);
+        self.create_bind_group_for(device);
+        self.texel_size = [
+            1.0 / config.width.max(1) as f32,
+            1.0 / config.height.max(1) as f32,
+        ];
+    }
+
+    // Same as for depth of field, rebuilds the bind group only for another target
+    pub fn set_scene(&mut self, device: &wgpu::Device, scene: Arc<RenderTarget>) {
+        if !Arc::ptr_eq(&self.scene, &scene) {
+            self.scene = scene;
+            self.create_bind_group_for(device);
+        }
+    }
+
+    fn create_bind_group_for(&mut self, device: &wgpu::Device) {
+        self.blur_bind_group = Self::create_bind_group(
+            device,
+            &self.layout,
+            &self.scene,
+            &self.velocity,
+            &self.sampler,
+            &self.params_buffer,
+        );
+    }
+
+    pub fn update(&self, queue: &wgpu::Queue, settings: &Settings) {
+        let params = MotionBlurUniform {
+            texel_size: self.texel_size,
+            strength: settings.motion_blur_strength,
+            max_pixels: settings.motion_blur_max_pixels,
+        };
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[params]));
+    }
+
+    // Draws the screen space motion of the instances, tested against the scene's depth so
+    // only the visible surfaces write. Everything else keeps a velocity of zero
+    pub fn render_velocity<'a>(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        depth_view: &wgpu::TextureView,
+        camera_bind_group: &wgpu::BindGroup,
+        controllers: impl IntoIterator<Item = &'a InstanceController>,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Velocity Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &self.velocity.view,
+                depth_slice: None,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        for controller in controllers {
+            render_pass.set_pipeline(if controller.is_textured() {
+                &self.textured_velocity_pipeline
+            } else {
+                &self.primitive_velocity_pipeline
+            });
+            controller.render_velocity(&mut render_pass);
+        }
+    }
+
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder, output: &wgpu::TextureView) {
+        fullscreen_pass(
+            encoder,
+            "Motion Blur Pass",
+            output,
+            &self.blur_pipeline,
+            &self.blur_bind_group,
+        );
+    }
+
+    fn create_velocity_pipeline(
+        device: &wgpu::Device,
+        shader: &wgpu::ShaderModule,
+        layout: &wgpu::PipelineLayout,
+        vertex: wgpu::VertexBufferLayout<'static>,
+    ) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Velocity Pipeline"),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: Some("vs_velocity"),
+                buffers: &[vertex, InstanceRaw::desc(), InstanceRaw::previous_desc()],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: Some("fs_velocity"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: VELOCITY_FORMAT,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                cull_mode: Some(wgpu::Face::Back),
+                ..Default::default()
+            },
+            // Same geometry as the scene pass, equal depth passes
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: wgpu::TextureFormat::Depth32Float,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        })
+    }
+
+    fn create_velocity(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> RenderTarget {
+        RenderTarget::new(
+            device,
+            config.width,
+            config.height,
+            VELOCITY_FORMAT,
+            "motion_blur_velocity",
+        )
+    }
+
+    fn create_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        let texture = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+        };
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                texture(0),
+                texture(1),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("motion_blur_bind_group_layout"),
+        })
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        scene: &RenderTarget,
+        velocity: &RenderTarget,
+        sampler: &wgpu::Sampler,
+        params_buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&scene.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&velocity.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: params_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("motion_blur_bind_group"),
+        })
+    }
+}
+
+pub fn fullscreen_pass(
+    encoder: &mut wgpu::CommandEncoder,
+    label: &str,
+    target: &wgpu::TextureView,
+    pipeline: &wgpu::RenderPipeline,
+    bind_group: &wgpu::BindGroup,
+) {
+    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some(label),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view: target,
+            depth_slice: None,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                store: wgpu::StoreOp::Store,
+            },
+        })],
+        depth_stencil_attachment: None,
+        occlusion_query_set: None,
+        timestamp_writes: None,
+    });
+    render_pass.set_pipeline(pipeline);
+    render_pass.set_bind_group(0, bind_group, &[]);
+    render_pass.draw(0..3, 0..1);
+}
+
+pub fn create_fullscreen_pipeline(
+    device: &wgpu::Device,
+    shader: &wgpu::ShaderModule,
+    layout: &wgpu::BindGroupLayout,
+    entry_point: &str,
+    format: wgpu::TextureFormat,
+    blend: wgpu::BlendState,
+    label: &str,
+) -> wgpu::RenderPipeline {
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some(label),
+        bind_group_layouts: &[layout],
+        push_constant_ranges: &[],
+    });
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(entry_point),
+        layout: Some(&pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: Some("vs_main"),
+            buffers: &[],
+            compilation_options: Default::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: Some(entry_point),
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(blend),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: Default::default(),
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+        cache: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Point3, Vector2};
+    use wgpu::util::DeviceExt;
+
+    use super::*;
+    use crate::{
+        core::{camera::CameraUniform, test_support},
+        entity::texture::Texture,
+    };
+
+    const SIZE: u32 = 256;
+    const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Bgra8UnormSrgb;
+
+    // Three steps, each two cubes deep and one higher than the last
+    fn staircase() -> Vec<crate::entity::entity::Instance> {
+        let mut cubes = Vec::new();
+        for x in 0..6 {
+            for y in 0..=x / 2 {
+                for z in 0..3 {
+                    cubes.push(test_support::cube(Vector3::new(
+                        x as f32, y as f32, z as f32,
+                    )));
+                }
+            }
+        }
+        cubes
+    }
+
+    // Looking up the stairs so both the treads and the risers face the camera
+    fn staircase_camera() -> Camera {
+        Camera {
+            eye: Point3::new(-3.0, 7.0, 8.0),
+            target: Point3::new(3.0, 1.5, 1.5),
+            up: Vector3::unit_y(),
+            roll: 0.0,
+            aspect: 1.0,
+            fovy: 45.0,
+            znear: 0.1,
+            zfar: 100.0,
+            center_offset: Vector2::new(0.0, 0.0),
+        }
+    }
+
+    // The staircase on the primitive pipeline with its camera bound, like one chunk of a frame
+    struct Scene {
+        camera: Camera,
+        layout: wgpu::BindGroupLayout,
+        camera_bind_group: wgpu::BindGroup,
+        controller: InstanceController,
+        config: wgpu::SurfaceConfiguration,
+    }
+
+    impl Scene {
+        fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Scene {
+            let camera = staircase_camera();
+            let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+                label: Some("camera_bind_group_layout"),
+            });
+            let mut uniform = CameraUniform::new();
+            uniform.update_view_proj(&camera);
+            let camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Camera Buffer"),
+                contents: bytemuck::cast_slice(&[uniform]),
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+            let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: &layout,
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: camera_buffer.as_entire_binding(),
+                }],
+                label: Some("camera_bind_group"),
+            });
+            Scene {
+                camera,
+                layout,
+                camera_bind_group,
+                controller: test_support::controller(device, queue, staircase()),
+                config: wgpu::SurfaceConfiguration {
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                    format: FORMAT,
+                    width: SIZE,
+                    height: SIZE,
+                    present_mode: wgpu::PresentMode::Fifo,
+                    desired_maximum_frame_latency: 2,
+                    alpha_mode: wgpu::CompositeAlphaMode::Auto,
+                    view_formats: vec![],
+                },
+            }
+        }
+
+        // Clears `color` and `depth` and draws the staircase into them
+        fn draw(
+            &self,
+            encoder: &mut wgpu::CommandEncoder,
+            color: &wgpu::TextureView,
+            depth: &wgpu::TextureView,
+        ) {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: color,
+                    depth_slice: None,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: depth,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+            render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+            self.controller.render(&mut render_pass, None, false);
+        }
+    }
+
+    fn texture(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        usage: wgpu::TextureUsages,
+    ) -> wgpu::Texture {
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d {
+                width: SIZE,
+                height: SIZE,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage,
+            view_formats: &[],
+        })
+    }
+
+    // Depth the post processing passes can sample
+    fn depth_texture(device: &wgpu::Device) -> wgpu::Texture {
+        texture(
+            device,
+            Texture::DEPTH_FORMAT,
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        )
+    }
+
+    // Where the final pass writes, copied back after the frame
+    fn output_texture(device: &wgpu::Device) -> wgpu::Texture {
+        texture(
+            device,
+            FORMAT,
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        )
+    }
+
+    // Submits the frame in `encoder` and reads `output` back
+    fn finish(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        mut encoder: wgpu::CommandEncoder,
+        output: &wgpu::Texture,
+    ) -> Vec<u8> {
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: (SIZE * SIZE * 4) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        encoder.copy_texture_to_buffer(
+            output.as_image_copy(),
+            wgpu::TexelCopyBufferInfo {
+                buffer: &readback,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(SIZE * 4),
+                    rows_per_image: None,
+                },
+            },
+            output.size(),
+        );
+        queue.submit(Some(encoder.finish()));
+        readback.slice(..).map_async(wgpu::MapMode::Read, |_| {});
+        device.poll(wgpu::PollType::Wait).unwrap();
+        let pixels = readback.slice(..).get_mapped_range().to_vec();
+        readback.unmap();
+        pixels
+    }
+
+    // Draws the staircase, runs it through SSAO at `intensity` and reads the pixels back
+    fn render_staircase(device: &wgpu::Device, queue: &wgpu::Queue, intensity: f32) -> Vec<u8> {
+        let scene = Scene::new(device, queue);
+        let target = Arc::new(RenderTarget::new(device, SIZE, SIZE, FORMAT, "Scene"));
+        let depth = depth_texture(device).create_view(&Default::default());
+        let output = output_texture(device);
+
+        let ssao = AmbientOcclusion::new(device, queue, &scene.config, &depth, 0, target.clone());
+        let settings = Settings {
+            ssao_intensity: intensity,
+            ..Settings::default()
+        };
+        ssao.update(queue, &scene.camera, &settings);
+
+        let mut encoder = device.create_command_encoder(&Default::default());
+        scene.draw(&mut encoder, &target.view, &depth);
+        ssao.render(&mut encoder, &output.create_view(&Default::default()));
+        finish(device, queue, encoder, &output)
+    }
+
+    // Summed colour channels over a small square around where `point` lands on screen
+    fn brightness_at(pixels: &[u8], point: Vector3<f32>) -> u64 {
+        let (x, y) = staircase_camera()
+            .project_world_to_screen(point, SIZE as f32, SIZE as f32)
+            .unwrap();
+        let mut sum = 0;
+        for py in y as u32 - 2..=y as u32 + 2 {
+            for px in x as u32 - 2..=x as u32 + 2 {
+                let index = ((py * SIZE + px) * 4) as usize;
+                sum += pixels[index..index + 3]
+                    .iter()
+                    .map(|&c| c as u64)
+                    .sum::<u64>();
+            }
+        }
+        sum
+    }
+
+    #[test]
+    fn ssao_darkens_the_inside_corners_of_a_staircase() {
+        // GL can't load from depth textures, the pass is off there just like in State::new
+        let (device, queue) = match test_support::gpu_with_capabilities() {
+            Some((device, queue, capabilities))
+                if AmbientOcclusion::is_supported(&capabilities) =>
+            {
+                (device, queue)
+            }
+            _ => return,
+        };
+        let off = render_staircase(&device, &queue, 0.0);
+        let on = render_staircase(&device, &queue, 1.0);
+        // On the tread just in front of each riser, and in the middle of the open top step
+        let corners = [Vector3::new(1.9, 1.0, 1.5), Vector3::new(3.9, 2.0, 1.5)];
+        let open = Vector3::new(5.0, 3.0, 1.5);
+        for corner in corners.iter() {
+            let (lit, occluded) = (brightness_at(&off, *corner), brightness_at(&on, *corner));
+            assert!(
+                occluded * 10 < lit * 9,
+                "{} vs {} at {:?}",
+                occluded,
+                lit,
+                corner
+            );
+        }
+        let (lit, occluded) = (brightness_at(&off, open), brightness_at(&on, open));
+        assert!(
+            occluded * 20 > lit * 19,
+            "{} vs {} in the open",
+            occluded,
+            lit
+        );
+    }
+
+    // Draws the staircase once its previous transforms caught up, through motion blur or not
+    fn render_settled(device: &wgpu::Device, queue: &wgpu::Queue, blur: bool) -> Vec<u8> {
+        let mut scene = Scene::new(device, queue);
+        scene.controller.set_track_previous(true, queue, device);
+        // A few frames without motion, as after the scene stopped moving
+        for _ in 0..3 {
+            scene.controller.update_buffer(queue);
+        }
+        let depth = depth_texture(device).create_view(&Default::default());
+        let output = output_texture(device);
+        let output_view = output.create_view(&Default::default());
+
+        let mut encoder = device.create_command_encoder(&Default::default());
+        if blur {
+            let target = Arc::new(RenderTarget::new(device, SIZE, SIZE, FORMAT, "Scene"));
+            let motion_blur = MotionBlur::new(device, &scene.config, &scene.layout, target.clone());
+            motion_blur.update(queue, &Settings::default());
+            scene.draw(&mut encoder, &target.view, &depth);
+            motion_blur.render_velocity(
+                &mut encoder,
+                &depth,
+                &scene.camera_bind_group,
+                std::iter::once(&scene.controller),
+            );
+            motion_blur.render(&mut encoder, &output_view);
+        } else {
+            scene.draw(&mut encoder, &output_view, &depth);
+        }
+        finish(device, queue, encoder, &output)
+    }
+
+    #[test]
+    fn motion_blur_leaves_a_static_frame_alone() {
+        let (device, queue) = match test_support::gpu() {
+            Some(gpu) => gpu,
+            None => return,
+        };
+        let sharp = render_settled(&device, &queue, false);
+        let blurred = render_settled(&device, &queue, true);
+        assert!(sharp.iter().any(|&channel| channel > 0));
+        assert!(sharp == blurred);
+    }
+}
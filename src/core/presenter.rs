@@ -0,0 +1,182 @@
+// A second native window showing the scene from its own free orbit camera, for presenting
+// while the main window follows the scroll. It shares the device, pipelines and instance
+// buffers with State and only owns its surface, depth target and camera
+
+use std::sync::Arc;
+
+use winit::{dpi::PhysicalSize, event::WindowEvent, window::Window};
+
+use crate::{
+    core::{
+        camera::{aspect_for, Camera, CameraController, CameraUniform},
+        game_loop::Gameloop,
+        gpu_memory::{self, MemoryCategory, Tracked},
+    },
+    entity::primitive_texture::PrimitiveTexture,
+};
+
+pub struct PresenterView {
+    pub window: Arc<Window>,
+    surface: wgpu::Surface<'static>,
+    config: wgpu::SurfaceConfiguration,
+    configured: bool,
+    // Both meshes use the same depth format, one target serves either
+    depth_texture: PrimitiveTexture,
+    pub camera: Camera,
+    pub camera_controller: CameraController,
+    camera_uniform: CameraUniform,
+    camera_buffer: Tracked<wgpu::Buffer>,
+    camera_bind_group: wgpu::BindGroup,
+}
+
+impl PresenterView {
+    // None when the window's surface can't take the format the shared pipelines were built for
+    pub fn new(
+        window: Arc<Window>,
+        instance: &wgpu::Instance,
+        adapter: &wgpu::Adapter,
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        camera: Camera,
+    ) -> Option<PresenterView> {
+        let surface = instance
+            .create_surface(window.clone())
+            .map_err(|error| log::error!("Presenter surface failed: {}", error))
+            .ok()?;
+        let caps = surface.get_capabilities(adapter);
+        if !caps.formats.contains(&format) {
+            log::error!("Presenter surface doesn't support {:?}", format);
+            return None;
+        }
+        let size = window.inner_size();
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format,
+            width: size.width,
+            height: size.height,
+            present_mode: caps.present_modes[0],
+            alpha_mode: caps.alpha_modes[0],
+            view_formats: vec![],
+            desired_maximum_frame_latency: 2,
+        };
+        let mut camera_uniform = CameraUniform::new();
+        camera_uniform.update_view_proj(&camera);
+        let camera_buffer = gpu_memory::create_buffer_init(
+            device,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Presenter Camera Buffer"),
+                contents: bytemuck::cast_slice(&[camera_uniform]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            },
+            MemoryCategory::Uniforms,
+        );
+        // Same layout as the main camera, the shared pipelines take either bind group
+        let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: camera_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: camera_buffer.as_entire_binding(),
+            }],
+            label: Some("presenter_camera_bind_group"),
+        });
+        let mut view = PresenterView {
+            window,
+            surface,
+            depth_texture: PrimitiveTexture::create_depth_texture(
+                device,
+                &config,
+                "presenter_depth_texture",
+            ),
+            config,
+            configured: false,
+            camera,
+            camera_controller: CameraController::new(0.2),
+            camera_uniform,
+            camera_buffer,
+            camera_bind_group,
+        };
+        view.resize(device, size);
+        Some(view)
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, size: PhysicalSize<u32>) {
+        if size.width == 0 || size.height == 0 {
+            self.configured = false;
+            return;
+        }
+        self.config.width = size.width;
+        self.config.height = size.height;
+        self.surface.configure(device, &self.config);
+        self.configured = true;
+        self.camera.aspect = aspect_for(size);
+        self.depth_texture =
+            PrimitiveTexture::create_depth_texture(device, &self.config, "presenter_depth_texture");
+    }
+
+    pub fn input(&mut self, event: &WindowEvent) {
+        self.camera_controller.process_events(event, &self.camera);
+    }
+
+    // Returns whether the camera still moves
+    pub fn update(&mut self, queue: &wgpu::Queue, dt: f32) -> bool {
+        self.camera_controller.update_camera(&mut self.camera, dt);
+        self.camera_controller.update_pose(&mut self.camera, dt);
+        self.camera_uniform.update_view_proj(&self.camera);
+        queue.write_buffer(
+            &self.camera_buffer,
+            0,
+            bytemuck::cast_slice(&[self.camera_uniform]),
+        );
+        let size = PhysicalSize::new(self.config.width, self.config.height);
+        self.camera_controller.is_moving(&self.camera, &size)
+    }
+
+    // Just the cubes, the overlays and post effects belong to the main window
+    pub fn render(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        game_loop: &Gameloop,
+    ) -> Result<(), wgpu::SurfaceError> {
+        if !self.configured {
+            return Ok(());
+        }
+        let output = self.surface.get_current_texture()?;
+        let view = output
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Presenter Encoder"),
+        });
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Presenter Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    depth_slice: None,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.depth_texture.view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+            render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+            game_loop.render(&mut render_pass);
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+        output.present();
+        Ok(())
+    }
+}
@@ -0,0 +1,168 @@
+// First load entrance. Cubes the builder delivers stay hidden until the loading screen is
+// gone, then appear in a ring growing out from the grid center
+
+use cgmath::Vector3;
+
+use crate::core::game_loop::Chunk;
+
+pub struct GridReveal {
+    duration: f32,
+    // None until the loading screen has faded
+    elapsed: Option<f32>,
+    // Scroll progress when the ring started, scrolling away skips the rest
+    progress: f32,
+    // Hidden cubes by chunk and instance index, with their position on the floor
+    hidden: Vec<(Chunk, usize, f32, f32)>,
+    min: (f32, f32),
+    max: (f32, f32),
+    done: bool,
+}
+
+impl GridReveal {
+    pub fn new(duration: f32) -> GridReveal {
+        GridReveal {
+            duration,
+            elapsed: None,
+            progress: 0.0,
+            hidden: Vec::new(),
+            min: (f32::INFINITY, f32::INFINITY),
+            max: (f32::NEG_INFINITY, f32::NEG_INFINITY),
+            done: false,
+        }
+    }
+
+    // Whether newly installed cubes should still be held back
+    pub fn is_pending(&self) -> bool {
+        !self.done
+    }
+
+    pub fn is_running(&self) -> bool {
+        !self.done && self.elapsed.is_some()
+    }
+
+    pub fn hide(&mut self, chunk: Chunk, index: usize, position: Vector3<f32>) {
+        self.min = (self.min.0.min(position.x), self.min.1.min(position.z));
+        self.max = (self.max.0.max(position.x), self.max.1.max(position.z));
+        self.hidden.push((chunk, index, position.x, position.z));
+    }
+
+    pub fn start(&mut self, progress: f32) {
+        if self.elapsed.is_none() {
+            self.elapsed = Some(0.0);
+            self.progress = progress;
+        }
+    }
+
+    // Cubes the ring reached this frame, all remaining ones once it ran `duration` seconds
+    // or the scroll moved. Finishes the reveal when nothing is left
+    pub fn update(&mut self, dt: f32, progress: f32) -> Vec<(Chunk, usize)> {
+        let elapsed = match &mut self.elapsed {
+            Some(elapsed) if !self.done => {
+                *elapsed += dt;
+                *elapsed
+            }
+            _ => return Vec::new(),
+        };
+        if progress != self.progress {
+            return self.finish();
+        }
+        let center = (
+            (self.min.0 + self.max.0) / 2.0,
+            (self.min.1 + self.max.1) / 2.0,
+        );
+        let extent = ((self.max.0 - center.0).powi(2) + (self.max.1 - center.1).powi(2)).sqrt();
+        let radius = extent * (elapsed / self.duration.max(f32::EPSILON));
+        let mut reached = Vec::new();
+        self.hidden.retain(|&(chunk, index, x, z)| {
+            let inside = (x - center.0).powi(2) + (z - center.1).powi(2) <= radius * radius;
+            if inside {
+                reached.push((chunk, index));
+            }
+            !inside
+        });
+        if self.hidden.is_empty() && elapsed >= self.duration {
+            self.done = true;
+        }
+        reached
+    }
+
+    // Every cube still hidden, the reveal is over afterwards
+    pub fn finish(&mut self) -> Vec<(Chunk, usize)> {
+        self.done = true;
+        self.hidden
+            .drain(..)
+            .map(|(chunk, index, _, _)| (chunk, index))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ORIGIN: Chunk = Chunk { x: 0, y: 0 };
+
+    // A 9×9 floor held back by a two second reveal, cube index x + 9z
+    fn floor() -> GridReveal {
+        let mut reveal = GridReveal::new(2.0);
+        for z in 0..9 {
+            for x in 0..9 {
+                reveal.hide(ORIGIN, x + 9 * z, Vector3::new(x as f32, 0.0, z as f32));
+            }
+        }
+        reveal
+    }
+
+    fn distance_from_center(index: usize) -> f32 {
+        let (x, z) = ((index % 9) as f32 - 4.0, (index / 9) as f32 - 4.0);
+        (x * x + z * z).sqrt()
+    }
+
+    #[test]
+    fn nothing_shows_before_the_loading_screen_is_gone() {
+        let mut reveal = floor();
+        assert!(reveal.update(5.0, 0.0).is_empty());
+        assert!(reveal.is_pending());
+        assert!(!reveal.is_running());
+    }
+
+    #[test]
+    fn the_ring_grows_out_from_the_center() {
+        let mut reveal = floor();
+        reveal.start(0.0);
+        let mut shown = Vec::new();
+        let mut frames = 0;
+        while reveal.is_pending() {
+            let reached = reveal.update(0.1, 0.0);
+            // Every cube this frame lies further out than every cube of the frames before
+            let nearest = reached
+                .iter()
+                .map(|&(_, index)| distance_from_center(index))
+                .fold(f32::INFINITY, f32::min);
+            let furthest = shown
+                .iter()
+                .map(|&index| distance_from_center(index))
+                .fold(f32::NEG_INFINITY, f32::max);
+            assert!(reached.is_empty() || nearest > furthest);
+            shown.extend(reached.iter().map(|&(_, index)| index));
+            frames += 1;
+        }
+        assert_eq!(shown[0], 40);
+        shown.sort_unstable();
+        assert_eq!(shown, (0..81).collect::<Vec<_>>());
+        // Done right as the duration runs out
+        assert_eq!(frames, 20);
+    }
+
+    #[test]
+    fn scrolling_shows_the_rest_at_once() {
+        let mut reveal = floor();
+        reveal.start(0.25);
+        let first = reveal.update(0.5, 0.25);
+        assert!(!first.is_empty() && first.len() < 81);
+        let rest = reveal.update(0.016, 0.3);
+        assert_eq!(first.len() + rest.len(), 81);
+        assert!(!reveal.is_pending());
+        assert!(reveal.update(0.016, 0.3).is_empty());
+    }
+}
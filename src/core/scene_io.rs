@@ -0,0 +1,371 @@
+// Scene snapshots as JSON for external tools and bug reproductions. F6 exports, F7 imports
+// the last export on native. The page can call export_scene/import_scene on wasm
+
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use cgmath::Vector3;
+
+use crate::{
+    core::{
+        camera::Camera,
+        game_loop::{Chunk, Gameloop},
+    },
+    helpers::{
+        animation::color_groups,
+        json::{self, write_vec3, JsonValue},
+    },
+};
+
+pub const SCENE_FILE: &str = "scene.json";
+const SCENE_VERSION: u32 = 1;
+
+static EXPORT_REQUEST: AtomicBool = AtomicBool::new(false);
+static IMPORT_REQUEST: Mutex<Option<String>> = Mutex::new(None);
+
+pub fn request_export() {
+    EXPORT_REQUEST.store(true, Ordering::SeqCst);
+}
+
+pub fn take_export_request() -> bool {
+    EXPORT_REQUEST.swap(false, Ordering::SeqCst)
+}
+
+pub fn request_import(json: String) {
+    if let Ok(mut request) = IMPORT_REQUEST.lock() {
+        *request = Some(json);
+    }
+}
+
+pub fn take_import_request() -> Option<String> {
+    IMPORT_REQUEST
+        .lock()
+        .ok()
+        .and_then(|mut request| request.take())
+}
+
+pub fn scene_requests_pending() -> bool {
+    EXPORT_REQUEST.load(Ordering::SeqCst)
+        || IMPORT_REQUEST.lock().is_ok_and(|request| request.is_some())
+}
+
+// Writes instance by instance, so large scenes never build the whole document in memory
+pub fn write_scene(
+    game_loop: &Gameloop,
+    camera: &Camera,
+    out: &mut impl Write,
+) -> std::io::Result<()> {
+    write!(out, "{{\"version\":{},\"camera\":{{\"eye\":", SCENE_VERSION)?;
+    write_vec3(out, camera.eye.into())?;
+    write!(out, ",\"target\":")?;
+    write_vec3(out, camera.target.into())?;
+    write!(out, ",\"fovy\":{}}},\"section\":", camera.fovy)?;
+    match game_loop.section_tracker.active() {
+        Some(section) => write!(out, "{}", section)?,
+        None => write!(out, "null")?,
+    }
+    write!(out, ",\"chunks\":[")?;
+    for (n, chunk) in sorted_chunks(game_loop).iter().enumerate() {
+        let controller = &game_loop.chunk_map[chunk];
+        let groups = color_groups(&controller.instances);
+        if n > 0 {
+            write!(out, ",")?;
+        }
+        write!(out, "{{\"chunk\":[{},{}],\"instances\":[", chunk.x, chunk.y)?;
+        for (index, (instance, group)) in controller.instances.iter().zip(groups).enumerate() {
+            if index > 0 {
+                write!(out, ",")?;
+            }
+            write!(out, "{{\"position\":")?;
+            write_vec3(out, instance.position.into())?;
+            write!(out, ",\"color\":")?;
+            write_vec3(out, instance.color.into())?;
+            write!(
+                out,
+                ",\"scale\":{},\"visible\":{},\"group\":{}}}",
+                instance.scale, instance.should_render, group
+            )?;
+        }
+        write!(out, "]}}")?;
+    }
+    write!(out, "]}}")?;
+    out.flush()
+}
+
+// A chunk's instances as read from the file, checked before anything is applied
+struct SavedChunk {
+    chunk: Chunk,
+    instances: Vec<SavedInstance>,
+}
+
+struct SavedInstance {
+    position: Vector3<f32>,
+    color: Vector3<f32>,
+    visible: bool,
+}
+
+// Restores positions, colors and visibility of the chunks present in both the scene and
+// the file, returns how many instances were restored. The whole document is checked
+// first, a bad entry leaves the scene as it was
+pub fn read_scene(game_loop: &mut Gameloop, text: &str) -> Result<usize, String> {
+    let saved = parse_scene(game_loop, text)?;
+    let origin = Chunk { x: 0, y: 0 };
+    let mut restored = 0;
+    for SavedChunk { chunk, instances } in saved {
+        let controller = match game_loop.chunk_map.get_mut(&chunk) {
+            Some(controller) => controller,
+            None => continue,
+        };
+        for (index, (instance, saved)) in controller.instances.iter_mut().zip(instances).enumerate()
+        {
+            instance.position = saved.position;
+            instance.bounding = saved.position + instance.size;
+            instance.color = saved.color;
+            instance.should_render = saved.visible;
+            // Chunk (0, 0) is driven by its animations, restore them as well
+            if chunk == origin {
+                if let Some(animation) = game_loop.animation_handler.movement_list.get_mut(index) {
+                    animation.current_pos = saved.position;
+                }
+                game_loop
+                    .animation_handler
+                    .set_manual_color(index, saved.color);
+            }
+            restored += 1;
+        }
+        controller.update_buffer(&game_loop.queue);
+    }
+    Ok(restored)
+}
+
+// The chunks of the document that exist in the scene, each holding exactly as many
+// instances as its controller
+fn parse_scene(game_loop: &Gameloop, text: &str) -> Result<Vec<SavedChunk>, String> {
+    let document = json::parse(text)?;
+    match document.get("version").and_then(JsonValue::as_f32) {
+        Some(version) if version == SCENE_VERSION as f32 => {}
+        Some(version) => return Err(format!("unsupported version {}", version)),
+        None => return Err("missing version".to_string()),
+    }
+    let chunks = document
+        .get("chunks")
+        .and_then(JsonValue::as_array)
+        .ok_or("missing chunks")?;
+    let mut saved = Vec::new();
+    for entry in chunks {
+        let coordinates = entry
+            .get("chunk")
+            .and_then(JsonValue::as_array)
+            .ok_or("chunk without coordinates")?;
+        let chunk = match coordinates {
+            [x, y] => Chunk {
+                x: x.as_f32().ok_or("invalid chunk")? as i32,
+                y: y.as_f32().ok_or("invalid chunk")? as i32,
+            },
+            _ => return Err("invalid chunk".to_string()),
+        };
+        let instances = entry
+            .get("instances")
+            .and_then(JsonValue::as_array)
+            .ok_or("chunk without instances")?;
+        let controller = match game_loop.chunk_map.get(&chunk) {
+            Some(controller) => controller,
+            None => continue,
+        };
+        if instances.len() != controller.instances.len() {
+            return Err(format!(
+                "chunk ({}, {}) has {} instances, the scene has {}",
+                chunk.x,
+                chunk.y,
+                instances.len(),
+                controller.instances.len()
+            ));
+        }
+        let instances = instances
+            .iter()
+            .enumerate()
+            .map(|(index, saved)| {
+                let position = saved.get("position").and_then(vec3);
+                let color = saved.get("color").and_then(vec3);
+                let visible = saved.get("visible").and_then(JsonValue::as_bool);
+                match (position, color, visible) {
+                    (Some(position), Some(color), Some(visible)) => Ok(SavedInstance {
+                        position,
+                        color,
+                        visible,
+                    }),
+                    _ => Err(format!("invalid instance {} in chunk", index)),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        saved.push(SavedChunk { chunk, instances });
+    }
+    Ok(saved)
+}
+
+fn vec3(value: &JsonValue) -> Option<Vector3<f32>> {
+    match value.as_array()? {
+        [x, y, z] => Some(Vector3::new(x.as_f32()?, y.as_f32()?, z.as_f32()?)),
+        _ => None,
+    }
+}
+
+fn sorted_chunks(game_loop: &Gameloop) -> Vec<Chunk> {
+    let mut chunks = game_loop.chunk_map.keys().copied().collect::<Vec<_>>();
+    chunks.sort_by_key(|chunk| (chunk.x, chunk.y));
+    chunks
+}
+
+// Native writes scene.json next to the other saved state, wasm hands the page a download
+pub fn export_scene(game_loop: &Gameloop, camera: &Camera) {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let dir = crate::core::storage::storage_dir();
+        let path = dir.join(SCENE_FILE);
+        let result = std::fs::create_dir_all(&dir)
+            .and_then(|_| std::fs::File::create(&path))
+            .and_then(|file| {
+                game_loop.export_scene_json(camera, &mut std::io::BufWriter::new(file))
+            });
+        match result {
+            Ok(()) => log::warn!("Scene exported to {}", path.display()),
+            Err(error) => log::error!("Scene export failed: {}", error),
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    {
+        let mut out = Vec::new();
+        if let Err(error) = game_loop.export_scene_json(camera, &mut out) {
+            log::error!("Scene export failed: {}", error);
+            return;
+        }
+        download(SCENE_FILE, &String::from_utf8_lossy(&out));
+    }
+}
+
+pub fn import_scene(game_loop: &mut Gameloop, text: &str) {
+    match game_loop.import_scene_json(text) {
+        Ok(restored) => log::warn!("Scene imported, {} instances restored", restored),
+        Err(error) => log::error!("Scene import failed: {}", error),
+    }
+}
+
+// Queues the last native export for import
+#[cfg(not(target_arch = "wasm32"))]
+pub fn request_import_exported() {
+    match crate::core::storage::load(SCENE_FILE) {
+        Some(json) => request_import(json),
+        None => log::warn!("No exported scene to import"),
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn download(name: &str, text: &str) {
+    use wasm_bindgen::JsCast;
+
+    let parts = wasm_bindgen_futures::js_sys::Array::of1(&text.into());
+    let options = web_sys::BlobPropertyBag::new();
+    options.set_type("application/json");
+    let url = match web_sys::Blob::new_with_str_sequence_and_options(&parts, &options)
+        .and_then(|blob| web_sys::Url::create_object_url_with_blob(&blob))
+    {
+        Ok(url) => url,
+        Err(_) => return,
+    };
+    let document = match web_sys::window().and_then(|window| window.document()) {
+        Some(document) => document,
+        None => return,
+    };
+    if let Ok(anchor) = document.create_element("a") {
+        if let Ok(anchor) = anchor.dyn_into::<web_sys::HtmlAnchorElement>() {
+            anchor.set_href(&url);
+            anchor.set_download(name);
+            anchor.click();
+        }
+    }
+    let _ = web_sys::Url::revoke_object_url(&url);
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Point3;
+
+    use super::*;
+    use crate::core::test_support;
+
+    fn export(game_loop: &Gameloop, camera: &Camera) -> String {
+        let mut out = Vec::new();
+        write_scene(game_loop, camera, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn export_then_import_restores_the_scene() {
+        let mut cubes = test_support::row(6);
+        for (n, cube) in cubes.iter_mut().enumerate() {
+            cube.color = Vector3::new(n as f32 / 6.0, 0.25, 1.0 / 3.0);
+        }
+        cubes[4].should_render = false;
+        let mut game_loop = test_support::game_loop(cubes);
+        let camera = test_support::camera_above(Point3::new(3.0, 0.0, 0.0));
+        let exported = export(&game_loop, &camera);
+
+        let origin = Chunk { x: 0, y: 0 };
+        for instance in game_loop
+            .chunk_map
+            .get_mut(&origin)
+            .unwrap()
+            .instances
+            .iter_mut()
+        {
+            instance.position += Vector3::new(0.5, 2.0, -1.0);
+            instance.color = Vector3::new(0.0, 0.0, 0.0);
+            instance.should_render = !instance.should_render;
+        }
+        assert_ne!(export(&game_loop, &camera), exported);
+
+        assert_eq!(read_scene(&mut game_loop, &exported), Ok(6));
+        assert_eq!(export(&game_loop, &camera), exported);
+    }
+
+    #[test]
+    fn import_rejects_broken_documents() {
+        let mut game_loop = test_support::game_loop(test_support::row(2));
+        assert!(read_scene(&mut game_loop, "{").is_err());
+        assert!(read_scene(&mut game_loop, "{\"version\":1}").is_err());
+        let missing_color =
+            "{\"version\":1,\"chunks\":[{\"chunk\":[0,0],\"instances\":[{\"position\":[0,0,0],\"visible\":true}]}]}";
+        assert!(read_scene(&mut game_loop, missing_color).is_err());
+    }
+
+    #[test]
+    fn a_bad_entry_leaves_the_scene_unchanged() {
+        let mut game_loop = test_support::game_loop(test_support::row(3));
+        let camera = test_support::camera_above(Point3::new(1.0, 0.0, 0.0));
+        let before = export(&game_loop, &camera);
+
+        // The first two entries are fine, the last one lost its color
+        let broken = "{\"version\":1,\"chunks\":[{\"chunk\":[0,0],\"instances\":[\
+            {\"position\":[5,5,5],\"color\":[1,0,0],\"visible\":false},\
+            {\"position\":[6,5,5],\"color\":[1,0,0],\"visible\":false},\
+            {\"position\":[7,5,5],\"visible\":false}]}]}";
+        assert!(read_scene(&mut game_loop, broken).is_err());
+        assert_eq!(export(&game_loop, &camera), before);
+
+        // One entry short of the scene, zipping them would drop the last cube silently
+        let last = before.rfind(",{\"position\"").unwrap();
+        let short = format!(
+            "{}{}",
+            &before[..last],
+            &before[before.rfind("]}]").unwrap()..]
+        );
+        assert!(read_scene(&mut game_loop, &short).is_err());
+        assert_eq!(export(&game_loop, &camera), before);
+
+        let newer = before.replacen("\"version\":1", "\"version\":2", 1);
+        assert!(read_scene(&mut game_loop, &newer).is_err());
+        assert!(read_scene(&mut game_loop, &before).is_ok());
+    }
+}
@@ -0,0 +1,192 @@
+// Eases the scroll progress onto the nearest section once the user stops scrolling
+
+use std::sync::Mutex;
+
+use crate::{
+    core::settings::Settings, entity::progress_bar::PROGRESS_CUBES,
+    helpers::animation::EaseInEaseOut,
+};
+
+// How quickly the velocity estimate forgets old input
+const VELOCITY_SMOOTHING: f32 = 0.1;
+
+struct SnapEase {
+    from: f32,
+    to: f32,
+    time: f32,
+}
+
+#[derive(Default)]
+pub struct ScrollSnap {
+    // Progress per second
+    velocity: f32,
+    pending: f32,
+    ease: Option<SnapEase>,
+    // The running ease is a keyboard jump rather than a snap
+    jumping: bool,
+}
+
+impl ScrollSnap {
+    // New input always wins over a running snap
+    pub fn on_scroll(&mut self, delta: f32) {
+        self.ease = None;
+        self.jumping = false;
+        self.pending += delta;
+    }
+
+    // Eases from `from` onto `to` like a snap, however far apart they are
+    pub fn jump(&mut self, from: f32, to: f32) {
+        self.velocity = 0.0;
+        self.pending = 0.0;
+        self.ease = Some(SnapEase {
+            from,
+            to,
+            time: 0.0,
+        });
+        self.jumping = true;
+    }
+
+    // Forgets the velocity and any running snap, for jumps that aren't user scrolling
+    pub fn reset(&mut self) {
+        self.velocity = 0.0;
+        self.pending = 0.0;
+        self.ease = None;
+        self.jumping = false;
+    }
+
+    pub fn is_snapping(&self) -> bool {
+        self.ease.is_some()
+    }
+
+    pub fn is_jumping(&self) -> bool {
+        self.jumping
+    }
+
+    // Where the running snap or jump ends up
+    pub fn target(&self) -> Option<f32> {
+        self.ease.as_ref().map(|ease| ease.to)
+    }
+
+    pub fn update(&mut self, progress: &mut f32, dt: f32, settings: &Settings) {
+        if dt > 0.0 {
+            let blend = 1.0 - (-dt / VELOCITY_SMOOTHING).exp();
+            self.velocity += (self.pending / dt - self.velocity) * blend;
+        }
+        self.pending = 0.0;
+
+        if let Some(ease) = &mut self.ease {
+            ease.time += dt / settings.scroll_snap_duration.max(f32::EPSILON);
+            let t = EaseInEaseOut::ease_in_ease_out_cubic(ease.time);
+            *progress = ease.from + (ease.to - ease.from) * t;
+            if ease.time >= 1.0 {
+                self.ease = None;
+                self.jumping = false;
+            }
+            return;
+        }
+        if !settings.scroll_snap || self.velocity.abs() > settings.scroll_snap_velocity {
+            return;
+        }
+        let sections = (PROGRESS_CUBES - 1) as f32;
+        let anchor = (*progress * sections).round() / sections;
+        let distance = (anchor - *progress).abs();
+        if distance > f32::EPSILON && distance <= settings.scroll_snap_fraction / sections {
+            self.ease = Some(SnapEase {
+                from: *progress,
+                to: anchor,
+                time: 0.0,
+            });
+        }
+    }
+}
+
+// Progress the page was restored at, set when the browser brings the page back from the
+// back/forward cache and picked up on the next frame
+static RESTORE_REQUEST: Mutex<Option<f32>> = Mutex::new(None);
+
+pub fn request_restore(progress: f32) {
+    if let Ok(mut request) = RESTORE_REQUEST.lock() {
+        *request = Some(progress.clamp(0.0, 1.0));
+    }
+}
+
+pub fn restore_pending() -> bool {
+    RESTORE_REQUEST
+        .lock()
+        .is_ok_and(|request| request.is_some())
+}
+
+pub fn take_restore_request() -> Option<f32> {
+    RESTORE_REQUEST
+        .lock()
+        .ok()
+        .and_then(|mut request| request.take())
+}
+
+// Fraction of the page scrolled, a page too short to scroll is at the start
+pub fn page_progress(scroll_y: f64, range: f64) -> f32 {
+    if range > 0.0 {
+        (scroll_y / range).clamp(0.0, 1.0) as f32
+    } else {
+        0.0
+    }
+}
+
+// Page height the scroll position can cover
+#[cfg(target_arch = "wasm32")]
+fn scroll_range(window: &wgpu::web_sys::Window) -> f64 {
+    let scroll_height = window
+        .document()
+        .and_then(|document| document.document_element())
+        .map(|element| element.scroll_height() as f64)
+        .unwrap_or(0.0);
+    let view_height = window
+        .inner_height()
+        .ok()
+        .and_then(|height| height.as_f64())
+        .unwrap_or(0.0);
+    (scroll_height - view_height).max(0.0)
+}
+
+// Mirrors the snapped progress onto the page scroll position
+#[cfg(target_arch = "wasm32")]
+pub fn scroll_page_to(progress: f32) {
+    let window = match wgpu::web_sys::window() {
+        Some(window) => window,
+        None => return,
+    };
+    window.scroll_to_with_x_and_y(0.0, progress as f64 * scroll_range(&window));
+}
+
+// A page restored from the back/forward cache comes back at its old scroll position with
+// the scene in whatever state it was left. Requests a restore instead of letting the jump
+// play out as scrolling
+#[cfg(target_arch = "wasm32")]
+pub fn watch_page_restore() {
+    use wasm_bindgen::{closure::Closure, JsCast};
+
+    let window = match wgpu::web_sys::window() {
+        Some(window) => window,
+        None => return,
+    };
+    let listener = Closure::<dyn FnMut(web_sys::PageTransitionEvent)>::new(
+        |event: web_sys::PageTransitionEvent| {
+            if !event.persisted() {
+                return;
+            }
+            if let Some(window) = wgpu::web_sys::window() {
+                request_restore(page_progress(
+                    window.scroll_y().unwrap_or(0.0),
+                    scroll_range(&window),
+                ));
+            }
+        },
+    );
+    if window
+        .add_event_listener_with_callback("pageshow", listener.as_ref().unchecked_ref())
+        .is_ok()
+    {
+        // Lives as long as the page
+        listener.forget();
+    }
+}
@@ -0,0 +1,146 @@
+// Info shown for each CV section once the scroll progress settles on it
+
+use crate::{
+    core::{camera_script::CameraCue, detail::DetailSpec, status},
+    entity::progress_bar::PROGRESS_CUBES,
+    helpers::json::json_string,
+};
+
+#[derive(Clone, Debug, Default)]
+pub struct SectionInfo {
+    pub title: String,
+    pub years: String,
+    pub tags: Vec<String>,
+    pub links: Vec<String>,
+    pub policy: InteractionPolicy,
+    // Hover tooltips per color group of the cubes as built, in the order colors first appear
+    pub labels: Vec<String>,
+    // Animation template or effect preset played on the cubes when the section activates
+    pub entrance: Option<String>,
+    // Scripted camera moves at points of the section's progress, see SectionCameraScript
+    pub camera: Vec<CameraCue>,
+    // Splits the cubes nearest the camera into smaller ones up close, see DetailRefiner
+    pub detail: Option<DetailSpec>,
+}
+
+// What visitors may do to the cubes while the section is active, everything by default
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InteractionPolicy {
+    pub allow_delete: bool,
+    pub allow_explode: bool,
+    pub allow_place: bool,
+    pub allow_paint: bool,
+}
+
+impl Default for InteractionPolicy {
+    fn default() -> Self {
+        InteractionPolicy {
+            allow_delete: true,
+            allow_explode: true,
+            allow_place: true,
+            allow_paint: true,
+        }
+    }
+}
+
+pub const MAX_TAGS: usize = 4;
+
+// Reports the section the progress has come to rest on, once per change
+#[derive(Default)]
+pub struct SectionTracker {
+    active: Option<usize>,
+}
+
+impl SectionTracker {
+    pub fn active(&self) -> Option<usize> {
+        self.active
+    }
+
+    pub fn update(&mut self, progress: f32, settled: bool) -> Option<usize> {
+        if !settled {
+            return None;
+        }
+        let sections = (PROGRESS_CUBES - 1) as f32;
+        let anchor = (progress * sections).round();
+        if (anchor - progress * sections).abs() > 1e-3 {
+            return None;
+        }
+        let index = anchor as usize;
+        if self.active == Some(index) {
+            return None;
+        }
+        self.active = Some(index);
+        Some(index)
+    }
+
+    // Jumps straight to the section the progress falls in and reports it even if it was
+    // already active, so a restored page hears about its section exactly once
+    pub fn restore(&mut self, progress: f32) -> usize {
+        let sections = (PROGRESS_CUBES - 1) as f32;
+        let index = (progress.clamp(0.0, 1.0) * sections).round() as usize;
+        self.active = Some(index);
+        index
+    }
+}
+
+impl SectionInfo {
+    pub fn to_json(&self, index: usize) -> String {
+        let list = |items: &[String]| {
+            items
+                .iter()
+                .map(|item| json_string(item))
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        format!(
+            "{{\"index\":{},\"title\":{},\"years\":{},\"tags\":[{}],\"links\":[{}]}}",
+            index,
+            json_string(&self.title),
+            json_string(&self.years),
+            list(&self.tags[..self.tags.len().min(MAX_TAGS)]),
+            list(&self.links),
+        )
+    }
+}
+
+// The latest scene status rides along so listeners don't have to poll for it
+fn section_json(index: usize, info: Option<&SectionInfo>) -> String {
+    let section = match info {
+        Some(info) => info.to_json(index),
+        None => format!("{{\"index\":{}}}", index),
+    };
+    match status::latest() {
+        Some(status) => format!(
+            "{},\"status\":{}}}",
+            section.strip_suffix('}').unwrap_or(&section),
+            status.to_json()
+        ),
+        None => section,
+    }
+}
+
+// Lets the page render the panel as HTML, listen for "cv-section" on window.
+// Sections without info only carry their index so the page can hide the panel
+#[cfg(target_arch = "wasm32")]
+pub fn dispatch_section(index: usize, info: Option<&SectionInfo>) {
+    use wasm_bindgen_futures::js_sys::JSON;
+
+    let window = match wgpu::web_sys::window() {
+        Some(window) => window,
+        None => return,
+    };
+    let detail = match JSON::parse(&section_json(index, info)) {
+        Ok(detail) => detail,
+        Err(_) => return,
+    };
+    let init = web_sys::CustomEventInit::new();
+    init.set_detail(&detail);
+    if let Ok(event) = web_sys::CustomEvent::new_with_event_init_dict("cv-section", &init) {
+        let _ = window.dispatch_event(&event);
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn dispatch_section(index: usize, info: Option<&SectionInfo>) {
+    log::warn!("Section {}", section_json(index, info));
+}
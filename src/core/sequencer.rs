@@ -0,0 +1,235 @@
+// Scripted playback of the scene ("story mode") as a list of timed cues
+
+use crate::core::settings::url_param;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Cue {
+    SetProgress(f32),
+    Lift { x: u32, z: u32 },
+    Implode { x: u32, z: u32 },
+    EnableAnimations(bool),
+    // Throws every cube outward from the grid center
+    Explode,
+    // Flies every cube back into its place, like a section reset
+    Reassemble,
+    // Stops the clock until the game loop sees no running animations, later cues count
+    // from then. Handled by the sequencer, never returned as due
+    AwaitAnimations,
+    // Swaps the grid between the flat and the textured cube
+    ToggleMesh,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TimedCue {
+    pub at: f32,
+    pub cue: Cue,
+}
+
+pub struct Sequencer {
+    cues: Vec<TimedCue>,
+    time: f32,
+    next: usize,
+    pub active: bool,
+    // Waiting on an AwaitAnimations cue
+    held: bool,
+}
+
+impl Sequencer {
+    pub fn new(mut cues: Vec<TimedCue>) -> Sequencer {
+        cues.sort_by(|a, b| a.at.total_cmp(&b.at));
+        Sequencer {
+            cues,
+            time: 0.0,
+            next: 0,
+            active: false,
+            held: false,
+        }
+    }
+
+    pub fn start(&mut self) {
+        self.time = 0.0;
+        self.next = 0;
+        self.active = true;
+        self.held = false;
+    }
+
+    pub fn is_held(&self) -> bool {
+        self.active && self.held
+    }
+
+    // Lets the clock run again after an AwaitAnimations cue
+    pub fn release(&mut self) {
+        self.held = false;
+    }
+
+    pub fn stop(&mut self) {
+        self.active = false;
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.cues.len()
+    }
+
+    // Advances the clock and returns the cues that became due, in script order
+    pub fn update(&mut self, dt: f32) -> Vec<Cue> {
+        if !self.active || self.held {
+            return Vec::new();
+        }
+        self.time += dt;
+        let mut due = Vec::new();
+        while let Some(timed) = self.cues.get(self.next) {
+            if timed.at > self.time {
+                break;
+            }
+            self.next += 1;
+            if timed.cue == Cue::AwaitAnimations {
+                self.held = true;
+                self.time = timed.at;
+                break;
+            }
+            due.push(timed.cue);
+        }
+        if self.is_finished() {
+            self.active = false;
+        }
+        due
+    }
+}
+
+// Story mode can be started from the page with ?story=1
+pub fn story_requested() -> bool {
+    url_param("story").as_deref() == Some("1")
+}
+
+pub fn story_script() -> Vec<TimedCue> {
+    vec![
+        TimedCue {
+            at: 0.0,
+            cue: Cue::SetProgress(0.0),
+        },
+        TimedCue {
+            at: 2.0,
+            cue: Cue::Lift { x: 17, z: 10 },
+        },
+        TimedCue {
+            at: 4.0,
+            cue: Cue::SetProgress(0.25),
+        },
+        TimedCue {
+            at: 6.0,
+            cue: Cue::Implode { x: 17, z: 17 },
+        },
+        TimedCue {
+            at: 8.0,
+            cue: Cue::SetProgress(0.5),
+        },
+        TimedCue {
+            at: 10.0,
+            cue: Cue::EnableAnimations(false),
+        },
+        TimedCue {
+            at: 12.0,
+            cue: Cue::SetProgress(0.75),
+        },
+        TimedCue {
+            at: 14.0,
+            cue: Cue::EnableAnimations(true),
+        },
+        TimedCue {
+            at: 16.0,
+            cue: Cue::SetProgress(1.0),
+        },
+    ]
+}
+
+// One lap of the screensaver, `period` seconds on each progress in turn, then the other
+// mesh for the next lap
+pub fn screensaver_script(progresses: &[f32], period: f32) -> Vec<TimedCue> {
+    let mut cues = progresses
+        .iter()
+        .enumerate()
+        .map(|(index, progress)| TimedCue {
+            at: index as f32 * period,
+            cue: Cue::SetProgress(*progress),
+        })
+        .collect::<Vec<_>>();
+    cues.push(TimedCue {
+        at: progresses.len() as f32 * period,
+        cue: Cue::ToggleMesh,
+    });
+    cues
+}
+
+// Played once the scroll settles on the last section, when the manifest has a finale
+pub fn finale_script() -> Vec<TimedCue> {
+    vec![
+        TimedCue {
+            at: 0.0,
+            cue: Cue::Explode,
+        },
+        TimedCue {
+            at: 0.0,
+            cue: Cue::AwaitAnimations,
+        },
+        TimedCue {
+            at: 3.0,
+            cue: Cue::Reassemble,
+        },
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn story_plays_every_cue_in_order_then_stops() {
+        let script = story_script();
+        let mut sequencer = Sequencer::new(script.clone());
+        sequencer.start();
+        let mut played = Vec::new();
+        for _ in 0..100 {
+            played.extend(sequencer.update(0.25));
+        }
+        assert_eq!(
+            played,
+            script.iter().map(|timed| timed.cue).collect::<Vec<_>>()
+        );
+        assert!(sequencer.is_finished());
+        assert!(!sequencer.active);
+    }
+
+    #[test]
+    fn cues_wait_for_their_time() {
+        let mut sequencer = Sequencer::new(story_script());
+        sequencer.start();
+        assert_eq!(sequencer.update(0.0), vec![Cue::SetProgress(0.0)]);
+        assert!(sequencer.update(1.9).is_empty());
+        assert_eq!(sequencer.update(0.2), vec![Cue::Lift { x: 17, z: 10 }]);
+    }
+
+    #[test]
+    fn await_animations_holds_the_clock() {
+        let mut sequencer = Sequencer::new(finale_script());
+        sequencer.start();
+        assert_eq!(sequencer.update(0.1), vec![Cue::Explode]);
+        assert!(sequencer.is_held());
+        assert!(sequencer.update(10.0).is_empty());
+        sequencer.release();
+        assert!(sequencer.update(2.9).is_empty());
+        assert_eq!(sequencer.update(0.2), vec![Cue::Reassemble]);
+        assert!(!sequencer.active);
+    }
+
+    #[test]
+    fn screensaver_lap_holds_each_section_then_swaps_the_mesh() {
+        let mut sequencer = Sequencer::new(screensaver_script(&[0.0, 0.5, 1.0], 10.0));
+        sequencer.start();
+        assert_eq!(sequencer.update(0.0), vec![Cue::SetProgress(0.0)]);
+        assert!(sequencer.update(9.9).is_empty());
+        assert_eq!(sequencer.update(0.2), vec![Cue::SetProgress(0.5)]);
+        assert_eq!(sequencer.update(10.0), vec![Cue::SetProgress(1.0)]);
+        assert_eq!(sequencer.update(10.0), vec![Cue::ToggleMesh]);
+        assert!(!sequencer.active);
+    }
+}
@@ -0,0 +1,272 @@
+use cgmath::{Deg, InnerSpace, Quaternion, Rotation3, Vector3};
+use rand::Rng;
+
+use crate::{
+    entity::entity::Jitter,
+    helpers::{
+        animation::LoopSpec, color_vision::ColorVisionMode, effects::EffectPreset,
+        line_trace::PickPolicy,
+    },
+};
+
+// Face brightness `?face_shading=on` turns on, lit from above
+pub const FACE_SHADING: [f32; 3] = [1.0, 0.85, 0.7];
+
+// Tunable parameters for the interactions and animations
+pub struct Settings {
+    pub implode_radius: f32,
+    pub implode_strength: f32,
+    pub implode_rebound_delay: f32,
+    pub seed: u64,
+    pub idle_timeout: f32,
+    pub idle_cooldown: f32,
+    pub idle_wave_weight: f32,
+    pub idle_implode_weight: f32,
+    pub stamp_column_height: u32,
+    pub scroll_snap: bool,
+    // Distance to a section anchor, as a fraction of a section, within which scrolling snaps
+    pub scroll_snap_fraction: f32,
+    // Scroll speed in progress per second below which snapping may start
+    pub scroll_snap_velocity: f32,
+    pub scroll_snap_duration: f32,
+    // Also move the page scroll position on wasm
+    pub scroll_snap_page: bool,
+    pub color_vision: ColorVisionMode,
+    // Time constant of the instance color smoothing in seconds, 0 disables it
+    pub color_smoothing: f32,
+    pub ssao_enabled: bool,
+    pub ssao_radius: f32,
+    pub ssao_intensity: f32,
+    pub dof_enabled: bool,
+    // None focuses on the camera target
+    pub dof_focus_distance: Option<f32>,
+    pub dof_aperture: f32,
+    pub dof_max_radius: f32,
+    // Blurs moving cubes along their motion, set with `?motion_blur=on`. Off with reduced
+    // motion and where depth can't be sampled
+    pub motion_blur_enabled: bool,
+    // Fraction of one frame's motion the blur spans
+    pub motion_blur_strength: f32,
+    // Longest blur in pixels
+    pub motion_blur_max_pixels: f32,
+    // Played on cubes as the instance builder delivers them, None pops them in
+    pub entrance_effect: Option<EffectPreset>,
+    pub jitter: JitterSpec,
+    // Height bob of the looping wave
+    pub wave_motion: LoopSpec,
+    // Color cycle of the looping wave, independent of the bob
+    pub wave_color: LoopSpec,
+    // Freezes the looping wave, set with `?motion=reduce`
+    pub reduced_motion: bool,
+    // Shows section announcements on screen, set with `?captions=on`
+    pub captions: bool,
+    // Snaps finished animations onto whole unit positions within this distance, None disables it
+    pub grid_snap_epsilon: Option<f32>,
+    pub pick_policy: PickPolicy,
+    // Instance bytes uploaded per frame, changed chunks beyond it wait for later frames
+    pub upload_budget_bytes: usize,
+    // Instances per draw call where Capabilities splits draws, set with `?draw_slice=`
+    pub draw_slice_instances: u32,
+    // Soft limit on buffer and texture memory, crossing it is logged with a breakdown
+    pub gpu_memory_budget_bytes: u64,
+    // Seconds without input after which a moved camera eases back to its starting pose
+    pub recenter_timeout: f32,
+    // Distance in units the eye or target has to be off that pose to re-center
+    pub recenter_threshold: f32,
+    // Grid cube face brightness as [top, sides, bottom], None shades every face alike. Set
+    // with `?face_shading=on`
+    pub face_shading: Option<[f32; 3]>,
+    // Cubes appear in a ring growing from the grid center after loading, set off with
+    // `?reveal=off`
+    pub first_load_reveal: bool,
+    // Seconds the ring takes to reach the edge of the grid
+    pub reveal_duration: f32,
+    // Draws the grid with the textured cube instead of the flat one, set with `?mesh=textured`
+    pub textured_mesh: bool,
+    // Frames taking longer than this many times the recent median are logged with a breakdown
+    pub long_frame_factor: f32,
+    // Starting camera roll in degrees, set with `?roll=`
+    pub camera_roll: f32,
+    // The delete tool cracks cubes before removing them, set with `?multi_hit=on`
+    pub multi_hit: bool,
+    // Launched with --screensaver, cycles through the sections and exits on input
+    pub screensaver: bool,
+    // Seconds the screensaver stays on each section
+    pub screensaver_period: f32,
+    // Sections the screensaver visits in order, empty visits all of them
+    pub screensaver_sections: Vec<usize>,
+    // Spinning cube logo pinned to the top right corner, set off with `?hud_logo=off`
+    pub hud_logo: bool,
+}
+
+// Largest random deviation per instance, sampled when the grids are built
+#[derive(Clone, Copy, Debug)]
+pub struct JitterSpec {
+    pub enabled: bool,
+    pub position: f32,
+    pub rotation_degrees: f32,
+    // Fraction of the instance scale
+    pub scale: f32,
+}
+
+impl JitterSpec {
+    pub fn sample(&self, rng: &mut impl Rng) -> Jitter {
+        let mut spread = |range: f32| {
+            if range > 0.0 {
+                rng.gen_range(-range..=range)
+            } else {
+                0.0
+            }
+        };
+        let offset = Vector3::new(
+            spread(self.position),
+            spread(self.position),
+            spread(self.position),
+        );
+        let axis = Vector3::new(spread(1.0), spread(1.0), spread(1.0));
+        let angle = spread(self.rotation_degrees);
+        let rotation = if axis.magnitude2() > 0.0 {
+            Quaternion::from_axis_angle(axis.normalize(), Deg(angle))
+        } else {
+            Quaternion::new(1.0, 0.0, 0.0, 0.0)
+        };
+        Jitter {
+            offset,
+            rotation,
+            scale: 1.0 + spread(self.scale),
+            amount: if self.enabled { 1.0 } else { 0.0 },
+        }
+    }
+}
+
+impl Settings {
+    // Screensaver mode, idle events play much sooner and more often than for a visitor
+    pub fn enable_screensaver(&mut self) {
+        self.screensaver = true;
+        self.idle_timeout = 4.0;
+        self.idle_cooldown = 2.0;
+    }
+
+    // Defaults with the overrides given in the page url
+    pub fn from_url() -> Settings {
+        let mut settings = Settings::default();
+        if let Some(seed) = url_param("seed").and_then(|seed| seed.parse().ok()) {
+            settings.seed = seed;
+        }
+        if let Some(mode) = url_param("cvd").and_then(|mode| ColorVisionMode::from_name(&mode)) {
+            settings.color_vision = mode;
+        }
+        if let Some(effect) = url_param("entrance").and_then(|name| EffectPreset::from_name(&name))
+        {
+            settings.entrance_effect = Some(effect);
+        }
+        if url_param("motion").is_some_and(|motion| motion == "reduce") {
+            settings.reduced_motion = true;
+        }
+        if url_param("captions").is_some_and(|captions| captions == "on") {
+            settings.captions = true;
+        }
+        if let Some(slice) = url_param("draw_slice").and_then(|slice| slice.parse().ok()) {
+            settings.draw_slice_instances = slice;
+        }
+        if url_param("reveal").is_some_and(|reveal| reveal == "off") {
+            settings.first_load_reveal = false;
+        }
+        if let Some(roll) = url_param("roll").and_then(|roll| roll.parse().ok()) {
+            settings.camera_roll = roll;
+        }
+        if url_param("multi_hit").is_some_and(|multi_hit| multi_hit == "on") {
+            settings.multi_hit = true;
+        }
+        if url_param("mesh").is_some_and(|mesh| mesh == "textured") {
+            settings.textured_mesh = true;
+        }
+        if url_param("motion_blur").is_some_and(|blur| blur == "on") {
+            settings.motion_blur_enabled = true;
+        }
+        if url_param("hud_logo").is_some_and(|logo| logo == "off") {
+            settings.hud_logo = false;
+        }
+        if url_param("face_shading").is_some_and(|shading| shading == "on") {
+            settings.face_shading = Some(FACE_SHADING);
+        }
+        settings
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            implode_radius: 4.0,
+            implode_strength: 1.5,
+            implode_rebound_delay: 0.3,
+            seed: 0x5eed,
+            idle_timeout: 20.0,
+            idle_cooldown: 6.0,
+            idle_wave_weight: 1.0,
+            idle_implode_weight: 1.0,
+            stamp_column_height: 4,
+            scroll_snap: true,
+            scroll_snap_fraction: 0.35,
+            scroll_snap_velocity: 0.05,
+            scroll_snap_duration: 0.3,
+            scroll_snap_page: false,
+            color_vision: ColorVisionMode::Normal,
+            color_smoothing: 0.05,
+            ssao_enabled: false,
+            ssao_radius: 0.5,
+            ssao_intensity: 1.0,
+            dof_enabled: false,
+            dof_focus_distance: None,
+            dof_aperture: 0.6,
+            dof_max_radius: 8.0,
+            motion_blur_enabled: false,
+            motion_blur_strength: 1.0,
+            motion_blur_max_pixels: 6.0,
+            entrance_effect: None,
+            jitter: JitterSpec {
+                enabled: false,
+                position: 0.03,
+                rotation_degrees: 2.0,
+                scale: 0.05,
+            },
+            wave_motion: LoopSpec::default(),
+            wave_color: LoopSpec::default(),
+            reduced_motion: false,
+            captions: false,
+            grid_snap_epsilon: Some(1e-3),
+            pick_policy: PickPolicy::PreferSettled,
+            upload_budget_bytes: 256 * 1024,
+            draw_slice_instances: 16384,
+            gpu_memory_budget_bytes: 256 * 1024 * 1024,
+            recenter_timeout: 15.0,
+            recenter_threshold: 0.5,
+            face_shading: None,
+            first_load_reveal: true,
+            reveal_duration: 2.0,
+            textured_mesh: false,
+            long_frame_factor: 2.0,
+            camera_roll: 0.0,
+            multi_hit: false,
+            screensaver: false,
+            screensaver_period: 30.0,
+            screensaver_sections: Vec::new(),
+            hud_logo: true,
+        }
+    }
+}
+
+// Value of `?name=value` in the page url
+#[cfg(target_arch = "wasm32")]
+pub fn url_param(name: &str) -> Option<String> {
+    let search = web_sys::window().and_then(|window| window.location().search().ok())?;
+    search.trim_start_matches('?').split('&').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        (key == name).then(|| value.to_string())
+    })
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn url_param(_name: &str) -> Option<String> {
+    None
+}
@@ -0,0 +1,52 @@
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+
+pub const SHADER_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/core/shaders");
+
+// Watches the shader directory so edits can be picked up without a rebuild
+pub struct ShaderWatcher {
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<notify::Event>>,
+}
+
+impl ShaderWatcher {
+    pub fn new() -> Option<Self> {
+        let (sender, events) = channel();
+        let mut watcher = match notify::recommended_watcher(sender) {
+            Ok(watcher) => watcher,
+            Err(error) => {
+                log::error!("Could not start shader watcher: {}", error);
+                return None;
+            }
+        };
+        if let Err(error) = watcher.watch(Path::new(SHADER_DIR), RecursiveMode::NonRecursive) {
+            log::error!("Could not watch {}: {}", SHADER_DIR, error);
+            return None;
+        }
+        log::warn!("Watching {} for shader changes", SHADER_DIR);
+        Some(Self {
+            _watcher: watcher,
+            events,
+        })
+    }
+
+    // Drains pending events, true if any wgsl file was modified since the last poll
+    pub fn poll(&self) -> bool {
+        let mut changed = false;
+        for event in self.events.try_iter().flatten() {
+            if event.kind.is_modify() || event.kind.is_create() {
+                changed |= event
+                    .paths
+                    .iter()
+                    .any(|path| path.extension().is_some_and(|ext| ext == "wgsl"));
+            }
+        }
+        changed
+    }
+}
+
+pub fn read_shader(name: &str) -> std::io::Result<String> {
+    std::fs::read_to_string(PathBuf::from(SHADER_DIR).join(name))
+}
@@ -0,0 +1,28 @@
+// Shared between shader.wgsl, primitive.wgsl and velocity.wgsl, prepended to each at module
+// creation. The instance locations must match InstanceRaw::desc()
+
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+}
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+struct InstanceInput {
+    @location(5) model_matrix_0: vec4<f32>,
+    @location(6) model_matrix_1: vec4<f32>,
+    @location(7) model_matrix_2: vec4<f32>,
+    @location(8) model_matrix_3: vec4<f32>,
+    @location(9) instance_color: vec3<f32>,
+    @location(10) instance_emissive: f32,
+    @location(11) instance_ao: f32,
+}
+
+fn instance_model_matrix(instance: InstanceInput) -> mat4x4<f32> {
+    return mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+}
+
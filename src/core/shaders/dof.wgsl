@@ -0,0 +1,79 @@
+// Depth of field: horizontal gather at half resolution, then a vertical
+// gather composited over the sharp scene by the circle of confusion
+
+struct DofParams {
+    focus_distance: f32,
+    aperture: f32,
+    znear: f32,
+    zfar: f32,
+    texel_size: vec2<f32>,
+    max_radius: f32,
+    _padding: f32,
+}
+
+@group(0) @binding(0)
+var t_scene: texture_2d<f32>;
+@group(0) @binding(1)
+var t_depth: texture_depth_2d;
+@group(0) @binding(2)
+var s_linear: sampler;
+@group(0) @binding(3)
+var<uniform> params: DofParams;
+// Only bound for the composite pass
+@group(0) @binding(4)
+var t_blurred: texture_2d<f32>;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+// Fullscreen triangle
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    out.uv = vec2<f32>(uv.x, 1.0 - uv.y);
+    return out;
+}
+
+fn linear_depth(uv: vec2<f32>) -> f32 {
+    let size = vec2<i32>(textureDimensions(t_depth));
+    let coords = clamp(vec2<i32>(uv * vec2<f32>(size)), vec2<i32>(0), size - 1);
+    // Back to OpenGL style NDC, see OPENGL_TO_WGPU_MATRIX
+    let z = textureLoad(t_depth, coords, 0) * 2.0 - 1.0;
+    return 2.0 * params.znear * params.zfar / (params.zfar + params.znear - z * (params.zfar - params.znear));
+}
+
+fn circle_of_confusion(uv: vec2<f32>) -> f32 {
+    let depth = linear_depth(uv);
+    return clamp(abs(depth - params.focus_distance) * params.aperture / max(depth, 0.0001), 0.0, 1.0);
+}
+
+const TAPS: i32 = 4;
+
+@fragment
+fn fs_blur(in: VertexOutput) -> @location(0) vec4<f32> {
+    let radius = circle_of_confusion(in.uv) * params.max_radius;
+    var color = vec4<f32>(0.0);
+    for (var i: i32 = -TAPS; i <= TAPS; i += 1) {
+        let offset = vec2<f32>(f32(i) / f32(TAPS) * radius * params.texel_size.x, 0.0);
+        color += textureSampleLevel(t_scene, s_linear, in.uv + offset, 0.0);
+    }
+    return color / f32(2 * TAPS + 1);
+}
+
+@fragment
+fn fs_composite(in: VertexOutput) -> @location(0) vec4<f32> {
+    let coc = circle_of_confusion(in.uv);
+    let radius = coc * params.max_radius;
+    var blurred = vec4<f32>(0.0);
+    for (var i: i32 = -TAPS; i <= TAPS; i += 1) {
+        let offset = vec2<f32>(0.0, f32(i) / f32(TAPS) * radius * params.texel_size.y);
+        blurred += textureSampleLevel(t_blurred, s_linear, in.uv + offset, 0.0);
+    }
+    blurred = blurred / f32(2 * TAPS + 1);
+    let sharp = textureSampleLevel(t_scene, s_linear, in.uv, 0.0);
+    return mix(sharp, blurred, coc);
+}
@@ -0,0 +1,49 @@
+// Loading screen: a progress bar with a pulsing highlight, also used to fade the scene in
+
+struct LoadingParams {
+    progress: f32,
+    time: f32,
+    opacity: f32,
+    aspect: f32,
+}
+
+@group(0) @binding(0)
+var<uniform> params: LoadingParams;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) ndc: vec2<f32>,
+}
+
+// Fullscreen triangle
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    out.ndc = uv * 2.0 - 1.0;
+    return out;
+}
+
+const BACKGROUND: vec3<f32> = vec3<f32>(0.0, 0.0, 0.0);
+const TRACK: vec3<f32> = vec3<f32>(0.2, 0.2, 0.2);
+const FILL: vec3<f32> = vec3<f32>(0.8, 0.0, 0.6);
+const HIGHLIGHT: vec3<f32> = vec3<f32>(0.9, 0.4, 0.702);
+const BAR_HALF_WIDTH: f32 = 0.3;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let half_height = 0.015 * params.aspect;
+    var color = BACKGROUND;
+    if abs(in.ndc.x) <= BAR_HALF_WIDTH && abs(in.ndc.y) <= half_height {
+        let t = (in.ndc.x + BAR_HALF_WIDTH) / (2.0 * BAR_HALF_WIDTH);
+        color = TRACK;
+        if t <= params.progress {
+            // Highlight sweeping along the filled part so the bar never looks stuck
+            let sweep = fract(params.time * 0.8);
+            let glow = 1.0 - smoothstep(0.0, 0.08, abs(t - sweep * params.progress));
+            color = mix(FILL, HIGHLIGHT, glow);
+        }
+    }
+    return vec4<f32>(color, params.opacity);
+}
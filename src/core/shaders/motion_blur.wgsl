@@ -0,0 +1,52 @@
+// Motion blur: averages the scene along each pixel's velocity, clamped to a few pixels.
+// Pixels that barely moved keep the sharp scene untouched
+
+struct BlurParams {
+    texel_size: vec2<f32>,
+    strength: f32,
+    max_pixels: f32,
+}
+
+@group(0) @binding(0)
+var t_scene: texture_2d<f32>;
+@group(0) @binding(1)
+var t_velocity: texture_2d<f32>;
+@group(0) @binding(2)
+var s_linear: sampler;
+@group(0) @binding(3)
+var<uniform> params: BlurParams;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+// Fullscreen triangle
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    out.uv = vec2<f32>(uv.x, 1.0 - uv.y);
+    return out;
+}
+
+const TAPS: i32 = 8;
+
+@fragment
+fn fs_blur(in: VertexOutput) -> @location(0) vec4<f32> {
+    let sharp = textureSampleLevel(t_scene, s_linear, in.uv, 0.0);
+    var velocity = textureSampleLevel(t_velocity, s_linear, in.uv, 0.0).xy * params.strength;
+    let pixels = length(velocity / params.texel_size);
+    if pixels < 0.5 {
+        return sharp;
+    }
+    velocity = velocity * min(1.0, params.max_pixels / pixels);
+    var color = vec4<f32>(0.0);
+    for (var i: i32 = 0; i < TAPS; i += 1) {
+        // Centered on the pixel, from where it was towards where it is
+        let t = f32(i) / f32(TAPS - 1) - 0.5;
+        color += textureSampleLevel(t_scene, s_linear, in.uv + velocity * t, 0.0);
+    }
+    return color / f32(TAPS);
+}
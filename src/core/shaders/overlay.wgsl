@@ -0,0 +1,31 @@
+// Screen space rectangle, used for the box selection
+
+struct OverlayRect {
+    // Corners in normalized device coordinates
+    min: vec2<f32>,
+    max: vec2<f32>,
+    color: vec4<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> rect: OverlayRect;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    // Two triangles spanning the rectangle
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(0.0, 1.0),
+    );
+    let position = mix(rect.min, rect.max, corners[index]);
+    return vec4<f32>(position, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {
+    return rect.color;
+}
@@ -1,26 +1,15 @@
-// Vertex shader
-
-struct CameraUniform {
-    view_proj: mat4x4<f32>,
-}
-@group(0) @binding(0)
-var<uniform> camera: CameraUniform;
+// Vertex shader, composed after common.wgsl
 
 struct VertexInput {
     @location(0) position: vec3<f32>,
     @location(1) color: vec3<f32>,
 }
-struct InstanceInput {
-    @location(5) model_matrix_0: vec4<f32>,
-    @location(6) model_matrix_1: vec4<f32>,
-    @location(7) model_matrix_2: vec4<f32>,
-    @location(8) model_matrix_3: vec4<f32>,
-    @location(9) instance_color: vec3<f32>,
-}
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) color: vec3<f32>,
+    @location(1) emissive: f32,
+    @location(2) ao: f32,
 }
 
 @vertex
@@ -28,14 +17,11 @@ fn vs_main(
     model: VertexInput,
     instance: InstanceInput,
 ) -> VertexOutput {
-    let model_matrix = mat4x4<f32>(
-        instance.model_matrix_0,
-        instance.model_matrix_1,
-        instance.model_matrix_2,
-        instance.model_matrix_3,
-    );
+    let model_matrix = instance_model_matrix(instance);
     var out: VertexOutput;
-    out.color = vec3<f32>(instance.instance_color.x, instance.instance_color.y, instance.instance_color.z);
+    out.color = model.color * vec3<f32>(instance.instance_color.x, instance.instance_color.y, instance.instance_color.z);
+    out.emissive = instance.instance_emissive;
+    out.ao = instance.instance_ao;
     out.clip_position = camera.view_proj * model_matrix * vec4<f32>(model.position, 1.0);
     return out;
 }
@@ -43,5 +29,5 @@ fn vs_main(
 // Fragment shader
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-     return vec4<f32>(in.color, 1.0);
+     return vec4<f32>(in.color * in.ao + in.emissive * in.color, 1.0);
 }
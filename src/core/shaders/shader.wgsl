@@ -1,25 +1,18 @@
-// Vertex shader
-
-struct CameraUniform {
-    view_proj: mat4x4<f32>,
-}
-@group(0) @binding(0)
-var<uniform> camera: CameraUniform;
+// Vertex shader, composed after common.wgsl
 
 struct VertexInput {
     @location(0) position: vec3<f32>,
     @location(1) tex_coords: vec2<f32>,
-}
-struct InstanceInput {
-    @location(5) model_matrix_0: vec4<f32>,
-    @location(6) model_matrix_1: vec4<f32>,
-    @location(7) model_matrix_2: vec4<f32>,
-    @location(8) model_matrix_3: vec4<f32>,
+    @location(2) color: vec3<f32>,
 }
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) tex_coords: vec2<f32>,
+    @location(1) color: vec3<f32>,
+    @location(2) emissive: f32,
+    @location(3) ao: f32,
+    @location(4) tint: vec3<f32>,
 }
 
 @vertex
@@ -27,14 +20,14 @@ fn vs_main(
     model: VertexInput,
     instance: InstanceInput,
 ) -> VertexOutput {
-    let model_matrix = mat4x4<f32>(
-        instance.model_matrix_0,
-        instance.model_matrix_1,
-        instance.model_matrix_2,
-        instance.model_matrix_3,
-    );
+    let model_matrix = instance_model_matrix(instance);
     var out: VertexOutput;
     out.tex_coords = model.tex_coords;
+    out.color = instance.instance_color;
+    // Baked face colors times the per-instance color
+    out.tint = model.color * instance.instance_color;
+    out.emissive = instance.instance_emissive;
+    out.ao = instance.instance_ao;
     out.clip_position = camera.view_proj * model_matrix * vec4<f32>(model.position, 1.0);
     return out;
 }
@@ -48,5 +41,6 @@ var s_diffuse: sampler;
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    let sampled = textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    return vec4<f32>(sampled.rgb * in.tint * in.ao + in.emissive * in.color, sampled.a);
 }
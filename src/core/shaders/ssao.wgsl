@@ -0,0 +1,123 @@
+// Screen space ambient occlusion: hemisphere occlusion at half resolution,
+// a box blur, then the result is multiplied into the scene
+
+const KERNEL_SIZE: i32 = 16;
+
+struct SsaoParams {
+    kernel: array<vec4<f32>, 16>,
+    znear: f32,
+    zfar: f32,
+    tan_half_fovy: f32,
+    aspect: f32,
+    radius: f32,
+    intensity: f32,
+    center_offset: vec2<f32>,
+}
+
+@group(0) @binding(0)
+var t_scene: texture_2d<f32>;
+@group(0) @binding(1)
+var t_depth: texture_depth_2d;
+@group(0) @binding(2)
+var s_linear: sampler;
+@group(0) @binding(3)
+var<uniform> params: SsaoParams;
+@group(0) @binding(4)
+var t_noise: texture_2d<f32>;
+// Raw occlusion for the blur pass, blurred occlusion for the composite pass
+@group(0) @binding(5)
+var t_ao: texture_2d<f32>;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+// Fullscreen triangle
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    out.uv = vec2<f32>(uv.x, 1.0 - uv.y);
+    return out;
+}
+
+fn linear_depth(coords: vec2<i32>) -> f32 {
+    // Back to OpenGL style NDC, see OPENGL_TO_WGPU_MATRIX
+    let z = textureLoad(t_depth, coords, 0) * 2.0 - 1.0;
+    return 2.0 * params.znear * params.zfar / (params.zfar + params.znear - z * (params.zfar - params.znear));
+}
+
+fn view_position(coords: vec2<i32>) -> vec3<f32> {
+    let size = vec2<f32>(textureDimensions(t_depth));
+    let uv = (vec2<f32>(coords) + 0.5) / size;
+    let depth = linear_depth(coords);
+    let ndc = vec2<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0) - params.center_offset;
+    return vec3<f32>(
+        ndc.x * params.tan_half_fovy * params.aspect * depth,
+        ndc.y * params.tan_half_fovy * depth,
+        -depth,
+    );
+}
+
+@fragment
+fn fs_occlusion(in: VertexOutput) -> @location(0) vec4<f32> {
+    let size = vec2<i32>(textureDimensions(t_depth));
+    let coords = clamp(vec2<i32>(in.uv * vec2<f32>(size)), vec2<i32>(0), size - 2);
+    // Nothing was drawn here
+    if textureLoad(t_depth, coords, 0) >= 1.0 {
+        return vec4<f32>(1.0);
+    }
+    let position = view_position(coords);
+    // Normals are reconstructed from the neighbouring depth samples
+    let normal = normalize(cross(
+        view_position(coords + vec2<i32>(0, 1)) - position,
+        view_position(coords + vec2<i32>(1, 0)) - position,
+    ));
+
+    let noise_size = vec2<i32>(textureDimensions(t_noise));
+    let random = vec3<f32>(textureLoad(t_noise, coords % noise_size, 0).xy * 2.0 - 1.0, 0.0);
+    let tangent = normalize(random - normal * dot(random, normal));
+    let bitangent = cross(normal, tangent);
+    let tbn = mat3x3<f32>(tangent, bitangent, normal);
+
+    var occlusion = 0.0;
+    for (var i: i32 = 0; i < KERNEL_SIZE; i += 1) {
+        let sample_position = position + tbn * params.kernel[i].xyz * params.radius;
+        let sample_depth = -sample_position.z;
+        let ndc = vec2<f32>(
+            sample_position.x / (sample_depth * params.tan_half_fovy * params.aspect),
+            sample_position.y / (sample_depth * params.tan_half_fovy),
+        ) + params.center_offset;
+        let uv = vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+        let sample_coords = clamp(vec2<i32>(uv * vec2<f32>(size)), vec2<i32>(0), size - 1);
+        let scene_depth = linear_depth(sample_coords);
+        // Ignore geometry far outside the sampling radius
+        let range = smoothstep(0.0, 1.0, params.radius / abs(-position.z - scene_depth));
+        occlusion += select(0.0, 1.0, scene_depth <= sample_depth - 0.025) * range;
+    }
+    let ao = clamp(1.0 - occlusion / f32(KERNEL_SIZE) * params.intensity, 0.0, 1.0);
+    return vec4<f32>(ao);
+}
+
+@fragment
+fn fs_blur(in: VertexOutput) -> @location(0) vec4<f32> {
+    let size = vec2<i32>(textureDimensions(t_ao));
+    let center = vec2<i32>(in.uv * vec2<f32>(size));
+    var ao = 0.0;
+    for (var x: i32 = -2; x < 2; x += 1) {
+        for (var y: i32 = -2; y < 2; y += 1) {
+            let coords = clamp(center + vec2<i32>(x, y), vec2<i32>(0), size - 1);
+            ao += textureLoad(t_ao, coords, 0).r;
+        }
+    }
+    return vec4<f32>(ao / 16.0);
+}
+
+@fragment
+fn fs_composite(in: VertexOutput) -> @location(0) vec4<f32> {
+    let scene = textureSampleLevel(t_scene, s_linear, in.uv, 0.0);
+    let ao = textureSampleLevel(t_ao, s_linear, in.uv, 0.0).r;
+    return vec4<f32>(scene.rgb * ao, scene.a);
+}
@@ -0,0 +1,49 @@
+// Velocity pass, composed after common.wgsl. Writes how far each pixel of a cube moved on
+// screen since the previous upload, in uv units. Both positions use the current camera so
+// only the cubes' own motion is blurred
+
+struct PreviousInput {
+    @location(12) model_matrix_0: vec4<f32>,
+    @location(13) model_matrix_1: vec4<f32>,
+    @location(14) model_matrix_2: vec4<f32>,
+    @location(15) model_matrix_3: vec4<f32>,
+}
+
+struct VelocityOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) current: vec4<f32>,
+    @location(1) previous: vec4<f32>,
+}
+
+@vertex
+fn vs_velocity(
+    @location(0) position: vec3<f32>,
+    instance: InstanceInput,
+    previous: PreviousInput,
+) -> VelocityOutput {
+    let current_matrix = instance_model_matrix(instance);
+    var previous_matrix = mat4x4<f32>(
+        previous.model_matrix_0,
+        previous.model_matrix_1,
+        previous.model_matrix_2,
+        previous.model_matrix_3,
+    );
+    // The slot was hidden, see InstanceRaw::HIDDEN. Appearing isn't motion
+    if dot(previous.model_matrix_0.xyz, previous.model_matrix_0.xyz) == 0.0 {
+        previous_matrix = current_matrix;
+    }
+    let local = vec4<f32>(position, 1.0);
+    var out: VelocityOutput;
+    out.clip_position = camera.view_proj * current_matrix * local;
+    out.current = out.clip_position;
+    out.previous = camera.view_proj * previous_matrix * local;
+    return out;
+}
+
+@fragment
+fn fs_velocity(in: VelocityOutput) -> @location(0) vec2<f32> {
+    let current = in.current.xy / in.current.w;
+    let previous = in.previous.xy / in.previous.w;
+    // NDC to uv, y points down in uv
+    return (current - previous) * vec2<f32>(0.5, -0.5);
+}
@@ -1,39 +1,111 @@
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::iter;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 
 use cgmath::{prelude::*, Vector2};
-use wgpu::util::DeviceExt;
 use winit::dpi::PhysicalPosition;
-use winit::event::{KeyEvent, WindowEvent};
+use winit::event::{ElementState, KeyEvent, MouseButton, WindowEvent};
 use winit::keyboard::{KeyCode, PhysicalKey};
 use winit::window::Window;
 
 use crate::core::game_loop::Chunk;
+use crate::entity::entities::cube::face_factors;
 use crate::entity::entity::{
-    instances_list, instances_list2, instances_list_circle, make_cube_primitive,
-    make_cube_textured, InstanceController, InstanceRaw, Mesh, PrimitiveMesh, TexturedVertex,
+    instances_list2, make_cube_primitive, make_cube_textured, Instance, InstanceController,
+    InstanceRaw, Mesh, PrimitiveMesh, TexturedVertex,
 };
 use crate::entity::primitive_texture::PrimitiveTexture;
+use crate::entity::progress_bar::{ProgressBar, PROGRESS_CUBES};
 use crate::entity::texture::Texture;
 use crate::helpers::animation::AnimationHandler;
 
-use super::camera::{Camera, CameraController, CameraUniform};
-use super::game_loop::Gameloop;
+use super::camera::{
+    aspect_for, safe_area_pending, take_safe_area_request, Camera, CameraController, CameraPose,
+    CameraUniform, ScreenMapper,
+};
+use super::camera_script::SectionCameraScript;
+use super::capabilities::Capabilities;
+use super::crash;
+use super::frame_graph::{
+    FrameGraph, TransientTargets, DEPTH_TARGET, DOF_INPUT_TARGET, MOTION_BLUR_INPUT_TARGET,
+    SSAO_INPUT_TARGET, SURFACE_TARGET, VELOCITY_TARGET,
+};
+#[cfg(feature = "frame-step")]
+use super::frame_step::FrameStep;
+use super::frame_timing::{self, FrameTimer, Phase};
+use super::game_loop::{reset_pending, Gameloop};
+use super::gpu_memory::{self, BudgetWatch, MemoryCategory, Tracked};
+#[cfg(target_arch = "wasm32")]
+use super::loading::yield_to_browser;
+use super::loading::LoadingScreen;
+use super::overlay::SelectionOverlay;
+use super::post_process::{AmbientOcclusion, DepthOfField, MotionBlur};
+#[cfg(not(target_arch = "wasm32"))]
+use super::presenter::PresenterView;
+use super::scene_io;
+use super::scroll::restore_pending;
+use super::settings::Settings;
+#[cfg(all(feature = "shader-hot-reload", not(target_arch = "wasm32")))]
+use super::shader_reload::{read_shader, ShaderWatcher};
+use super::status;
+
+// Makes the stamp preview stand out from the placed cubes
+const STAMP_GHOST_EMISSIVE: f32 = 0.6;
+
+// Both mesh shaders are composed with common.wgsl, which holds the camera and instance inputs
+pub(crate) const SHADER_SOURCE: &str = concat!(
+    include_str!("shaders/common.wgsl"),
+    include_str!("shaders/shader.wgsl")
+);
+pub(crate) const PRIMITIVE_SOURCE: &str = concat!(
+    include_str!("shaders/common.wgsl"),
+    include_str!("shaders/primitive.wgsl")
+);
+
+// Mesh the page asked the grid to be drawn with, true for textured, picked up on the next frame
+static MESH_REQUEST: Mutex<Option<bool>> = Mutex::new(None);
+
+pub fn request_mesh(textured: bool) {
+    if let Ok(mut request) = MESH_REQUEST.lock() {
+        *request = Some(textured);
+    }
+}
+
+pub fn mesh_request_pending() -> bool {
+    MESH_REQUEST.lock().is_ok_and(|request| request.is_some())
+}
+
+pub fn take_mesh_request() -> Option<bool> {
+    MESH_REQUEST
+        .lock()
+        .ok()
+        .and_then(|mut request| request.take())
+}
+
 // The main application state holding all GPU resources and game logic
 pub struct State {
-    pub surface: wgpu::Surface<'static>,     // GPU rendering surface
-    pub surface_configured: bool,            // Tracks if surface is configured
-    pub device: Arc<wgpu::Device>,           // Logical GPU device
+    pub instance: wgpu::Instance, // Creates the surfaces of further windows
+    pub surface: wgpu::Surface<'static>, // GPU rendering surface
+    pub surface_configured: bool, // Tracks if surface is configured
+    pub adapter: wgpu::Adapter,   // Adapter used to (re)create the device
+    pub device: Arc<wgpu::Device>, // Logical GPU device
+    pub device_lost: Arc<AtomicBool>, // Set when the device needs to be recreated
+    pub capabilities: Capabilities,
     pub queue: Arc<wgpu::Queue>,             // Command queue for GPU
     pub config: wgpu::SurfaceConfiguration,  // Surface configuration settings
     pub size: winit::dpi::PhysicalSize<u32>, // Window size
+    pub screen: ScreenMapper,                // Maps cursor positions onto the surface
     #[allow(dead_code)]
     pub camera: Camera, // Camera object
     pub camera_controller: CameraController, // Handles input-based camera movement
     pub camera_uniform: CameraUniform,       // Uniform buffer for camera
-    pub camera_buffer: wgpu::Buffer,         // GPU buffer for camera data
+    pub camera_buffer: Tracked<wgpu::Buffer>, // GPU buffer for camera data
     pub camera_bind_group: wgpu::BindGroup,  // Bind group for camera
+    pub camera_bind_group_layout: wgpu::BindGroupLayout,
+    // Scripted moves of the active section, composed onto the camera
+    pub camera_script: SectionCameraScript,
     #[allow(dead_code)]
     pub depth_texture: Texture,
     pub depth_texture_primitive: PrimitiveTexture,
@@ -42,7 +114,29 @@ pub struct State {
     //temp solution
     //--TODO change
     pub chunk_size: Vector2<u32>,
-    pub mesh: Mesh, // Game logic loop
+    pub mesh: Mesh, // Mesh the grid is drawn with
+    // The other cube mesh, swapped in when Settings.textured_mesh changes
+    pub spare_mesh: Mesh,
+    pub progress_bar: ProgressBar,
+    pub ambient_occlusion: Option<AmbientOcclusion>,
+    pub depth_of_field: Option<DepthOfField>,
+    pub motion_blur: Option<MotionBlur>,
+    // Offscreen scene targets of the post effects, handed to the enabled ones every frame
+    transient_targets: TransientTargets,
+    pub selection_overlay: SelectionOverlay,
+    pub stamp_ghost: InstanceController, // Preview of the next stamp placement
+    // Cubes of the groups pinned to the camera, placed again every frame
+    pub hud: InstanceController,
+    pub loading_screen: LoadingScreen,
+    pub memory_watch: BudgetWatch,
+    pub frame_timer: FrameTimer,
+    #[cfg(feature = "frame-step")]
+    pub frame_step: FrameStep,
+    // Second window with a free camera, F11 opens and closes it
+    #[cfg(not(target_arch = "wasm32"))]
+    pub presenter: Option<PresenterView>,
+    #[cfg(all(feature = "shader-hot-reload", not(target_arch = "wasm32")))]
+    pub shader_watcher: Option<ShaderWatcher>,
 }
 
 impl State {
@@ -73,28 +167,14 @@ impl State {
             .unwrap();
 
         log::warn!("{:?}", adapter.get_info());
+        crash::set_adapter_info(&adapter.get_info());
 
         // Request device and queue from adapter
-        let (tdevice, tqueue) = adapter
-            .request_device(&wgpu::DeviceDescriptor {
-                label: None,
-                required_features: wgpu::Features::empty(),
-                required_limits: if cfg!(target_arch = "wasm32") {
-                    wgpu::Limits {
-                        max_texture_dimension_1d: 4096,
-                        max_texture_dimension_2d: 4096,
-                        ..wgpu::Limits::downlevel_webgl2_defaults()
-                    }
-                } else {
-                    wgpu::Limits::default()
-                },
-                ..Default::default()
-            })
-            .await
-            .unwrap();
-
+        let (tdevice, tqueue) = Self::request_device(&adapter).await;
         let device = Arc::new(tdevice);
         let queue = Arc::new(tqueue);
+        let device_lost = Arc::new(AtomicBool::new(false));
+        Self::watch_device_lost(&device, &device_lost);
 
         log::warn!("Surface");
 
@@ -118,67 +198,43 @@ impl State {
             view_formats: vec![],
             desired_maximum_frame_latency: 2,
         };
+        // Configured early so the loading screen can be drawn while the rest is built
+        if size.width > 0 && size.height > 0 {
+            surface.configure(&device, &config);
+        }
+        let capabilities = Capabilities::detect(&adapter, &device, surface_format);
+        capabilities.log();
+        let mut loading_screen = LoadingScreen::new(&device, surface_format);
+        loading_screen.show(&surface, &device, &queue, &config, 0.2, "device ready");
+        #[cfg(target_arch = "wasm32")]
+        yield_to_browser().await;
 
+        // The game loop reads the same settings once it exists
+        let startup_settings = Settings::from_url();
         // Setup camera
         let camera = Camera {
             eye: (-18.0, 23.0, -18.0).into(),
             target: (15.0, 0.0, 15.0).into(),
             up: cgmath::Vector3::unit_y(),
-            aspect: config.width as f32 / config.height as f32,
+            roll: startup_settings.camera_roll,
+            aspect: aspect_for(size),
             fovy: 20.0,
             znear: 0.1,
             zfar: 1.0,
+            center_offset: Vector2::new(0.0, 0.0),
         };
-        let camera_controller = CameraController::new(0.2);
+        let mut camera_controller = CameraController::new(0.2);
+        camera_controller.home = Some(CameraPose::from_camera(&camera));
         log::warn!("Camera");
 
         let mut camera_uniform = CameraUniform::new();
         camera_uniform.update_view_proj(&camera);
 
-        // Create uniform buffer for camera
-        let camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Camera Buffer"),
-            contents: bytemuck::cast_slice(&[camera_uniform]),
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-        });
-
-        // Create layout and bind group for camera
-        let camera_bind_group_layout: wgpu::BindGroupLayout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                }],
-                label: Some("camera_bind_group_layout"),
-            });
-
-        let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &camera_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: camera_buffer.as_entire_binding(),
-            }],
-            label: Some("camera_bind_group"),
-        });
+        let (camera_buffer, camera_bind_group_layout, camera_bind_group) =
+            Self::create_camera_resources(&device, &camera_uniform);
         log::warn!("Shader");
 
-        // Load shaders
-        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/shader.wgsl").into()),
-        });
-
-        // Load shaders
-        let primitive_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("PrimitiveShader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/primitive.wgsl").into()),
-        });
+        let (shader, primitive_shader) = Self::create_shaders(&device);
 
         // Create depth texture for texture meshes
         let depth_texture = Texture::create_depth_texture(&device, &config, "depth_texture");
@@ -188,6 +244,9 @@ impl State {
         // Create depth texture for primitive
 
         log::warn!("Pipeline");
+        loading_screen.show(&surface, &device, &queue, &config, 0.4, "shaders created");
+        #[cfg(target_arch = "wasm32")]
+        yield_to_browser().await;
 
         // Create render pipeline
 
@@ -195,13 +254,18 @@ impl State {
 
         let chunk_size = Vector2::new(35, 35);
         let mut chunk_map: HashMap<Chunk, InstanceController> = HashMap::new();
-        let mesh = make_cube_primitive();
+        let face_shading = startup_settings.face_shading;
+        let mesh = make_cube_primitive(face_shading);
+        let spare_mesh = make_cube_textured(
+            [1.0, 1.0, 1.0],
+            face_shading.map(|shading| face_factors(shading).map(|factor| [factor; 3])),
+        );
         match mesh {
             Mesh::Primitive(_) => {
                 for n in 0..1 {
                     for y in 0..1 {
                         let origin = Chunk { x: n, y: y };
-                        let mesh = make_cube_primitive();
+                        let mesh = make_cube_primitive(face_shading);
                         let (mb, renderer) = mesh.get_mesh_buffer(
                             &device,
                             &primitive_shader,
@@ -209,13 +273,9 @@ impl State {
                             &queue,
                             camera_bind_group_layout.clone(),
                         );
-                        let mut instance_controller = InstanceController::new(
-                            instances_list_circle(origin, chunk_size),
-                            0,
-                            mb,
-                            renderer,
-                            &device,
-                        );
+                        // Filled in batches by the instance builder
+                        let mut instance_controller =
+                            InstanceController::new(Vec::new(), 0, mb, renderer, &device);
                         chunk_map.insert(origin, instance_controller);
                     }
                 }
@@ -224,7 +284,7 @@ impl State {
                 for n in 0..3 {
                     for y in 0..3 {
                         let origin = Chunk { x: n, y: y };
-                        let mesh = make_cube_textured();
+                        let mesh = make_cube_textured([1.0, 1.0, 1.0], None);
                         let (mb, renderer) = mesh.get_mesh_buffer(
                             &device,
                             &shader,
@@ -232,13 +292,8 @@ impl State {
                             &queue,
                             camera_bind_group_layout.clone(),
                         );
-                        let instance_controller = InstanceController::new(
-                            instances_list(origin, chunk_size),
-                            0,
-                            mb,
-                            renderer,
-                            &device,
-                        );
+                        let instance_controller =
+                            InstanceController::new(Vec::new(), 0, mb, renderer, &device);
                         // let instance_controller2 = InstanceController::new(instances_list2(), 0, make_cube(&device), &device);
                         chunk_map.insert(origin, instance_controller);
                     }
@@ -246,6 +301,32 @@ impl State {
             }
         }
 
+        // Progress bar is always drawn with the primitive pipeline
+        let (mb, renderer) = make_cube_primitive(None).get_mesh_buffer(
+            &device,
+            &primitive_shader,
+            surface_format,
+            &queue,
+            camera_bind_group_layout.clone(),
+        );
+        let progress_bar = ProgressBar::new(PROGRESS_CUBES, mb, renderer, &device);
+        loading_screen.show(&surface, &device, &queue, &config, 0.7, "chunks created");
+        #[cfg(target_arch = "wasm32")]
+        yield_to_browser().await;
+
+        // The detail cubes take the grid's mesh so they shade like the cubes they split
+        let mesh_shader = match mesh {
+            Mesh::Primitive(_) => &primitive_shader,
+            Mesh::Textured(_) => &shader,
+        };
+        let (mb, renderer) = mesh.get_mesh_buffer(
+            &device,
+            mesh_shader,
+            surface_format,
+            &queue,
+            camera_bind_group_layout.clone(),
+        );
+        let detail_cubes = InstanceController::new(Vec::new(), 0, mb, renderer, &device);
         let game_loop = Gameloop::new(
             "Loop".to_string(),
             PhysicalPosition::new(0.0, 0.0),
@@ -253,21 +334,79 @@ impl State {
             Arc::clone(&queue),
             chunk_size,
             chunk_map,
+            detail_cubes,
+            capabilities,
+        );
+        let depth_view = match mesh {
+            Mesh::Primitive(_) => &depth_texture_primitive.view,
+            Mesh::Textured(_) => &depth_texture.view,
+        };
+        let mut transient_targets = TransientTargets::default();
+        let mut scene_target =
+            || transient_targets.acquire(&device, config.width, config.height, config.format);
+        let ambient_occlusion = AmbientOcclusion::is_supported(&capabilities).then(|| {
+            AmbientOcclusion::new(
+                &device,
+                &queue,
+                &config,
+                depth_view,
+                game_loop.settings.seed,
+                scene_target(),
+            )
+        });
+        let depth_of_field = DepthOfField::is_supported(&capabilities)
+            .then(|| DepthOfField::new(&device, &config, depth_view, scene_target()));
+        let motion_blur = MotionBlur::is_supported(&capabilities)
+            .then(|| MotionBlur::new(&device, &config, &camera_bind_group_layout, scene_target()));
+        transient_targets.recycle();
+        let selection_overlay = SelectionOverlay::new(&device, surface_format);
+        let (mb, renderer) = make_cube_primitive(None).get_mesh_buffer(
+            &device,
+            &primitive_shader,
+            surface_format,
+            &queue,
+            camera_bind_group_layout.clone(),
+        );
+        let stamp_ghost = InstanceController::new(Vec::new(), 0, mb, renderer, &device);
+        let (mb, renderer) = make_cube_primitive(None).get_mesh_buffer(
+            &device,
+            &primitive_shader,
+            surface_format,
+            &queue,
+            camera_bind_group_layout.clone(),
         );
+        let hud = InstanceController::new(Vec::new(), 0, mb, renderer, &device);
+        loading_screen.show(
+            &surface,
+            &device,
+            &queue,
+            &config,
+            1.0,
+            "post effects ready",
+        );
+        #[cfg(target_arch = "wasm32")]
+        yield_to_browser().await;
         log::warn!("Done");
 
         // Return initialized State
         Self {
+            instance,
             surface,
             surface_configured: false,
+            adapter,
             device,
+            device_lost,
+            capabilities,
             queue,
             config,
             size,
+            screen: ScreenMapper::new(size, window.scale_factor()),
             camera,
             camera_controller,
             camera_buffer,
             camera_bind_group,
+            camera_bind_group_layout,
+            camera_script: SectionCameraScript::default(),
             camera_uniform,
             depth_texture,
             depth_texture_primitive,
@@ -275,7 +414,283 @@ impl State {
             game_loop,
             chunk_size,
             mesh,
+            spare_mesh,
+            progress_bar,
+            ambient_occlusion,
+            depth_of_field,
+            motion_blur,
+            transient_targets,
+            selection_overlay,
+            stamp_ghost,
+            hud,
+            loading_screen,
+            memory_watch: BudgetWatch::default(),
+            frame_timer: FrameTimer::default(),
+            #[cfg(feature = "frame-step")]
+            frame_step: FrameStep::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            presenter: None,
+            #[cfg(all(feature = "shader-hot-reload", not(target_arch = "wasm32")))]
+            shader_watcher: ShaderWatcher::new(),
+        }
+    }
+
+    pub async fn request_device(adapter: &wgpu::Adapter) -> (wgpu::Device, wgpu::Queue) {
+        adapter
+            .request_device(&wgpu::DeviceDescriptor {
+                label: None,
+                required_features: wgpu::Features::empty(),
+                required_limits: if cfg!(target_arch = "wasm32") {
+                    wgpu::Limits {
+                        max_texture_dimension_1d: 4096,
+                        max_texture_dimension_2d: 4096,
+                        ..wgpu::Limits::downlevel_webgl2_defaults()
+                    }
+                } else {
+                    wgpu::Limits::default()
+                },
+                ..Default::default()
+            })
+            .await
+            .unwrap()
+    }
+
+    // Flags the device as lost instead of panicking, the event loop rebuilds it on the next frame
+    fn watch_device_lost(device: &wgpu::Device, device_lost: &Arc<AtomicBool>) {
+        let lost = Arc::clone(device_lost);
+        device.set_device_lost_callback(move |reason, message| {
+            log::error!("Device lost ({:?}): {}", reason, message);
+            lost.store(true, Ordering::SeqCst);
+        });
+        device.on_uncaptured_error(Box::new(|error| {
+            log::error!("Uncaptured wgpu error: {}", error);
+        }));
+    }
+
+    fn create_camera_resources(
+        device: &wgpu::Device,
+        camera_uniform: &CameraUniform,
+    ) -> (
+        Tracked<wgpu::Buffer>,
+        wgpu::BindGroupLayout,
+        wgpu::BindGroup,
+    ) {
+        // Create uniform buffer for camera
+        let camera_buffer = gpu_memory::create_buffer_init(
+            device,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Camera Buffer"),
+                contents: bytemuck::cast_slice(&[*camera_uniform]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            },
+            MemoryCategory::Uniforms,
+        );
+
+        // Create layout and bind group for camera
+        let camera_bind_group_layout: wgpu::BindGroupLayout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+                label: Some("camera_bind_group_layout"),
+            });
+
+        let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &camera_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: camera_buffer.as_entire_binding(),
+            }],
+            label: Some("camera_bind_group"),
+        });
+        (camera_buffer, camera_bind_group_layout, camera_bind_group)
+    }
+
+    fn create_shaders(device: &wgpu::Device) -> (wgpu::ShaderModule, wgpu::ShaderModule) {
+        Self::create_shader_modules(device, SHADER_SOURCE.into(), PRIMITIVE_SOURCE.into())
+    }
+
+    fn create_shader_modules(
+        device: &wgpu::Device,
+        shader_source: Cow<'static, str>,
+        primitive_source: Cow<'static, str>,
+    ) -> (wgpu::ShaderModule, wgpu::ShaderModule) {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Shader"),
+            source: wgpu::ShaderSource::Wgsl(shader_source),
+        });
+        let primitive_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("PrimitiveShader"),
+            source: wgpu::ShaderSource::Wgsl(primitive_source),
+        });
+        (shader, primitive_shader)
+    }
+
+    // Rebuilds the mesh pipelines from the shaders on disk. Everything is built inside an
+    // error scope first so a broken shader keeps the old pipelines running
+    #[cfg(all(feature = "shader-hot-reload", not(target_arch = "wasm32")))]
+    fn reload_shaders(&mut self) {
+        let compose = |name: &str| -> std::io::Result<String> {
+            Ok(read_shader("common.wgsl")? + &read_shader(name)?)
+        };
+        let (shader_source, primitive_source) =
+            match (compose("shader.wgsl"), compose("primitive.wgsl")) {
+                (Ok(shader), Ok(primitive)) => (shader, primitive),
+                (Err(error), _) | (_, Err(error)) => {
+                    log::error!("Could not read shaders: {}", error);
+                    return;
+                }
+            };
+        let device = Arc::clone(&self.device);
+        let queue = Arc::clone(&self.queue);
+        let format = self.config.format;
+        let layout = &self.camera_bind_group_layout;
+
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let (shader, primitive_shader) =
+            Self::create_shader_modules(&device, shader_source.into(), primitive_source.into());
+        let mesh_shader = match self.mesh {
+            Mesh::Primitive(_) => &primitive_shader,
+            Mesh::Textured(_) => &shader,
+        };
+        // One more for the detail cubes
+        let mut chunks = (0..=self.game_loop.chunk_map.len())
+            .map(|_| {
+                self.mesh
+                    .get_mesh_buffer(&device, mesh_shader, format, &queue, layout.clone())
+            })
+            .collect::<Vec<_>>();
+        let (progress_mb, progress_renderer) = make_cube_primitive(None).get_mesh_buffer(
+            &device,
+            &primitive_shader,
+            format,
+            &queue,
+            layout.clone(),
+        );
+        let (ghost_mb, ghost_renderer) = make_cube_primitive(None).get_mesh_buffer(
+            &device,
+            &primitive_shader,
+            format,
+            &queue,
+            layout.clone(),
+        );
+        let (hud_mb, hud_renderer) = make_cube_primitive(None).get_mesh_buffer(
+            &device,
+            &primitive_shader,
+            format,
+            &queue,
+            layout.clone(),
+        );
+        if let Some(error) = pollster::block_on(device.pop_error_scope()) {
+            log::error!(
+                "Shader reload failed, keeping the old pipelines:\n{}",
+                error
+            );
+            return;
+        }
+
+        self.game_loop
+            .recreate_gpu_resources(Arc::clone(&device), Arc::clone(&queue), || {
+                chunks.pop().unwrap()
+            });
+        self.progress_bar
+            .instance_controller
+            .recreate_gpu_resources(progress_mb, progress_renderer, &device, &queue);
+        self.stamp_ghost
+            .recreate_gpu_resources(ghost_mb, ghost_renderer, &device, &queue);
+        self.hud
+            .recreate_gpu_resources(hud_mb, hud_renderer, &device, &queue);
+        log::warn!("Reloaded shaders");
+    }
+
+    // Rebuilds every GPU resource on a fresh device, keeping all CPU side state
+    pub fn install_device(&mut self, device: wgpu::Device, queue: wgpu::Queue) {
+        let device = Arc::new(device);
+        let queue = Arc::new(queue);
+        self.device_lost.store(false, Ordering::SeqCst);
+        Self::watch_device_lost(&device, &self.device_lost);
+        self.capabilities = Capabilities::detect(&self.adapter, &device, self.config.format);
+        self.game_loop.capabilities = self.capabilities;
+        // Its camera buffer and surface configuration belong to the lost device
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.presenter = None;
         }
+
+        let (camera_buffer, camera_bind_group_layout, camera_bind_group) =
+            Self::create_camera_resources(&device, &self.camera_uniform);
+        let (shader, primitive_shader) = Self::create_shaders(&device);
+        let mesh_shader = match self.mesh {
+            Mesh::Primitive(_) => &primitive_shader,
+            Mesh::Textured(_) => &shader,
+        };
+
+        let mesh = &self.mesh;
+        let format = self.config.format;
+        self.game_loop
+            .recreate_gpu_resources(Arc::clone(&device), Arc::clone(&queue), || {
+                mesh.get_mesh_buffer(
+                    &device,
+                    mesh_shader,
+                    format,
+                    &queue,
+                    camera_bind_group_layout.clone(),
+                )
+            });
+        let (mb, renderer) = make_cube_primitive(None).get_mesh_buffer(
+            &device,
+            &primitive_shader,
+            format,
+            &queue,
+            camera_bind_group_layout.clone(),
+        );
+        self.progress_bar
+            .instance_controller
+            .recreate_gpu_resources(mb, renderer, &device, &queue);
+
+        let (mb, renderer) = make_cube_primitive(None).get_mesh_buffer(
+            &device,
+            &primitive_shader,
+            format,
+            &queue,
+            camera_bind_group_layout.clone(),
+        );
+        self.stamp_ghost
+            .recreate_gpu_resources(mb, renderer, &device, &queue);
+
+        let (mb, renderer) = make_cube_primitive(None).get_mesh_buffer(
+            &device,
+            &primitive_shader,
+            format,
+            &queue,
+            camera_bind_group_layout.clone(),
+        );
+        self.hud
+            .recreate_gpu_resources(mb, renderer, &device, &queue);
+        self.selection_overlay = SelectionOverlay::new(&device, format);
+        // Fades the scene back in after the reset
+        self.loading_screen = LoadingScreen::new(&device, format);
+        self.loading_screen.progress = 1.0;
+        self.camera_buffer = camera_buffer;
+        self.camera_bind_group = camera_bind_group;
+        self.camera_bind_group_layout = camera_bind_group_layout;
+        self.device = device;
+        self.queue = queue;
+        // Rebuilt for the new device by resize below
+        self.ambient_occlusion = None;
+        self.depth_of_field = None;
+        self.motion_blur = None;
+        self.transient_targets = TransientTargets::default();
+        // Reconfigures the surface and depth textures for the new device
+        self.resize(self.size);
+        log::warn!("Recreated GPU resources");
     }
 
     pub fn window(&self) -> &Arc<Window> {
@@ -285,12 +700,12 @@ impl State {
     pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
         if new_size.width > 0 && new_size.height > 0 {
             self.size = new_size;
+            self.screen.resize(new_size, self.window.inner_size());
             self.config.width = new_size.width;
             self.config.height = new_size.height;
             self.surface.configure(&self.device, &self.config);
             self.surface_configured = true;
-            self.camera.aspect = self.config.width as f32 / self.config.height as f32;
-            // NEW!
+            self.camera.aspect = aspect_for(new_size);
             self.depth_texture =
                 Texture::create_depth_texture(&self.device, &self.config, "depth_texture");
             self.depth_texture_primitive = PrimitiveTexture::create_depth_texture(
@@ -298,35 +713,471 @@ impl State {
                 &self.config,
                 "depth_texture_primitive",
             );
+            let depth_view = match self.mesh {
+                Mesh::Primitive(_) => &self.depth_texture_primitive.view,
+                Mesh::Textured(_) => &self.depth_texture.view,
+            };
+            let (device, config) = (&self.device, &self.config);
+            let targets = &mut self.transient_targets;
+            let mut scene_target =
+                || targets.acquire(device, config.width, config.height, config.format);
+            if AmbientOcclusion::is_supported(&self.capabilities) {
+                match &mut self.ambient_occlusion {
+                    Some(ssao) => ssao.resize(device, config, depth_view, scene_target()),
+                    None => {
+                        self.ambient_occlusion = Some(AmbientOcclusion::new(
+                            device,
+                            &self.queue,
+                            config,
+                            depth_view,
+                            self.game_loop.settings.seed,
+                            scene_target(),
+                        ))
+                    }
+                }
+            }
+            if DepthOfField::is_supported(&self.capabilities) {
+                match &mut self.depth_of_field {
+                    Some(dof) => dof.resize(device, config, depth_view, scene_target()),
+                    None => {
+                        self.depth_of_field = Some(DepthOfField::new(
+                            device,
+                            config,
+                            depth_view,
+                            scene_target(),
+                        ))
+                    }
+                }
+            }
+            if MotionBlur::is_supported(&self.capabilities) {
+                match &mut self.motion_blur {
+                    Some(blur) => blur.resize(device, config, scene_target()),
+                    None => {
+                        self.motion_blur = Some(MotionBlur::new(
+                            device,
+                            config,
+                            &self.camera_bind_group_layout,
+                            scene_target(),
+                        ))
+                    }
+                }
+            }
+            self.transient_targets.recycle();
         } else {
             println!("Not configured");
             self.surface_configured = false;
         }
     }
     pub fn input(&mut self, event: &WindowEvent) -> bool {
+        let _timer = frame_timing::scope(Phase::Input);
+        if let WindowEvent::KeyboardInput {
+            event:
+                KeyEvent {
+                    state: ElementState::Pressed,
+                    physical_key: PhysicalKey::Code(KeyCode::F1),
+                    ..
+                },
+            ..
+        } = event
+        {
+            // Debug: dump the recent long frames
+            for frame in self.frame_timer.long_frames() {
+                frame.log();
+            }
+            return true;
+        }
+        // Debug: simulate a GPU reset, debug builds only so a stray F12 can't kill the page
+        #[cfg(debug_assertions)]
+        if let WindowEvent::KeyboardInput {
+            event:
+                KeyEvent {
+                    state: ElementState::Pressed,
+                    physical_key: PhysicalKey::Code(KeyCode::F12),
+                    ..
+                },
+            ..
+        } = event
+        {
+            self.device_lost.store(true, Ordering::SeqCst);
+            self.device.destroy();
+            return true;
+        }
+        if let WindowEvent::ScaleFactorChanged { scale_factor, .. } = event {
+            self.screen.scale_factor = *scale_factor;
+        }
+        #[cfg(feature = "frame-step")]
+        {
+            if let WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key: PhysicalKey::Code(code),
+                        ..
+                    },
+                ..
+            } = event
+            {
+                if self.frame_step.handle_key(*code) {
+                    return true;
+                }
+            }
+            // Replayed by the next step so paused input isn't lost
+            if self.frame_step.queue(event) {
+                return true;
+            }
+        }
+        self.dispatch_input(event)
+    }
+
+    // Input that moves the simulation, the progress bar, game loop and camera
+    fn dispatch_input(&mut self, event: &WindowEvent) -> bool {
+        if let WindowEvent::MouseInput {
+            state: ElementState::Pressed,
+            button: MouseButton::Left,
+            ..
+        } = event
+        {
+            if let Some(index) = self
+                .progress_bar
+                .cube_at(&self.game_loop.cursor_position, &self.screen.surface)
+            {
+                self.game_loop.scroll_progress = self.progress_bar.progress_for(index);
+                return true;
+            }
+        }
         self.game_loop
-            .process_event(event, &self.camera, &self.size);
-        self.camera_controller.process_events(event)
+            .process_event(event, &self.camera, &self.screen);
+        self.camera_controller.process_events(event, &self.camera)
     }
 
-    pub fn update(&mut self, dt: std::time::Duration) {
-        self.camera_controller.update_camera(&mut self.camera);
+    // Returns whether the next frame will differ, the loop stops redrawing while it doesn't
+    pub fn update(&mut self, dt: std::time::Duration) -> bool {
+        #[cfg(feature = "frame-step")]
+        let dt = match self.frame_step.advance(dt) {
+            Some(dt) => {
+                for event in self.frame_step.take_queued() {
+                    self.dispatch_input(&event);
+                }
+                dt
+            }
+            None => {
+                // Frozen, the scene keeps rendering the paused frame
+                self.publish_status();
+                return true;
+            }
+        };
+        let mut changed = false;
+        #[cfg(all(feature = "shader-hot-reload", not(target_arch = "wasm32")))]
+        if self
+            .shader_watcher
+            .as_ref()
+            .is_some_and(ShaderWatcher::poll)
+        {
+            self.reload_shaders();
+            changed = true;
+        }
+        if scene_io::take_export_request() {
+            scene_io::export_scene(&self.game_loop, &self.camera);
+        }
+        if let Some(json) = scene_io::take_import_request() {
+            scene_io::import_scene(&mut self.game_loop, &json);
+            changed = true;
+        }
+        if let Some(safe_area) = take_safe_area_request() {
+            self.camera_controller.safe_area = safe_area.scaled(self.screen.scale_factor as f32);
+        }
+        if let Some(textured) = take_mesh_request() {
+            self.game_loop.settings.textured_mesh = textured;
+        }
+        if self.game_loop.settings.textured_mesh != matches!(self.mesh, Mesh::Textured(_)) {
+            self.swap_mesh();
+            changed = true;
+        }
+        changed |= self.camera_controller.is_moving(&self.camera, &self.size);
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(presenter) = &mut self.presenter {
+            changed |= presenter.update(&self.queue, dt.as_secs_f32());
+        }
+        if self.game_loop.sequencer.active {
+            self.camera_controller.stop_inertia();
+        } else if self.recenter_due() {
+            self.camera_controller.recenter(&self.camera);
+        }
+        self.camera_controller
+            .update_camera(&mut self.camera, dt.as_secs_f32());
+        self.camera_controller
+            .update_pose(&mut self.camera, dt.as_secs_f32());
+        self.camera_controller
+            .update_framing(&mut self.camera, &self.size, dt.as_secs_f32());
+        self.camera_script
+            .update(
+                self.game_loop.section_progress(),
+                self.game_loop.camera_cues(),
+                dt.as_secs_f32(),
+            )
+            .apply(&mut self.camera);
+        changed |= self.camera_script.is_moving();
+        debug_assert!(self.camera.is_finite(), "Camera went non-finite");
         self.camera_uniform.update_view_proj(&self.camera);
         self.queue.write_buffer(
             &self.camera_buffer,
             0,
             bytemuck::cast_slice(&[self.camera_uniform]),
         );
-        self.game_loop.update(dt);
+        self.game_loop.focus = self.game_loop.chunk_at(self.camera.target);
+        // Previous transforms are only kept while the blur needs them
+        let blur = self.motion_blur_active();
+        self.game_loop.set_track_previous(blur);
+        changed |= self.game_loop.update(dt);
+        changed |= self.game_loop.update_detail(self.camera.eye);
+        changed |= self
+            .game_loop
+            .update_tooltip(&self.camera, &self.screen, dt.as_secs_f32());
+        changed |= self
+            .game_loop
+            .update_reveal(dt.as_secs_f32(), self.loading_screen.is_visible());
+        self.publish_status();
+        self.memory_watch
+            .check(self.game_loop.settings.gpu_memory_budget_bytes);
+        changed |= self.loading_screen.is_visible() || self.progress_bar.is_animating();
+        self.loading_screen
+            .update(&self.queue, self.camera.aspect, dt.as_secs_f32());
+        self.progress_bar.update(
+            &self.camera,
+            self.game_loop.scroll_progress,
+            self.game_loop.settings.color_vision,
+            dt.as_secs_f32(),
+            &self.queue,
+        );
+        self.selection_overlay
+            .update(&self.queue, self.game_loop.box_select, &self.size);
+        self.update_stamp_ghost();
+        self.update_hud();
+        if let Some(ssao) = &self.ambient_occlusion {
+            ssao.update(&self.queue, &self.camera, &self.game_loop.settings);
+        }
+        if let Some(dof) = &self.depth_of_field {
+            dof.update(&self.queue, &self.camera, &self.game_loop.settings);
+        }
+        if let Some(blur) = &self.motion_blur {
+            blur.update(&self.queue, &self.game_loop.settings);
+        }
+        changed
+    }
+
+    // Hands the enabled post effects their offscreen scene targets for this frame. A disabled
+    // effect keeps its old one but no longer holds it back from the others
+    fn lend_targets(&mut self) {
+        let blur_active = self.motion_blur_active();
+        let settings = &self.game_loop.settings;
+        let depth_view = match self.mesh {
+            Mesh::Primitive(_) => &self.depth_texture_primitive.view,
+            Mesh::Textured(_) => &self.depth_texture.view,
+        };
+        let (device, config) = (&self.device, &self.config);
+        let targets = &mut self.transient_targets;
+        let mut scene_target =
+            || targets.acquire(device, config.width, config.height, config.format);
+        if let Some(ssao) = self
+            .ambient_occlusion
+            .as_mut()
+            .filter(|_| settings.ssao_enabled)
+        {
+            ssao.set_scene(device, scene_target(), depth_view);
+        }
+        if let Some(dof) = self
+            .depth_of_field
+            .as_mut()
+            .filter(|_| settings.dof_enabled)
+        {
+            dof.set_scene(device, scene_target(), depth_view);
+        }
+        if let Some(blur) = self.motion_blur.as_mut().filter(|_| blur_active) {
+            blur.set_scene(device, scene_target());
+        }
+    }
+
+    // Enabled, supported and not turned off by reduced motion
+    fn motion_blur_active(&self) -> bool {
+        let settings = &self.game_loop.settings;
+        self.motion_blur.is_some() && settings.motion_blur_enabled && !settings.reduced_motion
+    }
+
+    // Checked while no frames are drawn, `idle` is the time since the last frame
+    pub fn wants_wake(&self, idle: std::time::Duration) -> bool {
+        reset_pending()
+            || safe_area_pending()
+            || scene_io::scene_requests_pending()
+            || restore_pending()
+            || mesh_request_pending()
+            || self.game_loop.idle_event_due(idle.as_secs_f32())
+            || self.recenter_due()
+            || self.device_lost.load(Ordering::SeqCst)
+    }
+
+    // Draws the grid with the spare mesh. The chunks keep their instances, so animations
+    // carry on through the swap
+    fn swap_mesh(&mut self) {
+        std::mem::swap(&mut self.mesh, &mut self.spare_mesh);
+        let device = &self.device;
+        let queue = &self.queue;
+        let format = self.config.format;
+        let layout = &self.camera_bind_group_layout;
+        let mesh = &self.mesh;
+        let (shader, primitive_shader) = Self::create_shaders(device);
+        let mesh_shader = match mesh {
+            Mesh::Primitive(_) => &primitive_shader,
+            Mesh::Textured(_) => &shader,
+        };
+        self.game_loop.swap_meshes(|| {
+            mesh.get_mesh_buffer(device, mesh_shader, format, queue, layout.clone())
+        });
+        // The post effects read the depth texture of the active mesh
+        self.resize(self.size);
+        log::warn!("Swapped the grid mesh");
+    }
+
+    fn publish_status(&mut self) {
+        #[allow(unused_mut)]
+        let mut status = self.game_loop.status(&self.camera);
+        #[cfg(feature = "alloc-counter")]
+        {
+            status.allocations = Some(super::alloc_counter::take_allocations());
+        }
+        #[cfg(feature = "frame-step")]
+        {
+            status.simulated_time = self.frame_step.simulated_time();
+        }
+        status::publish(status);
+    }
+
+    // Opens the presenter window on the current camera, or closes it
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn toggle_presenter(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
+        if self.presenter.take().is_some() {
+            return;
+        }
+        let attributes = Window::default_attributes().with_title("cv-game presenter");
+        let window = match event_loop.create_window(attributes) {
+            Ok(window) => Arc::new(window),
+            Err(error) => {
+                log::error!("Presenter window failed: {}", error);
+                return;
+            }
+        };
+        self.presenter = PresenterView::new(
+            window,
+            &self.instance,
+            &self.adapter,
+            &self.device,
+            self.config.format,
+            &self.camera_bind_group_layout,
+            self.camera.clone(),
+        );
+    }
+
+    // Events of the presenter window. Closing it only drops its surface and camera, the
+    // main loop keeps drawing both windows
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn presenter_input(&mut self, event: &WindowEvent) {
+        let presenter = match &mut self.presenter {
+            Some(presenter) => presenter,
+            None => return,
+        };
+        match event {
+            WindowEvent::CloseRequested => self.presenter = None,
+            WindowEvent::Resized(size) => presenter.resize(&self.device, *size),
+            // Drawn along with the main window
+            WindowEvent::RedrawRequested => return,
+            _ => presenter.input(event),
+        }
+        self.window.request_redraw();
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn render_presenter(&mut self) {
+        let presenter = match &mut self.presenter {
+            Some(presenter) => presenter,
+            None => return,
+        };
+        match presenter.render(&self.device, &self.queue, &self.game_loop) {
+            Ok(()) => {}
+            Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                presenter.resize(&self.device, presenter.window.inner_size())
+            }
+            Err(error) => log::error!("Unable to render the presenter view {}", error),
+        }
+    }
+
+    // Waits for the submitted GPU work, then releases the instance buffers ahead of the
+    // device and surface
+    pub fn shutdown(&mut self) {
+        if let Err(error) = self.device.poll(wgpu::PollType::Wait) {
+            log::warn!("GPU work did not finish before shutdown: {}", error);
+        }
+        self.game_loop.chunk_map.clear();
+    }
+
+    // Closes the frame timing with the time update and render took together
+    pub fn finish_frame(&mut self, work: std::time::Duration) {
+        let game_loop = &self.game_loop;
+        self.frame_timer
+            .finish(work, game_loop.settings.long_frame_factor, || {
+                let instances = game_loop
+                    .chunk_map
+                    .values()
+                    .map(|controller| controller.count)
+                    .sum();
+                (instances, game_loop.animation_handler.animating_count())
+            });
+    }
+
+    fn recenter_due(&self) -> bool {
+        let settings = &self.game_loop.settings;
+        self.camera_controller.recenter_due(
+            &self.camera,
+            settings.recenter_timeout,
+            settings.recenter_threshold,
+        )
+    }
+
+    fn update_stamp_ghost(&mut self) {
+        self.stamp_ghost.instances.clear();
+        let ghost = self
+            .game_loop
+            .stamp_preview(&self.camera, &self.screen)
+            .map(|(template, position)| Instance {
+                position,
+                bounding: position + template.size,
+                should_render: true,
+                emissive: STAMP_GHOST_EMISSIVE,
+                ..template
+            });
+        self.stamp_ghost
+            .add_instances(ghost.into_iter().collect(), &self.queue, &self.device);
+    }
+
+    fn update_hud(&mut self) {
+        self.hud.instances.clear();
+        let attached = self.game_loop.attached_instances(&self.camera);
+        self.hud.add_instances(attached, &self.queue, &self.device);
     }
 
     pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
         // We can't render unless the surface is configured
         if !self.surface_configured {
-            return Ok(());
+            // Un-minimizing doesn't always send a resize, pick the size back up here
+            let size = self.window.inner_size();
+            if size.width == 0 || size.height == 0 || self.device_lost.load(Ordering::SeqCst) {
+                return Ok(());
+            }
+            self.resize(size);
         }
-        self.window.request_redraw();
-        let output = self.surface.get_current_texture()?;
+        let output = {
+            let _timer = frame_timing::scope(Phase::Present);
+            self.surface.get_current_texture()?
+        };
+        let encode_timer = frame_timing::scope(Phase::Encode);
         let view = output
             .texture
             .create_view(&wgpu::TextureViewDescriptor::default());
@@ -337,55 +1188,198 @@ impl State {
                 label: Some("Render Encoder"),
             });
 
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    depth_slice: None,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.0,
-                            g: 0.0,
-                            b: 0.0,
-                            a: 0.0,
-                        }),
-                        store: wgpu::StoreOp::Store,
-                    },
-                })],
-                depth_stencil_attachment: {
-                    match self.mesh {
-                        Mesh::Primitive(_) => Some(wgpu::RenderPassDepthStencilAttachment {
-                            view: &self.depth_texture_primitive.view,
-                            depth_ops: Some(wgpu::Operations {
-                                load: wgpu::LoadOp::Clear(1.0), // Clear depth buffer to far plane
-                                store: wgpu::StoreOp::Store,
-                            }),
-                            stencil_ops: None,
-                        }),
-                        Mesh::Textured(_) => Some(wgpu::RenderPassDepthStencilAttachment {
-                            view: &self.depth_texture.view,
-                            depth_ops: Some(wgpu::Operations {
-                                load: wgpu::LoadOp::Clear(1.0),
-                                store: wgpu::StoreOp::Store,
+        self.lend_targets();
+        let depth_view = match self.mesh {
+            Mesh::Primitive(_) => &self.depth_texture_primitive.view,
+            Mesh::Textured(_) => &self.depth_texture.view,
+        };
+        // With post effects the scene is drawn offscreen, each effect feeds the next one
+        let settings = &self.game_loop.settings;
+        let ssao = self
+            .ambient_occlusion
+            .as_ref()
+            .filter(|_| settings.ssao_enabled);
+        let dof = self
+            .depth_of_field
+            .as_ref()
+            .filter(|_| settings.dof_enabled);
+        let blur = self
+            .motion_blur
+            .as_ref()
+            .filter(|_| self.motion_blur_active());
+        let surface_view = &view;
+        let dof_view = dof.map_or(surface_view, |dof| &dof.scene.view);
+        let blur_view = blur.map_or(dof_view, |blur| &blur.scene.view);
+        let scene_view = ssao.map_or(blur_view, |ssao| &ssao.scene.view);
+        let dof_target = dof.map_or(SURFACE_TARGET, |_| DOF_INPUT_TARGET);
+        let blur_target = blur.map_or(dof_target, |_| MOTION_BLUR_INPUT_TARGET);
+        let scene_target = ssao.map_or(blur_target, |_| SSAO_INPUT_TARGET);
+
+        // Closures capture whole variables, so every pass gets its own borrows
+        let camera_bind_group = &self.camera_bind_group;
+        let game_loop = &self.game_loop;
+        let stamp_ghost = &self.stamp_ghost;
+        let progress_bar = &self.progress_bar.instance_controller;
+        let hud = &self.hud;
+        let selection_overlay = &self.selection_overlay;
+        let loading_screen = &self.loading_screen;
+
+        let mut graph = FrameGraph::new();
+        graph.add_pass(
+            "scene",
+            &[],
+            &[scene_target, DEPTH_TARGET],
+            move |encoder| {
+                let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("Render Pass"),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: scene_view,
+                        depth_slice: None,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color {
+                                r: 0.0,
+                                g: 0.0,
+                                b: 0.0,
+                                a: 0.0,
                             }),
-                            stencil_ops: None,
+                            store: wgpu::StoreOp::Store,
+                        },
+                    })],
+                    depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                        view: depth_view,
+                        depth_ops: Some(wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(1.0), // Clear depth buffer to far plane
+                            store: wgpu::StoreOp::Store,
                         }),
-                    }
-                },
-                occlusion_query_set: None,
-                timestamp_writes: None,
-            });
+                        stencil_ops: None,
+                    }),
+                    occlusion_query_set: None,
+                    timestamp_writes: None,
+                });
 
-            render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
-            for instance_controller in self.game_loop.chunk_map.values_mut() {
-                instance_controller.render(&mut render_pass);
-            }
+                render_pass.set_bind_group(0, camera_bind_group, &[]);
+                game_loop.render(&mut render_pass);
+                stamp_ghost.render(&mut render_pass, None, true);
+            },
+        );
+        if let Some(ssao) = ssao {
+            graph.add_pass(
+                "ssao",
+                &[SSAO_INPUT_TARGET, DEPTH_TARGET],
+                &[blur_target],
+                move |encoder| ssao.render(encoder, blur_view),
+            );
+        }
+        if let Some(blur) = blur {
+            graph.add_pass(
+                "velocity",
+                &[DEPTH_TARGET],
+                &[VELOCITY_TARGET],
+                move |encoder| {
+                    blur.render_velocity(
+                        encoder,
+                        depth_view,
+                        camera_bind_group,
+                        game_loop.chunk_map.values(),
+                    )
+                },
+            );
+            graph.add_pass(
+                "motion_blur",
+                &[MOTION_BLUR_INPUT_TARGET, VELOCITY_TARGET],
+                &[dof_target],
+                move |encoder| blur.render(encoder, dof_view),
+            );
         }
+        if let Some(dof) = dof {
+            graph.add_pass(
+                "dof",
+                &[DOF_INPUT_TARGET, DEPTH_TARGET],
+                &[SURFACE_TARGET],
+                move |encoder| dof.render(encoder, surface_view),
+            );
+        }
+        // The progress bar and the HUD are drawn last so they stay sharp
+        graph.add_pass(
+            "overlay",
+            &[SURFACE_TARGET, DEPTH_TARGET],
+            &[SURFACE_TARGET],
+            move |encoder| {
+                let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("Overlay Pass"),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: surface_view,
+                        depth_slice: None,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Load,
+                            store: wgpu::StoreOp::Store,
+                        },
+                    })],
+                    depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                        view: depth_view,
+                        depth_ops: Some(wgpu::Operations {
+                            load: wgpu::LoadOp::Load,
+                            store: wgpu::StoreOp::Store,
+                        }),
+                        stencil_ops: None,
+                    }),
+                    occlusion_query_set: None,
+                    timestamp_writes: None,
+                });
+                render_pass.set_bind_group(0, camera_bind_group, &[]);
+                progress_bar.render(&mut render_pass, None, true);
+                hud.render(&mut render_pass, None, true);
+                selection_overlay.render(&mut render_pass);
+            },
+        );
+        graph.add_pass(
+            "loading",
+            &[SURFACE_TARGET],
+            &[SURFACE_TARGET],
+            move |encoder| loading_screen.render(encoder, surface_view),
+        );
+        graph.execute(&mut encoder);
+        self.transient_targets.recycle();
         self.queue.submit(iter::once(encoder.finish()));
+        drop(encode_timer);
+        let _timer = frame_timing::scope(Phase::Present);
         output.present();
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wgpu::naga;
+
+    fn validate(name: &str, source: &str) {
+        let module = naga::front::wgsl::parse_str(source)
+            .unwrap_or_else(|error| panic!("{}: {}", name, error.emit_to_string(source)));
+        naga::valid::Validator::new(
+            naga::valid::ValidationFlags::all(),
+            naga::valid::Capabilities::default(),
+        )
+        .validate(&module)
+        .unwrap_or_else(|error| panic!("{}: {}", name, error.emit_to_string(source)));
+    }
+
+    #[test]
+    fn composed_shaders_validate() {
+        validate("shader.wgsl", SHADER_SOURCE);
+        validate("primitive.wgsl", PRIMITIVE_SOURCE);
+        validate("velocity.wgsl", super::super::post_process::VELOCITY_SOURCE);
+    }
+
+    #[test]
+    fn standalone_shaders_validate() {
+        validate("dof.wgsl", include_str!("shaders/dof.wgsl"));
+        validate("ssao.wgsl", include_str!("shaders/ssao.wgsl"));
+        validate("motion_blur.wgsl", include_str!("shaders/motion_blur.wgsl"));
+        validate("overlay.wgsl", include_str!("shaders/overlay.wgsl"));
+        validate("loading.wgsl", include_str!("shaders/loading.wgsl"));
+    }
+}
@@ -0,0 +1,98 @@
+// A plain snapshot of what the scene is doing, for the page, overlays and logging.
+// State publishes one every frame, readers take a copy
+
+use std::sync::Mutex;
+
+use crate::{
+    core::gpu_memory::{self, CATEGORIES},
+    helpers::json::write_vec3,
+};
+
+static LATEST: Mutex<Option<SceneStatus>> = Mutex::new(None);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SceneStatus {
+    // Section the scroll progress has settled on
+    pub section: Option<usize>,
+    pub scroll_progress: f32,
+    // Animations are moving, waiting on a delay or about to rebound, or scrolling snaps
+    pub transition_active: bool,
+    // Average completion of the running animations, 1 when nothing runs
+    pub transition_progress: f32,
+    pub camera_eye: [f32; 3],
+    pub camera_target: [f32; 3],
+    pub camera_fovy: f32,
+    // The sequencer is playing the story
+    pub story_mode: bool,
+    pub visible_instances: usize,
+    // Instances with a running or waiting animation
+    pub active_animations: usize,
+    // Seconds simulated since frame stepping paused, None while running normally
+    pub simulated_time: Option<f32>,
+    // Heap allocations during the last frame, None without the alloc-counter feature
+    pub allocations: Option<usize>,
+    // Bytes held on the GPU, in the order of gpu_memory::CATEGORIES
+    pub gpu_memory: [u64; CATEGORIES.len()],
+}
+
+impl SceneStatus {
+    pub fn to_json(&self) -> String {
+        let mut out = Vec::with_capacity(256);
+        let _ = self.write_json(&mut out);
+        String::from_utf8(out).unwrap_or_default()
+    }
+
+    pub fn write_json(&self, out: &mut impl std::io::Write) -> std::io::Result<()> {
+        write!(out, "{{\"section\":")?;
+        match self.section {
+            Some(section) => write!(out, "{}", section)?,
+            None => write!(out, "null")?,
+        }
+        write!(
+            out,
+            ",\"scrollProgress\":{},\"transitionActive\":{},\"transitionProgress\":{}",
+            self.scroll_progress, self.transition_active, self.transition_progress
+        )?;
+        write!(out, ",\"camera\":{{\"eye\":")?;
+        write_vec3(out, self.camera_eye)?;
+        write!(out, ",\"target\":")?;
+        write_vec3(out, self.camera_target)?;
+        write!(
+            out,
+            ",\"fovy\":{}}},\"storyMode\":{},\"visibleInstances\":{},\"activeAnimations\":{}",
+            self.camera_fovy, self.story_mode, self.visible_instances, self.active_animations
+        )?;
+        write!(out, ",\"simulatedTime\":")?;
+        match self.simulated_time {
+            Some(time) => write!(out, "{}", time)?,
+            None => write!(out, "null")?,
+        }
+        write!(out, ",\"allocations\":")?;
+        match self.allocations {
+            Some(allocations) => write!(out, "{}", allocations)?,
+            None => write!(out, "null")?,
+        }
+        write!(out, ",\"gpuMemory\":{{")?;
+        for (index, (category, bytes)) in CATEGORIES.iter().zip(self.gpu_memory).enumerate() {
+            let separator = if index == 0 { "" } else { "," };
+            write!(out, "{}\"{}\":{}", separator, category.name(), bytes)?;
+        }
+        write!(out, "}}}}")
+    }
+}
+
+// Current GPU memory per category, for SceneStatus::gpu_memory
+pub fn gpu_memory_usage() -> [u64; CATEGORIES.len()] {
+    CATEGORIES.map(gpu_memory::allocated)
+}
+
+pub fn publish(status: SceneStatus) {
+    if let Ok(mut latest) = LATEST.lock() {
+        *latest = Some(status);
+    }
+}
+
+// Last published status, None before the first frame
+pub fn latest() -> Option<SceneStatus> {
+    LATEST.lock().ok().and_then(|latest| *latest)
+}
@@ -0,0 +1,73 @@
+// Small text values that survive restarts, localStorage on wasm and files under ~/.cv_game on native
+
+#[cfg(target_arch = "wasm32")]
+pub fn load(key: &str) -> Option<String> {
+    let storage = web_sys::window()?.local_storage().ok()??;
+    storage.get_item(&storage_key(key)).ok()?
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn save(key: &str, value: &str) {
+    let storage = match web_sys::window().and_then(|window| window.local_storage().ok().flatten()) {
+        Some(storage) => storage,
+        None => return,
+    };
+    if storage.set_item(&storage_key(key), value).is_err() {
+        log::warn!("Could not store {}", key);
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn storage_key(key: &str) -> String {
+    format!("cv-game.{}", key)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn load(key: &str) -> Option<String> {
+    std::fs::read_to_string(storage_dir().join(key)).ok()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn save(key: &str, value: &str) {
+    save_file(key, value);
+}
+
+// Like save, but returns where the value was written
+#[cfg(not(target_arch = "wasm32"))]
+pub fn save_file(key: &str, value: &str) -> Option<std::path::PathBuf> {
+    let dir = storage_dir();
+    let path = dir.join(key);
+    let result = std::fs::create_dir_all(&dir).and_then(|_| std::fs::write(&path, value));
+    match result {
+        Ok(()) => Some(path),
+        Err(error) => {
+            log::warn!("Could not store {}: {}", key, error);
+            None
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn storage_dir() -> std::path::PathBuf {
+    std::env::var_os("HOME")
+        .map(std::path::PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir)
+        .join(".cv_game")
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saved_values_are_on_disk_right_away() {
+        // Nothing is buffered, a value saved just before closing is there on the next start
+        let key = format!("test-{}", std::process::id());
+        let path = save_file(&key, "eye 1 2 3\n").expect("storage dir is writable");
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "eye 1 2 3\n");
+        assert_eq!(load(&key).as_deref(), Some("eye 1 2 3\n"));
+        save(&key, "");
+        assert_eq!(load(&key).as_deref(), Some(""));
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,177 @@
+// Builds the GPU side of the scene on wgpu's no-op backend, so the tests drive the real
+// controllers and game loop without an adapter
+
+use std::{collections::HashMap, sync::Arc};
+
+use cgmath::{Point3, Quaternion, Vector2, Vector3};
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+
+use crate::{
+    core::{
+        camera::{Camera, ScreenMapper},
+        capabilities::Capabilities,
+        game_loop::{Chunk, Gameloop},
+        instance_builder::chunk_instances,
+        settings::Settings,
+        state,
+    },
+    entity::entity::{make_cube_primitive, Instance, InstanceController, Jitter},
+    helpers::animation::AnimationHandler,
+};
+
+pub fn device() -> (Arc<wgpu::Device>, Arc<wgpu::Queue>) {
+    let (device, queue) = wgpu::Device::noop(&wgpu::DeviceDescriptor::default());
+    (Arc::new(device), Arc::new(queue))
+}
+
+// An empty-by-default controller on the primitive pipeline, like the ones State::new builds
+pub fn controller(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    instances: Vec<Instance>,
+) -> InstanceController {
+    let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        entries: &[wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::VERTEX,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }],
+        label: Some("camera_bind_group_layout"),
+    });
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("PrimitiveShader"),
+        source: wgpu::ShaderSource::Wgsl(state::PRIMITIVE_SOURCE.into()),
+    });
+    let (mb, renderer) = make_cube_primitive(None).get_mesh_buffer(
+        device,
+        &shader,
+        wgpu::TextureFormat::Bgra8UnormSrgb,
+        queue,
+        layout,
+    );
+    InstanceController::new(instances, 0, mb, renderer, device)
+}
+
+// A handler tracking `instances`, built the way Gameloop::new builds its own
+pub fn animation_handler(instances: &[Instance]) -> AnimationHandler {
+    let (device, queue) = device();
+    AnimationHandler::new(&controller(&device, &queue, instances.to_vec()))
+}
+
+// Same chunk size as State::new
+pub const CHUNK_SIZE: Vector2<u32> = Vector2::new(35, 35);
+
+// A game loop whose chunk (0, 0) got `instances` from the instance builder, revealed
+pub fn game_loop(instances: Vec<Instance>) -> Gameloop {
+    let (device, queue) = device();
+    let origin = Chunk { x: 0, y: 0 };
+    let mut chunk_map = HashMap::new();
+    chunk_map.insert(origin, controller(&device, &queue, Vec::new()));
+    let detail_cubes = controller(&device, &queue, Vec::new());
+    let mut game_loop = Gameloop::new(
+        "Test".to_string(),
+        PhysicalPosition::new(0.0, 0.0),
+        device,
+        queue,
+        CHUNK_SIZE,
+        chunk_map,
+        detail_cubes,
+        Capabilities::downlevel(),
+    );
+    game_loop.install_instances(origin, instances);
+    game_loop.skip_reveal();
+    game_loop
+}
+
+// The flat grid of chunk (0, 0) as the page builds it
+pub fn grid() -> Vec<Instance> {
+    let settings = Settings::default();
+    chunk_instances(
+        Chunk { x: 0, y: 0 },
+        CHUNK_SIZE,
+        false,
+        settings.jitter,
+        settings.seed,
+    )
+}
+
+// A visible unit cube at `position`
+pub fn cube(position: Vector3<f32>) -> Instance {
+    Instance {
+        position,
+        rotation: Quaternion::new(1.0, 0.0, 0.0, 0.0),
+        should_render: true,
+        scale: 1.0,
+        color: Vector3::new(0.5, 0.5, 0.5),
+        size: Vector3::new(1.0, 1.0, 1.0),
+        bounding: position + Vector3::new(1.0, 1.0, 1.0),
+        emissive: 0.0,
+        jitter: Jitter::NONE,
+        ao: 1.0,
+    }
+}
+
+// A row of `count` cubes along x
+pub fn row(count: usize) -> Vec<Instance> {
+    (0..count)
+        .map(|x| cube(Vector3::new(x as f32, 0.0, 0.0)))
+        .collect()
+}
+
+// An 800×600 surface with the window the same size
+pub fn screen() -> ScreenMapper {
+    ScreenMapper::new(PhysicalSize::new(800, 600), 1.0)
+}
+
+pub fn screen_center() -> PhysicalPosition<f32> {
+    PhysicalPosition::new(400.0, 300.0)
+}
+
+// Straight above `point` looking down on it, so the screen center picks whatever is on top.
+// Same lens as State::new, picking depends on the clip planes
+pub fn camera_above(point: Point3<f32>) -> Camera {
+    Camera {
+        eye: point + Vector3::new(0.0, 40.0, 0.0),
+        target: point,
+        up: -Vector3::unit_z(),
+        roll: 0.0,
+        aspect: 800.0 / 600.0,
+        fovy: 20.0,
+        znear: 0.1,
+        zfar: 1.0,
+        center_offset: Vector2::new(0.0, 0.0),
+    }
+}
+
+// A real adapter for the tests that need pixels back, software renderers like llvmpipe
+// count. None on machines without one, those tests skip
+pub fn gpu() -> Option<(wgpu::Device, wgpu::Queue)> {
+    gpu_with_capabilities().map(|(device, queue, _)| (device, queue))
+}
+
+// Same adapter, plus what it can do for the tests whose passes are gated on that
+pub fn gpu_with_capabilities() -> Option<(wgpu::Device, wgpu::Queue, Capabilities)> {
+    let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
+        backends: wgpu::Backends::PRIMARY | wgpu::Backends::GL,
+        ..Default::default()
+    });
+    let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+        power_preference: wgpu::PowerPreference::LowPower,
+        force_fallback_adapter: false,
+        compatible_surface: None,
+    }))
+    .ok()?;
+    let (device, queue) = pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor {
+        required_limits:
+            wgpu::Limits::downlevel_webgl2_defaults().using_resolution(adapter.limits()),
+        ..Default::default()
+    }))
+    .ok()?;
+    let capabilities = Capabilities::detect(&adapter, &device, wgpu::TextureFormat::Bgra8UnormSrgb);
+    Some((device, queue, capabilities))
+}
@@ -0,0 +1,213 @@
+// Names what the cube under a resting cursor belongs to. The web build shows a small panel
+// next to the cursor, native logs the text
+
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+
+// Seconds the cursor has to rest before the tooltip shows
+const HOVER_DWELL: f32 = 0.3;
+// Cursor movement in pixels that still counts as resting
+const HOVER_SLOP: f32 = 4.0;
+// Gap between the cursor and the panel
+const CURSOR_OFFSET: f32 = 16.0;
+#[cfg(target_arch = "wasm32")]
+const TOOLTIP_ID: &str = "cv-tooltip";
+#[cfg(target_arch = "wasm32")]
+const TOOLTIP_STYLE: &str = "position:fixed;padding:0.3rem 0.6rem;background:rgba(0,0,0,0.8);\
+     color:#fff;font:13px sans-serif;border-radius:4px;pointer-events:none;z-index:910";
+
+#[derive(Default)]
+pub struct Tooltip {
+    anchor: PhysicalPosition<f32>,
+    rested: f32,
+    visible: bool,
+}
+
+impl Tooltip {
+    // Whether the cursor just came to rest, true once per rest
+    pub fn update(&mut self, cursor: PhysicalPosition<f32>, dt: f32) -> bool {
+        let dx = cursor.x - self.anchor.x;
+        let dy = cursor.y - self.anchor.y;
+        if dx * dx + dy * dy > HOVER_SLOP * HOVER_SLOP {
+            self.anchor = cursor;
+            self.rested = 0.0;
+            self.hide();
+            return false;
+        }
+        let waiting = self.rested < HOVER_DWELL;
+        self.rested += dt;
+        waiting && self.rested >= HOVER_DWELL
+    }
+
+    // Still counting down to a tooltip, frames have to keep coming until then
+    pub fn is_waiting(&self) -> bool {
+        self.rested < HOVER_DWELL
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn show(&mut self, text: &str, _cursor: PhysicalPosition<f32>, _css_scale: f32) {
+        self.visible = true;
+        log::warn!("Tooltip: {}", text);
+    }
+
+    // `css_scale` converts surface pixels to CSS pixels. The panel is placed in the
+    // viewport, the canvas fills it
+    #[cfg(target_arch = "wasm32")]
+    pub fn show(&mut self, text: &str, cursor: PhysicalPosition<f32>, css_scale: f32) {
+        use wasm_bindgen::JsCast;
+
+        let element = match tooltip_element() {
+            Some(element) => element,
+            None => return,
+        };
+        element.set_text_content(Some(text));
+        let _ = element.set_attribute("style", TOOLTIP_STYLE);
+        let viewport = web_sys::window().and_then(|window| {
+            let width = window.inner_width().ok()?.as_f64()?;
+            let height = window.inner_height().ok()?.as_f64()?;
+            Some(PhysicalSize::new(width as f32, height as f32))
+        });
+        let (viewport, panel) = match (viewport, element.dyn_ref::<web_sys::HtmlElement>()) {
+            (Some(viewport), Some(panel)) => (
+                viewport,
+                PhysicalSize::new(panel.offset_width() as f32, panel.offset_height() as f32),
+            ),
+            _ => return,
+        };
+        let cursor = PhysicalPosition::new(cursor.x * css_scale, cursor.y * css_scale);
+        let position = tooltip_position(cursor, panel, viewport);
+        let _ = element.set_attribute(
+            "style",
+            &format!(
+                "{};left:{}px;top:{}px",
+                TOOLTIP_STYLE, position.x, position.y
+            ),
+        );
+        self.visible = true;
+    }
+
+    pub fn hide(&mut self) {
+        if !self.visible {
+            return;
+        }
+        self.visible = false;
+        #[cfg(target_arch = "wasm32")]
+        if let Some(element) = tooltip_element() {
+            element.set_text_content(None);
+            let _ = element.set_attribute("style", &format!("{};display:none", TOOLTIP_STYLE));
+        }
+    }
+}
+
+// Below right of the cursor, flipped to the other side of it where the panel would leave
+// the screen and clamped into it when it fits on neither side
+pub fn tooltip_position(
+    cursor: PhysicalPosition<f32>,
+    panel: PhysicalSize<f32>,
+    screen: PhysicalSize<f32>,
+) -> PhysicalPosition<f32> {
+    let place = |cursor: f32, panel: f32, screen: f32| {
+        let after = cursor + CURSOR_OFFSET;
+        let before = cursor - CURSOR_OFFSET - panel;
+        let position = if after + panel <= screen || before < 0.0 {
+            after
+        } else {
+            before
+        };
+        position.min(screen - panel).max(0.0)
+    };
+    PhysicalPosition::new(
+        place(cursor.x, panel.width, screen.width),
+        place(cursor.y, panel.height, screen.height),
+    )
+}
+
+#[cfg(target_arch = "wasm32")]
+fn tooltip_element() -> Option<web_sys::Element> {
+    let document = web_sys::window()?.document()?;
+    if let Some(element) = document.get_element_by_id(TOOLTIP_ID) {
+        return Some(element);
+    }
+    let canvas = document.get_element_by_id("canvas")?;
+    let element = document.create_element("div").ok()?;
+    element.set_id(TOOLTIP_ID);
+    element.set_attribute("role", "tooltip").ok()?;
+    canvas.insert_adjacent_element("afterend", &element).ok()?;
+    Some(element)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: f32 = 0.05;
+
+    // Frames on which the tooltip fires while the cursor follows `path`, one point per frame
+    fn fired(tooltip: &mut Tooltip, path: &[(f32, f32)]) -> Vec<usize> {
+        path.iter()
+            .enumerate()
+            .filter(|(_, (x, y))| tooltip.update(PhysicalPosition::new(*x, *y), FRAME))
+            .map(|(frame, _)| frame)
+            .collect()
+    }
+
+    #[test]
+    fn fires_once_after_resting() {
+        let mut tooltip = Tooltip::default();
+        let path = vec![(100.0, 100.0); 20];
+        // The first frame moves onto the spot, the rest then counts 0.3 s
+        let frames = fired(&mut tooltip, &path);
+        assert_eq!(frames, vec![(HOVER_DWELL / FRAME).round() as usize]);
+        assert!(!tooltip.is_waiting());
+    }
+
+    #[test]
+    fn jitter_within_the_slop_still_rests() {
+        let mut tooltip = Tooltip::default();
+        // Drifting around the spot it first stopped on, never past the slop from it
+        let path = (0..20)
+            .map(|frame| match frame % 3 {
+                0 => (100.0, 100.0),
+                1 => (102.5, 101.0),
+                _ => (98.0, 97.5),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(fired(&mut tooltip, &path).len(), 1);
+    }
+
+    #[test]
+    fn moving_past_the_slop_starts_over() {
+        let mut tooltip = Tooltip::default();
+        let mut path = vec![(100.0, 100.0); 5];
+        path.extend(vec![(100.0 + HOVER_SLOP + 1.0, 100.0); 20]);
+        let frames = fired(&mut tooltip, &path);
+        // The move at frame 5 restarts the dwell, it fires 0.3 s after that
+        assert_eq!(frames, vec![5 + (HOVER_DWELL / FRAME).round() as usize]);
+    }
+
+    #[test]
+    fn panel_flips_at_the_edges_and_clamps() {
+        let screen = PhysicalSize::new(800.0, 600.0);
+        let panel = PhysicalSize::new(200.0, 50.0);
+        let at = |x, y| tooltip_position(PhysicalPosition::new(x, y), panel, screen);
+        assert_eq!(
+            at(100.0, 100.0),
+            PhysicalPosition::new(100.0 + CURSOR_OFFSET, 100.0 + CURSOR_OFFSET)
+        );
+        // Right edge flips it left of the cursor, bottom edge above it
+        assert_eq!(
+            at(700.0, 100.0),
+            PhysicalPosition::new(700.0 - CURSOR_OFFSET - 200.0, 100.0 + CURSOR_OFFSET)
+        );
+        assert_eq!(
+            at(100.0, 580.0),
+            PhysicalPosition::new(100.0 + CURSOR_OFFSET, 580.0 - CURSOR_OFFSET - 50.0)
+        );
+        // Wider than either side of the cursor, it is kept on screen
+        let wide = PhysicalSize::new(500.0, 50.0);
+        let position = tooltip_position(PhysicalPosition::new(400.0, 100.0), wide, screen);
+        assert_eq!(position.x, 800.0 - 500.0);
+        let huge = PhysicalSize::new(900.0, 700.0);
+        let position = tooltip_position(PhysicalPosition::new(400.0, 300.0), huge, screen);
+        assert_eq!(position, PhysicalPosition::new(0.0, 0.0));
+    }
+}
@@ -0,0 +1,132 @@
+// Spreads instance buffer uploads over frames so many changed chunks don't stall one frame
+
+use std::collections::HashMap;
+
+use crate::{core::game_loop::Chunk, entity::entity::InstanceController};
+
+pub struct UploadScheduler {
+    // Bytes of queue.write_buffer allowed per frame
+    pub budget_bytes: usize,
+    frame: u64,
+    // Frame each chunk was last modified on, recently modified chunks go first
+    modified: HashMap<Chunk, u64>,
+}
+
+impl UploadScheduler {
+    pub fn new(budget_bytes: usize) -> UploadScheduler {
+        UploadScheduler {
+            budget_bytes,
+            frame: 0,
+            modified: HashMap::new(),
+        }
+    }
+
+    // Marks the chunk for upload and bumps its priority
+    pub fn touch(&mut self, chunk: Chunk, controller: &mut InstanceController) {
+        controller.mark_dirty();
+        self.modified.insert(chunk, self.frame);
+    }
+
+    // Uploads dirty chunks in priority order until the budget runs out. The chunk with the
+    // camera target comes first, then recently modified ones, then the nearest. The first
+    // chunk is always uploaded so a chunk larger than the budget still gets through. Chunks
+    // left alone this frame get their previous transforms settled afterwards
+    pub fn upload(
+        &mut self,
+        chunk_map: &mut HashMap<Chunk, InstanceController>,
+        focus: Chunk,
+        queue: &wgpu::Queue,
+    ) -> usize {
+        self.frame += 1;
+        let mut pending = chunk_map
+            .iter()
+            .filter(|(_, controller)| controller.needs_upload())
+            .map(|(chunk, _)| *chunk)
+            .collect::<Vec<_>>();
+        pending.sort_by_key(|chunk| {
+            let distance = (chunk.x - focus.x).abs() + (chunk.y - focus.y).abs();
+            let modified = self.modified.get(chunk).copied().unwrap_or(0);
+            (*chunk != focus, std::cmp::Reverse(modified), distance)
+        });
+        let mut used = 0;
+        for chunk in pending {
+            let controller = match chunk_map.get_mut(&chunk) {
+                Some(controller) => controller,
+                None => continue,
+            };
+            let budget = if used == 0 {
+                usize::MAX
+            } else {
+                self.budget_bytes.saturating_sub(used)
+            };
+            used += controller.upload_budgeted(queue, budget);
+            if used >= self.budget_bytes {
+                break;
+            }
+        }
+        for controller in chunk_map.values_mut() {
+            controller.settle_previous(queue);
+        }
+        used
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::test_support;
+
+    #[test]
+    fn one_chunk_budget_uploads_the_nearest_chunk_each_frame() {
+        let (device, queue) = test_support::device();
+        let mut chunk_map = HashMap::new();
+        for x in -2..=2 {
+            for y in -2..=2 {
+                let controller = test_support::controller(&device, &queue, test_support::row(4));
+                chunk_map.insert(Chunk { x, y }, controller);
+            }
+        }
+        let chunk_bytes = chunk_map[&Chunk { x: 0, y: 0 }].upload_size();
+        let mut scheduler = UploadScheduler::new(chunk_bytes);
+        for (chunk, controller) in chunk_map.iter_mut() {
+            scheduler.touch(*chunk, controller);
+        }
+
+        let focus = Chunk { x: 0, y: 0 };
+        let mut distances = Vec::new();
+        for _ in 0..25 {
+            let dirty = chunk_map
+                .iter()
+                .filter(|(_, controller)| controller.needs_upload())
+                .map(|(chunk, _)| *chunk)
+                .collect::<Vec<_>>();
+            assert!(scheduler.upload(&mut chunk_map, focus, &queue) <= chunk_bytes);
+            let uploaded = dirty
+                .into_iter()
+                .filter(|chunk| !chunk_map[chunk].needs_upload())
+                .collect::<Vec<_>>();
+            assert_eq!(uploaded.len(), 1);
+            distances.push(uploaded[0].x.abs() + uploaded[0].y.abs());
+        }
+        assert_eq!(distances[0], 0);
+        assert!(distances.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert!(chunk_map
+            .values()
+            .all(|controller| !controller.needs_upload()));
+    }
+
+    #[test]
+    fn oversized_chunk_still_goes_through() {
+        let (device, queue) = test_support::device();
+        let mut chunk_map = HashMap::new();
+        let chunk = Chunk { x: 0, y: 0 };
+        chunk_map.insert(
+            chunk,
+            test_support::controller(&device, &queue, test_support::row(4)),
+        );
+        let mut scheduler = UploadScheduler::new(1);
+        scheduler.touch(chunk, chunk_map.get_mut(&chunk).unwrap());
+        scheduler.upload(&mut chunk_map, chunk, &queue);
+        assert!(!chunk_map[&chunk].needs_upload());
+    }
+}
@@ -0,0 +1,216 @@
+// Headless content check for `cv_game validate [manifest]`, no window or GPU. Prints every
+// problem it finds and fails on errors so CI can gate content changes
+
+use cgmath::Vector3;
+
+use crate::{
+    core::{
+        capabilities::Capabilities,
+        content::{parse_manifest, Manifest, EMBEDDED_MANIFEST},
+        detail,
+        section::MAX_TAGS,
+    },
+    entity::progress_bar::PROGRESS_CUBES,
+};
+
+#[derive(Default)]
+pub struct Report {
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    pub fn print(&self, source: &str) {
+        for error in &self.errors {
+            println!("error: {}: {}", source, error);
+        }
+        for warning in &self.warnings {
+            println!("warning: {}: {}", source, warning);
+        }
+        println!(
+            "{}: {} errors, {} warnings",
+            source,
+            self.errors.len(),
+            self.warnings.len()
+        );
+    }
+}
+
+pub fn validate_manifest(text: &str) -> Report {
+    let mut report = Report::default();
+    match parse_manifest(text) {
+        Ok(manifest) => check_manifest(&manifest, &mut report),
+        Err(error) => report.errors.push(error),
+    }
+    report
+}
+
+fn check_manifest(manifest: &Manifest, report: &mut Report) {
+    if manifest.version == 0 {
+        report
+            .warnings
+            .push("no version, asset urls won't be cache busted".to_string());
+    }
+    // One anchor per progress cube, sections past the last anchor are never reached
+    if manifest.sections.len() > PROGRESS_CUBES {
+        report.errors.push(format!(
+            "{} sections but only {} scroll anchors",
+            manifest.sections.len(),
+            PROGRESS_CUBES
+        ));
+    }
+    for (index, section) in manifest.sections.iter().enumerate() {
+        let name = format!("section {}", index + 1);
+        if section.title.is_empty() && (!section.tags.is_empty() || !section.links.is_empty()) {
+            report.warnings.push(format!(
+                "{} has tags or links but no title, they won't show",
+                name
+            ));
+        }
+        if section.tags.len() > MAX_TAGS {
+            report.warnings.push(format!(
+                "{} has {} tags, only the first {} show",
+                name,
+                section.tags.len(),
+                MAX_TAGS
+            ));
+        }
+        for link in &section.links {
+            if !link.starts_with("https://") && !link.starts_with("http://") {
+                report
+                    .warnings
+                    .push(format!("{} link `{}` is not an http url", name, link));
+            }
+        }
+        for (index, cue) in section.camera.iter().enumerate() {
+            let movement = cue.movement;
+            let values = [
+                cue.progress,
+                cue.duration,
+                movement.dolly,
+                movement.orbit,
+                movement.roll,
+                movement.zoom,
+            ];
+            if values.iter().any(|value| !value.is_finite()) {
+                report
+                    .errors
+                    .push(format!("{} camera cue {} is not finite", name, index + 1));
+            }
+            // Two moves starting together play over each other
+            if section.camera[..index]
+                .iter()
+                .any(|earlier| earlier.progress == cue.progress)
+            {
+                report.errors.push(format!(
+                    "{} camera cue {} overlaps an earlier cue at the same progress",
+                    name,
+                    index + 1
+                ));
+            }
+        }
+        // Checked against the smallest instance buffer the page has to run with
+        if let Some(spec) = section.detail {
+            let budget = Capabilities::downlevel().max_instances_hint as usize;
+            let cubes = spec.max_cubes.saturating_mul(detail::CHILDREN);
+            if cubes > budget {
+                report.errors.push(format!(
+                    "{} detail needs up to {} cubes, over the {} instance budget",
+                    name, cubes, budget
+                ));
+            }
+        }
+    }
+    for (index, (min, max)) in manifest.pinned.iter().enumerate() {
+        let name = format!("pinned box {}", index + 1);
+        if !is_finite(*min) || !is_finite(*max) {
+            report
+                .errors
+                .push(format!("{} has a non-finite corner", name));
+        } else if min.x > max.x || min.y > max.y || min.z > max.z {
+            report
+                .errors
+                .push(format!("{} has its min corner above its max", name));
+        }
+    }
+}
+
+fn is_finite(corner: Vector3<f32>) -> bool {
+    corner.x.is_finite() && corner.y.is_finite() && corner.z.is_finite()
+}
+
+// Validates the manifest at `path`, or the embedded one, and returns the exit code
+#[cfg(not(target_arch = "wasm32"))]
+pub fn run(path: Option<&str>) -> i32 {
+    let (source, report) = match path {
+        Some(path) => match std::fs::read_to_string(path) {
+            Ok(text) => (path.to_string(), validate_manifest(&text)),
+            Err(error) => {
+                println!("error: {}: {}", path, error);
+                return 2;
+            }
+        },
+        None => (
+            "embedded manifest".to_string(),
+            validate_manifest(EMBEDDED_MANIFEST),
+        ),
+    };
+    report.print(&source);
+    if report.is_ok() {
+        0
+    } else {
+        1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_manifests_pass() {
+        for text in [
+            EMBEDDED_MANIFEST,
+            include_str!("content/fixtures/valid.txt"),
+        ]
+        .iter()
+        {
+            let report = validate_manifest(text);
+            assert!(report.is_ok(), "{:?}", report.errors);
+            assert!(report.warnings.is_empty(), "{:?}", report.warnings);
+        }
+    }
+
+    #[test]
+    fn overlapping_cues_fail() {
+        let report = validate_manifest(include_str!("content/fixtures/overlapping.txt"));
+        assert_eq!(
+            report.errors,
+            vec!["section 2 camera cue 2 overlaps an earlier cue at the same progress"]
+        );
+    }
+
+    #[test]
+    fn oversized_detail_fails() {
+        let report = validate_manifest(include_str!("content/fixtures/oversized.txt"));
+        assert_eq!(report.errors.len(), 1);
+        assert!(report.errors[0].starts_with("section 1 detail needs up to 8000000 cubes"));
+    }
+
+    #[test]
+    fn broken_values_fail() {
+        let report = validate_manifest("[section]\ntitle = A\ncamera = NaN, dolly 2, 1, ease\n");
+        assert_eq!(report.errors, vec!["section 1 camera cue 1 is not finite"]);
+        let report = validate_manifest("version = 1\npinned = 0, 0, 0, -1, 1, 1\n");
+        assert_eq!(
+            report.errors,
+            vec!["pinned box 1 has its min corner above its max"]
+        );
+        let report = validate_manifest("[section]\ncolor = red\n");
+        assert_eq!(report.errors, vec!["line 2: unexpected key `color`"]);
+    }
+}
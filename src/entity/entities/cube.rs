@@ -4,38 +4,49 @@ pub struct TexturedCube {
     pub vertices: Vec<TexturedVertex>,
     pub indices: Vec<u16>,
 }
+const WHITE: [f32; 3] = [1.0, 1.0, 1.0];
+// Six indices per face, in INDICES order
+const FACE_INDICES: usize = 6;
 const VERTICES: &[TexturedVertex] = &[
     TexturedVertex {
         position: [0.0, 0.0, 1.0],
         tex_coords: [0.0, 0.0],
+        color: WHITE,
     }, // A
     TexturedVertex {
         position: [1.0, 0.0, 1.0],
         tex_coords: [0.0, 1.0],
+        color: WHITE,
     }, // B
     TexturedVertex {
         position: [0.0, 1.0, 1.0],
         tex_coords: [0.0, 1.0],
+        color: WHITE,
     }, // C
     TexturedVertex {
         position: [1.0, 1.0, 1.0],
         tex_coords: [0.0, 0.0],
+        color: WHITE,
     }, // D
     TexturedVertex {
         position: [0.0, 0.0, 0.0],
         tex_coords: [1.0, 0.0],
+        color: WHITE,
     }, // A
     TexturedVertex {
         position: [1.0, 0.0, 0.0],
         tex_coords: [1.0, 1.0],
+        color: WHITE,
     }, // B
     TexturedVertex {
         position: [0.0, 1.0, 0.0],
         tex_coords: [1.0, 1.0],
+        color: WHITE,
     }, // C
     TexturedVertex {
         position: [1.0, 1.0, 0.0],
         tex_coords: [1.0, 0.0],
+        color: WHITE,
     }, // D
 ];
 #[rustfmt::skip]
@@ -59,6 +70,36 @@ impl TexturedCube {
             indices: INDICES.to_vec(),
         }
     }
+
+    // Multiplies the texture by the tint, and per face by `face_colors` in INDICES order:
+    // top, bottom, left, right, front, back. Faces with their own colors can't share
+    // corners, so those cubes get a vertex per index
+    pub fn with_colors(tint: [f32; 3], face_colors: Option<[[f32; 3]; 6]>) -> TexturedCube {
+        let blend = |color: [f32; 3]| [tint[0] * color[0], tint[1] * color[1], tint[2] * color[2]];
+        match face_colors {
+            None => TexturedCube {
+                vertices: VERTICES
+                    .iter()
+                    .map(|vertex| TexturedVertex {
+                        color: blend(vertex.color),
+                        ..*vertex
+                    })
+                    .collect(),
+                indices: INDICES.to_vec(),
+            },
+            Some(face_colors) => TexturedCube {
+                vertices: INDICES
+                    .iter()
+                    .enumerate()
+                    .map(|(i, &index)| TexturedVertex {
+                        color: blend(face_colors[i / FACE_INDICES]),
+                        ..VERTICES[index as usize]
+                    })
+                    .collect(),
+                indices: (0..INDICES.len() as u16).collect(),
+            },
+        }
+    }
 }
 
 use crate::entity::entity::PrimitiveVertex;
@@ -70,35 +111,35 @@ pub struct PrimitiveCube {
 const PRIMITIVE_VERTICES: &[PrimitiveVertex] = &[
     PrimitiveVertex {
         position: [0.0, 0.0, 1.0],
-        color: [1.0, 0.0, 1.0],
+        color: WHITE,
     }, // A
     PrimitiveVertex {
         position: [1.0, 0.0, 1.0],
-        color: [1.0, 0.0, 1.0],
+        color: WHITE,
     }, // B
     PrimitiveVertex {
         position: [0.0, 1.0, 1.0],
-        color: [1.0, 0.0, 1.0],
+        color: WHITE,
     }, // C
     PrimitiveVertex {
         position: [1.0, 1.0, 1.0],
-        color: [1.0, 0.0, 1.0],
+        color: WHITE,
     }, // D
     PrimitiveVertex {
         position: [0.0, 0.0, 0.0],
-        color: [1.0, 0.0, 1.0],
+        color: WHITE,
     }, // A
     PrimitiveVertex {
         position: [1.0, 0.0, 0.0],
-        color: [1.0, 0.0, 1.0],
+        color: WHITE,
     }, // B
     PrimitiveVertex {
         position: [0.0, 1.0, 0.0],
-        color: [1.0, 0.0, 1.0],
+        color: WHITE,
     }, // C
     PrimitiveVertex {
         position: [1.0, 1.0, 0.0],
-        color: [1.0, 0.0, 1.0],
+        color: WHITE,
     }, // D
 ];
 impl PrimitiveCube {
@@ -108,4 +149,32 @@ impl PrimitiveCube {
             indices: INDICES.to_vec(),
         }
     }
+
+    // Darkens faces by `[top, sides, bottom]`, multiplied under the instance color. Like
+    // TexturedCube::with_colors, shaded faces get a vertex per index
+    pub fn with_face_shading(shading: Option<[f32; 3]>) -> PrimitiveCube {
+        let factors = match shading {
+            Some(shading) => face_factors(shading),
+            None => return PrimitiveCube::new(),
+        };
+        PrimitiveCube {
+            vertices: INDICES
+                .iter()
+                .enumerate()
+                .map(|(i, &index)| {
+                    let factor = factors[i / FACE_INDICES];
+                    PrimitiveVertex {
+                        color: [factor; 3],
+                        ..PRIMITIVE_VERTICES[index as usize]
+                    }
+                })
+                .collect(),
+            indices: (0..INDICES.len() as u16).collect(),
+        }
+    }
+}
+
+// `[top, sides, bottom]` spread over the faces in INDICES order
+pub fn face_factors([top, side, bottom]: [f32; 3]) -> [f32; 6] {
+    [top, bottom, side, side, side, side]
 }
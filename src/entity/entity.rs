@@ -1,16 +1,18 @@
 use std::{io::empty, u32};
 
 use crate::{
-    core::game_loop::Chunk,
+    core::{
+        game_loop::Chunk,
+        gpu_memory::{self, MemoryCategory, Tracked},
+    },
     entity::{
         entities::cube::{PrimitiveCube, TexturedCube},
         texture::Texture,
     },
+    helpers::vector::safe_normalize,
 };
 use cgmath::{prelude::*, Vector2, Vector3};
-use wgpu::{
-    util::DeviceExt, wgc::device, BindGroupLayout, RenderPass, SurfaceConfiguration, TextureFormat,
-};
+use wgpu::{wgc::device, BindGroupLayout, RenderPass, SurfaceConfiguration, TextureFormat};
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
@@ -23,6 +25,8 @@ pub struct PrimitiveVertex {
 pub struct TexturedVertex {
     pub position: [f32; 3],
     pub tex_coords: [f32; 2],
+    // Multiplies the sampled texture, white leaves it as is
+    pub color: [f32; 3],
 }
 impl TexturedVertex {
     pub fn desc() -> wgpu::VertexBufferLayout<'static> {
@@ -41,13 +45,18 @@ impl TexturedVertex {
                     shader_location: 1,
                     format: wgpu::VertexFormat::Float32x2,
                 },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 5]>() as wgpu::BufferAddress,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
             ],
         }
     }
 }
 
 impl PrimitiveVertex {
-    fn desc() -> wgpu::VertexBufferLayout<'static> {
+    pub fn desc() -> wgpu::VertexBufferLayout<'static> {
         use std::mem;
         wgpu::VertexBufferLayout {
             array_stride: mem::size_of::<PrimitiveVertex>() as wgpu::BufferAddress,
@@ -69,6 +78,14 @@ impl PrimitiveVertex {
     fn make_mesh_buffer(&self) {}
 }
 
+// Consecutive ranges of at most `slice` instances covering the first `count`
+pub fn instance_slices(count: u32, slice: u32) -> impl Iterator<Item = std::ops::Range<u32>> {
+    let slice = slice.max(1);
+    (0..count)
+        .step_by(slice as usize)
+        .map(move |start| start..(start + slice).min(count))
+}
+
 #[rustfmt::skip]
 pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
     1.0, 0.0, 0.0, 0.0,
@@ -77,6 +94,10 @@ pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
     0.0, 0.0, 0.0, 1.0,
 );
 
+// Visible runs drawn one call each before the whole slot range is drawn at once instead,
+// hidden slots in between then cost a degenerate instance each
+const MAX_VISIBLE_RUNS: usize = 64;
+
 pub const NUM_INSTANCES_PER_ROW: u32 = 10;
 pub const NUM_INSTANCES: u32 = 100;
 pub const INSTANCE_DISPLACEMENT: cgmath::Vector3<f32> = cgmath::Vector3::new(
@@ -85,14 +106,31 @@ pub const INSTANCE_DISPLACEMENT: cgmath::Vector3<f32> = cgmath::Vector3::new(
     NUM_INSTANCES_PER_ROW as f32,
 );
 
+// Every instance keeps the buffer slot of its index for its lifetime, hiding one writes a
+// degenerate instance into its slot instead of packing the others together. Anything keyed
+// by slot, like picking ids or per-slot buffers, stays valid across hide and show
 pub struct InstanceController {
     pub instances: Vec<Instance>,
-    pub instance_buffer: wgpu::Buffer,
+    pub instance_buffer: Tracked<wgpu::Buffer>,
     pub entity_buffers: MeshBuffer,
     pub buffer_address: u64,
     pub render: Renderer,
     capacity: usize,
+    // Visible instances
     pub count: usize,
+    // Consecutive slots of visible instances, drawn without the hidden ones in between
+    visible_runs: Vec<std::ops::Range<u32>>,
+    // Instances changed since the last upload
+    dirty: bool,
+    // Reused by every upload so serializing the instances doesn't allocate each frame
+    raw_scratch: Vec<InstanceRaw>,
+    // What every slot held one upload earlier, only kept while motion blur needs velocities
+    pub previous_buffer: Option<Tracked<wgpu::Buffer>>,
+    previous_scratch: Vec<InstanceRaw>,
+    // Uploads until the previous transforms catch up with the current ones, see settle_previous
+    previous_lag: u8,
+    // The other mesh and pipeline, kept after the first swap so swapping back is free
+    spare: Option<(MeshBuffer, Renderer)>,
 }
 
 impl InstanceController {
@@ -109,25 +147,32 @@ impl InstanceController {
             entity_buffers,
             render,
             capacity: instances.len(),
+            dirty: false,
+            raw_scratch: Vec::new(),
+            previous_buffer: None,
+            previous_scratch: Vec::new(),
+            previous_lag: 0,
+            spare: None,
             count: instances
-                .clone()
                 .iter()
                 .filter(|instance| instance.should_render)
-                .map(Instance::to_raw)
-                .collect::<Vec<_>>()
-                .len(),
+                .count(),
+            visible_runs: {
+                let mut runs = Vec::new();
+                visible_runs(&instances, &mut runs);
+                runs
+            },
             instance_buffer: {
-                let instance_data = instances
-                    .clone()
-                    .iter()
-                    .filter(|instance| instance.should_render)
-                    .map(Instance::to_raw)
-                    .collect::<Vec<_>>();
-                device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                    label: Some("Instance Buffer"),
-                    contents: bytemuck::cast_slice(&instance_data),
-                    usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-                })
+                let instance_data = instances.iter().map(Instance::to_slot).collect::<Vec<_>>();
+                gpu_memory::create_buffer_init(
+                    device,
+                    &wgpu::util::BufferInitDescriptor {
+                        label: Some("Instance Buffer"),
+                        contents: bytemuck::cast_slice(&instance_data),
+                        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                    },
+                    MemoryCategory::Instances,
+                )
             },
         }
     }
@@ -143,16 +188,23 @@ impl InstanceController {
         let new_size = instance_size * new_capacity as u64;
 
         // Create a new larger buffer
-        let new_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("Instance Buffer (Resized)"),
-            size: new_size,
-            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
+        let new_buffer = gpu_memory::create_buffer(
+            device,
+            &wgpu::BufferDescriptor {
+                label: Some("Instance Buffer (Resized)"),
+                size: new_size,
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+            MemoryCategory::Instances,
+        );
 
         // Replace old buffer
         self.instance_buffer = new_buffer;
         self.capacity = new_capacity;
+        if self.previous_buffer.is_some() {
+            self.previous_buffer = Some(self.create_previous_buffer(device));
+        }
     }
 
     pub fn add_instance(&mut self, instance: Instance, queue: &wgpu::Queue, device: &wgpu::Device) {
@@ -164,26 +216,19 @@ impl InstanceController {
         if required > self.capacity {
             self.grow_buffer(queue, device, instance_size);
         }
-        let data = self.to_raw();
-
-        queue.write_buffer(
-            &self.instance_buffer,
-            self.buffer_address,
-            bytemuck::cast_slice(&data),
-        );
+        self.write_buffers(queue);
     }
 
     pub fn remove_instance(&mut self, index: usize, queue: &wgpu::Queue) {
         if let Some(instance) = self.instances.get_mut(index) {
             instance.should_render = false;
         }
-        let data = self.to_raw();
-        self.count = data.len();
-        queue.write_buffer(
-            &self.instance_buffer,
-            self.buffer_address,
-            bytemuck::cast_slice(&data),
-        );
+        self.write_buffers(queue);
+    }
+
+    // Buffer slot of an instance, its index for as long as the instance exists
+    pub fn slot(&self, index: usize) -> Option<u32> {
+        (index < self.instances.len()).then_some(index as u32)
     }
 
     pub fn remove_instance_at_pos(
@@ -214,16 +259,178 @@ impl InstanceController {
         true
     }
 
+    // Replaces every GPU handle after a device loss, the CPU side instances are kept
+    pub fn recreate_gpu_resources(
+        &mut self,
+        entity_buffers: MeshBuffer,
+        render: Renderer,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) {
+        let instance_size = std::mem::size_of::<InstanceRaw>() as wgpu::BufferAddress;
+        self.instance_buffer = gpu_memory::create_buffer(
+            device,
+            &wgpu::BufferDescriptor {
+                label: Some("Instance Buffer"),
+                size: instance_size * self.capacity.max(1) as u64,
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+            MemoryCategory::Instances,
+        );
+        if self.previous_buffer.is_some() {
+            self.previous_buffer = Some(self.create_previous_buffer(device));
+        }
+        self.entity_buffers = entity_buffers;
+        self.render = render;
+        // Built for the old device or shaders
+        self.spare = None;
+        self.update_buffer(queue);
+    }
+
+    // Draws the instances with the spare mesh, `build` makes it on the first swap
+    pub fn swap_mesh(&mut self, build: impl FnOnce() -> (MeshBuffer, Renderer)) {
+        let (entity_buffers, render) = self.spare.take().unwrap_or_else(build);
+        let entity_buffers = std::mem::replace(&mut self.entity_buffers, entity_buffers);
+        let render = std::mem::replace(&mut self.render, render);
+        self.spare = Some((entity_buffers, render));
+    }
+
+    pub fn set_emissive(&mut self, index: usize, strength: f32) {
+        if let Some(instance) = self.instances.get_mut(index) {
+            instance.emissive = strength.max(0.0);
+        }
+    }
+
+    // Appends a batch of instances, growing the buffer once if needed
+    pub fn add_instances(
+        &mut self,
+        instances: Vec<Instance>,
+        queue: &wgpu::Queue,
+        device: &wgpu::Device,
+    ) {
+        self.instances.extend(instances);
+        let instance_size = std::mem::size_of::<InstanceRaw>() as wgpu::BufferAddress;
+        while self.instances.len() > self.capacity {
+            self.grow_buffer(queue, device, instance_size);
+        }
+        self.update_buffer(queue);
+    }
+
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    pub fn needs_upload(&self) -> bool {
+        self.dirty
+    }
+
+    // Uploads pending changes if they fit in `budget` bytes, returns the bytes written
+    pub fn upload_budgeted(&mut self, queue: &wgpu::Queue, budget: usize) -> usize {
+        if !self.dirty {
+            return 0;
+        }
+        let bytes = self.upload_size();
+        if bytes > budget {
+            return 0;
+        }
+        self.update_buffer(queue);
+        bytes
+    }
+
+    // Bytes the next upload writes, every slot including hidden ones
+    pub fn upload_size(&self) -> usize {
+        self.instances.len() * std::mem::size_of::<InstanceRaw>()
+    }
+
     pub fn update_buffer(&mut self, queue: &wgpu::Queue) {
-        let data = self.to_raw();
-        self.count = data.len();
+        self.dirty = false;
+        self.write_buffers(queue);
+    }
+
+    // Serializes the instances into their slots. While previous transforms are tracked the
+    // last upload moves into the previous buffer first, slots added since then start out
+    // with their current transform
+    fn write_buffers(&mut self, queue: &wgpu::Queue) {
+        if self.previous_buffer.is_some() {
+            std::mem::swap(&mut self.raw_scratch, &mut self.previous_scratch);
+        }
+        self.to_raw();
         queue.write_buffer(
             &self.instance_buffer,
             self.buffer_address,
-            bytemuck::cast_slice(&data),
+            bytemuck::cast_slice(&self.raw_scratch),
         );
+        if let Some(previous) = &self.previous_buffer {
+            let slots = self.raw_scratch.len();
+            self.previous_scratch.truncate(slots);
+            let known = self.previous_scratch.len();
+            self.previous_scratch
+                .extend_from_slice(&self.raw_scratch[known..]);
+            queue.write_buffer(
+                previous,
+                self.buffer_address,
+                bytemuck::cast_slice(&self.previous_scratch),
+            );
+            self.previous_lag = 2;
+        }
+    }
+
+    // Called once per frame after the uploads. A slot that wasn't written this frame didn't
+    // move, so the frame after an upload the previous transforms are set to the current ones
+    pub fn settle_previous(&mut self, queue: &wgpu::Queue) {
+        if self.previous_lag == 1 {
+            if let Some(previous) = &self.previous_buffer {
+                self.previous_scratch.clone_from(&self.raw_scratch);
+                queue.write_buffer(
+                    previous,
+                    self.buffer_address,
+                    bytemuck::cast_slice(&self.previous_scratch),
+                );
+            }
+        }
+        self.previous_lag = self.previous_lag.saturating_sub(1);
+    }
+
+    // Starts or stops keeping the previous transforms. Tracking starts with both buffers
+    // holding the current instances, so nothing appears to move on the first frame
+    pub fn set_track_previous(&mut self, track: bool, queue: &wgpu::Queue, device: &wgpu::Device) {
+        if track == self.previous_buffer.is_some() {
+            return;
+        }
+        if !track {
+            self.previous_buffer = None;
+            self.previous_scratch = Vec::new();
+            return;
+        }
+        self.previous_buffer = Some(self.create_previous_buffer(device));
+        self.write_buffers(queue);
+        self.previous_lag = 1;
+        self.settle_previous(queue);
+        self.dirty = false;
     }
-    pub fn render(&mut self, render_pass: &mut RenderPass) {
+
+    fn create_previous_buffer(&self, device: &wgpu::Device) -> Tracked<wgpu::Buffer> {
+        let instance_size = std::mem::size_of::<InstanceRaw>() as wgpu::BufferAddress;
+        gpu_memory::create_buffer(
+            device,
+            &wgpu::BufferDescriptor {
+                label: Some("Previous Instance Buffer"),
+                size: instance_size * self.capacity.max(1) as u64,
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+            MemoryCategory::Instances,
+        )
+    }
+    // `slice` caps the instances per draw call. With `first_instance` only the visible runs
+    // are drawn while there are few enough of them. Otherwise every slot is drawn, hidden ones
+    // as degenerate instances, and every slice binds its own range of the instance buffer
+    pub fn render(&self, render_pass: &mut RenderPass, slice: Option<u32>, first_instance: bool) {
+        // Empty buffers can't be bound, e.g. before the first batch arrived
+        if self.count == 0 {
+            return;
+        }
         render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
         render_pass.set_pipeline(&self.render.pipeline);
         if let Some(diffuse) = &self.render.diffuse {
@@ -234,20 +441,93 @@ impl InstanceController {
         let polygon = &self.entity_buffers;
         render_pass.set_vertex_buffer(0, polygon.vertex_buffer.slice(..));
         render_pass.set_index_buffer(polygon.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-        render_pass.draw_indexed(
-            0..polygon.num_indices,
-            0,
-            0..(*(&self.count.clone()) as usize) as _,
-        );
+        if first_instance && self.visible_runs.len() <= MAX_VISIBLE_RUNS {
+            for instances in self.visible_slices(slice) {
+                render_pass.draw_indexed(0..polygon.num_indices, 0, instances);
+            }
+            return;
+        }
+        let slots = self.instances.len() as u32;
+        let slice = match slice {
+            Some(slice) => slice,
+            None => {
+                render_pass.draw_indexed(0..polygon.num_indices, 0, 0..slots);
+                return;
+            }
+        };
+        let stride = std::mem::size_of::<InstanceRaw>() as wgpu::BufferAddress;
+        for instances in instance_slices(slots, slice) {
+            if first_instance {
+                render_pass.draw_indexed(0..polygon.num_indices, 0, instances);
+            } else {
+                let bytes = instances.start as wgpu::BufferAddress * stride
+                    ..instances.end as wgpu::BufferAddress * stride;
+                render_pass.set_vertex_buffer(1, self.instance_buffer.slice(bytes));
+                render_pass.draw_indexed(0..polygon.num_indices, 0, 0..instances.len() as u32);
+            }
+        }
+    }
+
+    // Draws the visible runs for the velocity pass, which has its own pipeline bound and
+    // needs first_instance. Does nothing while previous transforms aren't tracked
+    pub fn render_velocity(&self, render_pass: &mut RenderPass) {
+        let previous = match &self.previous_buffer {
+            Some(previous) if self.count > 0 => previous,
+            _ => return,
+        };
+        let polygon = &self.entity_buffers;
+        render_pass.set_vertex_buffer(0, polygon.vertex_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+        render_pass.set_vertex_buffer(2, previous.slice(..));
+        render_pass.set_index_buffer(polygon.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        for run in &self.visible_runs {
+            render_pass.draw_indexed(0..polygon.num_indices, 0, run.clone());
+        }
     }
 
-    fn to_raw(&mut self) -> Vec<InstanceRaw> {
-        self.instances
-            .clone()
+    // Slot ranges the first instance path draws, the visible runs cut into slices
+    fn visible_slices(
+        &self,
+        slice: Option<u32>,
+    ) -> impl Iterator<Item = std::ops::Range<u32>> + '_ {
+        self.visible_runs.iter().flat_map(move |run| {
+            let slice = slice.unwrap_or(run.len() as u32);
+            instance_slices(run.len() as u32, slice)
+                .map(move |instances| run.start + instances.start..run.start + instances.end)
+        })
+    }
+
+    // Whether the instances are drawn with the textured mesh, its vertices have another layout
+    pub fn is_textured(&self) -> bool {
+        self.render.diffuse.is_some()
+    }
+
+    // Refills raw_scratch with one entry per slot, it keeps its capacity between frames
+    fn to_raw(&mut self) {
+        self.raw_scratch.clear();
+        self.raw_scratch
+            .extend(self.instances.iter().map(Instance::to_slot));
+        self.count = self
+            .instances
             .iter()
-            .filter(|instance| instance.should_render) // only include visible instances
-            .map(Instance::to_raw)
-            .collect()
+            .filter(|instance| instance.should_render)
+            .count();
+        visible_runs(&self.instances, &mut self.visible_runs);
+    }
+}
+
+// Refills `runs` with the slot ranges of the visible instances, reusing its storage
+fn visible_runs(instances: &[Instance], runs: &mut Vec<std::ops::Range<u32>>) {
+    runs.clear();
+    for (slot, instance) in instances.iter().enumerate() {
+        if !instance.should_render {
+            continue;
+        }
+        let slot = slot as u32;
+        match runs.last_mut() {
+            Some(run) if run.end == slot => run.end += 1,
+            _ => runs.push(slot..slot + 1),
+        }
     }
 }
 
@@ -262,13 +542,11 @@ pub fn instances_list(chunk: Chunk, chunk_size: Vector2<u32>) -> Vec<Instance> {
                 z: z as f32 + (chunk.y * chunk_size.y as i32) as f32,
             };
 
-            let rotation = if position.is_zero() {
-                // this is needed so an object at (0, 0, 0) won't get scaled to zero
-                // as Quaternions can effect scale if they're not created correctly
-                cgmath::Quaternion::from_axis_angle(cgmath::Vector3::unit_z(), cgmath::Deg(0.0))
-            } else {
-                cgmath::Quaternion::from_axis_angle(position.normalize(), cgmath::Deg(0.0))
-            };
+            // The origin cube has no direction of its own, a NaN axis would collapse it
+            let rotation = cgmath::Quaternion::from_axis_angle(
+                safe_normalize(position, cgmath::Vector3::unit_z()),
+                cgmath::Deg(0.0),
+            );
             let default_color = cgmath::Vector3::new(0.0, 0.0, 0.0);
             let default_size = cgmath::Vector3::new(1.0, 1.0, 1.0);
             let default_bounding = default_size + position;
@@ -281,6 +559,9 @@ pub fn instances_list(chunk: Chunk, chunk_size: Vector2<u32>) -> Vec<Instance> {
                 color: default_color,
                 size: default_size,
                 bounding: default_bounding,
+                emissive: 0.0,
+                jitter: Jitter::NONE,
+                ao: 1.0,
             }
         })
         .collect::<Vec<_>>()
@@ -304,13 +585,11 @@ pub fn instances_list_circle(chunk: Chunk, chunk_size: Vector2<u32>) -> Vec<Inst
                 z: z as f32 + (chunk.y * chunk_size.y as i32) as f32,
             };
 
-            let rotation = if position.is_zero() {
-                // this is needed so an object at (0, 0, 0) won't get scaled to zero
-                // as Quaternions can effect scale if they're not created correctly
-                cgmath::Quaternion::from_axis_angle(cgmath::Vector3::unit_z(), cgmath::Deg(0.0))
-            } else {
-                cgmath::Quaternion::from_axis_angle(position.normalize(), cgmath::Deg(0.0))
-            };
+            // The origin cube has no direction of its own, a NaN axis would collapse it
+            let rotation = cgmath::Quaternion::from_axis_angle(
+                safe_normalize(position, cgmath::Vector3::unit_z()),
+                cgmath::Deg(0.0),
+            );
             let default_color = cgmath::Vector3::new(0.0, 0.0, 0.0);
             let default_size = cgmath::Vector3::new(1.0, 1.0, 1.0);
             let default_bounding = default_size + position;
@@ -329,6 +608,9 @@ pub fn instances_list_circle(chunk: Chunk, chunk_size: Vector2<u32>) -> Vec<Inst
                     color: default_color,
                     size: default_size,
                     bounding: default_bounding,
+                    emissive: 0.0,
+                    jitter: Jitter::NONE,
+                    ao: 1.0,
                 }
             } else {
                 Instance {
@@ -339,6 +621,9 @@ pub fn instances_list_circle(chunk: Chunk, chunk_size: Vector2<u32>) -> Vec<Inst
                     color: default_color,
                     size: default_size,
                     bounding: default_bounding,
+                    emissive: 0.0,
+                    jitter: Jitter::NONE,
+                    ao: 1.0,
                 }
             }
         })
@@ -355,13 +640,11 @@ pub fn instances_list2() -> Vec<Instance> {
                 z: z as f32 + 10.0,
             };
 
-            let rotation = if position.is_zero() {
-                // this is needed so an object at (0, 0, 0) won't get scaled to zero
-                // as Quaternions can effect scale if they're not created correctly
-                cgmath::Quaternion::from_axis_angle(cgmath::Vector3::unit_z(), cgmath::Deg(0.0))
-            } else {
-                cgmath::Quaternion::from_axis_angle(position.normalize(), cgmath::Deg(0.0))
-            };
+            // The origin cube has no direction of its own, a NaN axis would collapse it
+            let rotation = cgmath::Quaternion::from_axis_angle(
+                safe_normalize(position, cgmath::Vector3::unit_z()),
+                cgmath::Deg(0.0),
+            );
 
             let default_color = cgmath::Vector3::new(0.0, 0.0, 0.0);
             let default_size = cgmath::Vector3::new(1.0, 1.0, 1.0);
@@ -375,6 +658,9 @@ pub fn instances_list2() -> Vec<Instance> {
                 color: default_color,
                 size: default_size,
                 bounding: default_bounding,
+                emissive: 0.0,
+                jitter: Jitter::NONE,
+                ao: 1.0,
             }
         })
         .collect::<Vec<_>>()
@@ -389,16 +675,87 @@ pub struct Instance {
     pub color: cgmath::Vector3<f32>,
     pub size: cgmath::Vector3<f32>,
     pub bounding: cgmath::Vector3<f32>,
+    // Added on top of the lit color, 0.0 leaves the instance unchanged
+    pub emissive: f32,
+    pub jitter: Jitter,
+    // Baked ambient occlusion from occupied neighbors, 1.0 is fully open
+    pub ao: f32,
+}
+
+// Small fixed imperfections applied on top of the animated transform
+#[derive(Clone, Copy, Debug)]
+pub struct Jitter {
+    pub offset: cgmath::Vector3<f32>,
+    pub rotation: cgmath::Quaternion<f32>,
+    pub scale: f32,
+    // How much of the jitter is shown, eased when it is toggled
+    pub amount: f32,
+}
+
+impl Jitter {
+    pub const NONE: Jitter = Jitter {
+        offset: cgmath::Vector3::new(0.0, 0.0, 0.0),
+        rotation: cgmath::Quaternion::new(1.0, 0.0, 0.0, 0.0),
+        scale: 1.0,
+        amount: 0.0,
+    };
+
+    fn offset(&self) -> cgmath::Vector3<f32> {
+        self.offset * self.amount
+    }
+
+    fn rotation(&self) -> cgmath::Quaternion<f32> {
+        cgmath::Quaternion::new(1.0, 0.0, 0.0, 0.0).nlerp(self.rotation, self.amount)
+    }
+
+    fn scale(&self) -> f32 {
+        1.0 + (self.scale - 1.0) * self.amount
+    }
 }
 
 impl Instance {
+    // Picking box, moved and grown along with the jitter
+    pub fn bounds(&self) -> (cgmath::Vector3<f32>, cgmath::Vector3<f32>) {
+        let offset = self.jitter.offset();
+        let grow = self.size * (self.jitter.scale() - 1.0);
+        (self.position + offset, self.bounding + offset + grow)
+    }
+
     pub fn to_raw(&self) -> InstanceRaw {
         InstanceRaw {
-            model: ((cgmath::Matrix4::from_translation(self.position)
-                * cgmath::Matrix4::from(self.rotation))
-                * self.scale)
-                .into(),
+            model: ((cgmath::Matrix4::from_translation(self.position + self.jitter.offset())
+                * cgmath::Matrix4::from(self.rotation * self.jitter.rotation()))
+                * (self.scale * self.jitter.scale()))
+            .into(),
             color: cgmath::Vector3::from(self.color).into(),
+            emissive: self.emissive,
+            ao: self.ao,
+        }
+    }
+
+    // A cube `fraction` of this one's size with its corner at `corner` in this cube's own
+    // 0..1 space, carried along by this cube's whole transform, jitter included
+    pub fn subcube(&self, corner: cgmath::Vector3<f32>, fraction: f32) -> Instance {
+        let rotation = self.rotation * self.jitter.rotation();
+        let scale = self.scale * self.jitter.scale();
+        let position =
+            self.position + self.jitter.offset() + rotation.rotate_vector(corner * scale);
+        Instance {
+            position,
+            rotation,
+            scale: scale * fraction,
+            bounding: position + self.size * (scale * fraction),
+            jitter: Jitter::NONE,
+            ..self.clone()
+        }
+    }
+
+    // What the instance's slot holds, hidden instances collapse to a point
+    pub fn to_slot(&self) -> InstanceRaw {
+        if self.should_render {
+            self.to_raw()
+        } else {
+            InstanceRaw::HIDDEN
         }
     }
 }
@@ -409,9 +766,24 @@ pub struct InstanceRaw {
     #[allow(dead_code)]
     pub model: [[f32; 4]; 4],
     pub color: [f32; 3],
+    pub emissive: f32,
+    pub ao: f32,
 }
 
 impl InstanceRaw {
+    // Zero scale, every vertex lands on one point so nothing is rasterized
+    pub const HIDDEN: InstanceRaw = InstanceRaw {
+        model: [
+            [0.0, 0.0, 0.0, 0.0],
+            [0.0, 0.0, 0.0, 0.0],
+            [0.0, 0.0, 0.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ],
+        color: [0.0, 0.0, 0.0],
+        emissive: 0.0,
+        ao: 0.0,
+    };
+
     pub fn desc() -> wgpu::VertexBufferLayout<'static> {
         use std::mem;
         wgpu::VertexBufferLayout {
@@ -450,14 +822,55 @@ impl InstanceRaw {
                     shader_location: 9,
                     format: wgpu::VertexFormat::Float32x3,
                 },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 19]>() as wgpu::BufferAddress,
+                    shader_location: 10,
+                    format: wgpu::VertexFormat::Float32,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 20]>() as wgpu::BufferAddress,
+                    shader_location: 11,
+                    format: wgpu::VertexFormat::Float32,
+                },
+            ],
+        }
+    }
+
+    // Just the model matrix of the previous instance buffer, at locations 12 to 15
+    pub fn previous_desc() -> wgpu::VertexBufferLayout<'static> {
+        use std::mem;
+        wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<InstanceRaw>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 12,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
+                    shader_location: 13,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
+                    shader_location: 14,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 12]>() as wgpu::BufferAddress,
+                    shader_location: 15,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
             ],
         }
     }
 }
 
 pub struct MeshBuffer {
-    pub vertex_buffer: wgpu::Buffer,
-    pub index_buffer: wgpu::Buffer,
+    pub vertex_buffer: Tracked<wgpu::Buffer>,
+    pub index_buffer: Tracked<wgpu::Buffer>,
     pub num_indices: u32,
 }
 
@@ -535,16 +948,24 @@ impl Mesh {
                     });
 
                 let mb = MeshBuffer {
-                    vertex_buffer: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                        label: Some("Vertex Buffer"),
-                        contents: bytemuck::cast_slice(&primitive_vertex.vertices),
-                        usage: wgpu::BufferUsages::VERTEX,
-                    }),
-                    index_buffer: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                        label: Some("Index Buffer"),
-                        contents: bytemuck::cast_slice(&primitive_vertex.indices),
-                        usage: wgpu::BufferUsages::INDEX,
-                    }),
+                    vertex_buffer: gpu_memory::create_buffer_init(
+                        device,
+                        &wgpu::util::BufferInitDescriptor {
+                            label: Some("Vertex Buffer"),
+                            contents: bytemuck::cast_slice(&primitive_vertex.vertices),
+                            usage: wgpu::BufferUsages::VERTEX,
+                        },
+                        MemoryCategory::Meshes,
+                    ),
+                    index_buffer: gpu_memory::create_buffer_init(
+                        device,
+                        &wgpu::util::BufferInitDescriptor {
+                            label: Some("Index Buffer"),
+                            contents: bytemuck::cast_slice(&primitive_vertex.indices),
+                            usage: wgpu::BufferUsages::INDEX,
+                        },
+                        MemoryCategory::Meshes,
+                    ),
                     num_indices: primitive_vertex.indices.len() as u32,
                 };
                 let renderer = Renderer {
@@ -660,16 +1081,24 @@ impl Mesh {
                     });
 
                 let mb = MeshBuffer {
-                    vertex_buffer: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                        label: Some("Vertex Buffer"),
-                        contents: bytemuck::cast_slice(&textured_vertex.vertices),
-                        usage: wgpu::BufferUsages::VERTEX,
-                    }),
-                    index_buffer: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                        label: Some("Index Buffer"),
-                        contents: bytemuck::cast_slice(&textured_vertex.indices),
-                        usage: wgpu::BufferUsages::INDEX,
-                    }),
+                    vertex_buffer: gpu_memory::create_buffer_init(
+                        device,
+                        &wgpu::util::BufferInitDescriptor {
+                            label: Some("Vertex Buffer"),
+                            contents: bytemuck::cast_slice(&textured_vertex.vertices),
+                            usage: wgpu::BufferUsages::VERTEX,
+                        },
+                        MemoryCategory::Meshes,
+                    ),
+                    index_buffer: gpu_memory::create_buffer_init(
+                        device,
+                        &wgpu::util::BufferInitDescriptor {
+                            label: Some("Index Buffer"),
+                            contents: bytemuck::cast_slice(&textured_vertex.indices),
+                            usage: wgpu::BufferUsages::INDEX,
+                        },
+                        MemoryCategory::Meshes,
+                    ),
                     num_indices: textured_vertex.indices.len() as u32,
                 };
 
@@ -698,8 +1127,9 @@ pub struct PrimitiveMesh {
     pub indices: Vec<u16>,
 }
 
-pub fn make_cube_textured() -> Mesh {
-    let cube = TexturedCube::new();
+// The happy tree texture, tinted and optionally colored per face, see TexturedCube::with_colors
+pub fn make_cube_textured(tint: [f32; 3], face_colors: Option<[[f32; 3]; 6]>) -> Mesh {
+    let cube = TexturedCube::with_colors(tint, face_colors);
     let polygon: TexturedMesh = TexturedMesh {
         vertices: cube.vertices,
         indices: cube.indices,
@@ -709,8 +1139,9 @@ pub fn make_cube_textured() -> Mesh {
     Mesh::Textured(polygon)
 }
 
-pub fn make_cube_primitive() -> Mesh {
-    let cube = PrimitiveCube::new();
+// Face shading is `[top, sides, bottom]`, see PrimitiveCube::with_face_shading
+pub fn make_cube_primitive(face_shading: Option<[f32; 3]>) -> Mesh {
+    let cube = PrimitiveCube::with_face_shading(face_shading);
     let polygon: PrimitiveMesh = PrimitiveMesh {
         vertices: cube.vertices,
         indices: cube.indices,
@@ -718,3 +1149,260 @@ pub fn make_cube_primitive() -> Mesh {
 
     Mesh::Primitive(polygon)
 }
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Point3, Vector2};
+    use wgpu::util::DeviceExt;
+
+    use super::*;
+    use crate::core::{
+        camera::{Camera, CameraUniform},
+        state, test_support,
+    };
+
+    // Once the scratch storage has grown to the slot count, refilling it is allocation free
+    #[cfg(feature = "alloc-counter")]
+    #[test]
+    fn steady_state_serialization_does_not_allocate() {
+        use crate::core::alloc_counter::thread_allocations;
+
+        let (device, queue) = test_support::device();
+        let mut instances = test_support::row(64);
+        instances[10].should_render = false;
+        let mut controller = test_support::controller(&device, &queue, instances);
+        controller.to_raw();
+        let before = thread_allocations();
+        for _ in 0..10 {
+            controller.to_raw();
+        }
+        assert_eq!(thread_allocations(), before);
+        assert_eq!(controller.count, 63);
+        assert_eq!(controller.visible_runs, vec![0..10, 11..64]);
+    }
+
+    const SIZE: u32 = 64;
+
+    // Draws one textured cube in `color` on transparent black and reads the pixels back
+    fn render_textured(device: &wgpu::Device, queue: &wgpu::Queue, color: [f32; 3]) -> Vec<u8> {
+        let format = wgpu::TextureFormat::Rgba8Unorm;
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            label: Some("camera_bind_group_layout"),
+        });
+        let camera = Camera {
+            eye: Point3::new(2.0, 2.5, 3.0),
+            target: Point3::new(0.5, 0.5, 0.5),
+            up: Vector3::unit_y(),
+            roll: 0.0,
+            aspect: 1.0,
+            fovy: 45.0,
+            znear: 0.1,
+            zfar: 100.0,
+            center_offset: Vector2::new(0.0, 0.0),
+        };
+        let mut uniform = CameraUniform::new();
+        uniform.update_view_proj(&camera);
+        let camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Camera Buffer"),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: camera_buffer.as_entire_binding(),
+            }],
+            label: Some("camera_bind_group"),
+        });
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Shader"),
+            source: wgpu::ShaderSource::Wgsl(state::SHADER_SOURCE.into()),
+        });
+        let (mb, renderer) = make_cube_textured([1.0, 1.0, 1.0], None)
+            .get_mesh_buffer(device, &shader, format, queue, layout);
+        let mut cube = test_support::cube(Vector3::new(0.0, 0.0, 0.0));
+        cube.color = Vector3::from(color);
+        let controller = InstanceController::new(vec![cube], 0, mb, renderer, device);
+
+        let extent = wgpu::Extent3d {
+            width: SIZE,
+            height: SIZE,
+            depth_or_array_layers: 1,
+        };
+        let target = |format, usage| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: None,
+                size: extent,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage,
+                view_formats: &[],
+            })
+        };
+        let color_target = target(
+            format,
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        );
+        let depth_target = target(
+            Texture::DEPTH_FORMAT,
+            wgpu::TextureUsages::RENDER_ATTACHMENT,
+        );
+        let color_view = color_target.create_view(&Default::default());
+        let depth_view = depth_target.create_view(&Default::default());
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: (SIZE * SIZE * 4) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&Default::default());
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &color_view,
+                    depth_slice: None,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &depth_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+            render_pass.set_bind_group(0, &camera_bind_group, &[]);
+            controller.render(&mut render_pass, None, false);
+        }
+        encoder.copy_texture_to_buffer(
+            color_target.as_image_copy(),
+            wgpu::TexelCopyBufferInfo {
+                buffer: &readback,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(SIZE * 4),
+                    rows_per_image: None,
+                },
+            },
+            extent,
+        );
+        queue.submit(Some(encoder.finish()));
+        readback.slice(..).map_async(wgpu::MapMode::Read, |_| {});
+        device.poll(wgpu::PollType::Wait).unwrap();
+        let pixels = readback.slice(..).get_mapped_range().to_vec();
+        readback.unmap();
+        pixels
+    }
+
+    // Sums of the red and green channels over every pixel the cube covered
+    fn channel_sums(pixels: &[u8]) -> (u64, u64) {
+        pixels
+            .chunks(4)
+            .filter(|pixel| pixel[3] > 0)
+            .fold((0, 0), |(red, green), pixel| {
+                (red + pixel[0] as u64, green + pixel[1] as u64)
+            })
+    }
+
+    #[test]
+    fn red_tint_darkens_the_green_channel() {
+        let (device, queue) = match test_support::gpu() {
+            Some(gpu) => gpu,
+            None => return,
+        };
+        let (white_red, white_green) = channel_sums(&render_textured(&device, &queue, [1.0; 3]));
+        let (red_red, red_green) = channel_sums(&render_textured(&device, &queue, [1.0, 0.0, 0.0]));
+        // The happy tree is mostly green, the red tint takes all of that out and leaves red
+        assert!(white_green > 0);
+        assert_eq!(red_green, 0);
+        assert_eq!(red_red, white_red);
+    }
+
+    #[test]
+    fn slices_cover_the_visible_slots_once() {
+        let (device, queue) = test_support::device();
+        let mut controller = test_support::controller(&device, &queue, test_support::row(100));
+        for index in (0..100).filter(|index| index % 3 == 0).chain(40..60) {
+            controller.remove_instance(index, &queue);
+        }
+        for index in 45..50 {
+            controller.instances[index].should_render = true;
+        }
+        controller.update_buffer(&queue);
+        let visible = (0..100)
+            .filter(|index| controller.instances[*index].should_render)
+            .collect::<Vec<_>>();
+        assert_eq!(controller.count, visible.len());
+
+        for slice in [1, 4, 7, 100].iter().copied() {
+            let slices = controller.visible_slices(Some(slice)).collect::<Vec<_>>();
+            assert!(slices.iter().all(|range| range.len() <= slice as usize));
+            assert!(slices.windows(2).all(|pair| pair[0].end <= pair[1].start));
+            let drawn = slices.into_iter().flatten().map(|slot| slot as usize);
+            assert_eq!(drawn.collect::<Vec<_>>(), visible, "slice {}", slice);
+        }
+        // Without first_instance every slot is drawn, hidden ones degenerate
+        for slice in [1, 7, 64, 1000].iter().copied() {
+            let slots = instance_slices(100, slice).flatten().collect::<Vec<_>>();
+            assert_eq!(slots, (0..100).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn slots_stay_put_across_hide_and_show() {
+        let (device, queue) = test_support::device();
+        let mut controller = test_support::controller(&device, &queue, test_support::row(10));
+        let bytes = |raw: &InstanceRaw| bytemuck::bytes_of(raw).to_vec();
+        let hidden = [2, 3, 7];
+        for _ in 0..3 {
+            for index in hidden.iter().copied() {
+                controller.remove_instance(index, &queue);
+            }
+            controller.update_buffer(&queue);
+            for index in 0..10 {
+                assert_eq!(controller.slot(index), Some(index as u32));
+                let expected = if hidden.contains(&index) {
+                    InstanceRaw::HIDDEN
+                } else {
+                    controller.instances[index].to_raw()
+                };
+                assert_eq!(bytes(&controller.raw_scratch[index]), bytes(&expected));
+            }
+            assert_eq!(controller.count, 7);
+
+            for index in hidden.iter().copied() {
+                controller.instances[index].should_render = true;
+            }
+            controller.update_buffer(&queue);
+            for index in 0..10 {
+                assert_eq!(controller.slot(index), Some(index as u32));
+                let expected = controller.instances[index].to_raw();
+                assert_eq!(bytes(&controller.raw_scratch[index]), bytes(&expected));
+            }
+            assert_eq!(controller.count, 10);
+        }
+        assert_eq!(controller.slot(10), None);
+    }
+}
@@ -1,4 +1,5 @@
 pub mod entities;
 pub mod entity;
 pub mod primitive_texture;
+pub mod progress_bar;
 pub mod texture;
@@ -1,9 +1,11 @@
 use anyhow::*;
 use image::GenericImageView;
 
+use crate::core::gpu_memory::{self, MemoryCategory, Tracked};
+
 pub struct PrimitiveTexture {
     #[allow(unused)]
-    pub texture: wgpu::Texture,
+    pub texture: Tracked<wgpu::Texture>,
     pub view: wgpu::TextureView,
 }
 
@@ -31,7 +33,7 @@ impl PrimitiveTexture {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
             view_formats: &[wgpu::TextureFormat::Depth32Float],
         };
-        let texture = device.create_texture(&desc);
+        let texture = gpu_memory::create_texture(device, &desc, MemoryCategory::RenderTargets);
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
         Self { texture, view }
     }
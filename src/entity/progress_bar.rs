@@ -0,0 +1,165 @@
+use cgmath::{EuclideanSpace, Matrix3, Quaternion, Vector3};
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+
+use crate::{
+    core::camera::{aspect_for, Camera},
+    entity::entity::{Instance, InstanceController, Jitter, MeshBuffer, Renderer},
+    helpers::{
+        animation::{get_height_color, EaseInEaseOut},
+        color_vision::ColorVisionMode,
+        vector::safe_normalize,
+    },
+};
+
+pub const PROGRESS_CUBES: usize = 10;
+// Layout of the bar in normalized device coordinates
+const BAR_Y: f32 = -0.85;
+const BAR_WIDTH: f32 = 1.2;
+const CUBE_SIZE: f32 = 0.06;
+const POP_DURATION: f32 = 0.25;
+const POP_SCALE: f32 = 0.4;
+
+pub struct ProgressBar {
+    pub instance_controller: InstanceController,
+    pub progress: f32,
+    filled: Vec<bool>,
+    pop_time: Vec<f32>,
+}
+
+impl ProgressBar {
+    pub fn new(
+        count: usize,
+        entity_buffers: MeshBuffer,
+        render: Renderer,
+        device: &wgpu::Device,
+    ) -> ProgressBar {
+        let instances = (0..count)
+            .map(|_| Instance {
+                position: Vector3::new(0.0, 0.0, 0.0),
+                rotation: Quaternion::new(1.0, 0.0, 0.0, 0.0),
+                should_render: true,
+                scale: 0.0,
+                color: unfilled_color(),
+                size: Vector3::new(1.0, 1.0, 1.0),
+                bounding: Vector3::new(1.0, 1.0, 1.0),
+                emissive: 0.0,
+                jitter: Jitter::NONE,
+                ao: 1.0,
+            })
+            .collect();
+        ProgressBar {
+            instance_controller: InstanceController::new(
+                instances,
+                0,
+                entity_buffers,
+                render,
+                device,
+            ),
+            progress: 0.0,
+            filled: vec![false; count],
+            pop_time: vec![POP_DURATION; count],
+        }
+    }
+
+    fn count(&self) -> usize {
+        self.filled.len()
+    }
+
+    // Horizontal NDC position of the center of cube `index`
+    fn ndc_x(&self, index: usize) -> f32 {
+        if self.count() < 2 {
+            return 0.0;
+        }
+        -BAR_WIDTH / 2.0 + BAR_WIDTH * index as f32 / (self.count() - 1) as f32
+    }
+
+    // Scroll progress that corresponds to cube `index` being the last filled one
+    pub fn progress_for(&self, index: usize) -> f32 {
+        if self.count() < 2 {
+            return 1.0;
+        }
+        index as f32 / (self.count() - 1) as f32
+    }
+
+    pub fn cube_at(
+        &self,
+        cursor: &PhysicalPosition<f32>,
+        screen: &PhysicalSize<u32>,
+    ) -> Option<usize> {
+        if screen.width == 0 || screen.height == 0 {
+            return None;
+        }
+        let x = cursor.x / screen.width as f32 * 2.0 - 1.0;
+        let y = (1.0 - cursor.y / screen.height as f32) * 2.0 - 1.0;
+        let aspect = aspect_for(*screen);
+        let half_height = CUBE_SIZE;
+        let half_width = CUBE_SIZE / aspect;
+        if (y - BAR_Y).abs() > half_height {
+            return None;
+        }
+        (0..self.count()).find(|index| (x - self.ndc_x(*index)).abs() <= half_width)
+    }
+
+    // True while a cube is still popping after the progress changed
+    pub fn is_animating(&self) -> bool {
+        self.pop_time.iter().any(|time| *time < POP_DURATION)
+    }
+
+    pub fn update(
+        &mut self,
+        camera: &Camera,
+        progress: f32,
+        color_vision: ColorVisionMode,
+        dt: f32,
+        queue: &wgpu::Queue,
+    ) {
+        self.progress = progress.clamp(0.0, 1.0);
+        let filled_count =
+            (self.progress * self.count().saturating_sub(1) as f32).round() as usize + 1;
+
+        let forward = safe_normalize(camera.target - camera.eye, -Vector3::unit_z());
+        let right = safe_normalize(forward.cross(camera.view_up()), Vector3::unit_x());
+        let up = right.cross(forward);
+        // Place the bar halfway between the clip planes so it is always drawn
+        let distance = (camera.znear + camera.zfar) * 0.5;
+        let half_height = distance * (camera.fovy.to_radians() / 2.0).tan();
+        let half_width = half_height * camera.aspect;
+        let rotation = Quaternion::from(Matrix3::from_cols(right, up, -forward));
+        let cube_size = CUBE_SIZE * half_height * 2.0;
+
+        for index in 0..self.count() {
+            let filled = index < filled_count;
+            if filled != self.filled[index] {
+                self.filled[index] = filled;
+                self.pop_time[index] = 0.0;
+            }
+            self.pop_time[index] = (self.pop_time[index] + dt).min(POP_DURATION);
+            let pop = EaseInEaseOut::ease_in_ease_out_cubic(self.pop_time[index] / POP_DURATION);
+            let scale = cube_size * (1.0 + POP_SCALE * (pop * std::f32::consts::PI).sin());
+            // Undo the safe area offset so the bar stays where cube_at expects it
+            let center = camera.eye
+                + forward * distance
+                + right * ((self.ndc_x(index) - camera.center_offset.x) * half_width)
+                + up * ((BAR_Y - camera.center_offset.y) * half_height);
+            // The cube mesh spans 0..1, so offset by half a cube to center it
+            let offset = (right + up - forward) * (scale / 2.0);
+
+            if let Some(instance) = self.instance_controller.instances.get_mut(index) {
+                instance.position = center.to_vec() - offset;
+                instance.rotation = rotation;
+                instance.scale = scale;
+                instance.color = color_vision.remap(if filled {
+                    get_height_color(1.0)
+                } else {
+                    unfilled_color()
+                });
+                instance.bounding = instance.position + instance.size * scale;
+            }
+        }
+        self.instance_controller.update_buffer(queue);
+    }
+}
+
+fn unfilled_color() -> Vector3<f32> {
+    Vector3::new(0.2, 0.2, 0.2)
+}
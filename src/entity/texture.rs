@@ -1,9 +1,11 @@
 use anyhow::*;
 use image::GenericImageView;
 
+use crate::core::gpu_memory::{self, MemoryCategory, Tracked};
+
 pub struct Texture {
     #[allow(unused)]
-    pub texture: wgpu::Texture,
+    pub texture: Tracked<wgpu::Texture>,
     pub view: wgpu::TextureView,
     pub sampler: wgpu::Sampler,
 }
@@ -32,7 +34,7 @@ impl Texture {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
             view_formats: &[Self::DEPTH_FORMAT],
         };
-        let texture = device.create_texture(&desc);
+        let texture = gpu_memory::create_texture(device, &desc, MemoryCategory::RenderTargets);
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
         let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
             address_mode_u: wgpu::AddressMode::ClampToEdge,
@@ -75,7 +77,7 @@ impl Texture {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
             view_formats: &[Self::DEPTH_FORMAT],
         };
-        let texture = device.create_texture(&desc);
+        let texture = gpu_memory::create_texture(device, &desc, MemoryCategory::RenderTargets);
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
         let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
             address_mode_u: wgpu::AddressMode::ClampToEdge,
@@ -122,16 +124,20 @@ impl Texture {
             depth_or_array_layers: 1,
         };
         let format = wgpu::TextureFormat::Rgba8UnormSrgb;
-        let texture = device.create_texture(&wgpu::TextureDescriptor {
-            label,
-            size,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-            view_formats: &[],
-        });
+        let texture = gpu_memory::create_texture(
+            device,
+            &wgpu::TextureDescriptor {
+                label,
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            },
+            MemoryCategory::Textures,
+        );
 
         queue.write_texture(
             texture.as_image_copy(),
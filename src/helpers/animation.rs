@@ -1,8 +1,9 @@
 use crate::entity::entity::Instance;
 use crate::entity::entity::InstanceController;
+use crate::helpers::vector::is_finite;
 use cgmath::{
     num_traits::{pow, ToPrimitive},
-    Vector3,
+    InnerSpace, Vector3,
 };
 
 // pub fn ease_in_ease_out_loop(dt: u64, delay: u64, freq: u64) -> f32 {
@@ -35,6 +36,50 @@ pub fn ease_in_ease_out_loop(dt: f32, delay: f32, freq: f32) -> f32 {
     sqr / (2.0 * (sqr - time) + 1.0)
 }
 
+// Speed, offset and size of a looping ease, the default is the original one second wave
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LoopSpec {
+    // Loops per second relative to the default, 0.5 takes twice as long
+    pub frequency: f32,
+    // Seconds added to each instance's delay
+    pub phase: f32,
+    // Scales the 0..1 loop value
+    pub amplitude: f32,
+}
+
+impl Default for LoopSpec {
+    fn default() -> Self {
+        LoopSpec {
+            frequency: 1.0,
+            phase: 0.0,
+            amplitude: 1.0,
+        }
+    }
+}
+
+impl LoopSpec {
+    pub fn sample(&self, elapsed: f32, delay: f32) -> f32 {
+        self.amplitude
+            * ease_in_ease_out_loop(
+                elapsed,
+                delay + self.phase,
+                1.0 / self.frequency.max(f32::EPSILON),
+            )
+    }
+}
+
+// Rounds each component that is within `epsilon` of a whole number
+pub fn snap_to_lattice(position: Vector3<f32>, epsilon: f32) -> Vector3<f32> {
+    let snap = |value: f32| {
+        if (value - value.round()).abs() <= epsilon {
+            value.round()
+        } else {
+            value
+        }
+    };
+    Vector3::new(snap(position.x), snap(position.y), snap(position.z))
+}
+
 pub fn get_height_color(height: f32) -> Vector3<f32> {
     // high color rgb(255, 153, 230)
     //low color rgb(204, 0, 153)
@@ -44,6 +89,24 @@ pub fn get_height_color(height: f32) -> Vector3<f32> {
     low_color + (high_color - low_color) * height
 }
 
+// Group id per instance, instances sharing a color share a group.
+// Ids follow the order colors first appear in
+pub fn color_groups(instances: &[Instance]) -> Vec<usize> {
+    let mut colors: Vec<Vector3<f32>> = Vec::new();
+    instances
+        .iter()
+        .map(
+            |instance| match colors.iter().position(|color| *color == instance.color) {
+                Some(group) => group,
+                None => {
+                    colors.push(instance.color);
+                    colors.len() - 1
+                }
+            },
+        )
+        .collect()
+}
+
 pub struct EaseInEaseOut;
 impl EaseInEaseOut {
     pub fn ease_in_ease_out_cubic(number: f32) -> f32 {
@@ -56,21 +119,138 @@ impl EaseInEaseOut {
     }
 }
 
-enum AnimationTransition {
+pub enum AnimationTransition {
     EaseInEaseOut(EaseInEaseOut),
 }
 
 impl AnimationTransition {
-    pub fn lerp(&self, start: Vector3<f32>, end: Vector3<f32>, number: f32) -> Vector3<f32> {
+    // How far along the path the transition is at `number` of the way through its duration
+    pub fn ease(&self, number: f32) -> f32 {
         match self {
-            AnimationTransition::EaseInEaseOut(_) => {
-                let lerp_value = EaseInEaseOut::ease_in_ease_out_cubic(number);
-                start + (end - start) * lerp_value
+            AnimationTransition::EaseInEaseOut(_) => EaseInEaseOut::ease_in_ease_out_cubic(number),
+        }
+    }
+
+    // `n` evaluations evenly spaced from 0 to 1, both ends included, for plotting the curve
+    pub fn sample(&self, n: usize) -> Vec<f32> {
+        sample_points(n)
+            .into_iter()
+            .map(|number| self.ease(number))
+            .collect()
+    }
+
+    pub fn lerp(
+        &self,
+        start: Vector3<f32>,
+        end: Vector3<f32>,
+        number: f32,
+        trajectory: TrajectoryMode,
+    ) -> Vector3<f32> {
+        trajectory.point(start, end, self.ease(number))
+    }
+}
+
+// `n` evenly spaced numbers from 0 to 1, a single one is 0
+fn sample_points(n: usize) -> Vec<f32> {
+    let last = n.saturating_sub(1).max(1) as f32;
+    (0..n).map(|step| step as f32 / last).collect()
+}
+
+// Path a cube takes from start to end, the easing picks how far along it is
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum TrajectoryMode {
+    #[default]
+    Linear,
+    // Bows upward, peaking halfway at `height` times the distance traveled
+    Arc {
+        height: f32,
+    },
+    // Quadratic curve pulled toward `control`
+    Bezier {
+        control: Vector3<f32>,
+    },
+}
+
+impl TrajectoryMode {
+    // `linear`, `arc <height>` or `bezier <x>, <y>, <z>`, as written by to_line
+    pub fn from_line(line: &str) -> Option<TrajectoryMode> {
+        let line = line.trim();
+        let (kind, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let numbers = rest
+            .split(',')
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(str::parse::<f32>)
+            .collect::<Result<Vec<_>, _>>()
+            .ok()?;
+        match (kind, numbers.as_slice()) {
+            ("linear", []) => Some(TrajectoryMode::Linear),
+            ("arc", [height]) => Some(TrajectoryMode::Arc { height: *height }),
+            ("bezier", [x, y, z]) => Some(TrajectoryMode::Bezier {
+                control: Vector3::new(*x, *y, *z),
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn to_line(&self) -> String {
+        match *self {
+            TrajectoryMode::Linear => "linear".to_string(),
+            TrajectoryMode::Arc { height } => format!("arc {}", height),
+            TrajectoryMode::Bezier { control } => {
+                format!("bezier {}, {}, {}", control.x, control.y, control.z)
+            }
+        }
+    }
+
+    // Exactly start at 0 and end at 1 for every mode, so reversing retraces the same curve
+    pub fn point(&self, start: Vector3<f32>, end: Vector3<f32>, t: f32) -> Vector3<f32> {
+        // Weighted rather than start + (end - start) * t, which can miss the end by rounding
+        let line = start * (1.0 - t) + end * t;
+        match *self {
+            TrajectoryMode::Linear => line,
+            TrajectoryMode::Arc { height } => {
+                let lift = height * (end - start).magnitude() * 4.0 * t * (1.0 - t);
+                line + Vector3::unit_y() * lift
+            }
+            TrajectoryMode::Bezier { control } => {
+                let u = 1.0 - t;
+                start * (u * u) + control * (2.0 * u * t) + end * (t * t)
             }
         }
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DurationMode {
+    // Seconds from start to end regardless of distance
+    FixedDuration(f32),
+    // Duration follows the distance traveled, up to `max_duration` seconds
+    ConstantSpeed {
+        units_per_second: f32,
+        max_duration: f32,
+    },
+}
+
+impl Default for DurationMode {
+    fn default() -> Self {
+        DurationMode::FixedDuration(1.0)
+    }
+}
+
+impl DurationMode {
+    pub fn duration(&self, start: Vector3<f32>, end: Vector3<f32>) -> f32 {
+        let duration = match *self {
+            DurationMode::FixedDuration(duration) => duration,
+            DurationMode::ConstantSpeed {
+                units_per_second,
+                max_duration,
+            } => ((end - start).magnitude() / units_per_second.max(f32::EPSILON)).min(max_duration),
+        };
+        duration.max(f32::EPSILON)
+    }
+}
+
 pub struct Animation {
     activated: bool,
     time: f32,
@@ -79,12 +259,55 @@ pub struct Animation {
     end: Vector3<f32>,
     pub current_pos: Vector3<f32>,
     animation_transition: AnimationTransition,
+    // Overrides the animated color, e.g. after the instance was painted
+    pub manual_color: Option<Vector3<f32>>,
+    // Seconds to wait after arriving before playing back to the start
+    rebound: Option<f32>,
+    // Color currently shown, eased toward the target color every frame
+    displayed_color: Option<Vector3<f32>>,
+    snap_color: bool,
+    // Seconds an activated animation waits before it starts moving
+    delay: f32,
+    pub duration_mode: DurationMode,
+    pub trajectory: TrajectoryMode,
+    // Arrived this frame, the instance still needs the final position
+    finished: bool,
+    // Signature cubes keep their place and color through every animation
+    pinned: bool,
+    // Inactive animations are frozen where they are and cost nothing per frame
+    active: bool,
 }
 
 impl Animation {
+    fn from_instance(instance: &Instance) -> Animation {
+        Animation {
+            activated: false,
+            start: instance.position,
+            end: instance.position,
+            current_pos: instance.position,
+            time: 0.0,
+            reversed: false,
+            animation_transition: AnimationTransition::EaseInEaseOut(EaseInEaseOut),
+            manual_color: None,
+            rebound: None,
+            displayed_color: None,
+            snap_color: false,
+            delay: 0.0,
+            duration_mode: DurationMode::default(),
+            trajectory: TrajectoryMode::default(),
+            finished: false,
+            pinned: false,
+            active: true,
+        }
+    }
+
+    pub fn start(&self) -> Vector3<f32> {
+        self.start
+    }
+
     pub fn set_animation(&mut self, start: &Vector3<f32>, end: &Vector3<f32>) {
-        self.start = start.clone();
-        self.end = end.clone();
+        self.start = *start;
+        self.end = *end;
     }
 
     pub fn set_animation_state(&mut self, state: bool) {
@@ -94,35 +317,57 @@ impl Animation {
     pub fn reverse(&mut self, state: bool) {
         self.reversed = state
     }
+
+    // `n` positions evenly spaced in time along the way from `start` to this animation's
+    // end, with its easing and trajectory. Leaves the animation untouched
+    pub fn preview_positions(&self, start: Vector3<f32>, n: usize) -> Vec<Vector3<f32>> {
+        sample_points(n)
+            .into_iter()
+            .map(|number| {
+                self.animation_transition
+                    .lerp(start, self.end, number, self.trajectory)
+            })
+            .collect()
+    }
+}
+
+// One color fade shared by many instances, a single clock instead of an animation each
+struct BulkFade {
+    elapsed: f32,
+    duration: f32,
+    // Index, start color and target color
+    colors: Vec<(usize, Vector3<f32>, Vector3<f32>)>,
 }
 
 pub struct AnimationHandler {
     pub movement_list: Vec<Animation>,
     pub disabled: bool,
+    // Arrivals this close to a whole unit position are snapped onto it, None keeps them as is
+    pub grid_snap: Option<f32>,
+    bulk_fade: Option<BulkFade>,
 }
 
 impl AnimationHandler {
     pub fn new(instance_controller: &InstanceController) -> AnimationHandler {
         AnimationHandler {
             disabled: false,
+            grid_snap: None,
+            bulk_fade: None,
             movement_list: {
                 instance_controller
                     .instances
                     .iter()
-                    .map(|instance| Animation {
-                        activated: false,
-                        start: instance.position,
-                        end: instance.position,
-                        current_pos: instance.position,
-                        time: 0.0,
-                        reversed: false,
-                        animation_transition: AnimationTransition::EaseInEaseOut(EaseInEaseOut),
-                    })
+                    .map(Animation::from_instance)
                     .collect()
             },
         }
     }
 
+    // Keeps the handler in step with InstanceController::add_instance
+    pub fn add_animation(&mut self, instance: &Instance) {
+        self.movement_list.push(Animation::from_instance(instance));
+    }
+
     pub fn disable(&mut self) {
         self.disabled = true;
     }
@@ -130,11 +375,42 @@ impl AnimationHandler {
         self.disabled = false;
     }
 
+    // Keeps the cube where it is and locks its color, every later animation request
+    // and color change for it is ignored
+    pub fn pin(&mut self, index: usize, color: Vector3<f32>) {
+        if let Some(animation) = self.movable(index) {
+            animation.manual_color = Some(color);
+            animation.pinned = true;
+        }
+    }
+
+    // Releases a pin, the cube follows its animations and colors again
+    pub fn unpin(&mut self, index: usize) {
+        if let Some(animation) = self.movement_list.get_mut(index) {
+            if animation.pinned {
+                animation.pinned = false;
+                animation.manual_color = None;
+            }
+        }
+    }
+
+    pub fn is_pinned(&self, index: usize) -> bool {
+        self.movement_list
+            .get(index)
+            .is_some_and(|animation| animation.pinned)
+    }
+
+    fn movable(&mut self, index: usize) -> Option<&mut Animation> {
+        self.movement_list
+            .get_mut(index)
+            .filter(|animation| !animation.pinned)
+    }
+
     pub fn set_animation(&mut self, index: usize, start: &Vector3<f32>, end: &Vector3<f32>) {
         if self.disabled {
             return;
         }
-        if let Some(animation) = self.movement_list.get_mut(index) {
+        if let Some(animation) = self.movable(index) {
             if !animation.activated {
                 animation.set_animation(start, end);
             }
@@ -145,7 +421,7 @@ impl AnimationHandler {
         if self.disabled {
             return;
         }
-        if let Some(animation) = self.movement_list.get_mut(index) {
+        if let Some(animation) = self.movable(index) {
             animation.set_animation_state(state);
         }
     }
@@ -157,7 +433,7 @@ impl AnimationHandler {
         if self.disabled {
             return;
         }
-        if let Some(animation) = self.movement_list.get_mut(index) {
+        if let Some(animation) = self.movable(index) {
             if !animation.activated {
                 animation.time = 0.0;
             }
@@ -168,38 +444,298 @@ impl AnimationHandler {
         if self.disabled {
             return;
         }
-        if let Some(animation) = self.movement_list.get_mut(index) {
+        if let Some(animation) = self.movable(index) {
             animation.reverse(state);
         }
     }
 
+    // Starts a fresh animation from `start` to `end` after `delay` seconds along a straight
+    // line, the instance waits at `start` until then
+    pub fn play(&mut self, index: usize, start: Vector3<f32>, end: Vector3<f32>, delay: f32) {
+        if self.disabled {
+            return;
+        }
+        if let Some(animation) = self.movable(index) {
+            animation.set_animation(&start, &end);
+            animation.current_pos = start;
+            animation.time = 0.0;
+            animation.reversed = false;
+            animation.rebound = None;
+            animation.delay = delay;
+            animation.trajectory = TrajectoryMode::Linear;
+            animation.activated = true;
+            animation.active = true;
+        }
+    }
+
+    // Positions the instance's current animation passes through, see Animation::preview_positions
+    pub fn preview_positions(&self, index: usize, n: usize) -> Option<Vec<Vector3<f32>>> {
+        self.movement_list
+            .get(index)
+            .map(|animation| animation.preview_positions(animation.start, n))
+    }
+
+    // Seconds the instance would take from start to end with its duration mode
+    pub fn duration(&self, index: usize, start: Vector3<f32>, end: Vector3<f32>) -> f32 {
+        self.movement_list
+            .get(index)
+            .map(|animation| animation.duration_mode)
+            .unwrap_or_default()
+            .duration(start, end)
+    }
+
+    // Freezes or thaws the animation of an instance, e.g. while it is hidden. Playing an
+    // animation thaws it again
+    pub fn set_active(&mut self, index: usize, active: bool) {
+        if let Some(animation) = self.movement_list.get_mut(index) {
+            animation.active = active;
+        }
+    }
+
+    // Curves the path of the animation `play` just started
+    pub fn set_trajectory(&mut self, index: usize, trajectory: TrajectoryMode) {
+        if let Some(animation) = self.movable(index) {
+            animation.trajectory = trajectory;
+        }
+    }
+
+    pub fn set_duration_mode(&mut self, index: usize, mode: DurationMode) {
+        if let Some(animation) = self.movement_list.get_mut(index) {
+            animation.duration_mode = mode;
+        }
+    }
+
+    pub fn set_rebound(&mut self, index: usize, delay: f32) {
+        if self.disabled {
+            return;
+        }
+        if let Some(animation) = self.movable(index) {
+            animation.rebound = Some(delay);
+        }
+    }
+
+    pub fn set_manual_color(&mut self, index: usize, color: Vector3<f32>) {
+        if let Some(animation) = self.movable(index) {
+            animation.manual_color = Some(color);
+        }
+    }
+
+    pub fn clear_manual_color(&mut self, index: usize) {
+        if let Some(animation) = self.movable(index) {
+            animation.manual_color = None;
+        }
+    }
+
+    // Fades many instances to new manual colors over `duration` seconds on one shared clock.
+    // A running bulk fade jumps to its end first. Each starts from the color it shows now
+    pub fn set_colors_bulk(
+        &mut self,
+        colors: impl IntoIterator<Item = (usize, Vector3<f32>)>,
+        duration: f32,
+    ) {
+        if let Some(fade) = &mut self.bulk_fade {
+            fade.elapsed = fade.duration;
+        }
+        self.fade_colors(0.0);
+        let colors = colors
+            .into_iter()
+            .filter_map(|(index, target)| {
+                let animation = self.movable(index)?;
+                let start = animation
+                    .displayed_color
+                    .or(animation.manual_color)
+                    .unwrap_or(target);
+                Some((index, start, target))
+            })
+            .collect();
+        self.bulk_fade = Some(BulkFade {
+            elapsed: 0.0,
+            duration,
+            colors,
+        });
+        self.fade_colors(0.0);
+    }
+
+    // Advances the bulk fade, its colors go through manual_color like painted ones
+    fn fade_colors(&mut self, dt: f32) {
+        let fade = match &mut self.bulk_fade {
+            Some(fade) => fade,
+            None => return,
+        };
+        fade.elapsed += dt;
+        let t = if fade.duration > 0.0 {
+            (fade.elapsed / fade.duration).min(1.0)
+        } else {
+            1.0
+        };
+        let eased = EaseInEaseOut::ease_in_ease_out_cubic(t);
+        for (index, start, target) in &fade.colors {
+            if let Some(animation) = self.movement_list.get_mut(*index) {
+                animation.manual_color = Some(start + (target - start) * eased);
+                // The fade is the easing, smoothing on top would lag behind it
+                animation.snap_color = true;
+            }
+        }
+        if t >= 1.0 {
+            self.bulk_fade = None;
+        }
+    }
+
+    // Delays each animation by its group id times `step`, so groups play one after another
+    pub fn stagger_by_group(&mut self, groups: &[usize], step: f32) {
+        for (animation, group) in self.movement_list.iter_mut().zip(groups) {
+            animation.delay = *group as f32 * step;
+        }
+    }
+
+    // Color an instance should show, given the color its animation would produce
+    pub fn color(&self, index: usize, animated: Vector3<f32>) -> Vector3<f32> {
+        self.movement_list
+            .get(index)
+            .and_then(|animation| animation.manual_color)
+            .unwrap_or(animated)
+    }
+
+    // Skips smoothing for the next color change of this instance
+    pub fn snap_color(&mut self, index: usize) {
+        if let Some(animation) = self.movement_list.get_mut(index) {
+            animation.snap_color = true;
+        }
+    }
+
+    // Moves the displayed color toward `color(index, animated)`, passed through `remap`,
+    // with time constant `smoothing`. Exponential decay keeps it frame rate independent
+    pub fn smoothed_color(
+        &mut self,
+        index: usize,
+        animated: Vector3<f32>,
+        remap: impl Fn(Vector3<f32>) -> Vector3<f32>,
+        dt: f32,
+        smoothing: f32,
+    ) -> Vector3<f32> {
+        let target = remap(self.color(index, animated));
+        let animation = match self.movement_list.get_mut(index) {
+            Some(animation) => animation,
+            None => return target,
+        };
+        let displayed = match animation.displayed_color {
+            Some(displayed) if !animation.snap_color && smoothing > 0.0 => {
+                displayed + (target - displayed) * (1.0 - (-dt / smoothing).exp())
+            }
+            _ => target,
+        };
+        animation.snap_color = false;
+        animation.displayed_color = Some(displayed);
+        displayed
+    }
+
     pub fn animate(&mut self, dt: f32) {
+        // Colors fade while the wave runs too
+        self.fade_colors(dt);
         if self.disabled {
             return;
         }
+        // Every animation is visited so indices keep matching the instances
         for animation in self.movement_list.iter_mut() {
-            let mut delta = dt;
+            if !animation.active {
+                continue;
+            }
             if !animation.activated {
+                if let Some(delay) = animation.rebound {
+                    if delay <= dt {
+                        animation.rebound = None;
+                        animation.reversed = true;
+                        animation.activated = true;
+                    } else {
+                        animation.rebound = Some(delay - dt);
+                    }
+                }
+                continue;
+            }
+            if animation.delay > 0.0 {
+                animation.delay -= dt;
                 continue;
             }
+            // Both directions use the same duration, so a rebound takes as long as the way out
+            let mut delta = dt
+                / animation
+                    .duration_mode
+                    .duration(animation.start, animation.end);
             if animation.reversed {
                 delta *= -1.0;
             }
             animation.time += delta;
             animation.time = animation.time.clamp(0.0, 1.0);
-            animation.current_pos =
-                animation
-                    .animation_transition
-                    .lerp(animation.start, animation.end, animation.time);
+            animation.current_pos = animation.animation_transition.lerp(
+                animation.start,
+                animation.end,
+                animation.time,
+                animation.trajectory,
+            );
+            debug_assert!(
+                is_finite(animation.current_pos),
+                "Animation produced a non-finite position"
+            );
             if animation.time == 1.0 || animation.time == 0.0 {
                 animation.activated = false;
+                animation.finished = true;
+                // Only arrivals that won't rebound, a rebound still starts from the exact end
+                if let (Some(epsilon), None) = (self.grid_snap, animation.rebound) {
+                    animation.current_pos = snap_to_lattice(animation.current_pos, epsilon);
+                }
             }
         }
     }
 
+    // True while any animation is moving, waiting on its delay or about to rebound
+    pub fn is_active(&self) -> bool {
+        self.bulk_fade.is_some()
+            || !self.disabled
+                && self.movement_list.iter().any(|animation| {
+                    animation.active && (animation.activated || animation.rebound.is_some())
+                })
+    }
+
+    // Average completion of the activated animations, 1 when none are running
+    pub fn progress(&self) -> f32 {
+        let (count, total) = self
+            .movement_list
+            .iter()
+            .filter(|animation| animation.active && animation.activated)
+            .fold((0, 0.0), |(count, total), animation| {
+                let done = if animation.reversed {
+                    1.0 - animation.time
+                } else {
+                    animation.time
+                };
+                (count + 1, total + done)
+            });
+        if count == 0 {
+            1.0
+        } else {
+            total / count as f32
+        }
+    }
+
+    // Instances moving or waiting on their delay, for the scene status
+    pub fn animating_count(&self) -> usize {
+        self.movement_list
+            .iter()
+            .filter(|animation| animation.activated)
+            .count()
+    }
+
+    // True while the instance is moving or waiting on its delay
+    pub fn is_animating(&self, index: usize) -> bool {
+        self.movement_list
+            .get(index)
+            .is_some_and(|animation| animation.activated)
+    }
+
     pub fn update_instance(&mut self, index: usize, instance: &mut Instance) {
         if let Some(animation) = self.movement_list.get_mut(index) {
-            if !animation.activated {
+            if !animation.active || !animation.activated && !std::mem::take(&mut animation.finished)
+            {
                 return;
             }
             instance.position = animation.current_pos;
@@ -207,3 +743,264 @@ impl AnimationHandler {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::test_support::cube;
+
+    fn handler(instances: &[Instance]) -> AnimationHandler {
+        AnimationHandler {
+            movement_list: instances.iter().map(Animation::from_instance).collect(),
+            disabled: false,
+            grid_snap: None,
+            bulk_fade: None,
+        }
+    }
+
+    fn run(handler: &mut AnimationHandler, seconds: f32) {
+        for _ in 0..(seconds / 0.05).round() as usize {
+            handler.animate(0.05);
+        }
+    }
+
+    #[test]
+    fn three_colors_stagger_in_three_buckets() {
+        let palette = [
+            Vector3::new(1.0, 0.0, 0.0),
+            Vector3::new(0.0, 1.0, 0.0),
+            Vector3::new(0.0, 0.0, 1.0),
+        ];
+        let instances = (0..9)
+            .map(|n| Instance {
+                color: palette[n % 3],
+                ..cube(Vector3::new(n as f32, 0.0, 0.0))
+            })
+            .collect::<Vec<_>>();
+        let groups = color_groups(&instances);
+        assert_eq!(groups, vec![0, 1, 2, 0, 1, 2, 0, 1, 2]);
+
+        let mut handler = handler(&instances);
+        for (index, instance) in instances.iter().enumerate() {
+            handler.play(
+                index,
+                instance.position,
+                instance.position + Vector3::unit_y(),
+                0.0,
+            );
+        }
+        handler.stagger_by_group(&groups, 1.0);
+        let moved = |handler: &AnimationHandler| {
+            (0..9)
+                .filter(|index| handler.movement_list[*index].current_pos.y > 0.0)
+                .map(|index| groups[index])
+                .collect::<std::collections::BTreeSet<_>>()
+                .into_iter()
+                .collect::<Vec<_>>()
+        };
+        run(&mut handler, 0.5);
+        assert_eq!(moved(&handler), vec![0]);
+        run(&mut handler, 1.0);
+        assert_eq!(moved(&handler), vec![0, 1]);
+        run(&mut handler, 1.0);
+        assert_eq!(moved(&handler), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn default_loop_spec_reproduces_the_original_wave() {
+        let spec = LoopSpec::default();
+        for step in 0..400 {
+            let elapsed = step as f32 * 0.037;
+            for delay in [0.0, 0.3, 1.7, 5.0].iter().copied() {
+                assert_eq!(
+                    spec.sample(elapsed, delay),
+                    ease_in_ease_out_loop(elapsed, delay, 1.0)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn loop_hits_its_key_values_at_any_frequency() {
+        for period in [0.25, 1.0, 3.0].iter().copied() {
+            let delay = 0.5;
+            let at =
+                |fraction: f32| ease_in_ease_out_loop(delay + fraction * period, delay, period);
+            assert_eq!(ease_in_ease_out_loop(0.2, delay, period), 0.0);
+            assert_eq!(at(0.0), 0.0);
+            assert!((at(0.5) - 0.5).abs() < 1e-5);
+            assert!((at(1.0) - 1.0).abs() < 1e-5);
+            assert!((at(1.5) - 0.5).abs() < 1e-5);
+            assert!(at(2.0).abs() < 1e-5);
+            // The way up mirrors the way down
+            assert!((at(0.3) - at(1.7)).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn loop_spec_scales_shifts_and_speeds_up() {
+        let spec = LoopSpec {
+            frequency: 2.0,
+            phase: 0.25,
+            amplitude: 0.5,
+        };
+        // Peaks half a second after the phase, at half height
+        assert!((spec.sample(0.75, 0.0) - 0.5).abs() < 1e-5);
+        assert_eq!(spec.sample(0.2, 0.0), 0.0);
+        assert_eq!(
+            spec.sample(1.1, 0.3),
+            0.5 * ease_in_ease_out_loop(1.1, 0.55, 0.5)
+        );
+    }
+
+    // Steady state, the wave only moves values around in the animations it already has
+    #[cfg(feature = "alloc-counter")]
+    #[test]
+    fn animating_frames_do_not_allocate() {
+        use crate::core::alloc_counter::thread_allocations;
+
+        let instances = crate::core::test_support::row(64);
+        let mut handler = handler(&instances);
+        for index in 0..instances.len() {
+            let start = instances[index].position;
+            handler.set_animation(index, &start, &(start + Vector3::new(0.0, 4.0, 0.0)));
+            handler.set_animation_state(index, true);
+        }
+        handler.animate(0.05);
+        let before = thread_allocations();
+        run(&mut handler, 0.5);
+        assert_eq!(thread_allocations(), before);
+    }
+
+    // Each hop is ten tenths, summed in floats the way offsets pile up over a session
+    fn hop_off_lattice(grid_snap: Option<f32>) -> f32 {
+        let mut instances = crate::core::test_support::row(1);
+        let mut handler = handler(&instances);
+        handler.grid_snap = grid_snap;
+        for _ in 0..100 {
+            let start = instances[0].position;
+            let end = (0..10).fold(start, |end, _| end + Vector3::new(0.1, 0.1, 0.1));
+            handler.set_animation(0, &start, &end);
+            handler.reset_animation_time(0);
+            handler.set_animation_state(0, true);
+            run(&mut handler, 3.0);
+            handler.update_instance(0, &mut instances[0]);
+        }
+        let position = instances[0].position;
+        let off = position - snap_to_lattice(position, 0.5);
+        off.x.abs().max(off.y.abs()).max(off.z.abs())
+    }
+
+    #[test]
+    fn snapping_keeps_hops_on_the_lattice() {
+        assert!(hop_off_lattice(None) > 0.0);
+        assert_eq!(hop_off_lattice(Some(1e-3)), 0.0);
+    }
+
+    #[test]
+    fn bulk_fade_runs_on_one_clock() {
+        let instances = crate::core::test_support::row(64 * 1024);
+        let mut handler = handler(&instances);
+        let red = Vector3::new(1.0, 0.0, 0.0);
+        let blue = Vector3::new(0.0, 0.0, 1.0);
+        for index in 0..instances.len() {
+            handler.set_manual_color(index, red);
+        }
+        handler.set_colors_bulk((0..instances.len()).map(|index| (index, blue)), 1.0);
+        // No animation per instance, only the shared fade
+        assert_eq!(handler.movement_list.len(), instances.len());
+        assert_eq!(handler.animating_count(), 0);
+        assert!(handler.is_active());
+
+        run(&mut handler, 0.5);
+        let halfway = handler.color(1000, Vector3::new(0.0, 0.0, 0.0));
+        assert!(halfway.x > 0.0 && halfway.z > 0.0);
+        assert!(handler.is_active());
+        run(&mut handler, 0.5);
+        assert!(!handler.is_active());
+        assert!((0..instances.len()).all(|index| handler.color(index, red) == blue));
+    }
+
+    #[test]
+    fn trajectories_end_exactly_on_their_endpoints() {
+        let modes = [
+            TrajectoryMode::Linear,
+            TrajectoryMode::Arc { height: 0.3 },
+            TrajectoryMode::Bezier {
+                control: Vector3::new(-4.5, 12.25, 0.7),
+            },
+        ];
+        let pairs = [
+            (Vector3::new(0.1, 0.2, 0.3), Vector3::new(0.7, -5.3, 19.9)),
+            (
+                Vector3::new(-33.3, 1.0e-3, 7.77),
+                Vector3::new(0.35, 61.1, -0.9),
+            ),
+            (Vector3::new(3.0, 3.0, 3.0), Vector3::new(3.0, 3.0, 3.0)),
+        ];
+        for mode in modes.iter() {
+            for (start, end) in pairs.iter() {
+                assert_eq!(mode.point(*start, *end, 0.0), *start, "{:?}", mode);
+                assert_eq!(mode.point(*start, *end, 1.0), *end, "{:?}", mode);
+            }
+            assert_eq!(TrajectoryMode::from_line(&mode.to_line()), Some(*mode));
+        }
+        assert_eq!(TrajectoryMode::from_line("arc"), None);
+        assert_eq!(TrajectoryMode::from_line("bezier 1, 2"), None);
+    }
+
+    #[test]
+    fn frozen_instance_leaves_its_neighbor_alone() {
+        let instances = crate::core::test_support::row(2);
+        let lift = Vector3::new(0.0, 10.0, 0.0);
+        let start = |handler: &mut AnimationHandler| {
+            for (index, instance) in instances.iter().enumerate() {
+                handler.play(index, instance.position, instance.position + lift, 0.0);
+            }
+        };
+        let mut reference = handler(&instances);
+        let mut frozen = handler(&instances);
+        start(&mut reference);
+        start(&mut frozen);
+        frozen.set_active(0, false);
+
+        for _ in 0..10 {
+            reference.animate(0.05);
+            frozen.animate(0.05);
+            assert_eq!(
+                frozen.movement_list[1].current_pos,
+                reference.movement_list[1].current_pos
+            );
+            assert_eq!(frozen.movement_list[0].current_pos, instances[0].position);
+        }
+        assert!(reference.movement_list[1].current_pos != instances[1].position);
+
+        // Thawed, it starts from where it was frozen
+        let mut fresh = handler(&instances);
+        start(&mut fresh);
+        fresh.animate(0.05);
+        frozen.set_active(0, true);
+        frozen.animate(0.05);
+        assert_eq!(
+            frozen.movement_list[0].current_pos,
+            fresh.movement_list[0].current_pos
+        );
+    }
+
+    #[test]
+    fn transition_samples_rise_from_zero_to_one() {
+        let transition = AnimationTransition::EaseInEaseOut(EaseInEaseOut);
+        for n in [2, 3, 10, 101, 1000].iter().copied() {
+            let samples = transition.sample(n);
+            assert_eq!(samples.len(), n);
+            assert_eq!(samples[0], 0.0);
+            assert_eq!(samples[n - 1], 1.0);
+            assert!(samples.windows(2).all(|pair| pair[0] <= pair[1]), "{}", n);
+        }
+        assert_eq!(transition.sample(1), vec![0.0]);
+        assert!(transition.sample(0).is_empty());
+        // Out of range input holds the ends
+        assert_eq!(transition.ease(-0.5), 0.0);
+        assert_eq!(transition.ease(1.5), 1.0);
+    }
+}
@@ -0,0 +1,76 @@
+use cgmath::Vector3;
+
+type Matrix = [[f32; 3]; 3];
+
+// Approximate simulations of each color vision deficiency in linear RGB
+const PROTANOPIA: Matrix = [
+    [0.567, 0.433, 0.0],
+    [0.558, 0.442, 0.0],
+    [0.0, 0.242, 0.758],
+];
+const DEUTERANOPIA: Matrix = [[0.625, 0.375, 0.0], [0.7, 0.3, 0.0], [0.0, 0.3, 0.7]];
+const TRITANOPIA: Matrix = [[0.95, 0.05, 0.0], [0.0, 0.433, 0.567], [0.0, 0.475, 0.525]];
+// Moves the information lost by the simulation into channels that are still visible
+const ERROR_SHIFT: Matrix = [[0.0, 0.0, 0.0], [0.7, 1.0, 0.0], [0.7, 0.0, 1.0]];
+
+const HIGH_CONTRAST_LOW: Vector3<f32> = Vector3::new(0.05, 0.05, 0.25);
+const HIGH_CONTRAST_HIGH: Vector3<f32> = Vector3::new(1.0, 0.85, 0.1);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ColorVisionMode {
+    Normal,
+    Deuteranopia,
+    Protanopia,
+    Tritanopia,
+    HighContrast,
+}
+
+impl ColorVisionMode {
+    pub fn next(self) -> ColorVisionMode {
+        match self {
+            ColorVisionMode::Normal => ColorVisionMode::Deuteranopia,
+            ColorVisionMode::Deuteranopia => ColorVisionMode::Protanopia,
+            ColorVisionMode::Protanopia => ColorVisionMode::Tritanopia,
+            ColorVisionMode::Tritanopia => ColorVisionMode::HighContrast,
+            ColorVisionMode::HighContrast => ColorVisionMode::Normal,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ColorVisionMode> {
+        match name.to_ascii_lowercase().as_str() {
+            "normal" => Some(ColorVisionMode::Normal),
+            "deuteranopia" => Some(ColorVisionMode::Deuteranopia),
+            "protanopia" => Some(ColorVisionMode::Protanopia),
+            "tritanopia" => Some(ColorVisionMode::Tritanopia),
+            "high-contrast" | "highcontrast" => Some(ColorVisionMode::HighContrast),
+            _ => None,
+        }
+    }
+
+    // Applied to every CPU side color right before it is uploaded
+    pub fn remap(self, color: Vector3<f32>) -> Vector3<f32> {
+        let simulation = match self {
+            ColorVisionMode::Normal => return color,
+            ColorVisionMode::HighContrast => {
+                let luma = color.x * 0.2126 + color.y * 0.7152 + color.z * 0.0722;
+                let t = ((luma - 0.2) / 0.6).clamp(0.0, 1.0);
+                return HIGH_CONTRAST_LOW + (HIGH_CONTRAST_HIGH - HIGH_CONTRAST_LOW) * t;
+            }
+            ColorVisionMode::Deuteranopia => DEUTERANOPIA,
+            ColorVisionMode::Protanopia => PROTANOPIA,
+            ColorVisionMode::Tritanopia => TRITANOPIA,
+        };
+        let error = color - multiply(&simulation, color);
+        let corrected = color + multiply(&ERROR_SHIFT, error);
+        Vector3::new(
+            corrected.x.clamp(0.0, 1.0),
+            corrected.y.clamp(0.0, 1.0),
+            corrected.z.clamp(0.0, 1.0),
+        )
+    }
+}
+
+fn multiply(matrix: &Matrix, color: Vector3<f32>) -> Vector3<f32> {
+    let row = |r: [f32; 3]| r[0] * color.x + r[1] * color.y + r[2] * color.z;
+    Vector3::new(row(matrix[0]), row(matrix[1]), row(matrix[2]))
+}
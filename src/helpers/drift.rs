@@ -0,0 +1,143 @@
+// Debug snapshots of instance positions and animation starts, diffed later to find cubes
+// that crept off their places over a long session
+
+use std::collections::HashMap;
+
+use cgmath::{InnerSpace, Vector3};
+
+use crate::{
+    core::game_loop::Chunk, entity::entity::InstanceController,
+    helpers::animation::AnimationHandler,
+};
+
+// Drift below this is float noise and left out of reports
+const DRIFT_EPSILON: f32 = 1e-4;
+
+pub struct DriftSnapshot {
+    // Visible instance positions per chunk, by instance index
+    positions: HashMap<Chunk, Vec<Option<Vector3<f32>>>>,
+    // Animation starts of chunk (0, 0)
+    starts: Vec<Vector3<f32>>,
+}
+
+#[derive(Clone, Copy)]
+pub struct Drift {
+    pub chunk: Chunk,
+    pub index: usize,
+    // Current minus snapshot
+    pub offset: Vector3<f32>,
+    // Whether the offset is in the animation start rather than the instance position
+    pub start: bool,
+}
+
+impl DriftSnapshot {
+    pub fn take(
+        chunk_map: &HashMap<Chunk, InstanceController>,
+        animation_handler: &AnimationHandler,
+    ) -> DriftSnapshot {
+        DriftSnapshot {
+            positions: chunk_map
+                .iter()
+                .map(|(chunk, controller)| {
+                    let positions = controller
+                        .instances
+                        .iter()
+                        .map(|instance| instance.should_render.then_some(instance.position))
+                        .collect();
+                    (*chunk, positions)
+                })
+                .collect(),
+            starts: animation_handler
+                .movement_list
+                .iter()
+                .map(|animation| animation.start())
+                .collect(),
+        }
+    }
+
+    // Instances visible in both the snapshot and now that moved, the worst `limit` first
+    pub fn diff(
+        &self,
+        chunk_map: &HashMap<Chunk, InstanceController>,
+        animation_handler: &AnimationHandler,
+        limit: usize,
+    ) -> Vec<Drift> {
+        let mut drifts = Vec::new();
+        for (chunk, positions) in &self.positions {
+            let controller = match chunk_map.get(chunk) {
+                Some(controller) => controller,
+                None => continue,
+            };
+            for (index, (instance, before)) in
+                controller.instances.iter().zip(positions).enumerate()
+            {
+                if let (true, Some(before)) = (instance.should_render, before) {
+                    drifts.push(Drift {
+                        chunk: *chunk,
+                        index,
+                        offset: instance.position - before,
+                        start: false,
+                    });
+                }
+            }
+        }
+        for (index, (animation, before)) in animation_handler
+            .movement_list
+            .iter()
+            .zip(&self.starts)
+            .enumerate()
+        {
+            drifts.push(Drift {
+                chunk: Chunk { x: 0, y: 0 },
+                index,
+                offset: animation.start() - before,
+                start: true,
+            });
+        }
+        drifts.retain(|drift| drift.offset.magnitude() > DRIFT_EPSILON);
+        drifts.sort_by(|a, b| b.offset.magnitude2().total_cmp(&a.offset.magnitude2()));
+        drifts.truncate(limit);
+        drifts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{core::test_support, entity::progress_bar::PROGRESS_CUBES};
+
+    use super::*;
+
+    // A hundred section changes, each left to settle, and back home
+    #[test]
+    fn snapped_transitions_stay_on_the_grid() {
+        let mut game_loop = test_support::game_loop(test_support::grid());
+        let epsilon = game_loop.settings.grid_snap_epsilon.unwrap();
+        let frame = Duration::from_millis(16);
+        let snapshot = DriftSnapshot::take(&game_loop.chunk_map, &game_loop.animation_handler);
+        for transition in 1..=100 {
+            game_loop.go_to_section(transition % PROGRESS_CUBES);
+            for _ in 0..150 {
+                game_loop.update(frame);
+            }
+        }
+        game_loop.go_to_section(0);
+        for _ in 0..150 {
+            game_loop.update(frame);
+        }
+
+        // Starts move with every animation on purpose, the places the cubes rest at must not
+        let drift = snapshot
+            .diff(
+                &game_loop.chunk_map,
+                &game_loop.animation_handler,
+                usize::MAX,
+            )
+            .iter()
+            .filter(|drift| !drift.start)
+            .map(|drift| drift.offset.magnitude())
+            .fold(0.0, f32::max);
+        assert!(drift < epsilon, "drifted {}", drift);
+    }
+}
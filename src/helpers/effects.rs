@@ -0,0 +1,297 @@
+// Named entrance and exit effects, each plays one animation per target with a stagger
+
+use std::f32::consts::TAU;
+
+use cgmath::{InnerSpace, Vector3};
+
+use crate::helpers::{
+    animation::{AnimationHandler, TrajectoryMode},
+    vector::safe_normalize,
+};
+
+// Stagger steps it takes the spiral to sweep a full turn
+const SPIRAL_TURN_STEPS: f32 = 20.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EffectPreset {
+    AssembleFromGround,
+    DissolveUp,
+    SpiralIn,
+    WaveReveal,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EffectOptions {
+    // How far from its target a cube starts (or ends, for exits)
+    pub distance: f32,
+    // Seconds between consecutive steps of the effect
+    pub stagger: f32,
+}
+
+impl Default for EffectOptions {
+    fn default() -> Self {
+        EffectOptions {
+            distance: 5.0,
+            stagger: 0.05,
+        }
+    }
+}
+
+// One cube's part in an effect
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EffectStep {
+    pub index: usize,
+    pub start: Vector3<f32>,
+    pub end: Vector3<f32>,
+    // Seconds before it starts moving
+    pub delay: f32,
+}
+
+impl EffectPreset {
+    pub fn from_name(name: &str) -> Option<EffectPreset> {
+        match name.to_ascii_lowercase().as_str() {
+            "assemble_from_ground" | "assemble" => Some(EffectPreset::AssembleFromGround),
+            "dissolve_up" | "dissolve" => Some(EffectPreset::DissolveUp),
+            "spiral_in" | "spiral" => Some(EffectPreset::SpiralIn),
+            "wave_reveal" | "wave" => Some(EffectPreset::WaveReveal),
+            _ => None,
+        }
+    }
+
+    // The name from_name reads back
+    pub fn name(self) -> &'static str {
+        match self {
+            EffectPreset::AssembleFromGround => "assemble_from_ground",
+            EffectPreset::DissolveUp => "dissolve_up",
+            EffectPreset::SpiralIn => "spiral_in",
+            EffectPreset::WaveReveal => "wave_reveal",
+        }
+    }
+
+    pub fn steps(
+        self,
+        targets: &[(usize, Vector3<f32>)],
+        options: &EffectOptions,
+    ) -> Vec<EffectStep> {
+        match self {
+            EffectPreset::AssembleFromGround => assemble_from_ground(targets, options),
+            EffectPreset::DissolveUp => dissolve_up(targets, options),
+            EffectPreset::SpiralIn => spiral_in(targets, options),
+            EffectPreset::WaveReveal => wave_reveal(targets, options),
+        }
+    }
+
+    pub fn play(
+        self,
+        handler: &mut AnimationHandler,
+        targets: &[(usize, Vector3<f32>)],
+        options: &EffectOptions,
+    ) {
+        for step in self.steps(targets, options) {
+            handler.play(step.index, step.start, step.end, step.delay);
+        }
+    }
+}
+
+// A preset with its options and path, named in the manifest so sections can share one
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EffectTemplate {
+    pub preset: EffectPreset,
+    pub options: EffectOptions,
+    pub trajectory: TrajectoryMode,
+}
+
+impl EffectTemplate {
+    pub fn from_preset(preset: EffectPreset) -> EffectTemplate {
+        EffectTemplate {
+            preset,
+            options: EffectOptions::default(),
+            trajectory: TrajectoryMode::Linear,
+        }
+    }
+
+    pub fn play(&self, handler: &mut AnimationHandler, targets: &[(usize, Vector3<f32>)]) {
+        self.preset.play(handler, targets, &self.options);
+        for (index, _) in targets {
+            handler.set_trajectory(*index, self.trajectory);
+        }
+    }
+
+    // What play would do to the first `cap` targets, each step with the seconds it takes
+    // once it starts moving. Changes nothing
+    pub fn preview(
+        &self,
+        handler: &AnimationHandler,
+        targets: &[(usize, Vector3<f32>)],
+        cap: usize,
+    ) -> Vec<(EffectStep, f32)> {
+        self.preset
+            .steps(targets, &self.options)
+            .into_iter()
+            .take(cap)
+            .map(|step| (step, handler.duration(step.index, step.start, step.end)))
+            .collect()
+    }
+}
+
+// Cubes rise into place from below, bottom layer first
+pub fn assemble_from_ground(
+    targets: &[(usize, Vector3<f32>)],
+    options: &EffectOptions,
+) -> Vec<EffectStep> {
+    let bottom = targets
+        .iter()
+        .map(|(_, target)| target.y)
+        .fold(f32::INFINITY, f32::min);
+    targets
+        .iter()
+        .map(|&(index, target)| EffectStep {
+            index,
+            start: target - Vector3::unit_y() * options.distance,
+            end: target,
+            delay: (target.y - bottom) * options.stagger,
+        })
+        .collect()
+}
+
+// Cubes float away upward from where they are, top layer first
+pub fn dissolve_up(targets: &[(usize, Vector3<f32>)], options: &EffectOptions) -> Vec<EffectStep> {
+    let top = targets
+        .iter()
+        .map(|(_, target)| target.y)
+        .fold(f32::NEG_INFINITY, f32::max);
+    targets
+        .iter()
+        .map(|&(index, target)| EffectStep {
+            index,
+            start: target,
+            end: target + Vector3::unit_y() * options.distance,
+            delay: (top - target.y) * options.stagger,
+        })
+        .collect()
+}
+
+// Cubes start pushed outward from the center and sweep in, ordered by angle around it
+pub fn spiral_in(targets: &[(usize, Vector3<f32>)], options: &EffectOptions) -> Vec<EffectStep> {
+    let center = centroid(targets);
+    let mut steps = Vec::with_capacity(targets.len());
+    for &(index, target) in targets {
+        let offset = Vector3::new(target.x - center.x, 0.0, target.z - center.z);
+        let direction = safe_normalize(offset, Vector3::unit_x());
+        let turn = (offset.z.atan2(offset.x) + TAU) % TAU / TAU;
+        let start = target + direction * options.distance + Vector3::unit_y() * options.distance;
+        steps.push(EffectStep {
+            index,
+            start,
+            end: target,
+            delay: turn * SPIRAL_TURN_STEPS * options.stagger,
+        });
+    }
+    steps
+}
+
+// Cubes rise from below in rings spreading out from the center
+pub fn wave_reveal(targets: &[(usize, Vector3<f32>)], options: &EffectOptions) -> Vec<EffectStep> {
+    let center = centroid(targets);
+    targets
+        .iter()
+        .map(|&(index, target)| {
+            let ring = Vector3::new(target.x - center.x, 0.0, target.z - center.z).magnitude();
+            EffectStep {
+                index,
+                start: target - Vector3::unit_y() * options.distance,
+                end: target,
+                delay: ring * options.stagger,
+            }
+        })
+        .collect()
+}
+
+fn centroid(targets: &[(usize, Vector3<f32>)]) -> Vector3<f32> {
+    if targets.is_empty() {
+        return Vector3::new(0.0, 0.0, 0.0);
+    }
+    targets
+        .iter()
+        .fold(Vector3::new(0.0, 0.0, 0.0), |sum, (_, target)| sum + target)
+        / targets.len() as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::test_support;
+
+    const PRESETS: [EffectPreset; 4] = [
+        EffectPreset::AssembleFromGround,
+        EffectPreset::DissolveUp,
+        EffectPreset::SpiralIn,
+        EffectPreset::WaveReveal,
+    ];
+
+    // A 5×3×5 block of cubes
+    fn targets() -> Vec<(usize, Vector3<f32>)> {
+        (0..75)
+            .map(|n| {
+                let position = Vector3::new((n % 5) as f32, (n / 25) as f32, (n / 5 % 5) as f32);
+                (n, position)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn every_preset_lands_exactly_on_its_ends() {
+        let targets = targets();
+        let cubes = targets
+            .iter()
+            .map(|(_, target)| test_support::cube(*target))
+            .collect::<Vec<_>>();
+        for preset in PRESETS.iter().copied() {
+            let mut handler = test_support::animation_handler(&cubes);
+            let options = EffectOptions::default();
+            preset.play(&mut handler, &targets, &options);
+            let mut frames = 0;
+            while handler.is_active() {
+                handler.animate(1.0 / 60.0);
+                frames += 1;
+                assert!(frames < 60 * 60, "{} never finished", preset.name());
+            }
+            for step in preset.steps(&targets, &options) {
+                assert_eq!(
+                    handler.movement_list[step.index].current_pos,
+                    step.end,
+                    "{}",
+                    preset.name()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn entrances_end_on_the_targets() {
+        let targets = targets();
+        for preset in [
+            EffectPreset::AssembleFromGround,
+            EffectPreset::SpiralIn,
+            EffectPreset::WaveReveal,
+        ]
+        .iter()
+        {
+            for (step, (index, target)) in preset
+                .steps(&targets, &EffectOptions::default())
+                .iter()
+                .zip(&targets)
+            {
+                assert_eq!((step.index, step.end), (*index, *target));
+            }
+        }
+    }
+
+    #[test]
+    fn preset_names_read_back() {
+        for preset in PRESETS.iter().copied() {
+            assert_eq!(EffectPreset::from_name(preset.name()), Some(preset));
+        }
+        assert_eq!(EffectPreset::from_name("sideways"), None);
+    }
+}
@@ -0,0 +1,84 @@
+// World positions to grid cells and the text Ctrl+C copies for a cube
+
+use cgmath::Vector3;
+
+use crate::core::game_loop::Chunk;
+
+// Chunk and index of the cell holding a world position, laid out like instances_list
+pub fn grid_cell(position: Vector3<f32>, chunk_size: cgmath::Vector2<u32>) -> (Chunk, usize) {
+    let width = chunk_size.x.max(1) as i32;
+    let depth = chunk_size.y.max(1) as i32;
+    let x = position.x.round() as i32;
+    let z = position.z.round() as i32;
+    let chunk = Chunk {
+        x: x.div_euclid(width),
+        y: z.div_euclid(depth),
+    };
+    let index = z.rem_euclid(depth) * depth + x.rem_euclid(width);
+    (chunk, index as usize)
+}
+
+// One line, ready to paste into notes or the manifest
+pub fn cube_info(
+    position: Vector3<f32>,
+    chunk: Chunk,
+    index: usize,
+    section: Option<(usize, &str)>,
+) -> String {
+    let mut info = format!(
+        "position = {:.3}, {:.3}, {:.3} | chunk = {}, {} | index = {}",
+        position.x, position.y, position.z, chunk.x, chunk.y, index
+    );
+    if let Some((section, title)) = section {
+        info.push_str(&format!(" | section = {}", section));
+        if !title.is_empty() {
+            info.push_str(&format!(" ({})", title));
+        }
+    }
+    info
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Vector2;
+
+    use super::*;
+    use crate::entity::entity::instances_list;
+
+    #[test]
+    fn grid_cell_inverts_the_grid_layout() {
+        let chunk_size = Vector2::new(35, 35);
+        for chunk in [
+            Chunk { x: 0, y: 0 },
+            Chunk { x: 2, y: 1 },
+            Chunk { x: -1, y: -3 },
+        ]
+        .iter()
+        {
+            for (index, instance) in instances_list(*chunk, chunk_size).iter().enumerate() {
+                assert!(grid_cell(instance.position, chunk_size) == (*chunk, index));
+                // Anywhere near the cube's spot rounds to the same cell
+                let lifted = instance.position + Vector3::new(0.4, 3.0, -0.4);
+                assert!(grid_cell(lifted, chunk_size) == (*chunk, index));
+            }
+        }
+    }
+
+    #[test]
+    fn cube_info_formats() {
+        let position = Vector3::new(1.0, -0.5, 12.3456);
+        let chunk = Chunk { x: -1, y: 2 };
+        assert_eq!(
+            cube_info(position, chunk, 7, None),
+            "position = 1.000, -0.500, 12.346 | chunk = -1, 2 | index = 7"
+        );
+        assert_eq!(
+            cube_info(position, chunk, 7, Some((3, "Rust"))),
+            "position = 1.000, -0.500, 12.346 | chunk = -1, 2 | index = 7 | section = 3 (Rust)"
+        );
+        assert_eq!(
+            cube_info(position, chunk, 7, Some((0, ""))),
+            "position = 1.000, -0.500, 12.346 | chunk = -1, 2 | index = 7 | section = 0"
+        );
+    }
+}
@@ -2,13 +2,26 @@ use cgmath::{InnerSpace, Point3, Rotation3, Vector2, Vector3};
 use winit::dpi::PhysicalPosition;
 
 use crate::{
-    core::{camera::Camera, state::State},
+    core::{camera::Camera, settings::Settings, state::State},
     entity::entity::{Instance, InstanceController},
     helpers::animation::AnimationHandler,
 };
 
 const STEPSIZE: f32 = 0.1;
 const DISTANCE: f32 = 100.0;
+// How much farther along the ray than the nearest hit a settled cube may be and still win
+const PICK_EPSILON: f32 = 0.5;
+const SHAKE_DISTANCE: f32 = 0.08;
+
+// Which of several cubes under the cursor a pick returns
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PickPolicy {
+    // Whatever the ray reaches first
+    NearestOnly,
+    // A cube that isn't animating over a moving one just in front of it
+    PreferSettled,
+}
+
 pub fn line_trace_cursor(
     state: &mut InstanceController,
     chunk_size: &Vector2<u32>,
@@ -49,9 +62,8 @@ pub fn line_trace_remove(
         let step = click_vector.0 - (click_vector.1 * (n as f32 * STEPSIZE));
 
         for instance in state.instances.iter_mut() {
-            if (instance.should_render
-                && aabb_intersect(&step, &instance.position, &instance.bounding))
-            {
+            let (min, max) = instance.bounds();
+            if (instance.should_render && aabb_intersect(&step, &min, &max)) {
                 instance.should_render = false;
                 state.update_buffer(queue);
                 break 'trace;
@@ -88,27 +100,194 @@ pub fn line_trace_remove(
 pub fn line_trace_animate_hit(
     state: &mut InstanceController,
     animation_handler: &mut AnimationHandler,
+    policy: PickPolicy,
     queue: &wgpu::Queue,
     click_vector: (Point3<f32>, Vector3<f32>),
 ) {
-    'trace: for n in 0..(DISTANCE / STEPSIZE) as u64 {
+    if let Some(index) = line_trace_index(state, Some(animation_handler), policy, click_vector) {
+        lift_at(state, animation_handler, index);
+    }
+    state.update_buffer(queue);
+}
+
+pub fn lift_at(
+    state: &mut InstanceController,
+    animation_handler: &mut AnimationHandler,
+    index: usize,
+) {
+    if let Some(instance) = state.instances.get(index) {
+        let mut animation_end = instance.position;
+        animation_end.y += 1.0;
+        animation_handler.set_animation(index, &instance.position, &animation_end);
+        animation_handler.reset_animation_time(index);
+        animation_handler.reverse(index, false);
+        animation_handler.set_animation_state(index, true);
+    }
+}
+
+// Nudges the cube sideways and plays it back right away, shown when an interaction is denied
+pub fn shake_at(
+    state: &mut InstanceController,
+    animation_handler: &mut AnimationHandler,
+    index: usize,
+) {
+    if let Some(instance) = state.instances.get(index) {
+        let mut animation_end = instance.position;
+        animation_end.x += SHAKE_DISTANCE;
+        animation_handler.set_animation(index, &instance.position, &animation_end);
+        animation_handler.reset_animation_time(index);
+        animation_handler.reverse(index, false);
+        animation_handler.set_rebound(index, 0.0);
+        animation_handler.set_animation_state(index, true);
+    }
+}
+
+pub fn line_trace_implode(
+    state: &mut InstanceController,
+    animation_handler: &mut AnimationHandler,
+    settings: &Settings,
+    queue: &wgpu::Queue,
+    click_vector: (Point3<f32>, Vector3<f32>),
+) {
+    let picked = line_trace_index(
+        state,
+        Some(animation_handler),
+        settings.pick_policy,
+        click_vector,
+    );
+    if let Some(index) = picked {
+        implode_at(state, animation_handler, settings, queue, index);
+    }
+}
+
+pub fn implode_at(
+    state: &mut InstanceController,
+    animation_handler: &mut AnimationHandler,
+    settings: &Settings,
+    queue: &wgpu::Queue,
+    hit_index: usize,
+) {
+    if hit_index >= state.instances.len() {
+        return;
+    }
+    let (hit_min, hit_max) = state.instances[hit_index].bounds();
+    let hit = hit_min + (hit_max - hit_min) / 2.0;
+    let radius = settings.implode_radius;
+
+    for (index, instance) in state.instances.iter().enumerate() {
+        let (min, max) = instance.bounds();
+        if !instance.should_render || !aabb_sphere_intersect(&hit, radius, &min, &max) {
+            continue;
+        }
+        let center = min + (max - min) / 2.0;
+        let offset = hit - center;
+        let distance = offset.magnitude();
+        if distance < f32::EPSILON {
+            continue;
+        }
+        // Pull falls off linearly with distance and never overshoots the hit point
+        let falloff = (1.0 - distance / radius).clamp(0.0, 1.0);
+        let pull = (settings.implode_strength * falloff).min(distance);
+        let animation_end = instance.position + offset / distance * pull;
+
+        animation_handler.set_animation(index, &instance.position, &animation_end);
+        animation_handler.reset_animation_time(index);
+        animation_handler.reverse(index, false);
+        animation_handler.set_rebound(index, settings.implode_rebound_delay);
+        animation_handler.set_animation_state(index, true);
+    }
+    state.update_buffer(queue);
+}
+
+// Without an animation handler every cube counts as settled
+pub fn line_trace_index(
+    state: &InstanceController,
+    animation_handler: Option<&AnimationHandler>,
+    policy: PickPolicy,
+    click_vector: (Point3<f32>, Vector3<f32>),
+) -> Option<usize> {
+    line_trace_pick(state, animation_handler, policy, click_vector).map(|(index, _)| index)
+}
+
+// Like line_trace_index, but also returns the normal of the face that was hit
+pub fn line_trace_hit(
+    state: &InstanceController,
+    animation_handler: Option<&AnimationHandler>,
+    policy: PickPolicy,
+    click_vector: (Point3<f32>, Vector3<f32>),
+) -> Option<(usize, Vector3<f32>)> {
+    let (index, point) = line_trace_pick(state, animation_handler, policy, click_vector)?;
+    let (min, max) = state.instances[index].bounds();
+    Some((index, face_normal(&point, &min, &max)))
+}
+
+// Index of the picked instance and the ray sample inside it
+fn line_trace_pick(
+    state: &InstanceController,
+    animation_handler: Option<&AnimationHandler>,
+    policy: PickPolicy,
+    click_vector: (Point3<f32>, Vector3<f32>),
+) -> Option<(usize, Point3<f32>)> {
+    let is_settled =
+        |index: usize| animation_handler.is_none_or(|handler| !handler.is_animating(index));
+    let window = (PICK_EPSILON / STEPSIZE) as u64;
+    let mut nearest: Option<(u64, usize, Point3<f32>)> = None;
+    for n in 0..(DISTANCE / STEPSIZE) as u64 {
+        if nearest.is_some_and(|(first, _, _)| n > first + window) {
+            break;
+        }
         let step = click_vector.0 - (click_vector.1 * (n as f32 * STEPSIZE));
 
-        for (index, instance) in state.instances.iter_mut().enumerate() {
-            if !instance.should_render {
+        for (index, instance) in state.instances.iter().enumerate() {
+            let (min, max) = instance.bounds();
+            if !instance.should_render || !aabb_intersect(&step, &min, &max) {
                 continue;
             }
-            if (aabb_intersect(&step, &instance.position, &instance.bounding)) {
-                let mut animation_end = instance.position.clone();
-                animation_end.y = animation_end.y + 1.0;
-                animation_handler.set_animation(index, &instance.position, &animation_end);
-                animation_handler.reset_animation_time(index);
-                animation_handler.set_animation_state(index, true);
-                break 'trace;
+            if policy == PickPolicy::NearestOnly || is_settled(index) {
+                return Some((index, step));
+            }
+            if nearest.is_none() {
+                nearest = Some((n, index, step));
             }
         }
     }
-    state.update_buffer(queue);
+    nearest.map(|(_, index, step)| (index, step))
+}
+
+// Normal of the box face closest to a point inside the box
+fn face_normal(
+    point: &cgmath::Point3<f32>,
+    bounding_min: &cgmath::Vector3<f32>,
+    bounding_max: &cgmath::Vector3<f32>,
+) -> Vector3<f32> {
+    let faces = [
+        (point.x - bounding_min.x, -Vector3::unit_x()),
+        (bounding_max.x - point.x, Vector3::unit_x()),
+        (point.y - bounding_min.y, -Vector3::unit_y()),
+        (bounding_max.y - point.y, Vector3::unit_y()),
+        (point.z - bounding_min.z, -Vector3::unit_z()),
+        (bounding_max.z - point.z, Vector3::unit_z()),
+    ];
+    faces
+        .iter()
+        .min_by(|a, b| a.0.total_cmp(&b.0))
+        .map(|face| face.1)
+        .unwrap()
+}
+
+pub fn aabb_sphere_intersect(
+    center: &cgmath::Vector3<f32>,
+    radius: f32,
+    bounding_min: &cgmath::Vector3<f32>,
+    bounding_max: &cgmath::Vector3<f32>,
+) -> bool {
+    // Closest point of the box to the sphere center
+    let closest = cgmath::Vector3::new(
+        center.x.clamp(bounding_min.x, bounding_max.x),
+        center.y.clamp(bounding_min.y, bounding_max.y),
+        center.z.clamp(bounding_min.z, bounding_max.z),
+    );
+    (closest - center).magnitude2() <= radius * radius
 }
 
 fn aabb_intersect(
@@ -123,3 +302,47 @@ fn aabb_intersect(
         && point.z >= bounding_min.z
         && point.z <= bounding_max.z;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::test_support;
+
+    // A settled cube with a second one overlapping it from above, the upper one mid-flight
+    fn overlapping(
+        moving: bool,
+    ) -> (
+        InstanceController,
+        AnimationHandler,
+        (Point3<f32>, Vector3<f32>),
+    ) {
+        let (device, queue) = test_support::device();
+        let cubes = vec![
+            test_support::cube(Vector3::new(0.0, 0.0, 0.0)),
+            test_support::cube(Vector3::new(0.0, 0.3, 0.0)),
+        ];
+        let mut handler = test_support::animation_handler(&cubes);
+        if moving {
+            handler.play(1, cubes[1].position, Vector3::new(0.0, 5.0, 0.0), 0.0);
+        }
+        let controller = test_support::controller(&device, &queue, cubes);
+        let camera = test_support::camera_above(Point3::new(0.5, 0.5, 0.5));
+        let ray = test_support::screen().cursor_ray(&camera, test_support::screen_center());
+        (controller, handler, ray)
+    }
+
+    #[test]
+    fn settled_cube_wins_over_a_moving_one_in_front() {
+        let (controller, handler, ray) = overlapping(true);
+        let pick = |policy| line_trace_hit(&controller, Some(&handler), policy, ray);
+        assert_eq!(pick(PickPolicy::PreferSettled).map(|hit| hit.0), Some(0));
+        assert_eq!(pick(PickPolicy::NearestOnly).map(|hit| hit.0), Some(1));
+    }
+
+    #[test]
+    fn nearest_cube_wins_when_nothing_moves() {
+        let (controller, handler, ray) = overlapping(false);
+        let hit = line_trace_hit(&controller, Some(&handler), PickPolicy::PreferSettled, ray);
+        assert_eq!(hit, Some((1, Vector3::unit_y())));
+    }
+}
@@ -1,2 +1,11 @@
 pub mod animation;
+pub mod color_vision;
+pub mod drift;
+pub mod effects;
+pub mod grid;
+pub mod json;
 pub mod line_trace;
+pub mod selection;
+pub mod sequence;
+pub mod tool;
+pub mod vector;
@@ -0,0 +1,72 @@
+use std::collections::BTreeSet;
+
+use cgmath::Vector3;
+
+use crate::entity::entity::Instance;
+
+// Indices of the selected cubes in chunk (0, 0). Removing an instance only hides it,
+// so indices stay valid through animations and deletes
+#[derive(Default)]
+pub struct Selection {
+    indices: BTreeSet<usize>,
+}
+
+impl Selection {
+    // Returns true if the index is selected afterwards
+    pub fn toggle(&mut self, index: usize) -> bool {
+        if self.indices.remove(&index) {
+            false
+        } else {
+            self.indices.insert(index)
+        }
+    }
+
+    pub fn insert(&mut self, index: usize) {
+        self.indices.insert(index);
+    }
+
+    pub fn contains(&self, index: usize) -> bool {
+        self.indices.contains(&index)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.indices.clear();
+    }
+
+    pub fn indices(&self) -> Vec<usize> {
+        self.indices.iter().copied().collect()
+    }
+
+    // Drops indices of instances that are gone or hidden
+    pub fn prune(&mut self, instances: &[Instance]) {
+        self.indices.retain(|index| {
+            instances
+                .get(*index)
+                .is_some_and(|instance| instance.should_render)
+        });
+    }
+
+    pub fn centroid(&self, instances: &[Instance]) -> Option<Vector3<f32>> {
+        let positions = self
+            .indices
+            .iter()
+            .filter_map(|index| instances.get(*index))
+            .map(|instance| instance.position)
+            .collect::<Vec<_>>();
+        if positions.is_empty() {
+            return None;
+        }
+        let sum = positions
+            .iter()
+            .fold(Vector3::new(0.0, 0.0, 0.0), |sum, position| sum + position);
+        Some(sum / positions.len() as f32)
+    }
+}
@@ -0,0 +1,106 @@
+// Recognizes a fixed sequence of inputs typed in a row, like the konami code
+
+use std::collections::VecDeque;
+
+use winit::keyboard::KeyCode;
+
+// Up, up, down, down, left, right, left, right, B, A
+pub const KONAMI_CODE: [KeyCode; 10] = [
+    KeyCode::ArrowUp,
+    KeyCode::ArrowUp,
+    KeyCode::ArrowDown,
+    KeyCode::ArrowDown,
+    KeyCode::ArrowLeft,
+    KeyCode::ArrowRight,
+    KeyCode::ArrowLeft,
+    KeyCode::ArrowRight,
+    KeyCode::KeyB,
+    KeyCode::KeyA,
+];
+
+pub struct SequenceDetector<T> {
+    sequence: Vec<T>,
+    // The last inputs, at most as many as the sequence is long
+    recent: VecDeque<T>,
+    // Seconds allowed between two inputs before the sequence starts over
+    timeout: f32,
+    since_input: f32,
+}
+
+impl<T: Copy + PartialEq> SequenceDetector<T> {
+    pub fn new(sequence: &[T], timeout: f32) -> SequenceDetector<T> {
+        SequenceDetector {
+            sequence: sequence.to_vec(),
+            recent: VecDeque::with_capacity(sequence.len()),
+            timeout,
+            since_input: 0.0,
+        }
+    }
+
+    // Whether this input completes the sequence, which then starts over
+    pub fn push(&mut self, input: T) -> bool {
+        if self.sequence.is_empty() {
+            return false;
+        }
+        if self.since_input > self.timeout {
+            self.recent.clear();
+        }
+        self.since_input = 0.0;
+        if self.recent.len() == self.sequence.len() {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(input);
+        let matched = self.recent.iter().eq(self.sequence.iter());
+        if matched {
+            self.recent.clear();
+        }
+        matched
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        self.since_input += dt;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn typed(detector: &mut SequenceDetector<KeyCode>, keys: &[KeyCode], gap: f32) -> Vec<bool> {
+        keys.iter()
+            .map(|key| {
+                detector.update(gap);
+                detector.push(*key)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn the_full_code_matches_on_its_last_key() {
+        let mut detector = SequenceDetector::new(&KONAMI_CODE, 1.5);
+        let matches = typed(&mut detector, &KONAMI_CODE, 0.2);
+        assert_eq!(matches.iter().filter(|matched| **matched).count(), 1);
+        assert_eq!(matches.last(), Some(&true));
+        // It starts over after a match
+        assert!(!typed(&mut detector, &KONAMI_CODE[1..], 0.2).contains(&true));
+    }
+
+    #[test]
+    fn a_long_pause_starts_the_sequence_over() {
+        let mut detector = SequenceDetector::new(&KONAMI_CODE, 1.5);
+        typed(&mut detector, &KONAMI_CODE[..5], 0.2);
+        assert!(!typed(&mut detector, &KONAMI_CODE[5..6], 2.0)[0]);
+        assert!(!typed(&mut detector, &KONAMI_CODE[6..], 0.2).contains(&true));
+        assert_eq!(typed(&mut detector, &KONAMI_CODE, 0.2).last(), Some(&true));
+    }
+
+    #[test]
+    fn a_wrong_key_then_the_code_still_matches() {
+        let mut detector = SequenceDetector::new(&KONAMI_CODE, 1.5);
+        typed(&mut detector, &KONAMI_CODE[..6], 0.2);
+        assert!(!detector.push(KeyCode::KeyX));
+        let matches = typed(&mut detector, &KONAMI_CODE, 0.2);
+        assert_eq!(matches.last(), Some(&true));
+        assert_eq!(matches.iter().filter(|matched| **matched).count(), 1);
+    }
+}
@@ -0,0 +1,54 @@
+use cgmath::Vector3;
+
+use crate::helpers::animation::get_height_color;
+
+const PALETTE_SIZE: usize = 5;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Tool {
+    Animate,
+    Paint,
+    Stamp,
+    // Clicked cubes are removed, in multi-hit mode only after a few hits
+    Delete,
+}
+
+impl Tool {
+    pub fn next(self) -> Tool {
+        match self {
+            Tool::Animate => Tool::Paint,
+            Tool::Paint => Tool::Stamp,
+            Tool::Stamp => Tool::Delete,
+            Tool::Delete => Tool::Animate,
+        }
+    }
+}
+
+pub struct Palette {
+    pub colors: Vec<Vector3<f32>>,
+    pub active: usize,
+}
+
+impl Palette {
+    // Samples the height gradient so the palette follows the current theme
+    pub fn from_theme() -> Palette {
+        Palette {
+            colors: (0..PALETTE_SIZE)
+                .map(|n| get_height_color(n as f32 / (PALETTE_SIZE - 1) as f32))
+                .collect(),
+            active: 0,
+        }
+    }
+
+    pub fn color(&self) -> Vector3<f32> {
+        self.colors[self.active]
+    }
+
+    pub fn next(&mut self) {
+        self.active = (self.active + 1) % self.colors.len();
+    }
+
+    pub fn previous(&mut self) {
+        self.active = (self.active + self.colors.len() - 1) % self.colors.len();
+    }
+}
@@ -0,0 +1,67 @@
+use cgmath::{InnerSpace, Vector3};
+
+// Squared length below which a vector has no usable direction
+const NEAR_ZERO: f32 = 1e-12;
+
+// `vector` scaled to unit length, or `fallback` when it is too short to have a direction.
+// normalize() divides by zero there and hands NaN to everything downstream
+pub fn safe_normalize(vector: Vector3<f32>, fallback: Vector3<f32>) -> Vector3<f32> {
+    let length2 = vector.magnitude2();
+    if length2 > NEAR_ZERO && length2.is_finite() {
+        vector / length2.sqrt()
+    } else {
+        fallback
+    }
+}
+
+pub fn is_finite(vector: Vector3<f32>) -> bool {
+    vector.x.is_finite() && vector.y.is_finite() && vector.z.is_finite()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn degenerate_vectors_fall_back_to_a_finite_direction() {
+        let fallback = Vector3::unit_y();
+        for vector in [
+            Vector3::new(0.0, 0.0, 0.0),
+            Vector3::new(1e-7, 0.0, -1e-7),
+            Vector3::new(f32::NAN, 1.0, 0.0),
+            Vector3::new(0.0, f32::INFINITY, 0.0),
+            Vector3::new(f32::NEG_INFINITY, f32::NAN, 1.0),
+            Vector3::new(1e30, 1e30, 0.0),
+        ]
+        .iter()
+        {
+            let normal = safe_normalize(*vector, fallback);
+            assert!(is_finite(normal), "{:?}", vector);
+            assert_eq!(normal, fallback, "{:?}", vector);
+        }
+    }
+
+    #[test]
+    fn usable_vectors_come_out_unit_length() {
+        for vector in [
+            Vector3::new(3.0, 0.0, 4.0),
+            Vector3::new(-1e-3, 2e-3, 0.0),
+            Vector3::new(1e15, -1e15, 1e15),
+        ]
+        .iter()
+        {
+            let normal = safe_normalize(*vector, Vector3::unit_y());
+            assert!(is_finite(normal));
+            assert!((normal.magnitude() - 1.0).abs() < 1e-6, "{:?}", vector);
+            assert!(normal.dot(*vector) > 0.0);
+        }
+    }
+
+    #[test]
+    fn is_finite_rejects_any_bad_component() {
+        assert!(is_finite(Vector3::new(0.0, -1e38, 1e-45)));
+        assert!(!is_finite(Vector3::new(f32::NAN, 0.0, 0.0)));
+        assert!(!is_finite(Vector3::new(0.0, f32::INFINITY, 0.0)));
+        assert!(!is_finite(Vector3::new(0.0, 0.0, f32::NEG_INFINITY)));
+    }
+}
@@ -1,5 +1,20 @@
-use cv_game::core::event_loop::run;
+use cv_game::core::event_loop::{run, run_screensaver};
 
 fn main() {
+    // `cv_game validate [manifest]` checks content without opening a window
+    let mut args = std::env::args().skip(1);
+    let first = args.next();
+    if first.as_deref() == Some("validate") {
+        std::process::exit(cv_game::core::validate::run(args.next().as_deref()));
+    }
+    if first.as_deref().is_some_and(is_screensaver_flag) {
+        run_screensaver().unwrap();
+        return;
+    }
     run().unwrap();
 }
+
+// Windows starts screensavers with /s
+fn is_screensaver_flag(arg: &str) -> bool {
+    arg == "--screensaver" || (cfg!(windows) && arg.eq_ignore_ascii_case("/s"))
+}